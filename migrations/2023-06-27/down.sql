@@ -0,0 +1,6 @@
+DROP TABLE orcamento_historicos;
+
+ALTER TABLE orcamentos
+  DROP CONSTRAINT ck_orcamentos_status,
+  DROP COLUMN status,
+  DROP COLUMN validade;
\ No newline at end of file
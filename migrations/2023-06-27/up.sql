@@ -0,0 +1,28 @@
+
+ALTER TABLE orcamentos
+  ADD COLUMN status VARCHAR(20) NOT NULL DEFAULT 'rascunho',
+  ADD COLUMN validade TIMESTAMP,
+  ADD CONSTRAINT ck_orcamentos_status
+   CHECK (status IN ('rascunho', 'enviado', 'aprovado', 'recusado', 'expirado'));
+
+CREATE TABLE orcamento_historicos (
+  id VARCHAR(36) NOT NULL PRIMARY KEY,
+  id_orcamento VARCHAR(36) NOT NULL,
+  status_anterior VARCHAR(20),
+  status_novo VARCHAR(20) NOT NULL,
+  created_by VARCHAR(36) NOT NULL,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  CONSTRAINT fk_orcamentos
+   FOREIGN KEY(id_orcamento) 
+   REFERENCES orcamentos(id)
+);
+
+insert into orcamento_historicos (id,
+id_orcamento,
+status_novo,
+created_by)
+VALUES
+('00000000-0000-0000-0000-000000000000',
+'00000000-0000-0000-0000-000000000000',
+'rascunho',
+'00000000-0000-0000-0000-000000000000');
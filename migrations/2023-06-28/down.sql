@@ -0,0 +1,5 @@
+ALTER TABLE orcamentos
+  DROP CONSTRAINT fk_clientes,
+  DROP COLUMN id_cliente,
+  DROP COLUMN updated_by,
+  DROP COLUMN updated_at;
\ No newline at end of file
@@ -0,0 +1,16 @@
+
+ALTER TABLE orcamentos
+  ADD COLUMN id_cliente VARCHAR(36),
+  ADD COLUMN updated_by VARCHAR(36),
+  ADD COLUMN updated_at TIMESTAMP NOT NULL DEFAULT NOW();
+
+UPDATE orcamentos
+SET id_cliente = '00000000-0000-0000-0000-000000000000',
+updated_by = created_by;
+
+ALTER TABLE orcamentos
+  ALTER COLUMN id_cliente SET NOT NULL,
+  ALTER COLUMN updated_by SET NOT NULL,
+  ADD CONSTRAINT fk_clientes
+   FOREIGN KEY(id_cliente) 
+   REFERENCES clientes(id);
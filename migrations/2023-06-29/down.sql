@@ -0,0 +1,14 @@
+UPDATE orcamentos
+SET status = 'aprovado'
+WHERE status = 'convertido';
+
+ALTER TABLE orcamentos
+  DROP CONSTRAINT ck_orcamentos_status,
+  DROP CONSTRAINT fk_pedidos,
+  DROP COLUMN id_pedido;
+ALTER TABLE orcamentos
+  ADD CONSTRAINT ck_orcamentos_status
+   CHECK (status IN ('rascunho', 'enviado', 'aprovado', 'recusado', 'expirado'));
+
+DROP TABLE pedido_produtos;
+DROP TABLE pedidos;
@@ -0,0 +1,42 @@
+CREATE TABLE pedidos (
+  id VARCHAR(36) NOT NULL PRIMARY KEY,
+  id_cliente VARCHAR(36) NOT NULL,
+  id_orcamento VARCHAR(36),
+  status VARCHAR(20) NOT NULL DEFAULT 'aberto',
+  created_by VARCHAR(36) NOT NULL,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  updated_by VARCHAR(36) NOT NULL,
+  updated_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  CONSTRAINT fk_clientes
+   FOREIGN KEY(id_cliente) 
+   REFERENCES clientes(id),
+  CONSTRAINT fk_orcamentos
+   FOREIGN KEY(id_orcamento) 
+   REFERENCES orcamentos(id)
+);
+
+CREATE TABLE pedido_produtos (
+  id VARCHAR(36) NOT NULL PRIMARY KEY,
+  id_produto VARCHAR(36) NOT NULL,
+  id_pedido VARCHAR(36) NOT NULL,
+  quantidade INTEGER NOT NULL,
+  preco_unitario DECIMAL(15,2) NOT NULL,
+  created_by VARCHAR(36) NOT NULL,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  CONSTRAINT fk_produtos
+   FOREIGN KEY(id_produto) 
+   REFERENCES produtos(id),
+  CONSTRAINT fk_pedidos
+   FOREIGN KEY(id_pedido) 
+   REFERENCES pedidos(id)
+);
+
+ALTER TABLE orcamentos
+  DROP CONSTRAINT ck_orcamentos_status;
+ALTER TABLE orcamentos
+  ADD COLUMN id_pedido VARCHAR(36),
+  ADD CONSTRAINT fk_pedidos
+   FOREIGN KEY(id_pedido) 
+   REFERENCES pedidos(id),
+  ADD CONSTRAINT ck_orcamentos_status
+   CHECK (status IN ('rascunho', 'enviado', 'aprovado', 'recusado', 'expirado', 'convertido'));
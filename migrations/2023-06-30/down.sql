@@ -0,0 +1,14 @@
+DROP TABLE user_permissoes;
+
+ALTER TABLE pedido_produtos
+  DROP COLUMN desconto;
+
+ALTER TABLE orcamentos
+  DROP CONSTRAINT ck_orcamentos_tipo_desconto,
+  DROP COLUMN tipo_desconto,
+  DROP COLUMN valor_desconto;
+
+ALTER TABLE orcamento_produtos
+  DROP CONSTRAINT ck_orcamento_produtos_tipo_desconto,
+  DROP COLUMN tipo_desconto,
+  DROP COLUMN valor_desconto;
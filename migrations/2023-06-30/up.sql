@@ -0,0 +1,27 @@
+ALTER TABLE orcamento_produtos
+  ADD COLUMN tipo_desconto VARCHAR(10),
+  ADD COLUMN valor_desconto DECIMAL(15,2) NOT NULL DEFAULT 0,
+  ADD CONSTRAINT ck_orcamento_produtos_tipo_desconto
+   CHECK (tipo_desconto IN ('percentual', 'valor'));
+
+ALTER TABLE orcamentos
+  ADD COLUMN tipo_desconto VARCHAR(10),
+  ADD COLUMN valor_desconto DECIMAL(15,2) NOT NULL DEFAULT 0,
+  ADD CONSTRAINT ck_orcamentos_tipo_desconto
+   CHECK (tipo_desconto IN ('percentual', 'valor'));
+
+ALTER TABLE pedido_produtos
+  ADD COLUMN desconto DECIMAL(15,2) NOT NULL DEFAULT 0;
+
+CREATE TABLE user_permissoes (
+  id VARCHAR(36) NOT NULL PRIMARY KEY,
+  id_user VARCHAR(36) NOT NULL,
+  permissao VARCHAR(50) NOT NULL,
+  created_by VARCHAR(36) NOT NULL,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  CONSTRAINT fk_users
+   FOREIGN KEY(id_user) 
+   REFERENCES users(id),
+  CONSTRAINT uq_user_permissoes
+   UNIQUE (id_user, permissao)
+);
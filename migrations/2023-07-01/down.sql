@@ -0,0 +1,10 @@
+ALTER TABLE orcamento_produtos
+  DROP COLUMN preco_unitario,
+  DROP COLUMN descricao,
+  DROP COLUMN unidade_medida,
+  DROP COLUMN ncm,
+  DROP COLUMN cest,
+  DROP COLUMN icms,
+  DROP COLUMN pis,
+  DROP COLUMN cofins,
+  DROP COLUMN porcentagem_ipi;
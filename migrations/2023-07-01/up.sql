@@ -0,0 +1,27 @@
+ALTER TABLE orcamento_produtos
+  ADD COLUMN preco_unitario DECIMAL(15,2),
+  ADD COLUMN descricao VARCHAR(100),
+  ADD COLUMN unidade_medida CHAR(2),
+  ADD COLUMN ncm VARCHAR(8),
+  ADD COLUMN cest VARCHAR(12),
+  ADD COLUMN icms VARCHAR(12),
+  ADD COLUMN pis VARCHAR(4),
+  ADD COLUMN cofins VARCHAR(4),
+  ADD COLUMN porcentagem_ipi DECIMAL(15,2);
+
+UPDATE orcamento_produtos
+SET preco_unitario = COALESCE(produtos.preco_venda, 0),
+descricao = produtos.descricao,
+unidade_medida = produtos.unidade_medida,
+ncm = produtos.ncm,
+cest = produtos.cest,
+icms = produtos.icms,
+pis = produtos.pis,
+cofins = produtos.cofins,
+porcentagem_ipi = produtos.porcentagem_ipi
+FROM produtos
+WHERE produtos.id = orcamento_produtos.id_produto;
+
+ALTER TABLE orcamento_produtos
+  ALTER COLUMN preco_unitario SET NOT NULL,
+  ALTER COLUMN descricao SET NOT NULL;
@@ -0,0 +1,19 @@
+UPDATE orcamento_produtos
+SET icms = CAST(CAST(aliquota_icms AS INTEGER) AS VARCHAR)
+WHERE icms IS NULL
+AND aliquota_icms IS NOT NULL;
+
+ALTER TABLE orcamento_produtos
+  DROP COLUMN aliquota_icms,
+  DROP COLUMN aliquota_pis,
+  DROP COLUMN aliquota_cofins;
+
+UPDATE produtos
+SET icms = CAST(CAST(aliquota_icms AS INTEGER) AS VARCHAR)
+WHERE icms IS NULL
+AND aliquota_icms IS NOT NULL;
+
+ALTER TABLE produtos
+  DROP COLUMN aliquota_icms,
+  DROP COLUMN aliquota_pis,
+  DROP COLUMN aliquota_cofins;
@@ -0,0 +1,24 @@
+ALTER TABLE produtos
+  ADD COLUMN aliquota_icms DECIMAL(5,2),
+  ADD COLUMN aliquota_pis DECIMAL(5,2),
+  ADD COLUMN aliquota_cofins DECIMAL(5,2);
+
+-- icms held the aliquota until now, from here on it holds the CST
+UPDATE produtos
+SET aliquota_icms = CAST(icms AS DECIMAL(5,2)),
+icms = NULL
+WHERE icms ~ '^[0-9]+(\.[0-9]+)?$'
+AND icms NOT IN ('00', '10', '20', '30', '40', '41', '50', '51', '60', '70', '90');
+
+ALTER TABLE orcamento_produtos
+  ADD COLUMN aliquota_icms DECIMAL(5,2),
+  ADD COLUMN aliquota_pis DECIMAL(5,2),
+  ADD COLUMN aliquota_cofins DECIMAL(5,2);
+
+UPDATE orcamento_produtos
+SET aliquota_icms = produtos.aliquota_icms,
+aliquota_pis = produtos.aliquota_pis,
+aliquota_cofins = produtos.aliquota_cofins,
+icms = produtos.icms
+FROM produtos
+WHERE produtos.id = orcamento_produtos.id_produto;
@@ -0,0 +1,7 @@
+ALTER TABLE produtos
+  DROP CONSTRAINT ck_produtos_ncm,
+  DROP CONSTRAINT ck_produtos_cest,
+  DROP CONSTRAINT ck_produtos_icms,
+  DROP CONSTRAINT ck_produtos_cod_ipi,
+  DROP CONSTRAINT ck_produtos_pis,
+  DROP CONSTRAINT ck_produtos_cofins;
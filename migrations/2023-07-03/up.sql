@@ -0,0 +1,39 @@
+-- codes used to be free text, keep only the digits of the ones that were
+-- written with dots and drop whatever still is not a valid code
+UPDATE produtos
+SET ncm = REPLACE(ncm, '.', ''),
+cest = REPLACE(cest, '.', '');
+
+UPDATE produtos SET ncm = NULL WHERE ncm !~ '^[0-9]{8}$';
+UPDATE produtos SET cest = NULL WHERE cest !~ '^[0-9]{7}$';
+UPDATE produtos SET icms = NULL
+WHERE icms NOT IN ('00', '10', '20', '30', '40', '41', '50', '51', '60', '70', '90');
+UPDATE produtos SET cod_ipi = NULL
+WHERE cod_ipi NOT IN ('00', '01', '02', '03', '04', '05', '49', '50', '51', '52', '53', '54', '55', '99');
+UPDATE produtos SET pis = NULL
+WHERE pis NOT IN ('01', '02', '03', '04', '05', '06', '07', '08', '09', '49', '50', '51', '52', '53', '54', '55', '56', '60', '61', '62', '63', '64', '65', '66', '67', '70', '71', '72', '73', '74', '75', '98', '99');
+UPDATE produtos SET cofins = NULL
+WHERE cofins NOT IN ('01', '02', '03', '04', '05', '06', '07', '08', '09', '49', '50', '51', '52', '53', '54', '55', '56', '60', '61', '62', '63', '64', '65', '66', '67', '70', '71', '72', '73', '74', '75', '98', '99');
+
+UPDATE produtos
+SET icms = '00',
+pis = '01',
+cofins = '01',
+cod_ipi = '53'
+WHERE id = '00000000-0000-0000-0000-000000000000';
+
+UPDATE orcamento_produtos
+SET icms = produtos.icms,
+pis = produtos.pis,
+cofins = produtos.cofins
+FROM produtos
+WHERE produtos.id = orcamento_produtos.id_produto
+AND orcamento_produtos.id = '00000000-0000-0000-0000-000000000000';
+
+ALTER TABLE produtos
+  ADD CONSTRAINT ck_produtos_ncm CHECK (ncm ~ '^[0-9]{8}$'),
+  ADD CONSTRAINT ck_produtos_cest CHECK (cest ~ '^[0-9]{7}$'),
+  ADD CONSTRAINT ck_produtos_icms CHECK (icms IN ('00', '10', '20', '30', '40', '41', '50', '51', '60', '70', '90')),
+  ADD CONSTRAINT ck_produtos_cod_ipi CHECK (cod_ipi IN ('00', '01', '02', '03', '04', '05', '49', '50', '51', '52', '53', '54', '55', '99')),
+  ADD CONSTRAINT ck_produtos_pis CHECK (pis IN ('01', '02', '03', '04', '05', '06', '07', '08', '09', '49', '50', '51', '52', '53', '54', '55', '56', '60', '61', '62', '63', '64', '65', '66', '67', '70', '71', '72', '73', '74', '75', '98', '99')),
+  ADD CONSTRAINT ck_produtos_cofins CHECK (cofins IN ('01', '02', '03', '04', '05', '06', '07', '08', '09', '49', '50', '51', '52', '53', '54', '55', '56', '60', '61', '62', '63', '64', '65', '66', '67', '70', '71', '72', '73', '74', '75', '98', '99'));
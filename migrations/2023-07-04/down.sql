@@ -0,0 +1,2 @@
+DROP TABLE cests;
+DROP TABLE ncms;
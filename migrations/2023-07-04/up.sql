@@ -0,0 +1,61 @@
+CREATE TABLE ncms (
+  codigo VARCHAR(8) NOT NULL PRIMARY KEY,
+  descricao VARCHAR(255) NOT NULL,
+  aliquota_ipi DECIMAL(5,2)
+);
+
+-- a CEST applies to every NCM that starts with its ncm, which the CEST table
+-- gives at the chapter, heading or item level
+CREATE TABLE cests (
+  codigo VARCHAR(7) NOT NULL,
+  ncm VARCHAR(8) NOT NULL,
+  descricao VARCHAR(255) NOT NULL,
+  PRIMARY KEY (codigo, ncm)
+);
+
+CREATE INDEX idx_cests_ncm ON cests (ncm);
+
+-- TIPI rates of the NCMs the store sells, the full TIPI and CEST tables are
+-- loaded with `importar ncm` and `importar cest`
+INSERT INTO ncms (codigo, descricao, aliquota_ipi) VALUES
+('04012010', 'Leite UHT (Ultra High Temperature), com teor de matérias gordas não superior a 6%', 0),
+('04061010', 'Queijo muçarela', 0),
+('09012100', 'Café torrado, não descafeinado', 0),
+('10063021', 'Arroz semibranqueado ou branqueado, não parboilizado, polido ou brunido', 0),
+('15079011', 'Óleo de soja refinado, em recipientes com capacidade inferior ou igual a 5 l', 0),
+('17019900', 'Outros açúcares de cana ou de beterraba, no estado sólido', 0),
+('19021900', 'Outras massas alimentícias não cozidas, nem recheadas, nem preparadas de outro modo', 0),
+('19053100', 'Bolachas e biscoitos, adicionados de edulcorante', 0),
+('19059090', 'Outros produtos de padaria, pastelaria ou indústria de bolachas e biscoitos', 0),
+('20091100', 'Suco de laranja, congelado', 0),
+('21069010', 'Preparações do tipo utilizado para elaboração de bebidas', 0),
+('22011000', 'Águas minerais e águas gaseificadas', 0),
+('22021000', 'Águas, incluindo as águas minerais e as gaseificadas, adicionadas de açúcar ou de outros edulcorantes ou aromatizadas', 4),
+('22029900', 'Outras bebidas não alcoólicas', 0),
+('22030000', 'Cervejas de malte', 3.9),
+('22042100', 'Vinhos em recipientes de capacidade não superior a 2 l', 6.5),
+('22083020', 'Uísques em embalagens de capacidade não superior a 2 l', 19.5),
+('33051000', 'Xampus', 7),
+('34011190', 'Sabões de toucador em barras, pedaços ou figuras moldados', 0),
+('34022000', 'Preparações tensoativas, para lavagem e limpeza, acondicionadas para venda a retalho', 3.25),
+('39232190', 'Sacos, bolsas e cartuchos, de polímeros de etileno', 9.75),
+('39239000', 'Outros artigos de transporte ou de embalagem, de plásticos', 9.75),
+('48181000', 'Papel higiênico', 0),
+('85044010', 'Carregadores de acumuladores', 9.75),
+('85171231', 'Telefones celulares portáteis', 9.75);
+
+INSERT INTO cests (codigo, ncm, descricao) VALUES
+('0300100', '22011000', 'Água mineral, gasosa ou não, ou potável, naturais, em garrafa de vidro, retornável ou não, com capacidade de até 500 ml'),
+('0300400', '22011000', 'Água mineral, gasosa ou não, ou potável, naturais, em embalagem com capacidade igual ou superior a 5.000 ml'),
+('0300700', '22021000', 'Refrigerante em garrafa com capacidade igual ou superior a 600 ml'),
+('0301000', '22021000', 'Refrigerante em outras embalagens'),
+('0302100', '22030000', 'Cerveja'),
+('0500100', '2208', 'Aguardentes e outras bebidas destiladas'),
+('0600100', '2204', 'Vinhos de uvas frescas'),
+('1700300', '19053100', 'Biscoitos e bolachas derivados de farinha de trigo'),
+('1705600', '1905', 'Outros produtos de padaria, pastelaria ou indústria de bolachas e biscoitos'),
+('1900200', '3305', 'Xampus para o cabelo'),
+('2000400', '3401', 'Sabões de toucador'),
+('2104700', '85044010', 'Carregadores de acumuladores'),
+('2106400', '85171231', 'Telefones celulares portáteis'),
+('2805700', '3923', 'Artigos de embalagem de plástico vendidos porta a porta');
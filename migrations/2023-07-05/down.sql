@@ -0,0 +1,2 @@
+DROP TABLE notas_fiscais;
+DROP SEQUENCE notas_fiscais_numero_seq;
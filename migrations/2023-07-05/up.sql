@@ -0,0 +1,28 @@
+CREATE SEQUENCE notas_fiscais_numero_seq;
+
+CREATE TABLE notas_fiscais (
+  id VARCHAR(36) NOT NULL PRIMARY KEY,
+  id_pedido VARCHAR(36),
+  id_orcamento VARCHAR(36),
+  serie INTEGER NOT NULL,
+  numero INTEGER NOT NULL,
+  chave VARCHAR(44) NOT NULL UNIQUE,
+  ambiente SMALLINT NOT NULL,
+  status VARCHAR(20) NOT NULL DEFAULT 'gerada',
+  xml TEXT NOT NULL,
+  protocolo VARCHAR(15),
+  motivo VARCHAR(255),
+  created_by VARCHAR(36) NOT NULL,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  updated_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  CONSTRAINT fk_pedidos
+   FOREIGN KEY(id_pedido) 
+   REFERENCES pedidos(id),
+  CONSTRAINT fk_orcamentos
+   FOREIGN KEY(id_orcamento) 
+   REFERENCES orcamentos(id),
+  CONSTRAINT uq_notas_fiscais_numero
+   UNIQUE (serie, numero),
+  CONSTRAINT ck_notas_fiscais_status
+   CHECK (status IN ('gerada', 'autorizada', 'rejeitada'))
+);
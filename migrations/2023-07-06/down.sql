@@ -0,0 +1 @@
+ALTER TABLE orcamentos DROP COLUMN observacoes;
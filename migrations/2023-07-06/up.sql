@@ -0,0 +1 @@
+ALTER TABLE orcamentos ADD COLUMN observacoes TEXT;
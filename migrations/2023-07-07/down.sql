@@ -0,0 +1,2 @@
+DROP TABLE estoque_movimentos;
+DROP TABLE depositos;
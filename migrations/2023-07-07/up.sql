@@ -0,0 +1,34 @@
+CREATE TABLE depositos (
+  id VARCHAR(36) NOT NULL PRIMARY KEY,
+  nome VARCHAR(100) NOT NULL UNIQUE,
+  created_by VARCHAR(36) NOT NULL,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+INSERT INTO depositos (id, nome, created_by) VALUES
+  ('00000000-0000-0000-0000-000000000000', 'Depósito principal', '00000000-0000-0000-0000-000000000000');
+
+CREATE TABLE estoque_movimentos (
+  id VARCHAR(36) NOT NULL PRIMARY KEY,
+  id_produto VARCHAR(36) NOT NULL,
+  id_deposito VARCHAR(36) NOT NULL,
+  tipo VARCHAR(20) NOT NULL,
+  quantidade INTEGER NOT NULL,
+  id_documento VARCHAR(36),
+  observacao VARCHAR(255),
+  created_by VARCHAR(36) NOT NULL,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  CONSTRAINT fk_produtos
+   FOREIGN KEY(id_produto) 
+   REFERENCES produtos(id),
+  CONSTRAINT fk_depositos
+   FOREIGN KEY(id_deposito) 
+   REFERENCES depositos(id),
+  CONSTRAINT ck_estoque_movimentos_tipo
+   CHECK (tipo IN ('entrada', 'saida', 'ajuste', 'transferencia')),
+  CONSTRAINT ck_estoque_movimentos_quantidade
+   CHECK (quantidade <> 0)
+);
+
+CREATE INDEX ix_estoque_movimentos_saldo ON estoque_movimentos (id_produto, id_deposito);
+CREATE INDEX ix_estoque_movimentos_documento ON estoque_movimentos (id_documento);
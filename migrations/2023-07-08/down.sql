@@ -0,0 +1,3 @@
+DROP TABLE produto_custos;
+ALTER TABLE estoque_movimentos DROP COLUMN custo_unitario;
+ALTER TABLE produtos ALTER COLUMN custo_medio TYPE DECIMAL(15,2);
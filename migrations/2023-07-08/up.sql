@@ -0,0 +1,31 @@
+ALTER TABLE produtos ALTER COLUMN custo_medio TYPE DECIMAL(15,4);
+ALTER TABLE estoque_movimentos ADD COLUMN custo_unitario DECIMAL(15,4);
+
+CREATE TABLE produto_custos (
+  id VARCHAR(36) NOT NULL PRIMARY KEY,
+  id_produto VARCHAR(36) NOT NULL,
+  custo_anterior DECIMAL(15,4),
+  custo_novo DECIMAL(15,4) NOT NULL,
+  origem VARCHAR(20) NOT NULL,
+  id_movimento VARCHAR(36),
+  motivo VARCHAR(255),
+  created_by VARCHAR(36) NOT NULL,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  CONSTRAINT fk_produtos
+   FOREIGN KEY(id_produto) 
+   REFERENCES produtos(id)
+   ON DELETE CASCADE,
+  CONSTRAINT fk_estoque_movimentos
+   FOREIGN KEY(id_movimento) 
+   REFERENCES estoque_movimentos(id)
+   ON DELETE CASCADE,
+  CONSTRAINT ck_produto_custos_origem
+   CHECK (origem IN ('cadastro', 'entrada', 'ajuste'))
+);
+
+CREATE INDEX ix_produto_custos_produto ON produto_custos (id_produto);
+
+INSERT INTO produto_custos (id, id_produto, custo_novo, origem, created_by)
+SELECT md5('custo-' || id)::uuid::text, id, custo_medio, 'cadastro', created_by
+FROM produtos
+WHERE custo_medio IS NOT NULL;
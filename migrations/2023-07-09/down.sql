@@ -0,0 +1,4 @@
+DROP TABLE notas_entrada;
+DROP TABLE fornecedor_produtos;
+DROP TABLE fornecedores;
+ALTER TABLE produtos DROP COLUMN rascunho;
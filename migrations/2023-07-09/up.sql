@@ -0,0 +1,48 @@
+-- produtos created from a purchase NF-e wait for someone to review them
+ALTER TABLE produtos ADD COLUMN rascunho BOOLEAN NOT NULL DEFAULT FALSE;
+
+CREATE TABLE fornecedores (
+  id VARCHAR(36) NOT NULL PRIMARY KEY,
+  cnpj VARCHAR(14) NOT NULL UNIQUE,
+  razao_social VARCHAR(150) NOT NULL,
+  nome_fantasia VARCHAR(150),
+  inscricao_estadual VARCHAR(20),
+  created_by VARCHAR(36) NOT NULL,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  updated_by VARCHAR(36) NOT NULL,
+  updated_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+-- the code a fornecedor gives to one of our produtos on its notas
+CREATE TABLE fornecedor_produtos (
+  id_fornecedor VARCHAR(36) NOT NULL,
+  codigo VARCHAR(60) NOT NULL,
+  id_produto VARCHAR(36) NOT NULL,
+  created_by VARCHAR(36) NOT NULL,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  PRIMARY KEY (id_fornecedor, codigo),
+  CONSTRAINT fk_fornecedores
+   FOREIGN KEY(id_fornecedor) 
+   REFERENCES fornecedores(id)
+   ON DELETE CASCADE,
+  CONSTRAINT fk_produtos
+   FOREIGN KEY(id_produto) 
+   REFERENCES produtos(id)
+   ON DELETE CASCADE
+);
+
+CREATE TABLE notas_entrada (
+  id VARCHAR(36) NOT NULL PRIMARY KEY,
+  chave VARCHAR(44) NOT NULL UNIQUE,
+  serie INTEGER NOT NULL,
+  numero INTEGER NOT NULL,
+  id_fornecedor VARCHAR(36) NOT NULL,
+  emissao TIMESTAMP NOT NULL,
+  valor_total DECIMAL(15,2) NOT NULL,
+  xml TEXT NOT NULL,
+  created_by VARCHAR(36) NOT NULL,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  CONSTRAINT fk_fornecedores
+   FOREIGN KEY(id_fornecedor) 
+   REFERENCES fornecedores(id)
+);
@@ -0,0 +1,6 @@
+DROP INDEX ux_fornecedor_produtos_preferencial;
+ALTER TABLE fornecedor_produtos DROP COLUMN preferencial;
+ALTER TABLE fornecedores
+  DROP COLUMN contato,
+  DROP COLUMN email,
+  DROP COLUMN telefone;
@@ -0,0 +1,9 @@
+ALTER TABLE fornecedores
+  ADD COLUMN contato VARCHAR(100),
+  ADD COLUMN email VARCHAR(100),
+  ADD COLUMN telefone VARCHAR(22);
+
+ALTER TABLE fornecedor_produtos ADD COLUMN preferencial BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- a produto has at most one preferred fornecedor
+CREATE UNIQUE INDEX ux_fornecedor_produtos_preferencial ON fornecedor_produtos (id_produto) WHERE preferencial;
@@ -0,0 +1,2 @@
+DROP TABLE pedido_compra_itens;
+DROP TABLE pedidos_compra;
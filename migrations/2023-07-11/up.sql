@@ -0,0 +1,50 @@
+CREATE TABLE pedidos_compra (
+  id VARCHAR(36) NOT NULL PRIMARY KEY,
+  id_fornecedor VARCHAR(36) NOT NULL,
+  id_deposito VARCHAR(36) NOT NULL,
+  status VARCHAR(20) NOT NULL DEFAULT 'rascunho',
+  previsao_entrega TIMESTAMP,
+  observacoes TEXT,
+  aprovado_by VARCHAR(36),
+  aprovado_at TIMESTAMP,
+  created_by VARCHAR(36) NOT NULL,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  updated_by VARCHAR(36) NOT NULL,
+  updated_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  CONSTRAINT fk_fornecedores
+   FOREIGN KEY(id_fornecedor) 
+   REFERENCES fornecedores(id),
+  CONSTRAINT fk_depositos
+   FOREIGN KEY(id_deposito) 
+   REFERENCES depositos(id),
+  CONSTRAINT ck_pedidos_compra_status
+   CHECK (status IN ('rascunho', 'aprovado', 'parcial', 'recebido', 'cancelado'))
+);
+
+CREATE INDEX ix_pedidos_compra_fornecedor ON pedidos_compra (id_fornecedor);
+
+CREATE TABLE pedido_compra_itens (
+  id VARCHAR(36) NOT NULL PRIMARY KEY,
+  id_pedido_compra VARCHAR(36) NOT NULL,
+  id_produto VARCHAR(36) NOT NULL,
+  quantidade INTEGER NOT NULL,
+  quantidade_recebida INTEGER NOT NULL DEFAULT 0,
+  custo_unitario DECIMAL(15,4) NOT NULL,
+  created_by VARCHAR(36) NOT NULL,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  CONSTRAINT fk_pedidos_compra
+   FOREIGN KEY(id_pedido_compra) 
+   REFERENCES pedidos_compra(id)
+   ON DELETE CASCADE,
+  CONSTRAINT fk_produtos
+   FOREIGN KEY(id_produto) 
+   REFERENCES produtos(id),
+  CONSTRAINT ux_pedido_compra_itens_produto
+   UNIQUE (id_pedido_compra, id_produto),
+  CONSTRAINT ck_pedido_compra_itens_quantidade
+   CHECK (quantidade > 0 AND quantidade_recebida BETWEEN 0 AND quantidade),
+  CONSTRAINT ck_pedido_compra_itens_custo
+   CHECK (custo_unitario >= 0)
+);
+
+CREATE INDEX ix_pedido_compra_itens_produto ON pedido_compra_itens (id_produto);
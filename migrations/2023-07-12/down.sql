@@ -0,0 +1,10 @@
+ALTER TABLE clientes DROP CONSTRAINT ck_clientes_tipo_pessoa;
+UPDATE clientes SET nome = LEFT(razao_social, 100), sobrenome = '' WHERE tipo_pessoa = 'juridica';
+ALTER TABLE clientes
+  DROP COLUMN tipo_pessoa,
+  DROP COLUMN cnpj,
+  DROP COLUMN razao_social,
+  DROP COLUMN nome_fantasia,
+  DROP COLUMN inscricao_estadual,
+  ALTER COLUMN nome SET NOT NULL,
+  ALTER COLUMN sobrenome SET NOT NULL;
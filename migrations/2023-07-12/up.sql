@@ -0,0 +1,14 @@
+-- companies have a razão social instead of nome and sobrenome
+ALTER TABLE clientes
+  ALTER COLUMN nome DROP NOT NULL,
+  ALTER COLUMN sobrenome DROP NOT NULL,
+  ADD COLUMN tipo_pessoa VARCHAR(10) NOT NULL DEFAULT 'fisica',
+  ADD COLUMN cnpj VARCHAR(14) UNIQUE,
+  ADD COLUMN razao_social VARCHAR(150),
+  ADD COLUMN nome_fantasia VARCHAR(150),
+  ADD COLUMN inscricao_estadual VARCHAR(20),
+  ADD CONSTRAINT ck_clientes_tipo_pessoa
+   CHECK (
+     (tipo_pessoa = 'fisica' AND nome IS NOT NULL AND sobrenome IS NOT NULL AND cnpj IS NULL AND razao_social IS NULL)
+     OR (tipo_pessoa = 'juridica' AND cnpj IS NOT NULL AND razao_social IS NOT NULL AND cpf IS NULL)
+   );
@@ -0,0 +1,3 @@
+DROP TABLE cliente_enderecos;
+DROP TABLE ceps;
+DROP TABLE municipios;
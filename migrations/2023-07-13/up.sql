@@ -0,0 +1,61 @@
+-- IBGE municípios, the code is what the NF-e asks for in every address
+CREATE TABLE municipios (
+  codigo VARCHAR(7) NOT NULL PRIMARY KEY,
+  nome VARCHAR(100) NOT NULL,
+  uf CHAR(2) NOT NULL
+);
+
+-- local CEP base, loaded through POST /cep/importar; CEPs that cover a whole
+-- município have no logradouro nor bairro
+CREATE TABLE ceps (
+  cep VARCHAR(8) NOT NULL PRIMARY KEY,
+  logradouro VARCHAR(150),
+  bairro VARCHAR(100),
+  codigo_municipio VARCHAR(7) NOT NULL,
+  CONSTRAINT fk_municipios
+   FOREIGN KEY(codigo_municipio) 
+   REFERENCES municipios(codigo)
+);
+
+CREATE TABLE cliente_enderecos (
+  id VARCHAR(36) NOT NULL PRIMARY KEY,
+  id_cliente VARCHAR(36) NOT NULL,
+  tipo VARCHAR(10) NOT NULL,
+  logradouro VARCHAR(150) NOT NULL,
+  numero VARCHAR(20) NOT NULL,
+  complemento VARCHAR(100),
+  bairro VARCHAR(100) NOT NULL,
+  codigo_municipio VARCHAR(7) NOT NULL,
+  municipio VARCHAR(100) NOT NULL,
+  uf CHAR(2) NOT NULL,
+  cep VARCHAR(8) NOT NULL,
+  created_by VARCHAR(36) NOT NULL,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  updated_by VARCHAR(36) NOT NULL,
+  updated_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  CONSTRAINT fk_clientes
+   FOREIGN KEY(id_cliente) 
+   REFERENCES clientes(id)
+   ON DELETE CASCADE,
+  CONSTRAINT ck_cliente_enderecos_tipo
+   CHECK (tipo IN ('cobranca', 'entrega'))
+);
+
+CREATE INDEX ix_cliente_enderecos_cliente ON cliente_enderecos (id_cliente);
+
+-- capitals and the municípios the store delivers to, the full IBGE and
+-- Correios tables are loaded through the import
+INSERT INTO municipios (codigo, nome, uf) VALUES
+('3550308', 'São Paulo', 'SP'),
+('3304557', 'Rio de Janeiro', 'RJ'),
+('3106200', 'Belo Horizonte', 'MG'),
+('4106902', 'Curitiba', 'PR'),
+('4205407', 'Florianópolis', 'SC'),
+('4204202', 'Chapecó', 'SC'),
+('4314902', 'Porto Alegre', 'RS'),
+('5300108', 'Brasília', 'DF');
+
+INSERT INTO ceps (cep, logradouro, bairro, codigo_municipio) VALUES
+('01001000', 'Praça da Sé', 'Sé', '3550308'),
+('01310100', 'Avenida Paulista', 'Bela Vista', '3550308'),
+('70040010', 'Esplanada dos Ministérios', 'Zona Cívico-Administrativa', '5300108');
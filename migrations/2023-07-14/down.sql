@@ -0,0 +1,2 @@
+DROP TABLE titulos_receber;
+ALTER TABLE clientes DROP COLUMN limite_credito;
@@ -0,0 +1,27 @@
+-- NULL leaves the cliente without a limit, only overdue títulos block it
+ALTER TABLE clientes ADD COLUMN limite_credito DECIMAL(15,2);
+
+CREATE TABLE titulos_receber (
+  id VARCHAR(36) NOT NULL PRIMARY KEY,
+  id_cliente VARCHAR(36) NOT NULL,
+  id_pedido VARCHAR(36),
+  documento VARCHAR(60),
+  valor DECIMAL(15,2) NOT NULL,
+  valor_pago DECIMAL(15,2) NOT NULL DEFAULT 0,
+  vencimento TIMESTAMP NOT NULL,
+  pago_at TIMESTAMP,
+  created_by VARCHAR(36) NOT NULL,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  updated_by VARCHAR(36) NOT NULL,
+  updated_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  CONSTRAINT fk_clientes
+   FOREIGN KEY(id_cliente) 
+   REFERENCES clientes(id),
+  CONSTRAINT fk_pedidos
+   FOREIGN KEY(id_pedido) 
+   REFERENCES pedidos(id),
+  CONSTRAINT ck_titulos_receber_valor
+   CHECK (valor > 0 AND valor_pago >= 0 AND valor_pago <= valor)
+);
+
+CREATE INDEX ix_titulos_receber_cliente ON titulos_receber (id_cliente);
@@ -0,0 +1,10 @@
+DROP INDEX ux_clientes_cnpj;
+DROP INDEX ux_clientes_cpf;
+
+UPDATE clientes
+SET cpf = COALESCE(clientes.cpf, repetidos.cpf),
+cnpj = COALESCE(clientes.cnpj, repetidos.cnpj)
+FROM clientes_documentos_repetidos repetidos
+WHERE repetidos.id_cliente = clientes.id;
+
+DROP TABLE clientes_documentos_repetidos;
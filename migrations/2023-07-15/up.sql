@@ -0,0 +1,45 @@
+-- the application stores cpf and cnpj as bare digits, older rows may still
+-- carry the mask or be blank
+UPDATE clientes SET cpf = regexp_replace(cpf, '[^0-9]', '', 'g') WHERE cpf IS NOT NULL;
+UPDATE clientes SET cnpj = regexp_replace(cnpj, '[^0-9]', '', 'g') WHERE cnpj IS NOT NULL;
+UPDATE clientes SET cpf = NULL WHERE cpf = '';
+UPDATE clientes SET cnpj = NULL WHERE cnpj = '';
+
+-- clientes registered more than once with the same document keep it only on
+-- the oldest of them, the others park theirs here until they are merged
+CREATE TABLE clientes_documentos_repetidos (
+  id_cliente VARCHAR(36) NOT NULL PRIMARY KEY,
+  cpf CHAR(11),
+  cnpj VARCHAR(14),
+  CONSTRAINT fk_clientes
+   FOREIGN KEY(id_cliente) 
+   REFERENCES clientes(id)
+   ON DELETE CASCADE
+);
+
+INSERT INTO clientes_documentos_repetidos (id_cliente, cpf)
+SELECT id, cpf
+FROM (
+  SELECT id, cpf, ROW_NUMBER() OVER (PARTITION BY cpf ORDER BY created_at, id) AS ordem
+  FROM clientes
+  WHERE cpf IS NOT NULL
+) repetidos
+WHERE ordem > 1;
+
+INSERT INTO clientes_documentos_repetidos (id_cliente, cnpj)
+SELECT id, cnpj
+FROM (
+  SELECT id, cnpj, ROW_NUMBER() OVER (PARTITION BY cnpj ORDER BY created_at, id) AS ordem
+  FROM clientes
+  WHERE cnpj IS NOT NULL
+) repetidos
+WHERE ordem > 1
+ON CONFLICT (id_cliente) DO UPDATE SET cnpj = excluded.cnpj;
+
+UPDATE clientes SET cpf = NULL
+WHERE id IN (SELECT id_cliente FROM clientes_documentos_repetidos WHERE cpf IS NOT NULL);
+UPDATE clientes SET cnpj = NULL
+WHERE id IN (SELECT id_cliente FROM clientes_documentos_repetidos WHERE cnpj IS NOT NULL);
+
+CREATE UNIQUE INDEX ux_clientes_cpf ON clientes (cpf) WHERE cpf IS NOT NULL;
+CREATE UNIQUE INDEX ux_clientes_cnpj ON clientes (cnpj) WHERE cnpj IS NOT NULL;
@@ -0,0 +1 @@
+DROP TABLE lgpd_registros;
@@ -0,0 +1,12 @@
+-- no foreign key, the record of a request has to outlive the cliente
+CREATE TABLE lgpd_registros (
+  id VARCHAR(36) NOT NULL PRIMARY KEY,
+  id_cliente VARCHAR(36) NOT NULL,
+  operacao VARCHAR(20) NOT NULL,
+  created_by VARCHAR(36) NOT NULL,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  CONSTRAINT ck_lgpd_registros_operacao
+   CHECK (operacao IN ('exportacao', 'anonimizacao'))
+);
+
+CREATE INDEX ix_lgpd_registros_cliente ON lgpd_registros (id_cliente);
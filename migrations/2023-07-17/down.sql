@@ -0,0 +1,16 @@
+ALTER TABLE pedido_produtos
+  DROP COLUMN descricao,
+  DROP COLUMN unidade_medida,
+  DROP COLUMN ncm,
+  DROP COLUMN cest,
+  DROP COLUMN icms,
+  DROP COLUMN pis,
+  DROP COLUMN cofins,
+  DROP COLUMN porcentagem_ipi,
+  DROP COLUMN aliquota_icms,
+  DROP COLUMN aliquota_pis,
+  DROP COLUMN aliquota_cofins,
+  DROP COLUMN cod_ipi;
+
+ALTER TABLE orcamento_produtos
+  DROP COLUMN cod_ipi;
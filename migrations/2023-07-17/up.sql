@@ -0,0 +1,61 @@
+-- pedido lines keep the produto as it was sold, like the orcamento lines,
+-- and both keep the CST of IPI the nota is issued with
+ALTER TABLE orcamento_produtos
+  ADD COLUMN cod_ipi VARCHAR(12);
+
+UPDATE orcamento_produtos
+SET cod_ipi = produtos.cod_ipi
+FROM produtos
+WHERE produtos.id = orcamento_produtos.id_produto;
+
+ALTER TABLE pedido_produtos
+  ADD COLUMN descricao VARCHAR(100),
+  ADD COLUMN unidade_medida CHAR(2),
+  ADD COLUMN ncm VARCHAR(8),
+  ADD COLUMN cest VARCHAR(12),
+  ADD COLUMN icms VARCHAR(12),
+  ADD COLUMN pis VARCHAR(4),
+  ADD COLUMN cofins VARCHAR(4),
+  ADD COLUMN porcentagem_ipi DECIMAL(15,2),
+  ADD COLUMN aliquota_icms DECIMAL(5,2),
+  ADD COLUMN aliquota_pis DECIMAL(5,2),
+  ADD COLUMN aliquota_cofins DECIMAL(5,2),
+  ADD COLUMN cod_ipi VARCHAR(12);
+
+UPDATE pedido_produtos
+SET descricao = produtos.descricao,
+unidade_medida = produtos.unidade_medida,
+ncm = produtos.ncm,
+cest = produtos.cest,
+icms = produtos.icms,
+pis = produtos.pis,
+cofins = produtos.cofins,
+porcentagem_ipi = produtos.porcentagem_ipi,
+aliquota_icms = produtos.aliquota_icms,
+aliquota_pis = produtos.aliquota_pis,
+aliquota_cofins = produtos.aliquota_cofins,
+cod_ipi = produtos.cod_ipi
+FROM produtos
+WHERE produtos.id = pedido_produtos.id_produto;
+
+-- pedidos converted from an orcamento were sold as the orcamento had them
+UPDATE pedido_produtos
+SET descricao = orcamento_produtos.descricao,
+unidade_medida = orcamento_produtos.unidade_medida,
+ncm = orcamento_produtos.ncm,
+cest = orcamento_produtos.cest,
+icms = orcamento_produtos.icms,
+pis = orcamento_produtos.pis,
+cofins = orcamento_produtos.cofins,
+porcentagem_ipi = orcamento_produtos.porcentagem_ipi,
+aliquota_icms = orcamento_produtos.aliquota_icms,
+aliquota_pis = orcamento_produtos.aliquota_pis,
+aliquota_cofins = orcamento_produtos.aliquota_cofins,
+cod_ipi = orcamento_produtos.cod_ipi
+FROM pedidos, orcamento_produtos
+WHERE pedidos.id = pedido_produtos.id_pedido
+AND orcamento_produtos.id_orcamento = pedidos.id_orcamento
+AND orcamento_produtos.id_produto = pedido_produtos.id_produto;
+
+ALTER TABLE pedido_produtos
+  ALTER COLUMN descricao SET NOT NULL;
@@ -0,0 +1,3 @@
+DROP INDEX ux_cliente_enderecos_principal;
+ALTER TABLE cliente_enderecos DROP COLUMN principal;
+ALTER TABLE cliente_enderecos DROP CONSTRAINT fk_municipios;
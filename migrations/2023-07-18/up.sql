@@ -0,0 +1,25 @@
+-- enderecos saved before the municípios were checked bring their own, taken
+-- from the most recent endereço that names them
+INSERT INTO municipios (codigo, nome, uf)
+SELECT DISTINCT ON (codigo_municipio) codigo_municipio, municipio, uf
+FROM cliente_enderecos
+WHERE codigo_municipio NOT IN (SELECT codigo FROM municipios)
+ORDER BY codigo_municipio, updated_at DESC;
+
+ALTER TABLE cliente_enderecos
+  ADD CONSTRAINT fk_municipios
+   FOREIGN KEY(codigo_municipio) 
+   REFERENCES municipios(codigo);
+
+-- the endereço notas are issued to, until now the oldest cobrança one or
+-- else the oldest entrega one
+ALTER TABLE cliente_enderecos ADD COLUMN principal BOOLEAN NOT NULL DEFAULT FALSE;
+
+UPDATE cliente_enderecos SET principal = TRUE
+WHERE id IN (
+  SELECT DISTINCT ON (id_cliente) id
+  FROM cliente_enderecos
+  ORDER BY id_cliente, tipo, created_at
+);
+
+CREATE UNIQUE INDEX ux_cliente_enderecos_principal ON cliente_enderecos (id_cliente) WHERE principal;
@@ -27,6 +27,39 @@ pub struct Config {
     pub session_name: String,
     pub session_secure: bool,
     pub session_timeout: i64,
+    // Emitente of the NF-e, optional so the server still starts without them
+    pub emitente_cnpj: Option<String>,
+    pub emitente_razao_social: Option<String>,
+    pub emitente_nome_fantasia: Option<String>,
+    pub emitente_ie: Option<String>,
+    pub emitente_crt: Option<u8>,
+    pub emitente_logradouro: Option<String>,
+    pub emitente_numero: Option<String>,
+    pub emitente_bairro: Option<String>,
+    pub emitente_codigo_municipio: Option<String>,
+    pub emitente_municipio: Option<String>,
+    pub emitente_uf: Option<String>,
+    pub emitente_cep: Option<String>,
+    pub emitente_telefone: Option<String>,
+    // Header of the documents sent to clientes, the logo is a PNG or JPEG file
+    pub empresa_logo: Option<String>,
+    pub empresa_email: Option<String>,
+    pub empresa_site: Option<String>,
+    #[serde(default)]
+    pub estoque_bloquear_negativo: bool,
+    #[serde(default = "default_nfe_ambiente")]
+    pub nfe_ambiente: u8,
+    #[serde(default = "default_nfe_serie")]
+    pub nfe_serie: i32,
+}
+
+/// NF-e are issued in homologação unless produção is asked for
+fn default_nfe_ambiente() -> u8 {
+    2
+}
+
+fn default_nfe_serie() -> i32 {
+    1
 }
 
 // Throw the Config struct into a CONFIG lazy_static to avoid multiple processing
@@ -18,6 +18,7 @@ pub enum ApiError {
     CacheError(String),
     CannotDecodeJwtToken(String),
     CannotEncodeJwtToken(String),
+    Conflict(String),
     InternalServerError(String),
     NotFound(String),
     ParseError(String),
@@ -40,6 +41,9 @@ impl ResponseError for ApiError {
             ApiError::BadRequest(error) => {
                 HttpResponse::BadRequest().json::<ErrorResponse>(error.into())
             }
+            ApiError::Conflict(message) => {
+                HttpResponse::Conflict().json::<ErrorResponse>(message.into())
+            }
             ApiError::NotFound(message) => {
                 HttpResponse::NotFound().json::<ErrorResponse>(message.into())
             }
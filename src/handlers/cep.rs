@@ -0,0 +1,112 @@
+use crate::database::PoolType;
+use crate::errors::ApiError;
+use crate::helpers::respond_json;
+use crate::models::cep::{buscar, importar_enviado, ConsultaCepLocal, EnderecoCep};
+use crate::models::user::AuthUser;
+use actix_web::web::{block, Data, Json, Path};
+use serde::Serialize;
+
+/// Largest CEP file the API takes, the whole DNE is loaded with the
+/// `importar cep` command instead
+pub const LIMITE_IMPORTACAO: usize = 32 * 1024 * 1024;
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub struct CepResponse {
+    pub cep: String,
+    pub logradouro: Option<String>,
+    pub bairro: Option<String>,
+    /// IBGE code of the município
+    pub codigo_municipio: String,
+    pub municipio: String,
+    pub uf: String,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub struct ImportacaoCepResponse {
+    pub municipios: usize,
+    pub ceps: usize,
+}
+
+/// Get the address of a CEP
+pub async fn get_cep(cep: Path<String>, pool: Data<PoolType>) -> Result<Json<CepResponse>, ApiError> {
+    let endereco = block(move || buscar(&ConsultaCepLocal::new(&pool), &cep)).await?;
+    respond_json(endereco)
+}
+
+/// Load the CEP file sent as the request body into the local base
+pub async fn importar_ceps(
+    pool: Data<PoolType>,
+    conteudo: String,
+    user: AuthUser,
+) -> Result<Json<ImportacaoCepResponse>, ApiError> {
+    let importacao = block(move || importar_enviado(&pool, &conteudo, &user.id)).await?;
+    respond_json(importacao)
+}
+
+impl From<EnderecoCep> for CepResponse {
+    fn from(endereco: EnderecoCep) -> Self {
+        CepResponse {
+            cep: endereco.cep,
+            logradouro: endereco.logradouro,
+            bairro: endereco.bairro,
+            codigo_municipio: endereco.codigo_municipio,
+            municipio: endereco.municipio,
+            uf: endereco.uf,
+        }
+    }
+}
+
+///Testes
+///Testes
+///Testes
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::models::user::delete as delete_user;
+    use crate::models::user::tests::create_user_by_email;
+    use crate::models::user_permissao::tests::grant_permissao;
+    use crate::models::user_permissao::{revoke, IMPORTAR_TABELAS};
+    use crate::tests::helpers::tests::{get_data_pool, get_pool};
+
+    fn auth_user(id: &str) -> AuthUser {
+        AuthUser {
+            id: id.into(),
+            email: "test@user.com".into(),
+        }
+    }
+
+    #[actix_rt::test]
+    async fn it_gets_a_cep() {
+        let response = get_cep(Path::from("01310-100".to_string()), get_data_pool())
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(response.logradouro, Some("Avenida Paulista".to_string()));
+        assert_eq!(response.codigo_municipio, "3550308");
+        assert_eq!(response.uf, "SP");
+    }
+
+    #[actix_rt::test]
+    async fn it_doesnt_get_an_invalid_cep() {
+        let response = get_cep(Path::from("1310-100".to_string()), get_data_pool()).await;
+        let expected_error =
+            ApiError::ValidationError(vec!["cep: o CEP deve ter 8 dígitos, recebido 1310-100".to_string()]);
+        assert_eq!(response.unwrap_err(), expected_error);
+    }
+
+    #[actix_rt::test]
+    async fn it_imports_ceps() {
+        let user = create_user_by_email("teste_handler_importar_ceps@teste.com").unwrap();
+        let user_id = user.id.to_string();
+        let conteudo = "70040010;Esplanada dos Ministérios;Zona Cívico-Administrativa;5300108;Brasília;DF".to_string();
+        let response = importar_ceps(get_data_pool(), conteudo.clone(), auth_user(&user_id)).await;
+        let expected_error = ApiError::Unauthorized(format!("User {} cannot import reference tables", user_id));
+        assert_eq!(response.unwrap_err(), expected_error);
+
+        grant_permissao(&user_id, IMPORTAR_TABELAS);
+        let response = importar_ceps(get_data_pool(), conteudo, auth_user(&user_id)).await;
+        revoke(&get_pool(), user.id, IMPORTAR_TABELAS).unwrap();
+        delete_user(&get_pool(), user.id).unwrap();
+        assert_eq!(response.unwrap().into_inner(), ImportacaoCepResponse { municipios: 1, ceps: 1 });
+    }
+}
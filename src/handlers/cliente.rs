@@ -1,9 +1,10 @@
 use crate::database::PoolType;
 use crate::errors::ApiError;
 use crate::helpers::{respond_json, respond_ok};
-use crate::models::cliente::{create, delete, find, get_all, update, NewCliente, UpdateCliente, Cliente};
-use crate::validate::validate;
+use crate::models::cliente::{create, delete, find, get_all, update, NewCliente, TipoPessoa, UpdateCliente, Cliente};
+use crate::validate::{formatar_cpf, normalizar_cnpj, normalizar_cpf, validar_cnpj, validar_cpf, validate};
 use actix_web::web::{block, Data, HttpResponse, Json, Path};
+use bigdecimal::BigDecimal;
 use rayon::prelude::*;
 use serde::Serialize;
 use uuid::Uuid;
@@ -13,14 +14,24 @@ use chrono::{NaiveDateTime, NaiveDate, NaiveTime};
 #[derive(Debug, Deserialize, Serialize, PartialEq)]
 pub struct ClienteResponse {
     pub id: Uuid,
-    pub nome: String,
-    pub sobrenome: String,
+    pub tipo_pessoa: String,
+    /// nome and sobrenome of a person, nome fantasia or razão social of a company
+    pub nome_exibicao: String,
+    pub nome: Option<String>,
+    pub sobrenome: Option<String>,
     pub cpf: Option<String>,
+    /// The cpf with its mask, as in 529.982.247-25
+    pub cpf_formatado: Option<String>,
     pub rg: Option<String>,
     pub data_nascimento: Option<NaiveDateTime>,
     pub sexo: Option<String>,
     pub estado_civil: Option<String>,
     pub telefone: Option<String>,
+    pub cnpj: Option<String>,
+    pub razao_social: Option<String>,
+    pub nome_fantasia: Option<String>,
+    pub inscricao_estadual: Option<String>,
+    pub limite_credito: Option<BigDecimal>,
 }
 
 #[derive(Debug, Deserialize, Serialize, PartialEq)]
@@ -28,22 +39,23 @@ pub struct ClientesResponse(pub Vec<ClienteResponse>);
 
 #[derive(Clone, Debug, Deserialize, Serialize, Validate)]
 pub struct CreateClienteRequest {
+    /// Defaults to a pessoa física
+    #[serde(default)]
+    pub tipo_pessoa: TipoPessoa,
+
     #[validate(length(
         min = 3,
         message = "O primeiro nome deve ter pelo menos 3 caracteres"
     ))]
-    pub nome: String,
+    pub nome: Option<String>,
 
     #[validate(length(
         min = 3,
         message = "O sobrenome deve ter pelo menos 3 caracteres"
     ))]
-    pub sobrenome: String,
+    pub sobrenome: Option<String>,
 
-    #[validate(length(
-        min = 11, max = 11,
-        message = "O cpf deve ter 11 caracteres"
-    ))]
+    #[validate(custom = "validar_cpf")]
     pub cpf: Option<String>,
 
     #[validate(length(
@@ -71,26 +83,48 @@ pub struct CreateClienteRequest {
     pub telefone: Option<String>,
 
     pub data_nascimento: Option<NaiveDateTime>,
+
+    #[validate(custom = "validar_cnpj")]
+    pub cnpj: Option<String>,
+
+    #[validate(length(
+        min = 3, max = 150,
+        message = "A razão social deve ter de 3 a 150 caracteres"
+    ))]
+    pub razao_social: Option<String>,
+
+    #[validate(length(
+        min = 1, max = 150,
+        message = "O nome fantasia deve ter de 1 a 150 caracteres"
+    ))]
+    pub nome_fantasia: Option<String>,
+
+    #[validate(length(
+        min = 2, max = 20,
+        message = "A inscrição estadual deve ter de 2 a 20 caracteres"
+    ))]
+    pub inscricao_estadual: Option<String>,
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize, Validate)]
 pub struct UpdateClienteRequest {
+    /// Defaults to a pessoa física
+    #[serde(default)]
+    pub tipo_pessoa: TipoPessoa,
+
     #[validate(length(
         min = 3,
         message = "O primeiro nome deve ter pelo menos 3 caracteres"
     ))]
-    pub nome: String,
+    pub nome: Option<String>,
 
     #[validate(length(
         min = 3,
         message = "O sobrenome deve ter pelo menos 3 caracteres"
     ))]
-    pub sobrenome: String,
+    pub sobrenome: Option<String>,
 
-    #[validate(length(
-        min = 11, max = 11,
-        message = "O cpf deve ter 11 caracteres"
-    ))]
+    #[validate(custom = "validar_cpf")]
     pub cpf: Option<String>,
 
     #[validate(length(
@@ -118,6 +152,27 @@ pub struct UpdateClienteRequest {
     pub telefone: Option<String>,
 
     pub data_nascimento: Option<NaiveDateTime>,
+
+    #[validate(custom = "validar_cnpj")]
+    pub cnpj: Option<String>,
+
+    #[validate(length(
+        min = 3, max = 150,
+        message = "A razão social deve ter de 3 a 150 caracteres"
+    ))]
+    pub razao_social: Option<String>,
+
+    #[validate(length(
+        min = 1, max = 150,
+        message = "O nome fantasia deve ter de 1 a 150 caracteres"
+    ))]
+    pub nome_fantasia: Option<String>,
+
+    #[validate(length(
+        min = 2, max = 20,
+        message = "A inscrição estadual deve ter de 2 a 20 caracteres"
+    ))]
+    pub inscricao_estadual: Option<String>,
 }
 
 /// Get a cliente
@@ -147,14 +202,19 @@ pub async fn create_cliente(
     let cliente_id = Uuid::new_v4();
     let new_cliente: Cliente = NewCliente {
         id: cliente_id.to_string(),
-        nome: params.nome.to_string(),
-        sobrenome: params.sobrenome.to_string(),
-        cpf: params.cpf.clone(),
+        tipo_pessoa: params.tipo_pessoa,
+        nome: params.nome.clone(),
+        sobrenome: params.sobrenome.clone(),
+        cpf: normalizar_cpf(&params.cpf)?,
         rg: params.rg.clone(),
         data_nascimento: params.data_nascimento.clone(),
         sexo: params.sexo.clone(),
         estado_civil: params.estado_civil.clone(),
         telefone: params.telefone.clone(),
+        cnpj: params.cnpj.as_deref().map(normalizar_cnpj).transpose()?,
+        razao_social: params.razao_social.clone(),
+        nome_fantasia: params.nome_fantasia.clone(),
+        inscricao_estadual: params.inscricao_estadual.clone(),
         created_by: cliente_id.to_string(),
         updated_by: cliente_id.to_string(),
     }
@@ -175,14 +235,19 @@ pub async fn update_cliente(
     // update when auth is added
     let update_cliente = UpdateCliente {
         id: cliente_id.to_string(),
-        nome: params.nome.to_string(),
-        sobrenome: params.sobrenome.to_string(),
-        cpf: params.cpf.clone(),
+        tipo_pessoa: params.tipo_pessoa.as_str().to_string(),
+        nome: params.nome.clone(),
+        sobrenome: params.sobrenome.clone(),
+        cpf: normalizar_cpf(&params.cpf)?,
         rg: params.rg.clone(),
         data_nascimento: params.data_nascimento.clone(),
         sexo: params.sexo.clone(),
         estado_civil: params.estado_civil.clone(),
         telefone:params.telefone.clone(),
+        cnpj: params.cnpj.as_deref().map(normalizar_cnpj).transpose()?,
+        razao_social: params.razao_social.clone(),
+        nome_fantasia: params.nome_fantasia.clone(),
+        inscricao_estadual: params.inscricao_estadual.clone(),
         updated_by: cliente_id.to_string(),
     };
     let cliente = block(move || update(&pool, &update_cliente)).await?;
@@ -202,14 +267,22 @@ impl From<Cliente> for ClienteResponse {
     fn from(cliente: Cliente) -> Self {
         ClienteResponse {
             id: Uuid::parse_str(&cliente.id).unwrap(),
-            nome: cliente.nome.to_string(),
-            sobrenome: cliente.sobrenome.to_string(),
+            nome_exibicao: cliente.nome_exibicao(),
+            tipo_pessoa: cliente.tipo_pessoa,
+            nome: cliente.nome,
+            sobrenome: cliente.sobrenome,
+            cpf_formatado: cliente.cpf.as_deref().map(formatar_cpf),
             cpf: cliente.cpf,
             rg: cliente.rg,
             data_nascimento: cliente.data_nascimento,
             sexo: cliente.sexo,
             estado_civil: cliente.estado_civil,
             telefone: cliente.telefone,
+            cnpj: cliente.cnpj,
+            razao_social: cliente.razao_social,
+            nome_fantasia: cliente.nome_fantasia,
+            inscricao_estadual: cliente.inscricao_estadual,
+            limite_credito: cliente.limite_credito,
         }
     }
 }
@@ -266,20 +339,27 @@ pub mod tests {
     #[actix_rt::test]
     async fn it_creates_a_cliente() {
         let params = Json(CreateClienteRequest {
-            nome: "Satoshi".into(),
-            sobrenome: "Nakamoto".into(),
-            cpf: Some("12345678901".into()),
+            tipo_pessoa: TipoPessoa::Fisica,
+            nome: Some("Satoshi".into()),
+            sobrenome: Some("Nakamoto".into()),
+            cpf: Some("529.982.247-25".into()),
             rg: Some("123456789".into()),
             data_nascimento: Some(NaiveDate::from_ymd(1990, 1, 1).and_hms(0, 0, 0)),
             sexo: Some("M".into()),
             estado_civil: Some("Solteiro".into()),
             telefone: Some("1234567890".into()),
+            cnpj: None,
+            razao_social: None,
+            nome_fantasia: None,
+            inscricao_estadual: None,
         });
         let response = create_cliente(get_data_pool(), Json(params.clone()))
             .await
             .unwrap()
             .into_inner();
         assert_eq!(response.nome, params.nome);
+        assert_eq!(response.cpf, Some("52998224725".into()));
+        assert_eq!(response.cpf_formatado, Some("529.982.247-25".into()));
         delete(&get_data_pool(), response.id);
     }
 
@@ -288,6 +368,7 @@ pub mod tests {
         let first_cliente = model_create_cliente().unwrap();
         let cliente_id: Path<Uuid> = Path::from(first_cliente.id);
         let params = Json(UpdateClienteRequest {
+            tipo_pessoa: TipoPessoa::Fisica,
             nome: first_cliente.nome.clone(),
             sobrenome: first_cliente.sobrenome.clone(),
             cpf: first_cliente.cpf.clone(),
@@ -296,6 +377,10 @@ pub mod tests {
             sexo: first_cliente.sexo.clone(),
             estado_civil: first_cliente.estado_civil.clone(),
             telefone: first_cliente.telefone.clone(),
+            cnpj: None,
+            razao_social: None,
+            nome_fantasia: None,
+            inscricao_estadual: None,
         });
         let response = update_cliente(cliente_id, get_data_pool(), Json(params.clone()))
             .await
@@ -315,4 +400,70 @@ pub mod tests {
         let cliente = find(&get_pool(), cliente_id);
         assert!(cliente.is_err());
     }
+
+    fn empresa_request(cnpj: &str) -> CreateClienteRequest {
+        CreateClienteRequest {
+            tipo_pessoa: TipoPessoa::Juridica,
+            nome: None,
+            sobrenome: None,
+            cpf: None,
+            rg: None,
+            data_nascimento: None,
+            sexo: None,
+            estado_civil: None,
+            telefone: Some("4133334444".into()),
+            cnpj: Some(cnpj.into()),
+            razao_social: Some("Satoshi Comercio Ltda".into()),
+            nome_fantasia: None,
+            inscricao_estadual: Some("9012345678".into()),
+        }
+    }
+
+    #[actix_rt::test]
+    async fn it_creates_a_pessoa_juridica() {
+        let response = create_cliente(get_data_pool(), Json(empresa_request("99.888.777/0002-82")))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(response.cnpj, Some("99888777000282".into()));
+        assert_eq!(response.nome_exibicao, "Satoshi Comercio Ltda");
+        delete(&get_data_pool(), response.id).unwrap();
+    }
+
+    #[actix_rt::test]
+    async fn it_validates_a_cliente_by_its_tipo_pessoa() {
+        let params = CreateClienteRequest {
+            cpf: Some("529.982.247-25".into()),
+            ..empresa_request("99888777000282")
+        };
+        let response = create_cliente(get_data_pool(), Json(params)).await;
+        let expected_error = ApiError::ValidationError(vec!["cpf: apenas pessoas físicas têm este campo".into()]);
+        assert_eq!(response.unwrap_err(), expected_error);
+
+        let response = create_cliente(get_data_pool(), Json(empresa_request("99888777000283"))).await;
+        let expected_error = ApiError::ValidationError(vec!["cnpj: o CNPJ 99888777000283 é inválido".into()]);
+        assert_eq!(response.unwrap_err(), expected_error);
+    }
+
+    #[actix_rt::test]
+    async fn it_validates_the_cpf_of_a_cliente() {
+        let params = Json(CreateClienteRequest {
+            tipo_pessoa: TipoPessoa::Fisica,
+            nome: Some("Satoshi".into()),
+            sobrenome: Some("Nakamoto".into()),
+            cpf: Some("111.111.111-11".into()),
+            rg: None,
+            data_nascimento: None,
+            sexo: None,
+            estado_civil: None,
+            telefone: None,
+            cnpj: None,
+            razao_social: None,
+            nome_fantasia: None,
+            inscricao_estadual: None,
+        });
+        let response = create_cliente(get_data_pool(), params).await;
+        let expected_error = ApiError::ValidationError(vec!["cpf: o CPF 111.111.111-11 é inválido".into()]);
+        assert_eq!(response.unwrap_err(), expected_error);
+    }
 }
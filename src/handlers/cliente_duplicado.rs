@@ -0,0 +1,126 @@
+use crate::database::PoolType;
+use crate::errors::ApiError;
+use crate::handlers::cliente::ClienteResponse;
+use crate::helpers::respond_json;
+use crate::models::cliente_duplicado::{find_duplicados, mesclar, GrupoDuplicado};
+use crate::models::user::AuthUser;
+use actix_web::web::{block, Data, Json, Path};
+use serde::Serialize;
+use uuid::Uuid;
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub struct GrupoDuplicadoResponse {
+    /// The name the clientes share once accents, case and punctuation are dropped
+    pub nome: String,
+    /// Oldest first
+    pub clientes: Vec<ClienteResponse>,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub struct ClienteDuplicadosResponse(pub Vec<GrupoDuplicadoResponse>);
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct MesclarClienteRequest {
+    /// The cliente that is merged into the one in the path and then deleted
+    pub id_duplicado: Uuid,
+}
+
+/// Get the clientes that look registered more than once
+pub async fn get_cliente_duplicados(pool: Data<PoolType>) -> Result<Json<ClienteDuplicadosResponse>, ApiError> {
+    let duplicados = block(move || find_duplicados(&pool)).await?;
+    respond_json(duplicados)
+}
+
+/// Merge a duplicate into a cliente
+pub async fn mesclar_cliente(
+    cliente_id: Path<Uuid>,
+    pool: Data<PoolType>,
+    params: Json<MesclarClienteRequest>,
+    user: AuthUser,
+) -> Result<Json<ClienteResponse>, ApiError> {
+    let cliente = block(move || mesclar(&pool, *cliente_id, params.id_duplicado, &user.id)).await?;
+    respond_json(cliente)
+}
+
+impl From<GrupoDuplicado> for GrupoDuplicadoResponse {
+    fn from(grupo: GrupoDuplicado) -> Self {
+        GrupoDuplicadoResponse {
+            nome: grupo.nome,
+            clientes: grupo.clientes.into_iter().map(|cliente| cliente.into()).collect(),
+        }
+    }
+}
+
+impl From<Vec<GrupoDuplicado>> for ClienteDuplicadosResponse {
+    fn from(grupos: Vec<GrupoDuplicado>) -> Self {
+        ClienteDuplicadosResponse(grupos.into_iter().map(|grupo| grupo.into()).collect())
+    }
+}
+
+///Testes
+///Testes
+///Testes
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::models::cliente::delete;
+    use crate::models::cliente::tests::create_cliente;
+    use crate::tests::helpers::tests::{get_data_pool, get_pool};
+
+    fn auth_user() -> AuthUser {
+        AuthUser {
+            id: "10000000-0000-0000-0000-000000000000".into(),
+            email: "test@user.com".into(),
+        }
+    }
+
+    #[actix_rt::test]
+    async fn it_finds_and_merges_duplicated_clientes() {
+        // the model helper always creates the same person
+        let primeiro = create_cliente().unwrap();
+        let segundo = create_cliente().unwrap();
+        let response = get_cliente_duplicados(get_data_pool()).await.unwrap().into_inner();
+        let grupo = response
+            .0
+            .iter()
+            .find(|grupo| grupo.clientes.iter().any(|cliente| cliente.id == primeiro.id))
+            .unwrap();
+        assert_eq!(grupo.nome, "model test");
+        assert!(grupo.clientes.iter().any(|cliente| cliente.id == segundo.id));
+
+        let mesclado = mesclar_cliente(
+            Path::from(primeiro.id),
+            get_data_pool(),
+            Json(MesclarClienteRequest { id_duplicado: segundo.id }),
+            auth_user(),
+        )
+        .await
+        .unwrap()
+        .into_inner();
+        assert_eq!(mesclado.cpf, primeiro.cpf);
+        assert!(get_cliente_duplicados(get_data_pool())
+            .await
+            .unwrap()
+            .into_inner()
+            .0
+            .iter()
+            .all(|grupo| grupo.clientes.iter().all(|cliente| cliente.id != segundo.id)));
+        delete(&get_pool(), primeiro.id).unwrap();
+    }
+
+    #[actix_rt::test]
+    async fn it_doesnt_merge_an_unknown_cliente() {
+        let cliente = create_cliente().unwrap();
+        let duplicado_id = Uuid::new_v4();
+        let response = mesclar_cliente(
+            Path::from(cliente.id),
+            get_data_pool(),
+            Json(MesclarClienteRequest { id_duplicado: duplicado_id }),
+            auth_user(),
+        )
+        .await;
+        let expected_error = ApiError::NotFound(format!("Cliente {} not found", duplicado_id));
+        assert_eq!(response.unwrap_err(), expected_error);
+        delete(&get_pool(), cliente.id).unwrap();
+    }
+}
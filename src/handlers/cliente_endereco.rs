@@ -0,0 +1,314 @@
+use crate::database::PoolType;
+use crate::errors::ApiError;
+use crate::helpers::{respond_json, respond_ok};
+use crate::models::cep::{Cep, ConsultaCepLocal};
+use crate::models::cliente_endereco::{
+    create, delete, find, get_all, update, ClienteEndereco, NewClienteEndereco, TipoEndereco,
+};
+use crate::models::nfe::codigo_uf;
+use crate::models::user::AuthUser;
+use crate::validate::validate;
+use actix_web::web::{block, Data, HttpResponse, Json, Path};
+use chrono::NaiveDateTime;
+use rayon::prelude::*;
+use serde::Serialize;
+use std::str::FromStr;
+use uuid::Uuid;
+use validator::Validate;
+use validator::ValidationError;
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct ClienteEnderecoResponse {
+    pub id: Uuid,
+    pub id_cliente: Uuid,
+    pub tipo: String,
+    pub logradouro: String,
+    pub numero: String,
+    pub complemento: Option<String>,
+    pub bairro: String,
+    /// IBGE code of the município
+    pub codigo_municipio: String,
+    pub municipio: String,
+    pub uf: String,
+    pub cep: String,
+    /// Whether notas are issued to this endereço
+    pub principal: bool,
+    pub updated_by: String,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub struct ClienteEnderecosResponse(pub Vec<ClienteEnderecoResponse>);
+
+/// Only the CEP, numero and tipo are required, the other fields default to
+/// what the CEP base has
+#[derive(Clone, Debug, Deserialize, Serialize, Validate)]
+pub struct ClienteEnderecoRequest {
+    pub tipo: TipoEndereco,
+
+    #[validate(custom = "validar_cep")]
+    pub cep: String,
+
+    #[validate(length(
+        min = 3, max = 150,
+        message = "O logradouro deve ter de 3 a 150 caracteres"
+    ))]
+    pub logradouro: Option<String>,
+
+    #[validate(length(
+        min = 1, max = 20,
+        message = "O número deve ter de 1 a 20 caracteres"
+    ))]
+    pub numero: String,
+
+    #[validate(length(
+        min = 1, max = 100,
+        message = "O complemento deve ter de 1 a 100 caracteres"
+    ))]
+    pub complemento: Option<String>,
+
+    #[validate(length(
+        min = 2, max = 100,
+        message = "O bairro deve ter de 2 a 100 caracteres"
+    ))]
+    pub bairro: Option<String>,
+
+    #[validate(length(
+        min = 7, max = 7,
+        message = "O código do município deve ter 7 dígitos"
+    ))]
+    pub codigo_municipio: Option<String>,
+
+    #[validate(length(
+        min = 2, max = 100,
+        message = "O município deve ter de 2 a 100 caracteres"
+    ))]
+    pub municipio: Option<String>,
+
+    #[validate(custom = "validar_uf")]
+    pub uf: Option<String>,
+
+    /// Makes this the endereço notas are issued to
+    #[serde(default)]
+    pub principal: bool,
+}
+
+fn validar_cep(cep: &str) -> Result<(), ValidationError> {
+    Cep::from_str(cep).map(|_| ()).map_err(|erro| {
+        let mut error = ValidationError::new("cep");
+        error.message = Some(format!("cep: {}", erro).into());
+        error
+    })
+}
+
+fn validar_uf(uf: &str) -> Result<(), ValidationError> {
+    match codigo_uf(uf) {
+        Some(_) => Ok(()),
+        None => {
+            let mut error = ValidationError::new("uf");
+            error.message = Some(format!("uf: a UF {} não existe", uf).into());
+            Err(error)
+        }
+    }
+}
+
+fn new_endereco(
+    cliente_id: Uuid,
+    endereco_id: Uuid,
+    params: &ClienteEnderecoRequest,
+    user: &AuthUser,
+) -> NewClienteEndereco {
+    NewClienteEndereco {
+        id: endereco_id.to_string(),
+        id_cliente: cliente_id.to_string(),
+        tipo: params.tipo,
+        cep: params.cep.clone(),
+        logradouro: params.logradouro.clone(),
+        numero: params.numero.trim().to_string(),
+        complemento: params.complemento.clone(),
+        bairro: params.bairro.clone(),
+        codigo_municipio: params.codigo_municipio.clone(),
+        municipio: params.municipio.clone(),
+        uf: params.uf.clone(),
+        principal: params.principal,
+        created_by: user.id.to_string(),
+        updated_by: user.id.to_string(),
+    }
+}
+
+/// Get the enderecos of a cliente
+pub async fn get_cliente_enderecos(
+    cliente_id: Path<Uuid>,
+    pool: Data<PoolType>,
+) -> Result<Json<ClienteEnderecosResponse>, ApiError> {
+    let enderecos = block(move || get_all(&pool, *cliente_id)).await?;
+    respond_json(enderecos)
+}
+
+/// Get an endereco of a cliente
+pub async fn get_cliente_endereco(
+    path: Path<(Uuid, Uuid)>,
+    pool: Data<PoolType>,
+) -> Result<Json<ClienteEnderecoResponse>, ApiError> {
+    let (cliente_id, endereco_id) = path.into_inner();
+    let endereco = block(move || find(&pool, cliente_id, endereco_id)).await?;
+    respond_json(endereco)
+}
+
+/// Add an endereco to a cliente
+pub async fn create_cliente_endereco(
+    cliente_id: Path<Uuid>,
+    pool: Data<PoolType>,
+    params: Json<ClienteEnderecoRequest>,
+    user: AuthUser,
+) -> Result<Json<ClienteEnderecoResponse>, ApiError> {
+    validate(&params)?;
+
+    let new_endereco = new_endereco(*cliente_id, Uuid::new_v4(), &params, &user);
+    let endereco = block(move || create(&pool, &ConsultaCepLocal::new(&pool), new_endereco)).await?;
+    respond_json(endereco)
+}
+
+/// Replace an endereco of a cliente
+pub async fn update_cliente_endereco(
+    path: Path<(Uuid, Uuid)>,
+    pool: Data<PoolType>,
+    params: Json<ClienteEnderecoRequest>,
+    user: AuthUser,
+) -> Result<Json<ClienteEnderecoResponse>, ApiError> {
+    validate(&params)?;
+
+    let (cliente_id, endereco_id) = path.into_inner();
+    let new_endereco = new_endereco(cliente_id, endereco_id, &params, &user);
+    let endereco = block(move || update(&pool, &ConsultaCepLocal::new(&pool), new_endereco)).await?;
+    respond_json(endereco)
+}
+
+/// Delete an endereco of a cliente
+pub async fn delete_cliente_endereco(
+    path: Path<(Uuid, Uuid)>,
+    pool: Data<PoolType>,
+) -> Result<HttpResponse, ApiError> {
+    let (cliente_id, endereco_id) = path.into_inner();
+    block(move || delete(&pool, cliente_id, endereco_id)).await?;
+    respond_ok()
+}
+
+impl From<ClienteEndereco> for ClienteEnderecoResponse {
+    fn from(endereco: ClienteEndereco) -> Self {
+        ClienteEnderecoResponse {
+            id: Uuid::parse_str(&endereco.id).unwrap(),
+            id_cliente: Uuid::parse_str(&endereco.id_cliente).unwrap(),
+            tipo: endereco.tipo,
+            logradouro: endereco.logradouro,
+            numero: endereco.numero,
+            complemento: endereco.complemento,
+            bairro: endereco.bairro,
+            codigo_municipio: endereco.codigo_municipio,
+            municipio: endereco.municipio,
+            uf: endereco.uf,
+            cep: endereco.cep,
+            principal: endereco.principal,
+            updated_by: endereco.updated_by,
+            updated_at: endereco.updated_at,
+        }
+    }
+}
+
+impl From<Vec<ClienteEndereco>> for ClienteEnderecosResponse {
+    fn from(enderecos: Vec<ClienteEndereco>) -> Self {
+        ClienteEnderecosResponse(enderecos.into_par_iter().map(|endereco| endereco.into()).collect())
+    }
+}
+
+///Testes
+///Testes
+///Testes
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::models::cliente::delete as delete_cliente;
+    use crate::models::cliente::tests::create_cliente;
+    use crate::tests::helpers::tests::{get_data_pool, get_pool};
+
+    fn auth_user() -> AuthUser {
+        AuthUser {
+            id: "10000000-0000-0000-0000-000000000000".into(),
+            email: "test@user.com".into(),
+        }
+    }
+
+    fn endereco_request(cep: &str) -> ClienteEnderecoRequest {
+        ClienteEnderecoRequest {
+            tipo: TipoEndereco::Entrega,
+            cep: cep.into(),
+            logradouro: None,
+            numero: "1578".into(),
+            complemento: None,
+            bairro: None,
+            codigo_municipio: None,
+            municipio: None,
+            uf: None,
+            principal: false,
+        }
+    }
+
+    #[actix_rt::test]
+    async fn it_creates_an_endereco_from_the_cep() {
+        let cliente = create_cliente().unwrap();
+        let response = create_cliente_endereco(
+            Path::from(cliente.id),
+            get_data_pool(),
+            Json(endereco_request("01310-100")),
+            auth_user(),
+        )
+        .await
+        .unwrap()
+        .into_inner();
+        assert_eq!(response.logradouro, "Avenida Paulista");
+        assert_eq!(response.municipio, "São Paulo");
+        assert_eq!(response.cep, "01310100");
+        assert!(response.principal);
+
+        let enderecos = get_cliente_enderecos(Path::from(cliente.id), get_data_pool())
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(enderecos.0, vec![response.clone()]);
+        let params = ClienteEnderecoRequest {
+            tipo: TipoEndereco::Cobranca,
+            ..endereco_request("01310100")
+        };
+        let alterado = update_cliente_endereco(
+            Path::from((cliente.id, response.id)),
+            get_data_pool(),
+            Json(params),
+            auth_user(),
+        )
+        .await
+        .unwrap()
+        .into_inner();
+        assert_eq!(alterado.tipo, "cobranca");
+
+        delete_cliente_endereco(Path::from((cliente.id, response.id)), get_data_pool())
+            .await
+            .unwrap();
+        let response = get_cliente_endereco(Path::from((cliente.id, response.id)), get_data_pool()).await;
+        assert!(response.is_err());
+        delete_cliente(&get_pool(), cliente.id).unwrap();
+    }
+
+    #[actix_rt::test]
+    async fn it_doesnt_create_an_endereco_for_an_unknown_cliente() {
+        let cliente_id = Uuid::new_v4();
+        let response = create_cliente_endereco(
+            Path::from(cliente_id),
+            get_data_pool(),
+            Json(endereco_request("01310100")),
+            auth_user(),
+        )
+        .await;
+        let expected_error = ApiError::NotFound(format!("Cliente {} not found", cliente_id));
+        assert_eq!(response.unwrap_err(), expected_error);
+    }
+}
@@ -0,0 +1,119 @@
+use crate::database::PoolType;
+use crate::errors::ApiError;
+use crate::helpers::respond_json;
+use crate::models::credito::{definir_limite, find, PosicaoCredito};
+use crate::models::user::AuthUser;
+use actix_web::web::{block, Data, Json, Path};
+use bigdecimal::{BigDecimal, Zero};
+use serde::Serialize;
+use uuid::Uuid;
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub struct CreditoResponse {
+    pub id_cliente: Uuid,
+    /// None when the cliente has no limit
+    pub limite_credito: Option<BigDecimal>,
+    pub saldo_aberto: BigDecimal,
+    pub saldo_vencido: BigDecimal,
+    pub titulos_vencidos: usize,
+    /// None when the cliente has no limit, negative when it is already passed
+    pub credito_disponivel: Option<BigDecimal>,
+    /// Whether new orcamentos for the cliente need a user allowed to release credit
+    pub bloqueado: bool,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct LimiteCreditoRequest {
+    /// None removes the limit
+    pub limite_credito: Option<BigDecimal>,
+}
+
+/// Get the credit position of a cliente
+pub async fn get_cliente_credito(
+    cliente_id: Path<Uuid>,
+    pool: Data<PoolType>,
+) -> Result<Json<CreditoResponse>, ApiError> {
+    let credito = block(move || find(&pool, *cliente_id)).await?;
+    respond_json(credito)
+}
+
+/// Change the credit limit of a cliente
+pub async fn update_cliente_credito(
+    cliente_id: Path<Uuid>,
+    pool: Data<PoolType>,
+    params: Json<LimiteCreditoRequest>,
+    user: AuthUser,
+) -> Result<Json<CreditoResponse>, ApiError> {
+    let limite = params.into_inner().limite_credito;
+    let credito = block(move || definir_limite(&pool, *cliente_id, limite, &user.id)).await?;
+    respond_json(credito)
+}
+
+impl From<PosicaoCredito> for CreditoResponse {
+    fn from(posicao: PosicaoCredito) -> Self {
+        let bloqueado = !posicao.bloqueios(&BigDecimal::zero()).is_empty();
+        CreditoResponse {
+            id_cliente: Uuid::parse_str(&posicao.id_cliente).unwrap(),
+            credito_disponivel: posicao.disponivel(),
+            limite_credito: posicao.limite,
+            saldo_aberto: posicao.saldo_aberto,
+            saldo_vencido: posicao.saldo_vencido,
+            titulos_vencidos: posicao.titulos_vencidos,
+            bloqueado,
+        }
+    }
+}
+
+///Testes
+///Testes
+///Testes
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::models::cliente::delete as delete_cliente;
+    use crate::models::cliente::tests::create_cliente;
+    use crate::tests::helpers::tests::{get_data_pool, get_pool};
+
+    #[actix_rt::test]
+    async fn it_gets_the_credito_of_a_cliente() {
+        let cliente = create_cliente().unwrap();
+        let response = get_cliente_credito(Path::from(cliente.id), get_data_pool())
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(response.limite_credito, None);
+        assert_eq!(response.saldo_aberto, BigDecimal::zero());
+        assert_eq!(response.credito_disponivel, None);
+        assert!(!response.bloqueado);
+        delete_cliente(&get_pool(), cliente.id).unwrap();
+    }
+
+    #[actix_rt::test]
+    async fn it_doesnt_let_any_user_change_the_limite() {
+        let cliente = create_cliente().unwrap();
+        let user = AuthUser {
+            id: "20000000-0000-0000-0000-000000000000".into(),
+            email: "vendedor@user.com".into(),
+        };
+        let response = update_cliente_credito(
+            Path::from(cliente.id),
+            get_data_pool(),
+            Json(LimiteCreditoRequest { limite_credito: Some(BigDecimal::from(5000)) }),
+            user,
+        )
+        .await;
+        let expected_error = ApiError::Unauthorized(
+            "User 20000000-0000-0000-0000-000000000000 cannot change credit limits".to_string(),
+        );
+        assert_eq!(response.unwrap_err(), expected_error);
+        delete_cliente(&get_pool(), cliente.id).unwrap();
+    }
+
+    #[actix_rt::test]
+    async fn it_doesnt_get_the_credito_of_an_unknown_cliente() {
+        let cliente_id = Uuid::new_v4();
+        let response = get_cliente_credito(Path::from(cliente_id), get_data_pool()).await;
+        let expected_error = ApiError::NotFound(format!("Cliente {} not found", cliente_id));
+        assert_eq!(response.unwrap_err(), expected_error);
+    }
+}
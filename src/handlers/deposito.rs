@@ -0,0 +1,133 @@
+use crate::database::PoolType;
+use crate::errors::ApiError;
+use crate::helpers::respond_json;
+use crate::models::deposito::{create, find, get_all, Deposito, NewDeposito};
+use crate::models::user::AuthUser;
+use crate::validate::validate;
+use actix_web::web::{block, Data, Json, Path};
+use chrono::NaiveDateTime;
+use rayon::prelude::*;
+use serde::Serialize;
+use uuid::Uuid;
+use validator::Validate;
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub struct DepositoResponse {
+    pub id: Uuid,
+    pub nome: String,
+    pub created_by: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub struct DepositosResponse(pub Vec<DepositoResponse>);
+
+#[derive(Clone, Debug, Deserialize, Serialize, Validate)]
+pub struct CreateDepositoRequest {
+    #[validate(length(
+        min = 3, max = 100,
+        message = "O nome do depósito deve ter de 3 a 100 caracteres"
+    ))]
+    pub nome: String,
+}
+
+/// Get a deposito
+pub async fn get_deposito(
+    deposito_id: Path<Uuid>,
+    pool: Data<PoolType>,
+) -> Result<Json<DepositoResponse>, ApiError> {
+    let deposito = block(move || find(&pool, *deposito_id)).await?;
+    respond_json(deposito)
+}
+
+/// Get all depositos
+pub async fn get_depositos(pool: Data<PoolType>) -> Result<Json<DepositosResponse>, ApiError> {
+    let depositos = block(move || get_all(&pool)).await?;
+    respond_json(depositos)
+}
+
+/// Create a deposito
+pub async fn create_deposito(
+    pool: Data<PoolType>,
+    params: Json<CreateDepositoRequest>,
+    user: AuthUser,
+) -> Result<Json<DepositoResponse>, ApiError> {
+    validate(&params)?;
+
+    let new_deposito: Deposito = NewDeposito {
+        id: Uuid::new_v4().to_string(),
+        nome: params.nome.trim().to_string(),
+        created_by: user.id.to_string(),
+    }
+    .into();
+    let deposito = block(move || create(&pool, &new_deposito)).await?;
+    respond_json(deposito)
+}
+
+impl From<Deposito> for DepositoResponse {
+    fn from(deposito: Deposito) -> Self {
+        DepositoResponse {
+            id: Uuid::parse_str(&deposito.id).unwrap(),
+            nome: deposito.nome,
+            created_by: deposito.created_by,
+            created_at: deposito.created_at,
+        }
+    }
+}
+
+impl From<Vec<Deposito>> for DepositosResponse {
+    fn from(depositos: Vec<Deposito>) -> Self {
+        DepositosResponse(depositos.into_par_iter().map(|deposito| deposito.into()).collect())
+    }
+}
+
+///Testes
+///Testes
+///Testes
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::models::deposito::tests::delete_deposito;
+    use crate::models::deposito::DEPOSITO_PADRAO;
+    use crate::tests::helpers::tests::get_data_pool;
+    use std::str::FromStr;
+
+    fn auth_user() -> AuthUser {
+        AuthUser {
+            id: "10000000-0000-0000-0000-000000000000".into(),
+            email: "test@user.com".into(),
+        }
+    }
+
+    #[actix_rt::test]
+    async fn it_gets_the_default_deposito() {
+        let deposito_id = Path::from(Uuid::from_str(DEPOSITO_PADRAO).unwrap());
+        let response = get_deposito(deposito_id, get_data_pool()).await;
+        assert!(response.is_ok());
+    }
+
+    #[actix_rt::test]
+    async fn it_gets_all_depositos() {
+        let response = get_depositos(get_data_pool()).await;
+        assert!(!response.unwrap().into_inner().0.is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn it_creates_a_deposito() {
+        let nome = format!("Depósito {}", Uuid::new_v4());
+        let params = Json(CreateDepositoRequest { nome: nome.clone() });
+        let response = create_deposito(get_data_pool(), params, auth_user()).await.unwrap();
+        assert_eq!(response.nome, nome);
+        delete_deposito(response.id);
+    }
+
+    #[actix_rt::test]
+    async fn it_doesnt_create_a_deposito_without_a_nome() {
+        let params = Json(CreateDepositoRequest { nome: "".into() });
+        let response = create_deposito(get_data_pool(), params, auth_user()).await;
+        let expected_error = ApiError::ValidationError(vec![
+            "O nome do depósito deve ter de 3 a 100 caracteres".to_string(),
+        ]);
+        assert_eq!(response.unwrap_err(), expected_error);
+    }
+}
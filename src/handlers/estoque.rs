@@ -0,0 +1,251 @@
+use crate::config::CONFIG;
+use crate::database::PoolType;
+use crate::errors::ApiError;
+use crate::helpers::respond_json;
+use crate::models::deposito::DEPOSITO_PADRAO;
+use crate::models::estoque::{
+    find_movimentos, find_saldo, get_saldos, movimentar, ConfigEstoque, EstoqueMovimento, NewEstoqueMovimento,
+    SaldoDeposito, TipoMovimento,
+};
+use crate::models::user::AuthUser;
+use crate::validate::validate;
+use actix_web::web::{block, Data, Json, Path};
+use bigdecimal::BigDecimal;
+use chrono::NaiveDateTime;
+use rayon::prelude::*;
+use serde::Serialize;
+use uuid::Uuid;
+use validator::Validate;
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub struct EstoqueMovimentoResponse {
+    pub id: Uuid,
+    pub id_produto: Uuid,
+    pub id_deposito: Uuid,
+    pub tipo: String,
+    pub quantidade: i32,
+    pub custo_unitario: Option<BigDecimal>,
+    pub id_documento: Option<Uuid>,
+    pub observacao: Option<String>,
+    pub created_by: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub struct EstoqueMovimentosResponse(pub Vec<EstoqueMovimentoResponse>);
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub struct SaldoDepositoResponse {
+    pub id_deposito: Uuid,
+    pub deposito: String,
+    pub quantidade: i64,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub struct SaldoProdutoResponse {
+    pub id_produto: Uuid,
+    pub depositos: Vec<SaldoDepositoResponse>,
+    pub total: i64,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub struct SaldosResponse(pub Vec<SaldoProdutoResponse>);
+
+#[derive(Clone, Debug, Deserialize, Serialize, Validate)]
+pub struct EstoqueMovimentoRequest {
+    pub id_produto: Uuid,
+    /// Defaults to the main depósito
+    pub id_deposito: Option<Uuid>,
+    pub id_deposito_destino: Option<Uuid>,
+    pub tipo: TipoMovimento,
+    pub quantidade: i32,
+    /// Cost of each unit of an entrada, averaged into the custo_medio
+    pub custo_unitario: Option<BigDecimal>,
+
+    #[validate(length(
+        max = 255,
+        message = "A observação deve ter no máximo 255 caracteres"
+    ))]
+    pub observacao: Option<String>,
+}
+
+/// Get the balance of every produto with stock movements
+pub async fn get_estoque(pool: Data<PoolType>) -> Result<Json<SaldosResponse>, ApiError> {
+    let saldos = block(move || get_saldos(&pool)).await?;
+    respond_json(saldos)
+}
+
+/// Get the balance of a produto per deposito
+pub async fn get_produto_estoque(
+    produto_id: Path<Uuid>,
+    pool: Data<PoolType>,
+) -> Result<Json<SaldoProdutoResponse>, ApiError> {
+    let saldo = block(move || find_saldo(&pool, *produto_id)).await?;
+    respond_json(saldo)
+}
+
+/// Get the stock movements of a produto
+pub async fn get_produto_movimentos(
+    produto_id: Path<Uuid>,
+    pool: Data<PoolType>,
+) -> Result<Json<EstoqueMovimentosResponse>, ApiError> {
+    let movimentos = block(move || find_movimentos(&pool, *produto_id)).await?;
+    respond_json(movimentos)
+}
+
+/// Post a stock movement
+pub async fn create_estoque_movimento(
+    pool: Data<PoolType>,
+    params: Json<EstoqueMovimentoRequest>,
+    user: AuthUser,
+) -> Result<Json<EstoqueMovimentosResponse>, ApiError> {
+    validate(&params)?;
+
+    let new_movimento = NewEstoqueMovimento {
+        id_produto: params.id_produto.to_string(),
+        id_deposito: params
+            .id_deposito
+            .map(|id_deposito| id_deposito.to_string())
+            .unwrap_or_else(|| DEPOSITO_PADRAO.to_string()),
+        id_deposito_destino: params.id_deposito_destino.map(|destino| destino.to_string()),
+        tipo: params.tipo,
+        quantidade: params.quantidade,
+        custo_unitario: params.custo_unitario.clone(),
+        id_documento: None,
+        observacao: params.observacao.clone(),
+        created_by: user.id.to_string(),
+    };
+    let config = ConfigEstoque::from_config(&CONFIG);
+    let movimentos = block(move || movimentar(&pool, &new_movimento, &config)).await?;
+    respond_json(movimentos)
+}
+
+impl From<EstoqueMovimento> for EstoqueMovimentoResponse {
+    fn from(movimento: EstoqueMovimento) -> Self {
+        EstoqueMovimentoResponse {
+            id: Uuid::parse_str(&movimento.id).unwrap(),
+            id_produto: Uuid::parse_str(&movimento.id_produto).unwrap(),
+            id_deposito: Uuid::parse_str(&movimento.id_deposito).unwrap(),
+            tipo: movimento.tipo,
+            quantidade: movimento.quantidade,
+            custo_unitario: movimento.custo_unitario,
+            id_documento: movimento
+                .id_documento
+                .map(|id_documento| Uuid::parse_str(&id_documento).unwrap()),
+            observacao: movimento.observacao,
+            created_by: movimento.created_by,
+            created_at: movimento.created_at,
+        }
+    }
+}
+
+impl From<Vec<EstoqueMovimento>> for EstoqueMovimentosResponse {
+    fn from(movimentos: Vec<EstoqueMovimento>) -> Self {
+        EstoqueMovimentosResponse(movimentos.into_par_iter().map(|movimento| movimento.into()).collect())
+    }
+}
+
+impl From<(String, Vec<SaldoDeposito>)> for SaldoProdutoResponse {
+    fn from((id_produto, depositos): (String, Vec<SaldoDeposito>)) -> Self {
+        let depositos: Vec<SaldoDepositoResponse> = depositos
+            .into_iter()
+            .map(|(id_deposito, deposito, quantidade)| SaldoDepositoResponse {
+                id_deposito: Uuid::parse_str(&id_deposito).unwrap(),
+                deposito,
+                quantidade,
+            })
+            .collect();
+        SaldoProdutoResponse {
+            id_produto: Uuid::parse_str(&id_produto).unwrap(),
+            total: depositos.iter().map(|saldo| saldo.quantidade).sum(),
+            depositos,
+        }
+    }
+}
+
+impl From<Vec<(String, Vec<SaldoDeposito>)>> for SaldosResponse {
+    fn from(saldos: Vec<(String, Vec<SaldoDeposito>)>) -> Self {
+        SaldosResponse(saldos.into_iter().map(|saldo| saldo.into()).collect())
+    }
+}
+
+///Testes
+///Testes
+///Testes
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::models::estoque::tests::delete_movimentos;
+    use crate::models::produto::{delete as delete_produto, find as find_produto};
+    use crate::models::produto::tests::create_produto;
+    use crate::tests::helpers::tests::{get_data_pool, get_pool};
+
+    fn auth_user() -> AuthUser {
+        AuthUser {
+            id: "10000000-0000-0000-0000-000000000000".into(),
+            email: "test@user.com".into(),
+        }
+    }
+
+    fn request(id_produto: Uuid, tipo: TipoMovimento, quantidade: i32) -> Json<EstoqueMovimentoRequest> {
+        Json(EstoqueMovimentoRequest {
+            id_produto,
+            id_deposito: None,
+            id_deposito_destino: None,
+            tipo,
+            quantidade,
+            custo_unitario: None,
+            observacao: None,
+        })
+    }
+
+    #[actix_rt::test]
+    async fn it_posts_movements_and_reports_the_balance() {
+        let produto = create_produto().unwrap();
+        let entrada = request(produto.id, TipoMovimento::Entrada, 7);
+        let response = create_estoque_movimento(get_data_pool(), entrada, auth_user())
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(response.0.len(), 1);
+        assert_eq!(response.0[0].quantidade, 7);
+        let ajuste = request(produto.id, TipoMovimento::Ajuste, -2);
+        create_estoque_movimento(get_data_pool(), ajuste, auth_user()).await.unwrap();
+
+        let saldo = get_produto_estoque(Path::from(produto.id), get_data_pool()).await.unwrap();
+        assert_eq!(saldo.total, 5);
+        let movimentos = get_produto_movimentos(Path::from(produto.id), get_data_pool()).await.unwrap();
+        assert_eq!(movimentos.into_inner().0.len(), 2);
+        assert!(get_estoque(get_data_pool()).await.is_ok());
+
+        delete_movimentos(produto.id);
+        delete_produto(&get_pool(), produto.id).unwrap();
+    }
+
+    #[actix_rt::test]
+    async fn it_averages_the_cost_of_costed_entradas() {
+        let produto = create_produto().unwrap();
+        let entrada = Json(EstoqueMovimentoRequest {
+            custo_unitario: "3.00".parse().ok(),
+            ..request(produto.id, TipoMovimento::Entrada, 4).into_inner()
+        });
+        let response = create_estoque_movimento(get_data_pool(), entrada, auth_user())
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(response.0[0].custo_unitario, "3.00".parse().ok());
+        let produto = find_produto(&get_pool(), produto.id).unwrap();
+        assert_eq!(produto.custo_medio, "3".parse().ok());
+
+        delete_movimentos(produto.id);
+        delete_produto(&get_pool(), produto.id).unwrap();
+    }
+
+    #[actix_rt::test]
+    async fn it_doesnt_post_a_movement_of_nothing() {
+        let params = request(Uuid::new_v4(), TipoMovimento::Entrada, 0);
+        let response = create_estoque_movimento(get_data_pool(), params, auth_user()).await;
+        let expected_error = ApiError::ValidationError(vec!["A quantidade deve ser maior que zero".to_string()]);
+        assert_eq!(response.unwrap_err(), expected_error);
+    }
+}
@@ -0,0 +1,86 @@
+use crate::database::PoolType;
+use crate::errors::ApiError;
+use crate::helpers::respond_json;
+use crate::models::catalogo_fiscal::{buscar, CestCatalogo, NcmCatalogo};
+use actix_web::web::{block, Data, Json, Query};
+use bigdecimal::BigDecimal;
+use rayon::prelude::*;
+use serde::Serialize;
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub struct NcmResponse {
+    pub codigo: String,
+    pub descricao: String,
+    pub aliquota_ipi: Option<BigDecimal>,
+    pub cests: Vec<CestResponse>,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub struct NcmsResponse(pub Vec<NcmResponse>);
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub struct CestResponse {
+    pub codigo: String,
+    pub descricao: String,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct BuscaNcmRequest {
+    pub q: String,
+}
+
+/// Search the NCM catalog, for autocomplete in the produto form
+pub async fn get_ncms(
+    pool: Data<PoolType>,
+    params: Query<BuscaNcmRequest>,
+) -> Result<Json<NcmsResponse>, ApiError> {
+    let ncms = block(move || buscar(&pool, &params.q)).await?;
+    respond_json(ncms)
+}
+
+impl From<CestCatalogo> for CestResponse {
+    fn from(cest: CestCatalogo) -> Self {
+        CestResponse {
+            codigo: cest.codigo,
+            descricao: cest.descricao,
+        }
+    }
+}
+
+impl From<(NcmCatalogo, Vec<CestCatalogo>)> for NcmResponse {
+    fn from((ncm, cests): (NcmCatalogo, Vec<CestCatalogo>)) -> Self {
+        NcmResponse {
+            codigo: ncm.codigo,
+            descricao: ncm.descricao,
+            aliquota_ipi: ncm.aliquota_ipi,
+            cests: cests.into_iter().map(|cest| cest.into()).collect(),
+        }
+    }
+}
+
+impl From<Vec<(NcmCatalogo, Vec<CestCatalogo>)>> for NcmsResponse {
+    fn from(ncms: Vec<(NcmCatalogo, Vec<CestCatalogo>)>) -> Self {
+        NcmsResponse(ncms.into_par_iter().map(|ncm| ncm.into()).collect())
+    }
+}
+
+///Testes
+///Testes
+///Testes
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::tests::helpers::tests::get_data_pool;
+
+    #[actix_rt::test]
+    async fn it_searches_the_ncm_catalog() {
+        let params = Query(BuscaNcmRequest { q: "vinhos".into() });
+        let response = get_ncms(get_data_pool(), params).await.unwrap().into_inner();
+        assert_eq!(response.0[0].codigo, "22042100");
+
+        let params = Query(BuscaNcmRequest { q: "39239000".into() });
+        let response = get_ncms(get_data_pool(), params).await.unwrap().into_inner();
+        assert_eq!(response.0.len(), 1);
+        assert_eq!(response.0[0].cests[0].codigo, "2805700");
+    }
+}
@@ -0,0 +1,411 @@
+use crate::database::PoolType;
+use crate::errors::ApiError;
+use crate::helpers::{respond_json, respond_ok};
+use crate::models::fornecedor::{
+    create, delete, find, find_by_produto, find_produtos, get_all, remover_produto, salvar_produto, update, Fornecedor,
+    FornecedorProduto, NewFornecedor, UpdateFornecedor,
+};
+use crate::models::user::AuthUser;
+use crate::validate::{normalizar_cnpj, validar_cnpj, validate};
+use actix_web::web::{block, Data, HttpResponse, Json, Path};
+use chrono::{NaiveDateTime, Utc};
+use rayon::prelude::*;
+use serde::Serialize;
+use uuid::Uuid;
+use validator::Validate;
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub struct FornecedorResponse {
+    pub id: Uuid,
+    pub cnpj: String,
+    pub razao_social: String,
+    pub nome_fantasia: Option<String>,
+    pub inscricao_estadual: Option<String>,
+    pub contato: Option<String>,
+    pub email: Option<String>,
+    pub telefone: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub struct FornecedoresResponse(pub Vec<FornecedorResponse>);
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub struct FornecedorProdutoResponse {
+    pub id_fornecedor: Uuid,
+    pub codigo: String,
+    pub id_produto: Uuid,
+    pub preferencial: bool,
+    pub created_by: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub struct FornecedorProdutosResponse(pub Vec<FornecedorProdutoResponse>);
+
+#[derive(Clone, Debug, Deserialize, Serialize, Validate)]
+pub struct CreateFornecedorRequest {
+    #[validate(custom = "validar_cnpj")]
+    pub cnpj: String,
+
+    #[validate(length(
+        min = 3, max = 150,
+        message = "A razão social deve ter de 3 a 150 caracteres"
+    ))]
+    pub razao_social: String,
+
+    #[validate(length(
+        min = 1, max = 150,
+        message = "O nome fantasia deve ter de 1 a 150 caracteres"
+    ))]
+    pub nome_fantasia: Option<String>,
+
+    #[validate(length(
+        min = 2, max = 20,
+        message = "A inscrição estadual deve ter de 2 a 20 caracteres"
+    ))]
+    pub inscricao_estadual: Option<String>,
+
+    #[validate(length(
+        min = 3, max = 100,
+        message = "O contato deve ter de 3 a 100 caracteres"
+    ))]
+    pub contato: Option<String>,
+
+    #[validate(email(message = "O email deve ser valido"))]
+    pub email: Option<String>,
+
+    #[validate(length(
+        min = 9, max = 11,
+        message = "O telefone deve ter de 9 a 11 caracteres"
+    ))]
+    pub telefone: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, Validate)]
+pub struct UpdateFornecedorRequest {
+    #[validate(custom = "validar_cnpj")]
+    pub cnpj: String,
+
+    #[validate(length(
+        min = 3, max = 150,
+        message = "A razão social deve ter de 3 a 150 caracteres"
+    ))]
+    pub razao_social: String,
+
+    #[validate(length(
+        min = 1, max = 150,
+        message = "O nome fantasia deve ter de 1 a 150 caracteres"
+    ))]
+    pub nome_fantasia: Option<String>,
+
+    #[validate(length(
+        min = 2, max = 20,
+        message = "A inscrição estadual deve ter de 2 a 20 caracteres"
+    ))]
+    pub inscricao_estadual: Option<String>,
+
+    #[validate(length(
+        min = 3, max = 100,
+        message = "O contato deve ter de 3 a 100 caracteres"
+    ))]
+    pub contato: Option<String>,
+
+    #[validate(email(message = "O email deve ser valido"))]
+    pub email: Option<String>,
+
+    #[validate(length(
+        min = 9, max = 11,
+        message = "O telefone deve ter de 9 a 11 caracteres"
+    ))]
+    pub telefone: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, Validate)]
+pub struct FornecedorProdutoRequest {
+    pub id_produto: Uuid,
+
+    #[validate(length(
+        min = 1, max = 60,
+        message = "O código do fornecedor deve ter de 1 a 60 caracteres"
+    ))]
+    pub codigo: String,
+
+    #[serde(default)]
+    pub preferencial: bool,
+}
+
+/// Get a fornecedor
+pub async fn get_fornecedor(
+    fornecedor_id: Path<Uuid>,
+    pool: Data<PoolType>,
+) -> Result<Json<FornecedorResponse>, ApiError> {
+    let fornecedor = block(move || find(&pool, *fornecedor_id)).await?;
+    respond_json(fornecedor)
+}
+
+/// Get all fornecedores
+pub async fn get_fornecedores(pool: Data<PoolType>) -> Result<Json<FornecedoresResponse>, ApiError> {
+    let fornecedores = block(move || get_all(&pool)).await?;
+    respond_json(fornecedores)
+}
+
+/// Create a fornecedor
+pub async fn create_fornecedor(
+    pool: Data<PoolType>,
+    params: Json<CreateFornecedorRequest>,
+    user: AuthUser,
+) -> Result<Json<FornecedorResponse>, ApiError> {
+    validate(&params)?;
+
+    let new_fornecedor: Fornecedor = NewFornecedor {
+        id: Uuid::new_v4().to_string(),
+        cnpj: normalizar_cnpj(&params.cnpj)?,
+        razao_social: params.razao_social.to_string(),
+        nome_fantasia: params.nome_fantasia.clone(),
+        inscricao_estadual: params.inscricao_estadual.clone(),
+        contato: params.contato.clone(),
+        email: params.email.clone(),
+        telefone: params.telefone.clone(),
+        created_by: user.id.to_string(),
+        updated_by: user.id.to_string(),
+    }
+    .into();
+    let fornecedor = block(move || create(&pool, &new_fornecedor)).await?;
+    respond_json(fornecedor)
+}
+
+/// Update a fornecedor
+pub async fn update_fornecedor(
+    fornecedor_id: Path<Uuid>,
+    pool: Data<PoolType>,
+    params: Json<UpdateFornecedorRequest>,
+    user: AuthUser,
+) -> Result<Json<FornecedorResponse>, ApiError> {
+    validate(&params)?;
+
+    let update_fornecedor = UpdateFornecedor {
+        id: fornecedor_id.to_string(),
+        cnpj: normalizar_cnpj(&params.cnpj)?,
+        razao_social: params.razao_social.to_string(),
+        nome_fantasia: params.nome_fantasia.clone(),
+        inscricao_estadual: params.inscricao_estadual.clone(),
+        contato: params.contato.clone(),
+        email: params.email.clone(),
+        telefone: params.telefone.clone(),
+        updated_by: user.id.to_string(),
+    };
+    let fornecedor = block(move || update(&pool, &update_fornecedor)).await?;
+    respond_json(fornecedor)
+}
+
+/// Delete a fornecedor
+pub async fn delete_fornecedor(
+    fornecedor_id: Path<Uuid>,
+    pool: Data<PoolType>,
+) -> Result<HttpResponse, ApiError> {
+    block(move || delete(&pool, *fornecedor_id)).await?;
+    respond_ok()
+}
+
+/// Get the produtos of a fornecedor with the fornecedor's codes
+pub async fn get_fornecedor_produtos(
+    fornecedor_id: Path<Uuid>,
+    pool: Data<PoolType>,
+) -> Result<Json<FornecedorProdutosResponse>, ApiError> {
+    let produtos = block(move || find_produtos(&pool, *fornecedor_id)).await?;
+    respond_json(produtos)
+}
+
+/// Link a fornecedor code to a produto
+pub async fn add_fornecedor_produto(
+    fornecedor_id: Path<Uuid>,
+    pool: Data<PoolType>,
+    params: Json<FornecedorProdutoRequest>,
+    user: AuthUser,
+) -> Result<Json<FornecedorProdutoResponse>, ApiError> {
+    validate(&params)?;
+
+    let vinculo = FornecedorProduto {
+        id_fornecedor: fornecedor_id.to_string(),
+        codigo: params.codigo.trim().to_string(),
+        id_produto: params.id_produto.to_string(),
+        created_by: user.id.to_string(),
+        created_at: Utc::now().naive_utc(),
+        preferencial: params.preferencial,
+    };
+    let produto = block(move || salvar_produto(&pool, &vinculo)).await?;
+    respond_json(produto)
+}
+
+/// Unlink a fornecedor code
+pub async fn remove_fornecedor_produto(
+    path: Path<(Uuid, String)>,
+    pool: Data<PoolType>,
+) -> Result<HttpResponse, ApiError> {
+    let (fornecedor_id, codigo) = path.into_inner();
+    block(move || remover_produto(&pool, fornecedor_id, &codigo)).await?;
+    respond_ok()
+}
+
+/// Get the fornecedores of a produto, the preferred one first
+pub async fn get_produto_fornecedores(
+    produto_id: Path<Uuid>,
+    pool: Data<PoolType>,
+) -> Result<Json<FornecedorProdutosResponse>, ApiError> {
+    let fornecedores = block(move || find_by_produto(&pool, *produto_id)).await?;
+    respond_json(fornecedores)
+}
+
+impl From<Fornecedor> for FornecedorResponse {
+    fn from(fornecedor: Fornecedor) -> Self {
+        FornecedorResponse {
+            id: Uuid::parse_str(&fornecedor.id).unwrap(),
+            cnpj: fornecedor.cnpj,
+            razao_social: fornecedor.razao_social,
+            nome_fantasia: fornecedor.nome_fantasia,
+            inscricao_estadual: fornecedor.inscricao_estadual,
+            contato: fornecedor.contato,
+            email: fornecedor.email,
+            telefone: fornecedor.telefone,
+        }
+    }
+}
+
+impl From<Vec<Fornecedor>> for FornecedoresResponse {
+    fn from(fornecedores: Vec<Fornecedor>) -> Self {
+        FornecedoresResponse(fornecedores.into_par_iter().map(|fornecedor| fornecedor.into()).collect())
+    }
+}
+
+impl From<FornecedorProduto> for FornecedorProdutoResponse {
+    fn from(vinculo: FornecedorProduto) -> Self {
+        FornecedorProdutoResponse {
+            id_fornecedor: Uuid::parse_str(&vinculo.id_fornecedor).unwrap(),
+            codigo: vinculo.codigo,
+            id_produto: Uuid::parse_str(&vinculo.id_produto).unwrap(),
+            preferencial: vinculo.preferencial,
+            created_by: vinculo.created_by,
+            created_at: vinculo.created_at,
+        }
+    }
+}
+
+impl From<Vec<FornecedorProduto>> for FornecedorProdutosResponse {
+    fn from(vinculos: Vec<FornecedorProduto>) -> Self {
+        FornecedorProdutosResponse(vinculos.into_par_iter().map(|vinculo| vinculo.into()).collect())
+    }
+}
+
+///Testes
+///Testes
+///Testes
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::models::fornecedor::tests::create_fornecedor as model_create_fornecedor;
+    use crate::models::produto::delete as delete_produto;
+    use crate::models::produto::tests::create_produto;
+    use crate::tests::helpers::tests::{get_data_pool, get_pool};
+
+    fn auth_user() -> AuthUser {
+        AuthUser {
+            id: "10000000-0000-0000-0000-000000000000".into(),
+            email: "test@user.com".into(),
+        }
+    }
+
+    fn create_request(cnpj: &str) -> CreateFornecedorRequest {
+        CreateFornecedorRequest {
+            cnpj: cnpj.into(),
+            razao_social: "Distribuidora Handler Ltda".into(),
+            nome_fantasia: Some("Distribuidora".into()),
+            inscricao_estadual: Some("9012345678".into()),
+            contato: Some("João".into()),
+            email: Some("vendas@distribuidora.com".into()),
+            telefone: Some("4132221111".into()),
+        }
+    }
+
+    #[actix_rt::test]
+    async fn it_creates_a_fornecedor() {
+        let params = create_request("44.455.566/6000-24");
+        let response = create_fornecedor(get_data_pool(), Json(params.clone()), auth_user())
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(response.cnpj, "44455566600024");
+        assert_eq!(response.razao_social, params.razao_social);
+        delete(&get_pool(), response.id).unwrap();
+    }
+
+    #[actix_rt::test]
+    async fn it_doesnt_create_a_fornecedor_with_an_invalid_cnpj() {
+        let params = create_request("44455566600025");
+        let response = create_fornecedor(get_data_pool(), Json(params), auth_user()).await;
+        assert!(response.is_err());
+    }
+
+    #[actix_rt::test]
+    async fn it_doesnt_find_a_fornecedor() {
+        let uuid = Uuid::new_v4();
+        let response = get_fornecedor(uuid.into(), get_data_pool()).await;
+        let expected_error = ApiError::NotFound(format!("Fornecedor {} not found", uuid));
+        assert_eq!(response.unwrap_err(), expected_error);
+    }
+
+    #[actix_rt::test]
+    async fn it_updates_a_fornecedor() {
+        let created = model_create_fornecedor().unwrap();
+        let params = UpdateFornecedorRequest {
+            cnpj: "12345678000195".into(),
+            razao_social: "Fornecedor Atualizado Ltda".into(),
+            nome_fantasia: created.nome_fantasia.clone(),
+            inscricao_estadual: created.inscricao_estadual.clone(),
+            contato: created.contato.clone(),
+            email: created.email.clone(),
+            telefone: created.telefone.clone(),
+        };
+        let response = update_fornecedor(Path::from(created.id), get_data_pool(), Json(params), auth_user())
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(response.razao_social, "Fornecedor Atualizado Ltda");
+        let fornecedores = get_fornecedores(get_data_pool()).await.unwrap().into_inner();
+        assert!(fornecedores.0.contains(&response));
+        delete_fornecedor(Path::from(created.id), get_data_pool()).await.unwrap();
+    }
+
+    #[actix_rt::test]
+    async fn it_links_a_fornecedor_code_to_a_produto() {
+        let fornecedor = model_create_fornecedor().unwrap();
+        let produto = create_produto().unwrap();
+        let params = FornecedorProdutoRequest {
+            id_produto: produto.id,
+            codigo: " ABC-123 ".into(),
+            preferencial: true,
+        };
+        let vinculo = add_fornecedor_produto(Path::from(fornecedor.id), get_data_pool(), Json(params), auth_user())
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(vinculo.codigo, "ABC-123");
+
+        let produtos = get_fornecedor_produtos(Path::from(fornecedor.id), get_data_pool())
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(produtos.0.len(), 1);
+        assert_eq!(produtos.0[0].id_produto, vinculo.id_produto);
+        let fornecedores = get_produto_fornecedores(Path::from(produto.id), get_data_pool())
+            .await
+            .unwrap()
+            .into_inner();
+        assert!(fornecedores.0[0].preferencial);
+
+        remove_fornecedor_produto(Path::from((fornecedor.id, "ABC-123".to_string())), get_data_pool())
+            .await
+            .unwrap();
+        delete(&get_pool(), fornecedor.id).unwrap();
+        delete_produto(&get_pool(), produto.id).unwrap();
+    }
+}
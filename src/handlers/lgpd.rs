@@ -0,0 +1,153 @@
+use crate::config::CONFIG;
+use crate::database::PoolType;
+use crate::errors::ApiError;
+use crate::handlers::cliente::ClienteResponse;
+use crate::handlers::cliente_endereco::ClienteEnderecoResponse;
+use crate::handlers::nota_fiscal::NotaFiscalResponse;
+use crate::handlers::orcamento::{OrcamentoHistoricoResponse, OrcamentoResponse};
+use crate::handlers::pedido::PedidoResponse;
+use crate::handlers::titulo_receber::TituloReceberResponse;
+use crate::helpers::respond_json;
+use crate::models::cliente_duplicado::DocumentoRepetido;
+use crate::models::fiscal::ConfigFiscal;
+use crate::models::lgpd::{anonimizar, exportar, LgpdRegistro};
+use crate::models::user::AuthUser;
+use actix_web::web::{block, Data, HttpResponse, Json, Path};
+use chrono::NaiveDateTime;
+use serde::Serialize;
+use uuid::Uuid;
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub struct LgpdRegistroResponse {
+    pub id: Uuid,
+    pub operacao: String,
+    /// The user who answered the request
+    pub created_by: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub struct OrcamentoExportResponse {
+    pub orcamento: OrcamentoResponse,
+    pub historico: Vec<OrcamentoHistoricoResponse>,
+}
+
+/// A cpf or cnpj the cliente was registered with again, parked until merged
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub struct DocumentoRepetidoResponse {
+    pub cpf: Option<String>,
+    pub cnpj: Option<String>,
+}
+
+/// A nota with the XML it was issued with, which names the cliente
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub struct NotaFiscalExportResponse {
+    pub nota: NotaFiscalResponse,
+    pub xml: String,
+}
+
+/// Every personal data held on a cliente
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub struct LgpdExportResponse {
+    pub gerado_at: NaiveDateTime,
+    pub gerado_by: String,
+    pub cliente: ClienteResponse,
+    pub documento_repetido: Option<DocumentoRepetidoResponse>,
+    pub enderecos: Vec<ClienteEnderecoResponse>,
+    pub orcamentos: Vec<OrcamentoExportResponse>,
+    pub pedidos: Vec<PedidoResponse>,
+    pub notas_fiscais: Vec<NotaFiscalExportResponse>,
+    pub titulos: Vec<TituloReceberResponse>,
+    /// The LGPD requests of the cliente, this export included
+    pub registros: Vec<LgpdRegistroResponse>,
+}
+
+/// Download the personal data of a cliente as a JSON file
+pub async fn get_cliente_lgpd_export(
+    cliente_id: Path<Uuid>,
+    pool: Data<PoolType>,
+    user: AuthUser,
+) -> Result<HttpResponse, ApiError> {
+    let id = *cliente_id;
+    let config = ConfigFiscal::from_config(&CONFIG);
+    let export = block(move || exportar(&pool, *cliente_id, &user.id, &config)).await?;
+    Ok(HttpResponse::Ok()
+        .header("Content-Disposition", format!("attachment; filename=\"lgpd-{}.json\"", id))
+        .json(export))
+}
+
+/// Scrub the personal data of a cliente
+pub async fn anonimizar_cliente(
+    cliente_id: Path<Uuid>,
+    pool: Data<PoolType>,
+    user: AuthUser,
+) -> Result<Json<ClienteResponse>, ApiError> {
+    let cliente = block(move || anonimizar(&pool, *cliente_id, &user.id)).await?;
+    respond_json(cliente)
+}
+
+impl From<DocumentoRepetido> for DocumentoRepetidoResponse {
+    fn from(documento: DocumentoRepetido) -> Self {
+        DocumentoRepetidoResponse {
+            cpf: documento.cpf,
+            cnpj: documento.cnpj,
+        }
+    }
+}
+
+impl From<LgpdRegistro> for LgpdRegistroResponse {
+    fn from(registro: LgpdRegistro) -> Self {
+        LgpdRegistroResponse {
+            id: Uuid::parse_str(&registro.id).unwrap(),
+            operacao: registro.operacao,
+            created_by: registro.created_by,
+            created_at: registro.created_at,
+        }
+    }
+}
+
+///Testes
+///Testes
+///Testes
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::models::cliente::delete as delete_cliente;
+    use crate::models::cliente::tests::create_cliente;
+    use crate::models::lgpd::tests::delete_registros;
+    use crate::tests::helpers::tests::{get_data_pool, get_pool};
+
+    fn auth_user() -> AuthUser {
+        AuthUser {
+            id: "10000000-0000-0000-0000-000000000000".into(),
+            email: "test@user.com".into(),
+        }
+    }
+
+    #[actix_rt::test]
+    async fn it_exports_and_anonymizes_a_cliente() {
+        let cliente = create_cliente().unwrap();
+        let response = get_cliente_lgpd_export(Path::from(cliente.id), get_data_pool(), auth_user()).await;
+        assert!(response.is_ok());
+
+        let anonimo = anonimizar_cliente(Path::from(cliente.id), get_data_pool(), auth_user())
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(anonimo.cpf, None);
+        assert_eq!(anonimo.nome_exibicao, "Cliente Anonimizado");
+
+        delete_registros(cliente.id);
+        delete_cliente(&get_pool(), cliente.id).unwrap();
+    }
+
+    #[actix_rt::test]
+    async fn it_doesnt_export_or_anonymize_an_unknown_cliente() {
+        let cliente_id = Uuid::new_v4();
+        let response = get_cliente_lgpd_export(Path::from(cliente_id), get_data_pool(), auth_user()).await;
+        assert!(response.is_err());
+        let response = anonimizar_cliente(Path::from(cliente_id), get_data_pool(), auth_user()).await;
+        let expected_error = ApiError::NotFound(format!("Cliente {} not found", cliente_id));
+        assert_eq!(response.unwrap_err(), expected_error);
+    }
+}
@@ -4,4 +4,18 @@ pub mod user;
 pub mod cliente;
 pub mod produto;
 pub mod orcamento_produto;
-pub mod orcamento;
\ No newline at end of file
+pub mod orcamento;
+pub mod pedido;
+pub mod fiscal;
+pub mod nota_fiscal;
+pub mod deposito;
+pub mod estoque;
+pub mod nota_entrada;
+pub mod fornecedor;
+pub mod pedido_compra;
+pub mod cep;
+pub mod cliente_endereco;
+pub mod titulo_receber;
+pub mod credito;
+pub mod cliente_duplicado;
+pub mod lgpd;
\ No newline at end of file
@@ -0,0 +1,200 @@
+use crate::config::CONFIG;
+use crate::database::PoolType;
+use crate::errors::ApiError;
+use crate::helpers::respond_json;
+use crate::models::deposito::DEPOSITO_PADRAO;
+use crate::models::nota_entrada::{find, importar, ConfigImportacao, Importacao, NotaEntrada};
+use crate::models::user::AuthUser;
+use actix_web::web::{block, Data, Json, Path, Query};
+use bigdecimal::BigDecimal;
+use chrono::NaiveDateTime;
+use serde::Serialize;
+use uuid::Uuid;
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub struct NotaEntradaResponse {
+    pub id: Uuid,
+    pub chave: String,
+    pub serie: i32,
+    pub numero: i32,
+    pub id_fornecedor: Uuid,
+    pub emissao: NaiveDateTime,
+    pub valor_total: BigDecimal,
+    pub created_by: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub struct FornecedorImportacaoResponse {
+    /// None when the import would register the fornecedor
+    pub id: Option<Uuid>,
+    pub cnpj: String,
+    pub razao_social: String,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub struct ItemImportacaoResponse {
+    pub numero: u32,
+    pub codigo: String,
+    pub codigo_de_barras: Option<String>,
+    pub descricao: String,
+    pub quantidade: i32,
+    pub custo_unitario: BigDecimal,
+    /// None when the import would create a rascunho for the item
+    pub id_produto: Option<Uuid>,
+    pub correspondencia: String,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub struct ImportacaoResponse {
+    /// None for a preview, nothing was stored
+    pub id: Option<Uuid>,
+    pub chave: String,
+    pub serie: i32,
+    pub numero: i32,
+    pub emissao: NaiveDateTime,
+    pub valor_total: BigDecimal,
+    pub fornecedor: FornecedorImportacaoResponse,
+    pub itens: Vec<ItemImportacaoResponse>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ImportacaoRequest {
+    /// Only show how the items match, without importing
+    #[serde(default)]
+    pub preview: bool,
+    /// Defaults to the main depósito
+    pub id_deposito: Option<Uuid>,
+}
+
+/// Get an imported purchase nota
+pub async fn get_nota_entrada(
+    nota_id: Path<Uuid>,
+    pool: Data<PoolType>,
+) -> Result<Json<NotaEntradaResponse>, ApiError> {
+    let nota = block(move || find(&pool, *nota_id)).await?;
+    respond_json(nota)
+}
+
+/// Import the XML of a purchase NF-e sent as the request body
+pub async fn importar_nota_entrada(
+    pool: Data<PoolType>,
+    params: Query<ImportacaoRequest>,
+    xml: String,
+    user: AuthUser,
+) -> Result<Json<ImportacaoResponse>, ApiError> {
+    let deposito_id = params
+        .id_deposito
+        .map(|id_deposito| id_deposito.to_string())
+        .unwrap_or_else(|| DEPOSITO_PADRAO.to_string());
+    let config = ConfigImportacao::from_config(&CONFIG);
+    let importacao =
+        block(move || importar(&pool, &xml, &deposito_id, &config, params.preview, &user.id)).await?;
+    respond_json(importacao)
+}
+
+impl From<NotaEntrada> for NotaEntradaResponse {
+    fn from(nota: NotaEntrada) -> Self {
+        NotaEntradaResponse {
+            id: Uuid::parse_str(&nota.id).unwrap(),
+            chave: nota.chave,
+            serie: nota.serie,
+            numero: nota.numero,
+            id_fornecedor: Uuid::parse_str(&nota.id_fornecedor).unwrap(),
+            emissao: nota.emissao,
+            valor_total: nota.valor_total,
+            created_by: nota.created_by,
+            created_at: nota.created_at,
+        }
+    }
+}
+
+impl From<Importacao> for ImportacaoResponse {
+    fn from(importacao: Importacao) -> Self {
+        let nota = importacao.nota;
+        ImportacaoResponse {
+            id: importacao.id.map(|id| Uuid::parse_str(&id).unwrap()),
+            chave: nota.chave,
+            serie: nota.serie,
+            numero: nota.numero,
+            emissao: nota.emissao,
+            valor_total: nota.valor_total,
+            fornecedor: FornecedorImportacaoResponse {
+                id: importacao.id_fornecedor.map(|id| Uuid::parse_str(&id).unwrap()),
+                cnpj: nota.emitente.cnpj,
+                razao_social: nota.emitente.razao_social,
+            },
+            itens: importacao
+                .itens
+                .into_iter()
+                .map(|(item, id_produto, correspondencia)| ItemImportacaoResponse {
+                    numero: item.numero,
+                    codigo: item.codigo,
+                    codigo_de_barras: item.codigo_de_barras,
+                    descricao: item.descricao,
+                    quantidade: item.quantidade,
+                    custo_unitario: item.custo_unitario,
+                    id_produto: id_produto.map(|id| Uuid::parse_str(&id).unwrap()),
+                    correspondencia: correspondencia.as_str().to_string(),
+                })
+                .collect(),
+        }
+    }
+}
+
+///Testes
+///Testes
+///Testes
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::models::nota_entrada::tests::{delete_importacao, xml_compra};
+    use crate::tests::helpers::tests::get_data_pool;
+
+    fn auth_user() -> AuthUser {
+        AuthUser {
+            id: "10000000-0000-0000-0000-000000000000".into(),
+            email: "test@user.com".into(),
+        }
+    }
+
+    fn params(preview: bool) -> Query<ImportacaoRequest> {
+        Query(ImportacaoRequest {
+            preview,
+            id_deposito: None,
+        })
+    }
+
+    #[actix_rt::test]
+    async fn it_previews_and_imports_a_purchase_nota() {
+        let numero = Uuid::new_v4().as_fields().0 % 999_999_999;
+        let codigo = format!("HANDLER-{}", numero);
+        let xml = xml_compra(numero, "99888777000247", "", &codigo);
+
+        let preview = importar_nota_entrada(get_data_pool(), params(true), xml.clone(), auth_user())
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(preview.id, None);
+        assert_eq!(preview.fornecedor.id, None);
+        assert!(preview.itens.iter().all(|item| item.id_produto.is_none()));
+
+        let importacao = importar_nota_entrada(get_data_pool(), params(false), xml, auth_user())
+            .await
+            .unwrap()
+            .into_inner();
+        let nota = get_nota_entrada(Path::from(importacao.id.unwrap()), get_data_pool())
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(nota.chave, preview.chave);
+        assert_eq!(Some(nota.id_fornecedor), importacao.fornecedor.id);
+        delete_importacao(&importacao);
+    }
+
+    #[actix_rt::test]
+    async fn it_doesnt_import_what_isnt_a_nota() {
+        let response = importar_nota_entrada(get_data_pool(), params(true), "nota".into(), auth_user()).await;
+        assert!(response.is_err());
+    }
+}
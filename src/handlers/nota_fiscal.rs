@@ -0,0 +1,153 @@
+use crate::config::CONFIG;
+use crate::database::PoolType;
+use crate::errors::ApiError;
+use crate::helpers::respond_json;
+use crate::models::nfe::{AssinadorLocal, ConfigNfe, TransmissorLocal, AMBIENTE_HOMOLOGACAO};
+use crate::models::nota_fiscal::{find, find_danfe, find_xml, gerar_de_orcamento, gerar_de_pedido, transmitir, NotaFiscal};
+use crate::models::user::AuthUser;
+use actix_web::web::{block, Data, HttpResponse, Json, Path};
+use chrono::NaiveDateTime;
+use serde::Serialize;
+use uuid::Uuid;
+
+/// A nota without its XML, which is served on its own
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub struct NotaFiscalResponse {
+    pub id: Uuid,
+    pub id_pedido: Option<Uuid>,
+    pub id_orcamento: Option<Uuid>,
+    pub serie: i32,
+    pub numero: i32,
+    pub chave: String,
+    pub ambiente: i16,
+    pub status: String,
+    pub protocolo: Option<String>,
+    pub motivo: Option<String>,
+    pub created_by: String,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+/// Issue the NF-e of a pedido
+pub async fn create_pedido_nfe(
+    pedido_id: Path<Uuid>,
+    pool: Data<PoolType>,
+    user: AuthUser,
+) -> Result<Json<NotaFiscalResponse>, ApiError> {
+    let config = ConfigNfe::from_config(&CONFIG)?;
+    let nota = block(move || gerar_de_pedido(&pool, *pedido_id, &config, &user.id)).await?;
+    respond_json(nota)
+}
+
+/// Issue the NF-e of an aprovado or convertido orcamento
+pub async fn create_orcamento_nfe(
+    orcamento_id: Path<Uuid>,
+    pool: Data<PoolType>,
+    user: AuthUser,
+) -> Result<Json<NotaFiscalResponse>, ApiError> {
+    let config = ConfigNfe::from_config(&CONFIG)?;
+    let nota = block(move || gerar_de_orcamento(&pool, *orcamento_id, &config, &user.id)).await?;
+    respond_json(nota)
+}
+
+/// Get a nota fiscal
+pub async fn get_nota_fiscal(
+    nota_id: Path<Uuid>,
+    pool: Data<PoolType>,
+) -> Result<Json<NotaFiscalResponse>, ApiError> {
+    let nota = block(move || find(&pool, *nota_id)).await?;
+    respond_json(nota)
+}
+
+/// Download the XML of a nota fiscal
+pub async fn get_nota_fiscal_xml(
+    nota_id: Path<Uuid>,
+    pool: Data<PoolType>,
+) -> Result<HttpResponse, ApiError> {
+    let xml = block(move || find_xml(&pool, *nota_id)).await?;
+    Ok(HttpResponse::Ok().content_type("application/xml").body(xml))
+}
+
+/// Download the DANFE of a nota fiscal as a PDF
+pub async fn get_nota_fiscal_danfe(
+    nota_id: Path<Uuid>,
+    pool: Data<PoolType>,
+) -> Result<HttpResponse, ApiError> {
+    let id = *nota_id;
+    let pdf = block(move || find_danfe(&pool, *nota_id)).await?;
+    Ok(HttpResponse::Ok()
+        .content_type("application/pdf")
+        .header("Content-Disposition", format!("inline; filename=\"danfe-{}.pdf\"", id))
+        .body(pdf))
+}
+
+/// Sign and send a nota fiscal for authorization
+///
+/// Only homologação is available, answered by the local stand-ins until a
+/// certificate and the SEFAZ web services are wired in.
+pub async fn transmitir_nota_fiscal(
+    nota_id: Path<Uuid>,
+    pool: Data<PoolType>,
+) -> Result<Json<NotaFiscalResponse>, ApiError> {
+    if CONFIG.nfe_ambiente != AMBIENTE_HOMOLOGACAO {
+        return Err(ApiError::BadRequest(
+            "A transmissão de NF-e em produção ainda não está disponível".to_string(),
+        ));
+    }
+    let nota = block(move || transmitir(&pool, *nota_id, &AssinadorLocal, &TransmissorLocal)).await?;
+    respond_json(nota)
+}
+
+impl From<NotaFiscal> for NotaFiscalResponse {
+    fn from(nota: NotaFiscal) -> Self {
+        NotaFiscalResponse {
+            id: Uuid::parse_str(&nota.id).unwrap(),
+            id_pedido: nota.id_pedido.map(|id_pedido| Uuid::parse_str(&id_pedido).unwrap()),
+            id_orcamento: nota
+                .id_orcamento
+                .map(|id_orcamento| Uuid::parse_str(&id_orcamento).unwrap()),
+            serie: nota.serie,
+            numero: nota.numero,
+            chave: nota.chave,
+            ambiente: nota.ambiente,
+            status: nota.status,
+            protocolo: nota.protocolo,
+            motivo: nota.motivo,
+            created_by: nota.created_by,
+            created_at: nota.created_at,
+            updated_at: nota.updated_at,
+        }
+    }
+}
+
+///Testes
+///Testes
+///Testes
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::tests::helpers::tests::get_data_pool;
+
+    fn auth_user() -> AuthUser {
+        AuthUser {
+            id: "10000000-0000-0000-0000-000000000000".into(),
+            email: "test@user.com".into(),
+        }
+    }
+
+    #[actix_rt::test]
+    async fn it_doesnt_find_a_nota_fiscal() {
+        let uuid = Uuid::new_v4();
+        let response = get_nota_fiscal(uuid.into(), get_data_pool()).await;
+        let expected_error = ApiError::NotFound(format!("Nota fiscal {} not found", uuid));
+        assert_eq!(response.unwrap_err(), expected_error);
+    }
+
+    #[actix_rt::test]
+    async fn it_doesnt_invoice_a_missing_pedido() {
+        let uuid = Uuid::new_v4();
+        let response = create_pedido_nfe(uuid.into(), get_data_pool(), auth_user()).await;
+        let expected_error = ApiError::NotFound(format!("Pedido {} not found", uuid));
+        assert_eq!(response.unwrap_err(), expected_error);
+    }
+}
@@ -1,10 +1,20 @@
+use crate::config::CONFIG;
 use crate::database::PoolType;
 use crate::errors::ApiError;
 use crate::helpers::{respond_json, respond_ok};
-use crate::models::orcamento::{create, delete, find, NewOrcamento, Orcamento};
-use crate::models::orcamento_produto::{create as create_orcamento_produto, 
-    delete as delete_orcamento_produto, 
-    find as find_orcamento_produto, NewOrcamentoProduto, OrcamentoProduto};
+use crate::handlers::pedido::PedidoResponse;
+use crate::models::cliente::find as find_cliente;
+use crate::models::desconto::TipoDesconto;
+use crate::models::estoque::ConfigEstoque;
+use crate::models::fiscal::{ConfigFiscal, Impostos, TotalImpostos};
+use crate::models::orcamento::{
+    converter, create, delete, find, find_by_cliente, find_pdf, transition, update, NewOrcamento, Orcamento,
+    OrcamentoItem, OrcamentoStatus, OrcamentoValorado, UpdateOrcamento, ValoresItem,
+};
+use crate::models::orcamento_pdf::Empresa;
+use crate::models::orcamento_historico::{find_by_orcamento, OrcamentoHistorico};
+use crate::models::user::AuthUser;
+use crate::models::orcamento_produto::{NewOrcamentoProduto, OrcamentoProduto};
 use crate::validate::validate;
 use actix_web::web::{block, Data, HttpResponse, Json, Path};
 use rayon::prelude::*;
@@ -12,20 +22,118 @@ use serde::Serialize;
 use uuid::Uuid;
 use validator::Validate;
 use chrono::{NaiveDateTime, NaiveDate, NaiveTime};
-use std::thread;
+use bigdecimal::BigDecimal;
 
 #[derive(Debug, Deserialize, Serialize, PartialEq)]
 pub struct OrcamentoResponse {
     pub id: Uuid,
+    pub id_cliente: Uuid,
+    pub created_by: String,
+    pub created_at: NaiveDateTime,
+    pub updated_by: String,
+    pub updated_at: NaiveDateTime,
+    pub status: String,
+    pub validade: Option<NaiveDateTime>,
+    pub id_pedido: Option<Uuid>,
+    pub tipo_desconto: Option<String>,
+    pub valor_desconto: BigDecimal,
+    pub observacoes: Option<String>,
+    pub itens: Vec<OrcamentoItemResponse>,
+    pub total_bruto: BigDecimal,
+    pub total_desconto: BigDecimal,
+    pub total_liquido: BigDecimal,
+    pub impostos: TotalImpostos,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub struct OrcamentoItemResponse {
+    pub id: Uuid,
+    pub id_produto: Uuid,
+    pub descricao: String,
+    pub unidade_medida: Option<String>,
+    pub ncm: Option<String>,
+    pub cest: Option<String>,
+    pub icms: Option<String>,
+    pub pis: Option<String>,
+    pub cofins: Option<String>,
+    pub porcentagem_ipi: Option<BigDecimal>,
+    pub preco_unitario: BigDecimal,
     pub quantidade: i32,
+    pub tipo_desconto: Option<String>,
+    pub valor_desconto: BigDecimal,
+    pub bruto: BigDecimal,
+    pub desconto: BigDecimal,
+    pub liquido: BigDecimal,
+    pub impostos: Impostos,
 }
 
 #[derive(Debug, Deserialize, Serialize, PartialEq)]
 pub struct OrcamentosResponse(pub Vec<OrcamentoResponse>);
 
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub struct OrcamentoHistoricoResponse {
+    pub id: Uuid,
+    pub status_anterior: Option<String>,
+    pub status_novo: String,
+    pub created_by: String,
+    pub created_at: NaiveDateTime,
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize, Validate)]
 pub struct CreateOrcamentoRequest {
-    produtos: Vec<OrcamentoProduto>,
+    pub id_cliente: Uuid,
+
+    #[validate(length(
+        min = 1,
+        message = "O orçamento deve ter ao menos um produto"
+    ))]
+    pub produtos: Vec<CreateOrcamentoItemRequest>,
+
+    pub validade: Option<NaiveDateTime>,
+
+    pub desconto: Option<DescontoRequest>,
+
+    #[validate(length(
+        max = 2000,
+        message = "As observações devem ter no máximo 2000 caracteres"
+    ))]
+    pub observacoes: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct CreateOrcamentoItemRequest {
+    pub id_produto: Uuid,
+    pub quantidade: i32,
+    pub desconto: Option<DescontoRequest>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, Validate)]
+pub struct UpdateOrcamentoRequest {
+    pub validade: Option<NaiveDateTime>,
+    pub desconto: Option<DescontoRequest>,
+
+    #[validate(length(
+        max = 2000,
+        message = "As observações devem ter no máximo 2000 caracteres"
+    ))]
+    pub observacoes: Option<String>,
+}
+
+/// A discount, either a percentual of the value or an absolute valor
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct DescontoRequest {
+    pub tipo: TipoDesconto,
+    pub valor: BigDecimal,
+}
+
+impl DescontoRequest {
+    /// Split an optional discount into its tipo_desconto and valor_desconto columns
+    pub fn columns(desconto: &Option<DescontoRequest>) -> (Option<String>, BigDecimal) {
+        match desconto {
+            Some(desconto) => (Some(desconto.tipo.as_str().to_string()), desconto.valor.clone()),
+            None => (None, BigDecimal::from(0)),
+        }
+    }
 }
 
 
@@ -34,42 +142,177 @@ pub async fn get_orcamento(
     orcamento_id: Path<Uuid>,
     pool: Data<PoolType>,
 ) -> Result<Json<OrcamentoResponse>, ApiError> {
-    let orcamento = block(move || find(&pool, *orcamento_id)).await?;
+    let config = ConfigFiscal::from_config(&CONFIG);
+    let orcamento = block(move || find(&pool, *orcamento_id, &config)).await?;
     respond_json(orcamento)
 }
 
+/// Download a orcamento as a PDF to send to the cliente
+pub async fn get_orcamento_pdf(
+    orcamento_id: Path<Uuid>,
+    pool: Data<PoolType>,
+) -> Result<HttpResponse, ApiError> {
+    let id = *orcamento_id;
+    // reading the logo touches the disk, so it stays off the async thread
+    let pdf = block(move || {
+        let empresa = Empresa::from_config(&CONFIG)?;
+        find_pdf(&pool, *orcamento_id, &empresa, &ConfigFiscal::from_config(&CONFIG))
+    })
+    .await?;
+    Ok(HttpResponse::Ok()
+        .content_type("application/pdf")
+        .header("Content-Disposition", format!("inline; filename=\"orcamento-{}.pdf\"", id))
+        .body(pdf))
+}
+
+/// Get all orcamentos of a cliente
+pub async fn get_cliente_orcamentos(
+    cliente_id: Path<Uuid>,
+    pool: Data<PoolType>,
+) -> Result<Json<OrcamentosResponse>, ApiError> {
+    let config = ConfigFiscal::from_config(&CONFIG);
+    let orcamentos = block(move || {
+        find_cliente(&pool, *cliente_id)?;
+        find_by_cliente(&pool, *cliente_id, &config)
+    })
+    .await?;
+    respond_json(orcamentos)
+}
+
 /// Create a orcamento
 pub async fn create_orcamento(
     pool: Data<PoolType>,
     params: Json<CreateOrcamentoRequest>,
+    user: AuthUser,
 ) -> Result<Json<OrcamentoResponse>, ApiError> {
     validate(&params)?;
+
     let orcamento_id = Uuid::new_v4();
+    let (tipo_desconto, valor_desconto) = DescontoRequest::columns(&params.desconto);
     let new_orcamento: Orcamento = NewOrcamento {
         id: orcamento_id.to_string(),
-        created_by: orcamento_id.to_string(),
+        id_cliente: params.id_cliente.to_string(),
+        created_by: user.id.to_string(),
+        validade: params.validade,
+        tipo_desconto,
+        valor_desconto,
+        observacoes: params.observacoes.clone(),
     }
     .into();
-    let new_pool = pool.clone();
-    let orcamento = block(move || create(&pool, &new_orcamento)).await?;
-    // temporarily use the new orcamento's id for created_at/updated_at
-    // update when auth is added
-    params.produtos.iter().for_each(|params|{
-        let new_pool = new_pool.clone();
-        let orcamento_produto_id = Uuid::new_v4();
-        let new_orcamento_produto: OrcamentoProduto = NewOrcamentoProduto {
-            id: orcamento_produto_id.to_string(),
-            id_produto: params.id_produto.to_string(),
-            id_orcamento: params.id_orcamento.to_string(),
-            quantidade: params.quantidade,
-            created_by: orcamento_produto_id.to_string(),
-        }
-        .into();
-        create_orcamento_produto(&new_pool, &new_orcamento_produto);
-    });
+    let new_itens: Vec<OrcamentoProduto> = params
+        .produtos
+        .iter()
+        .map(|item| {
+            let (tipo_desconto, valor_desconto) = DescontoRequest::columns(&item.desconto);
+            NewOrcamentoProduto {
+                id: Uuid::new_v4().to_string(),
+                id_produto: item.id_produto.to_string(),
+                id_orcamento: orcamento_id.to_string(),
+                quantidade: item.quantidade,
+                created_by: user.id.to_string(),
+                tipo_desconto,
+                valor_desconto,
+            }
+            .into()
+        })
+        .collect();
+    let config = ConfigFiscal::from_config(&CONFIG);
+    let orcamento = block(move || create(&pool, &new_orcamento, &new_itens, &config)).await?;
+    respond_json(orcamento)
+}
+
+/// Update a rascunho orcamento
+pub async fn update_orcamento(
+    orcamento_id: Path<Uuid>,
+    pool: Data<PoolType>,
+    params: Json<UpdateOrcamentoRequest>,
+    user: AuthUser,
+) -> Result<Json<OrcamentoResponse>, ApiError> {
+    validate(&params)?;
+
+    let (tipo_desconto, valor_desconto) = DescontoRequest::columns(&params.desconto);
+    let update_orcamento = UpdateOrcamento {
+        id: orcamento_id.to_string(),
+        validade: params.validade,
+        tipo_desconto,
+        valor_desconto,
+        observacoes: params.observacoes.clone(),
+        updated_by: user.id.to_string(),
+    };
+    let config = ConfigFiscal::from_config(&CONFIG);
+    let orcamento = block(move || update(&pool, &update_orcamento, &config)).await?;
+    respond_json(orcamento)
+}
+
+/// Get the status history of a orcamento
+pub async fn get_orcamento_historico(
+    orcamento_id: Path<Uuid>,
+    pool: Data<PoolType>,
+) -> Result<Json<Vec<OrcamentoHistoricoResponse>>, ApiError> {
+    let historico = block(move || {
+        let mut conn = pool.get()?;
+        find_by_orcamento(&mut conn, &orcamento_id.to_string())
+    })
+    .await?;
+    respond_json(historico)
+}
+
+/// Send a rascunho orcamento to the cliente
+pub async fn enviar_orcamento(
+    orcamento_id: Path<Uuid>,
+    pool: Data<PoolType>,
+    user: AuthUser,
+) -> Result<Json<OrcamentoResponse>, ApiError> {
+    change_status(orcamento_id, pool, user, OrcamentoStatus::Enviado).await
+}
+
+/// Mark a sent orcamento as approved by the cliente
+pub async fn aprovar_orcamento(
+    orcamento_id: Path<Uuid>,
+    pool: Data<PoolType>,
+    user: AuthUser,
+) -> Result<Json<OrcamentoResponse>, ApiError> {
+    change_status(orcamento_id, pool, user, OrcamentoStatus::Aprovado).await
+}
 
-    
-    respond_json(orcamento.into())
+/// Mark a sent orcamento as rejected by the cliente
+pub async fn recusar_orcamento(
+    orcamento_id: Path<Uuid>,
+    pool: Data<PoolType>,
+    user: AuthUser,
+) -> Result<Json<OrcamentoResponse>, ApiError> {
+    change_status(orcamento_id, pool, user, OrcamentoStatus::Recusado).await
+}
+
+/// Expire an open orcamento before its validade
+pub async fn expirar_orcamento(
+    orcamento_id: Path<Uuid>,
+    pool: Data<PoolType>,
+    user: AuthUser,
+) -> Result<Json<OrcamentoResponse>, ApiError> {
+    change_status(orcamento_id, pool, user, OrcamentoStatus::Expirado).await
+}
+
+/// Turn an approved orcamento into a pedido
+pub async fn converter_orcamento(
+    orcamento_id: Path<Uuid>,
+    pool: Data<PoolType>,
+    user: AuthUser,
+) -> Result<Json<PedidoResponse>, ApiError> {
+    let config = ConfigEstoque::from_config(&CONFIG);
+    let pedido = block(move || converter(&pool, *orcamento_id, &user.id, &config)).await?;
+    respond_json(pedido)
+}
+
+async fn change_status(
+    orcamento_id: Path<Uuid>,
+    pool: Data<PoolType>,
+    user: AuthUser,
+    destino: OrcamentoStatus,
+) -> Result<Json<OrcamentoResponse>, ApiError> {
+    let config = ConfigFiscal::from_config(&CONFIG);
+    let orcamento = block(move || transition(&pool, *orcamento_id, destino, &user.id, &config)).await?;
+    respond_json(orcamento)
 }
 
 /// Delete a orcamento
@@ -81,17 +324,80 @@ pub async fn delete_orcamento(
     respond_ok()
 }
 
-impl From<Orcamento> for OrcamentoResponse {
-    fn from(orcamento: Orcamento) -> Self {
+impl From<(OrcamentoItem, ValoresItem, Impostos)> for OrcamentoItemResponse {
+    fn from(((orcamento_produto, _), valores, impostos): (OrcamentoItem, ValoresItem, Impostos)) -> Self {
+        OrcamentoItemResponse {
+            id: Uuid::parse_str(&orcamento_produto.id).unwrap(),
+            id_produto: Uuid::parse_str(&orcamento_produto.id_produto).unwrap(),
+            descricao: orcamento_produto.descricao,
+            unidade_medida: orcamento_produto.unidade_medida,
+            ncm: orcamento_produto.ncm,
+            cest: orcamento_produto.cest,
+            icms: orcamento_produto.icms,
+            pis: orcamento_produto.pis,
+            cofins: orcamento_produto.cofins,
+            porcentagem_ipi: orcamento_produto.porcentagem_ipi,
+            preco_unitario: orcamento_produto.preco_unitario,
+            quantidade: orcamento_produto.quantidade,
+            tipo_desconto: orcamento_produto.tipo_desconto,
+            valor_desconto: orcamento_produto.valor_desconto,
+            bruto: valores.bruto,
+            desconto: valores.desconto,
+            liquido: valores.liquido,
+            impostos,
+        }
+    }
+}
+
+impl From<OrcamentoValorado> for OrcamentoResponse {
+    fn from((orcamento, itens): OrcamentoValorado) -> Self {
+        let total_bruto = itens
+            .iter()
+            .fold(BigDecimal::from(0), |total, (_, valor, _)| total + &valor.bruto);
+        let total_desconto = itens
+            .iter()
+            .fold(BigDecimal::from(0), |total, (_, valor, _)| total + &valor.desconto + &valor.rateio);
+        let total_liquido = &total_bruto - &total_desconto;
+        let impostos = TotalImpostos::somar(itens.iter().map(|(_, _, impostos)| impostos));
+        let itens: Vec<OrcamentoItemResponse> = itens.into_iter().map(|item| item.into()).collect();
         OrcamentoResponse {
             id: Uuid::parse_str(&orcamento.id).unwrap(),
-            quantidade: 1i32,
+            id_cliente: Uuid::parse_str(&orcamento.id_cliente).unwrap(),
+            created_by: orcamento.created_by,
+            created_at: orcamento.created_at,
+            updated_by: orcamento.updated_by,
+            updated_at: orcamento.updated_at,
+            status: orcamento.status,
+            validade: orcamento.validade,
+            id_pedido: orcamento
+                .id_pedido
+                .map(|id_pedido| Uuid::parse_str(&id_pedido).unwrap()),
+            tipo_desconto: orcamento.tipo_desconto,
+            valor_desconto: orcamento.valor_desconto,
+            observacoes: orcamento.observacoes,
+            itens,
+            total_bruto,
+            total_desconto,
+            total_liquido,
+            impostos,
+        }
+    }
+}
+
+impl From<OrcamentoHistorico> for OrcamentoHistoricoResponse {
+    fn from(historico: OrcamentoHistorico) -> Self {
+        OrcamentoHistoricoResponse {
+            id: Uuid::parse_str(&historico.id).unwrap(),
+            status_anterior: historico.status_anterior,
+            status_novo: historico.status_novo,
+            created_by: historico.created_by,
+            created_at: historico.created_at,
         }
     }
 }
 
-impl From<Vec<Orcamento>> for OrcamentosResponse {
-    fn from(orcamentos: Vec<Orcamento>) -> Self {
+impl From<Vec<OrcamentoValorado>> for OrcamentosResponse {
+    fn from(orcamentos: Vec<OrcamentoValorado>) -> Self {
         OrcamentosResponse(orcamentos.into_par_iter().map(|orcamento| orcamento.into()).collect())
     }
 }
@@ -106,6 +412,8 @@ pub mod tests {
     use super::*;
     use crate::models::orcamento::tests::create_orcamento as model_create_orcamento;
     use crate::models::orcamento::delete as model_delete;
+    use crate::handlers::pedido::get_pedido;
+    use crate::models::pedido::delete as delete_pedido;
     use crate::tests::helpers::tests::{get_data_pool, get_pool};
 
     #[actix_rt::test]
@@ -115,6 +423,41 @@ pub mod tests {
         assert!(response.is_ok());
     }
 
+    #[actix_rt::test]
+    async fn it_gets_a_orcamento_pdf() {
+        let orcamento_id = Path::from(Uuid::from_str("00000000-0000-0000-0000-000000000000").unwrap());
+        let response = get_orcamento_pdf(orcamento_id, get_data_pool()).await;
+        assert!(response.is_ok());
+    }
+
+    #[actix_rt::test]
+    async fn it_totals_the_lines_of_a_orcamento() {
+        let orcamento_id = Path::from(Uuid::from_str("00000000-0000-0000-0000-000000000000").unwrap());
+        let response = get_orcamento(orcamento_id, get_data_pool()).await.unwrap().into_inner();
+        assert!(!response.itens.is_empty());
+        let item = &response.itens[0];
+        assert_eq!(item.bruto, &item.preco_unitario * BigDecimal::from(item.quantidade));
+        assert_eq!(item.liquido, &item.bruto - &item.desconto);
+        let total = response
+            .itens
+            .iter()
+            .fold(BigDecimal::from(0), |total, item| total + &item.bruto);
+        assert_eq!(response.total_bruto, total);
+        assert_eq!(response.total_liquido, &response.total_bruto - &response.total_desconto);
+    }
+
+    #[actix_rt::test]
+    async fn it_totals_the_taxes_of_a_orcamento() {
+        let orcamento_id = Path::from(Uuid::from_str("00000000-0000-0000-0000-000000000000").unwrap());
+        let response = get_orcamento(orcamento_id, get_data_pool()).await.unwrap().into_inner();
+        let valor_icms = response
+            .itens
+            .iter()
+            .fold(BigDecimal::from(0), |total, item| total + &item.impostos.icms.valor);
+        assert_eq!(response.impostos.valor_icms, valor_icms);
+        assert_eq!(response.itens[0].impostos.icms.base_calculo, response.itens[0].liquido);
+    }
+
     #[actix_rt::test]
     async fn it_doesnt_find_a_orcamento() {
         let uuid = Uuid::new_v4();
@@ -127,21 +470,153 @@ pub mod tests {
 
     #[actix_rt::test]
     async fn it_creates_a_orcamento() {
-        let orcamento_produto: OrcamentoProduto = NewOrcamentoProduto{
-            id: Uuid::new_v4().to_string(),
-            id_produto: "00000000-0000-0000-0000-000000000000".into(),
-            id_orcamento: "00000000-0000-0000-0000-000000000000".into(),
-            quantidade: 1,
-            created_by: "00000000-0000-0000-0000-000000000000".into(),
-        }.into();
-        let orcamentos_produtos = vec![orcamento_produto];
         let params = Json(CreateOrcamentoRequest {
-            produtos: orcamentos_produtos,
+            id_cliente: Uuid::from_str("00000000-0000-0000-0000-000000000000").unwrap(),
+            produtos: vec![CreateOrcamentoItemRequest {
+                id_produto: Uuid::from_str("00000000-0000-0000-0000-000000000000").unwrap(),
+                quantidade: 3,
+                desconto: None,
+            }],
+            validade: None,
+            desconto: None,
+            observacoes: None,
+        });
+        let response = create_orcamento(get_data_pool(), Json(params.clone()), auth_user())
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(response.itens.len(), 1);
+        assert_eq!(response.itens[0].quantidade, 3);
+        assert_eq!(response.created_by, auth_user().id);
+        delete(&get_data_pool(), response.id).unwrap();
+    }
+
+    #[actix_rt::test]
+    async fn it_creates_a_orcamento_with_discounts() {
+        let params = Json(CreateOrcamentoRequest {
+            id_cliente: Uuid::from_str("00000000-0000-0000-0000-000000000000").unwrap(),
+            produtos: vec![CreateOrcamentoItemRequest {
+                id_produto: Uuid::from_str("00000000-0000-0000-0000-000000000000").unwrap(),
+                quantidade: 2,
+                desconto: Some(DescontoRequest {
+                    tipo: TipoDesconto::Valor,
+                    valor: BigDecimal::from(1),
+                }),
+            }],
+            validade: None,
+            desconto: Some(DescontoRequest {
+                tipo: TipoDesconto::Percentual,
+                valor: BigDecimal::from(5),
+            }),
+            observacoes: None,
+        });
+        let response = create_orcamento(get_data_pool(), params, auth_user())
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(response.itens[0].desconto, BigDecimal::from(1));
+        assert_eq!(response.total_liquido, &response.total_bruto - &response.total_desconto);
+        assert!(response.total_desconto > BigDecimal::from(1));
+        delete(&get_data_pool(), response.id).unwrap();
+    }
+
+    #[actix_rt::test]
+    async fn it_doesnt_create_a_orcamento_with_invalid_lines() {
+        let params = Json(CreateOrcamentoRequest {
+            id_cliente: Uuid::from_str("00000000-0000-0000-0000-000000000000").unwrap(),
+            produtos: vec![CreateOrcamentoItemRequest {
+                id_produto: Uuid::from_str("00000000-0000-0000-0000-000000000000").unwrap(),
+                quantidade: -1,
+                desconto: None,
+            }],
+            validade: None,
+            desconto: None,
+            observacoes: None,
         });
-        let response = create_orcamento(get_data_pool(), Json(params.clone()))
-            .await;
+        let response = create_orcamento(get_data_pool(), Json(params.clone()), auth_user()).await;
+        let expected_error = ApiError::ValidationError(vec![
+            "Item 1: a quantidade deve ser maior que zero".to_string(),
+        ]);
+        assert_eq!(response.unwrap_err(), expected_error);
+    }
+
+    fn auth_user() -> AuthUser {
+        AuthUser {
+            id: "10000000-0000-0000-0000-000000000000".into(),
+            email: "test@user.com".into(),
+        }
+    }
+
+    #[actix_rt::test]
+    async fn it_gets_the_orcamentos_of_a_cliente() {
+        let cliente_id = Path::from(Uuid::from_str("00000000-0000-0000-0000-000000000000").unwrap());
+        let response = get_cliente_orcamentos(cliente_id, get_data_pool()).await;
         assert!(response.is_ok());
-        delete(&get_data_pool(), response.unwrap().id);
+    }
+
+    #[actix_rt::test]
+    async fn it_sends_and_approves_a_orcamento() {
+        let created = model_create_orcamento().unwrap();
+        let sent = enviar_orcamento(created.id.into(), get_data_pool(), auth_user())
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(sent.status, "enviado");
+        let approved = aprovar_orcamento(created.id.into(), get_data_pool(), auth_user())
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(approved.status, "aprovado");
+        let historico = get_orcamento_historico(created.id.into(), get_data_pool())
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(historico.len(), 3);
+        assert_eq!(historico[2].created_by, auth_user().id);
+        delete(&get_pool(), created.id).unwrap();
+    }
+
+    #[actix_rt::test]
+    async fn it_doesnt_approve_a_rascunho() {
+        let created = model_create_orcamento().unwrap();
+        let response = aprovar_orcamento(created.id.into(), get_data_pool(), auth_user()).await;
+        let expected_error = ApiError::Conflict(format!(
+            "Orcamento {} cannot go from rascunho to aprovado",
+            created.id
+        ));
+        assert_eq!(response.unwrap_err(), expected_error);
+        delete(&get_pool(), created.id).unwrap();
+    }
+
+    #[actix_rt::test]
+    async fn it_converts_a_orcamento_into_a_pedido() {
+        let created = model_create_orcamento().unwrap();
+        enviar_orcamento(created.id.into(), get_data_pool(), auth_user()).await.unwrap();
+        aprovar_orcamento(created.id.into(), get_data_pool(), auth_user()).await.unwrap();
+        let pedido = converter_orcamento(created.id.into(), get_data_pool(), auth_user())
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(pedido.id_orcamento, Some(created.id));
+        assert_eq!(pedido.created_by, auth_user().id);
+        let response = get_pedido(pedido.id.into(), get_data_pool()).await.unwrap();
+        assert_eq!(response.into_inner(), pedido);
+        delete_pedido(&get_pool(), pedido.id).unwrap();
+        delete(&get_pool(), created.id).unwrap();
+    }
+
+    #[actix_rt::test]
+    async fn it_updates_a_orcamento() {
+        let created = model_create_orcamento().unwrap();
+        let validade = NaiveDate::from_ymd(2099, 1, 1).and_hms(0, 0, 0);
+        let params = Json(UpdateOrcamentoRequest { validade: Some(validade), desconto: None, observacoes: None });
+        let response = update_orcamento(created.id.into(), get_data_pool(), params, auth_user())
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(response.validade, Some(validade));
+        assert_eq!(response.updated_by, auth_user().id);
+        delete(&get_pool(), created.id).unwrap();
     }
 
     #[actix_rt::test]
@@ -149,10 +624,10 @@ pub mod tests {
         let created = model_create_orcamento();
         let orcamento_id = created.unwrap().id;
         let orcamento_id_path: Path<Uuid> = orcamento_id.into();
-        let orcamento = find(&get_pool(), orcamento_id);
+        let orcamento = find(&get_pool(), orcamento_id, &ConfigFiscal::default());
         assert!(orcamento.is_ok());
         delete_orcamento(orcamento_id_path, get_data_pool()).await.unwrap();
-        let orcamento = find(&get_pool(), orcamento_id);
+        let orcamento = find(&get_pool(), orcamento_id, &ConfigFiscal::default());
         assert!(orcamento.is_err());
     }
 }
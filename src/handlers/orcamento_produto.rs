@@ -1,7 +1,14 @@
+use crate::config::CONFIG;
 use crate::database::PoolType;
 use crate::errors::ApiError;
 use crate::helpers::{respond_json, respond_ok};
-use crate::models::orcamento_produto::{create, delete, find, NewOrcamentoProduto, OrcamentoProduto};
+use crate::handlers::orcamento::{CreateOrcamentoItemRequest, DescontoRequest, OrcamentoResponse};
+use crate::models::fiscal::ConfigFiscal;
+use crate::models::orcamento::{add_item, remove_item, update_item};
+use crate::models::orcamento_produto::{
+    create, delete, find, NewOrcamentoProduto, OrcamentoProduto, UpdateOrcamentoProduto,
+};
+use crate::models::user::AuthUser;
 use crate::validate::validate;
 use actix_web::web::{block, Data, HttpResponse, Json, Path};
 use rayon::prelude::*;
@@ -9,6 +16,7 @@ use serde::Serialize;
 use uuid::Uuid;
 use validator::Validate;
 use chrono::{NaiveDateTime, NaiveDate, NaiveTime};
+use bigdecimal::BigDecimal;
 
 #[derive(Debug, Deserialize, Serialize, PartialEq)]
 pub struct OrcamentoProdutoResponse {
@@ -28,6 +36,12 @@ pub struct CreateOrcamentoProdutoRequest {
     pub quantidade: i32,
 }
 
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct UpdateOrcamentoItemRequest {
+    pub quantidade: i32,
+    pub desconto: Option<DescontoRequest>,
+}
+
 
 /// Get a orcamento_produto
 pub async fn get_orcamento_produto(
@@ -54,6 +68,8 @@ pub async fn create_orcamento_produto(
         id_orcamento: params.id_orcamento.to_string(),
         quantidade: params.quantidade,
         created_by: orcamento_produto_id.to_string(),
+        tipo_desconto: None,
+        valor_desconto: BigDecimal::from(0),
     }
     .into();
     let orcamento_produto = block(move || create(&pool, &new_orcamento_produto)).await?;
@@ -69,13 +85,70 @@ pub async fn delete_orcamento_produto(
     respond_ok()
 }
 
+/// Add a line to a rascunho orcamento, returning the recomputed orcamento
+pub async fn add_orcamento_item(
+    orcamento_id: Path<Uuid>,
+    pool: Data<PoolType>,
+    params: Json<CreateOrcamentoItemRequest>,
+    user: AuthUser,
+) -> Result<Json<OrcamentoResponse>, ApiError> {
+    let (tipo_desconto, valor_desconto) = DescontoRequest::columns(&params.desconto);
+    let new_item: OrcamentoProduto = NewOrcamentoProduto {
+        id: Uuid::new_v4().to_string(),
+        id_produto: params.id_produto.to_string(),
+        id_orcamento: orcamento_id.to_string(),
+        quantidade: params.quantidade,
+        created_by: user.id.to_string(),
+        tipo_desconto,
+        valor_desconto,
+    }
+    .into();
+    let config = ConfigFiscal::from_config(&CONFIG);
+    let orcamento = block(move || add_item(&pool, &new_item, &config)).await?;
+    respond_json(orcamento)
+}
+
+/// Change the quantidade and discount of a line of a rascunho orcamento
+pub async fn update_orcamento_item(
+    path: Path<(Uuid, Uuid)>,
+    pool: Data<PoolType>,
+    params: Json<UpdateOrcamentoItemRequest>,
+    user: AuthUser,
+) -> Result<Json<OrcamentoResponse>, ApiError> {
+    let (orcamento_id, item_id) = path.into_inner();
+    let (tipo_desconto, valor_desconto) = DescontoRequest::columns(&params.desconto);
+    let update_orcamento_produto = UpdateOrcamentoProduto {
+        quantidade: params.quantidade,
+        tipo_desconto,
+        valor_desconto,
+    };
+    let config = ConfigFiscal::from_config(&CONFIG);
+    let orcamento = block(move || {
+        update_item(&pool, orcamento_id, item_id, &update_orcamento_produto, &user.id, &config)
+    })
+    .await?;
+    respond_json(orcamento)
+}
+
+/// Remove a line from a rascunho orcamento
+pub async fn remove_orcamento_item(
+    path: Path<(Uuid, Uuid)>,
+    pool: Data<PoolType>,
+    user: AuthUser,
+) -> Result<Json<OrcamentoResponse>, ApiError> {
+    let (orcamento_id, item_id) = path.into_inner();
+    let config = ConfigFiscal::from_config(&CONFIG);
+    let orcamento = block(move || remove_item(&pool, orcamento_id, item_id, &user.id, &config)).await?;
+    respond_json(orcamento)
+}
+
 impl From<OrcamentoProduto> for OrcamentoProdutoResponse {
     fn from(orcamento_produto: OrcamentoProduto) -> Self {
         OrcamentoProdutoResponse {
             id: Uuid::parse_str(&orcamento_produto.id).unwrap(),
             id_produto: Uuid::parse_str(&orcamento_produto.id_produto).unwrap(),
             id_orcamento: Uuid::parse_str(&orcamento_produto.id_orcamento).unwrap(),
-            quantidade: 1i32,
+            quantidade: orcamento_produto.quantidade,
         }
     }
 }
@@ -94,6 +167,8 @@ pub mod tests {
     use std::str::FromStr;
 
     use super::*;
+    use crate::models::orcamento::tests::create_orcamento as model_create_orcamento;
+    use crate::models::orcamento::delete as model_delete_orcamento;
     use crate::models::orcamento_produto::tests::create_orcamento_produto as model_create_orcamento_produto;
     use crate::models::orcamento_produto::delete as model_delete;
     use crate::tests::helpers::tests::{get_data_pool, get_pool};
@@ -141,4 +216,43 @@ pub mod tests {
         let orcamento_produto = find(&get_pool(), orcamento_produto_id);
         assert!(orcamento_produto.is_err());
     }
+
+    fn auth_user() -> AuthUser {
+        AuthUser {
+            id: "10000000-0000-0000-0000-000000000000".into(),
+            email: "test@user.com".into(),
+        }
+    }
+
+    #[actix_rt::test]
+    async fn it_edits_the_lines_of_a_orcamento() {
+        let created = model_create_orcamento().unwrap();
+        let params = Json(CreateOrcamentoItemRequest {
+            id_produto: Uuid::from_str("00000000-0000-0000-0000-000000000000").unwrap(),
+            quantidade: 1,
+            desconto: None,
+        });
+        let added = add_orcamento_item(created.id.into(), get_data_pool(), params, auth_user())
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(added.itens.len(), 2);
+        assert_eq!(added.updated_by, auth_user().id);
+
+        let item_id = added.itens[1].id;
+        let params = Json(UpdateOrcamentoItemRequest { quantidade: 4, desconto: None });
+        let changed = update_orcamento_item((created.id, item_id).into(), get_data_pool(), params, auth_user())
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(changed.itens[1].quantidade, 4);
+
+        let removed = remove_orcamento_item((created.id, item_id).into(), get_data_pool(), auth_user())
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(removed.itens.len(), 1);
+        assert_eq!(removed.total_liquido, created.total_liquido);
+        model_delete_orcamento(&get_pool(), created.id).unwrap();
+    }
 }
@@ -0,0 +1,135 @@
+use crate::database::PoolType;
+use crate::errors::ApiError;
+use crate::helpers::respond_json;
+use crate::models::pedido::{find, get_all, Pedido, PedidoItem};
+use actix_web::web::{block, Data, Json, Path};
+use bigdecimal::BigDecimal;
+use chrono::NaiveDateTime;
+use rayon::prelude::*;
+use serde::Serialize;
+use uuid::Uuid;
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub struct PedidoResponse {
+    pub id: Uuid,
+    pub id_cliente: Uuid,
+    pub id_orcamento: Option<Uuid>,
+    pub status: String,
+    pub created_by: String,
+    pub created_at: NaiveDateTime,
+    pub updated_by: String,
+    pub updated_at: NaiveDateTime,
+    pub itens: Vec<PedidoItemResponse>,
+    pub total_bruto: BigDecimal,
+    pub total_desconto: BigDecimal,
+    pub total_liquido: BigDecimal,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub struct PedidoItemResponse {
+    pub id: Uuid,
+    pub id_produto: Uuid,
+    pub descricao: String,
+    pub unidade_medida: Option<String>,
+    pub preco_unitario: BigDecimal,
+    pub quantidade: i32,
+    pub bruto: BigDecimal,
+    pub desconto: BigDecimal,
+    pub liquido: BigDecimal,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub struct PedidosResponse(pub Vec<PedidoResponse>);
+
+/// Get a pedido
+pub async fn get_pedido(
+    pedido_id: Path<Uuid>,
+    pool: Data<PoolType>,
+) -> Result<Json<PedidoResponse>, ApiError> {
+    let pedido = block(move || find(&pool, *pedido_id)).await?;
+    respond_json(pedido)
+}
+
+/// Get all pedidos
+pub async fn get_pedidos(pool: Data<PoolType>) -> Result<Json<PedidosResponse>, ApiError> {
+    let pedidos = block(move || get_all(&pool)).await?;
+    respond_json(pedidos)
+}
+
+impl From<PedidoItem> for PedidoItemResponse {
+    fn from((pedido_produto, produto): PedidoItem) -> Self {
+        let bruto = &pedido_produto.preco_unitario * BigDecimal::from(pedido_produto.quantidade);
+        let liquido = &bruto - &pedido_produto.desconto;
+        PedidoItemResponse {
+            id: Uuid::parse_str(&pedido_produto.id).unwrap(),
+            id_produto: Uuid::parse_str(&produto.id).unwrap(),
+            descricao: produto.descricao,
+            unidade_medida: produto.unidade_medida,
+            preco_unitario: pedido_produto.preco_unitario,
+            quantidade: pedido_produto.quantidade,
+            bruto,
+            desconto: pedido_produto.desconto,
+            liquido,
+        }
+    }
+}
+
+impl From<(Pedido, Vec<PedidoItem>)> for PedidoResponse {
+    fn from((pedido, itens): (Pedido, Vec<PedidoItem>)) -> Self {
+        let itens: Vec<PedidoItemResponse> = itens.into_iter().map(|item| item.into()).collect();
+        let total_bruto = itens
+            .iter()
+            .fold(BigDecimal::from(0), |total, item| total + &item.bruto);
+        let total_desconto = itens
+            .iter()
+            .fold(BigDecimal::from(0), |total, item| total + &item.desconto);
+        let total_liquido = &total_bruto - &total_desconto;
+        PedidoResponse {
+            id: Uuid::parse_str(&pedido.id).unwrap(),
+            id_cliente: Uuid::parse_str(&pedido.id_cliente).unwrap(),
+            id_orcamento: pedido
+                .id_orcamento
+                .map(|id_orcamento| Uuid::parse_str(&id_orcamento).unwrap()),
+            status: pedido.status,
+            created_by: pedido.created_by,
+            created_at: pedido.created_at,
+            updated_by: pedido.updated_by,
+            updated_at: pedido.updated_at,
+            itens,
+            total_bruto,
+            total_desconto,
+            total_liquido,
+        }
+    }
+}
+
+impl From<Vec<(Pedido, Vec<PedidoItem>)>> for PedidosResponse {
+    fn from(pedidos: Vec<(Pedido, Vec<PedidoItem>)>) -> Self {
+        PedidosResponse(pedidos.into_par_iter().map(|pedido| pedido.into()).collect())
+    }
+}
+
+///Testes
+///Testes
+///Testes
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::tests::helpers::tests::get_data_pool;
+
+    #[actix_rt::test]
+    async fn it_gets_all_pedidos() {
+        let response = get_pedidos(get_data_pool()).await;
+        assert!(response.is_ok());
+    }
+
+    #[actix_rt::test]
+    async fn it_doesnt_find_a_pedido() {
+        let uuid = Uuid::new_v4();
+        let pedido_id: Path<Uuid> = uuid.into();
+        let response = get_pedido(pedido_id, get_data_pool()).await;
+        let expected_error = ApiError::NotFound(format!("Pedido {} not found", uuid.to_string()));
+        assert!(response.is_err());
+        assert_eq!(response.unwrap_err(), expected_error);
+    }
+}
@@ -0,0 +1,436 @@
+use crate::database::PoolType;
+use crate::errors::ApiError;
+use crate::helpers::{respond_json, respond_ok};
+use crate::models::deposito::DEPOSITO_PADRAO;
+use crate::models::pedido_compra::{
+    aprovar, cancelar, create, delete, find, get_all, pendentes, receber, ItemRecebimento, NewPedidoCompra,
+    NewPedidoCompraItem, PedidoCompra, PedidoCompraItem, PedidoCompraLinha, Pendencia,
+};
+use crate::models::user::AuthUser;
+use crate::validate::validate;
+use actix_web::web::{block, Data, HttpResponse, Json, Path, Query};
+use bigdecimal::BigDecimal;
+use chrono::NaiveDateTime;
+use rayon::prelude::*;
+use serde::Serialize;
+use uuid::Uuid;
+use validator::Validate;
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub struct PedidoCompraResponse {
+    pub id: Uuid,
+    pub id_fornecedor: Uuid,
+    pub id_deposito: Uuid,
+    pub status: String,
+    pub previsao_entrega: Option<NaiveDateTime>,
+    pub observacoes: Option<String>,
+    pub aprovado_by: Option<String>,
+    pub aprovado_at: Option<NaiveDateTime>,
+    pub created_by: String,
+    pub created_at: NaiveDateTime,
+    pub updated_by: String,
+    pub updated_at: NaiveDateTime,
+    pub itens: Vec<PedidoCompraItemResponse>,
+    pub valor_total: BigDecimal,
+    pub quantidade_pendente: i64,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub struct PedidoCompraItemResponse {
+    pub id: Uuid,
+    pub id_produto: Uuid,
+    pub descricao: String,
+    pub unidade_medida: Option<String>,
+    pub quantidade: i32,
+    pub quantidade_recebida: i32,
+    pub quantidade_pendente: i32,
+    pub custo_unitario: BigDecimal,
+    pub total: BigDecimal,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub struct PedidosCompraResponse(pub Vec<PedidoCompraResponse>);
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub struct PendenciaPedidoResponse {
+    pub id_pedido_compra: Uuid,
+    pub id_fornecedor: Uuid,
+    pub status: String,
+    pub previsao_entrega: Option<NaiveDateTime>,
+    pub quantidade_pendente: i32,
+    pub custo_unitario: BigDecimal,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub struct PendenciaProdutoResponse {
+    pub id_produto: Uuid,
+    pub descricao: String,
+    pub quantidade_pendente: i64,
+    pub pedidos: Vec<PendenciaPedidoResponse>,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub struct PendenciasResponse(pub Vec<PendenciaProdutoResponse>);
+
+#[derive(Clone, Debug, Deserialize, Serialize, Validate)]
+pub struct CreatePedidoCompraRequest {
+    pub id_fornecedor: Uuid,
+
+    /// Defaults to the main depósito
+    pub id_deposito: Option<Uuid>,
+
+    #[validate(length(
+        min = 1,
+        message = "O pedido de compra deve ter ao menos um produto"
+    ))]
+    pub produtos: Vec<CreatePedidoCompraItemRequest>,
+
+    pub previsao_entrega: Option<NaiveDateTime>,
+
+    #[validate(length(
+        max = 2000,
+        message = "As observações devem ter no máximo 2000 caracteres"
+    ))]
+    pub observacoes: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct CreatePedidoCompraItemRequest {
+    pub id_produto: Uuid,
+    pub quantidade: i32,
+    pub custo_unitario: BigDecimal,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ReceberPedidoCompraRequest {
+    /// What arrived, everything still missing when empty
+    #[serde(default)]
+    pub produtos: Vec<ItemRecebimentoRequest>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ItemRecebimentoRequest {
+    pub id_produto: Uuid,
+    pub quantidade: i32,
+    /// What the fornecedor charged, when it differs from the pedido
+    pub custo_unitario: Option<BigDecimal>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PendenciasRequest {
+    pub id_produto: Option<Uuid>,
+}
+
+/// Get a pedido de compra
+pub async fn get_pedido_compra(
+    pedido_id: Path<Uuid>,
+    pool: Data<PoolType>,
+) -> Result<Json<PedidoCompraResponse>, ApiError> {
+    let pedido = block(move || find(&pool, *pedido_id)).await?;
+    respond_json(pedido)
+}
+
+/// Get all pedidos de compra
+pub async fn get_pedidos_compra(pool: Data<PoolType>) -> Result<Json<PedidosCompraResponse>, ApiError> {
+    let pedidos = block(move || get_all(&pool)).await?;
+    respond_json(pedidos)
+}
+
+/// Get what is still to be received of each produto, or of one of them
+pub async fn get_pendencias_compra(
+    pool: Data<PoolType>,
+    params: Query<PendenciasRequest>,
+) -> Result<Json<PendenciasResponse>, ApiError> {
+    let pendencias = block(move || pendentes(&pool, params.id_produto)).await?;
+    respond_json(pendencias)
+}
+
+/// Create a rascunho pedido de compra
+pub async fn create_pedido_compra(
+    pool: Data<PoolType>,
+    params: Json<CreatePedidoCompraRequest>,
+    user: AuthUser,
+) -> Result<Json<PedidoCompraResponse>, ApiError> {
+    validate(&params)?;
+
+    let pedido_id = Uuid::new_v4();
+    let new_pedido: PedidoCompra = NewPedidoCompra {
+        id: pedido_id.to_string(),
+        id_fornecedor: params.id_fornecedor.to_string(),
+        id_deposito: params
+            .id_deposito
+            .map(|id_deposito| id_deposito.to_string())
+            .unwrap_or_else(|| DEPOSITO_PADRAO.to_string()),
+        previsao_entrega: params.previsao_entrega,
+        observacoes: params.observacoes.clone(),
+        created_by: user.id.to_string(),
+    }
+    .into();
+    let new_itens: Vec<PedidoCompraItem> = params
+        .produtos
+        .iter()
+        .map(|item| {
+            NewPedidoCompraItem {
+                id: Uuid::new_v4().to_string(),
+                id_pedido_compra: pedido_id.to_string(),
+                id_produto: item.id_produto.to_string(),
+                quantidade: item.quantidade,
+                custo_unitario: item.custo_unitario.clone(),
+                created_by: user.id.to_string(),
+            }
+            .into()
+        })
+        .collect();
+    let pedido = block(move || create(&pool, &new_pedido, &new_itens)).await?;
+    respond_json(pedido)
+}
+
+/// Approve a rascunho pedido de compra
+pub async fn aprovar_pedido_compra(
+    pedido_id: Path<Uuid>,
+    pool: Data<PoolType>,
+    user: AuthUser,
+) -> Result<Json<PedidoCompraResponse>, ApiError> {
+    let pedido = block(move || aprovar(&pool, *pedido_id, &user.id)).await?;
+    respond_json(pedido)
+}
+
+/// Receive a delivery of an approved pedido de compra into stock
+pub async fn receber_pedido_compra(
+    pedido_id: Path<Uuid>,
+    pool: Data<PoolType>,
+    params: Json<ReceberPedidoCompraRequest>,
+    user: AuthUser,
+) -> Result<Json<PedidoCompraResponse>, ApiError> {
+    let recebimento: Vec<ItemRecebimento> = params
+        .produtos
+        .iter()
+        .map(|item| ItemRecebimento {
+            id_produto: item.id_produto.to_string(),
+            quantidade: item.quantidade,
+            custo_unitario: item.custo_unitario.clone(),
+        })
+        .collect();
+    let pedido = block(move || receber(&pool, *pedido_id, &recebimento, &user.id)).await?;
+    respond_json(pedido)
+}
+
+/// Cancel what is still to be received of a pedido de compra
+pub async fn cancelar_pedido_compra(
+    pedido_id: Path<Uuid>,
+    pool: Data<PoolType>,
+    user: AuthUser,
+) -> Result<Json<PedidoCompraResponse>, ApiError> {
+    let pedido = block(move || cancelar(&pool, *pedido_id, &user.id)).await?;
+    respond_json(pedido)
+}
+
+/// Delete a rascunho pedido de compra
+pub async fn delete_pedido_compra(
+    pedido_id: Path<Uuid>,
+    pool: Data<PoolType>,
+) -> Result<HttpResponse, ApiError> {
+    block(move || delete(&pool, *pedido_id)).await?;
+    respond_ok()
+}
+
+impl From<PedidoCompraLinha> for PedidoCompraItemResponse {
+    fn from((item, produto): PedidoCompraLinha) -> Self {
+        let total = &item.custo_unitario * BigDecimal::from(item.quantidade);
+        PedidoCompraItemResponse {
+            id: Uuid::parse_str(&item.id).unwrap(),
+            id_produto: Uuid::parse_str(&item.id_produto).unwrap(),
+            descricao: produto.descricao,
+            unidade_medida: produto.unidade_medida,
+            quantidade: item.quantidade,
+            quantidade_recebida: item.quantidade_recebida,
+            quantidade_pendente: item.pendente(),
+            custo_unitario: item.custo_unitario,
+            total,
+        }
+    }
+}
+
+impl From<(PedidoCompra, Vec<PedidoCompraLinha>)> for PedidoCompraResponse {
+    fn from((pedido, itens): (PedidoCompra, Vec<PedidoCompraLinha>)) -> Self {
+        let itens: Vec<PedidoCompraItemResponse> = itens.into_iter().map(|item| item.into()).collect();
+        let valor_total = itens
+            .iter()
+            .fold(BigDecimal::from(0), |total, item| total + &item.total);
+        let quantidade_pendente = itens.iter().map(|item| i64::from(item.quantidade_pendente)).sum();
+        PedidoCompraResponse {
+            id: Uuid::parse_str(&pedido.id).unwrap(),
+            id_fornecedor: Uuid::parse_str(&pedido.id_fornecedor).unwrap(),
+            id_deposito: Uuid::parse_str(&pedido.id_deposito).unwrap(),
+            status: pedido.status,
+            previsao_entrega: pedido.previsao_entrega,
+            observacoes: pedido.observacoes,
+            aprovado_by: pedido.aprovado_by,
+            aprovado_at: pedido.aprovado_at,
+            created_by: pedido.created_by,
+            created_at: pedido.created_at,
+            updated_by: pedido.updated_by,
+            updated_at: pedido.updated_at,
+            itens,
+            valor_total,
+            quantidade_pendente,
+        }
+    }
+}
+
+impl From<Vec<(PedidoCompra, Vec<PedidoCompraLinha>)>> for PedidosCompraResponse {
+    fn from(pedidos: Vec<(PedidoCompra, Vec<PedidoCompraLinha>)>) -> Self {
+        PedidosCompraResponse(pedidos.into_par_iter().map(|pedido| pedido.into()).collect())
+    }
+}
+
+impl From<Pendencia> for PendenciaProdutoResponse {
+    fn from((produto, linhas): Pendencia) -> Self {
+        let pedidos: Vec<PendenciaPedidoResponse> = linhas
+            .into_iter()
+            .map(|(pedido, item)| PendenciaPedidoResponse {
+                id_pedido_compra: Uuid::parse_str(&pedido.id).unwrap(),
+                id_fornecedor: Uuid::parse_str(&pedido.id_fornecedor).unwrap(),
+                status: pedido.status,
+                previsao_entrega: pedido.previsao_entrega,
+                quantidade_pendente: item.pendente(),
+                custo_unitario: item.custo_unitario,
+            })
+            .collect();
+        PendenciaProdutoResponse {
+            id_produto: Uuid::parse_str(&produto.id).unwrap(),
+            descricao: produto.descricao,
+            quantidade_pendente: pedidos.iter().map(|pedido| i64::from(pedido.quantidade_pendente)).sum(),
+            pedidos,
+        }
+    }
+}
+
+impl From<Vec<Pendencia>> for PendenciasResponse {
+    fn from(pendencias: Vec<Pendencia>) -> Self {
+        PendenciasResponse(pendencias.into_iter().map(|pendencia| pendencia.into()).collect())
+    }
+}
+
+///Testes
+///Testes
+///Testes
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::models::estoque::tests::delete_movimentos;
+    use crate::models::fornecedor::delete as delete_fornecedor;
+    use crate::models::fornecedor::tests::create_fornecedor;
+    use crate::models::pedido_compra::tests::delete_pedido_compra as delete_pedido;
+    use crate::models::produto::delete as delete_produto;
+    use crate::models::produto::tests::create_produto;
+    use crate::tests::helpers::tests::{get_data_pool, get_pool};
+    use std::str::FromStr;
+
+    fn auth_user() -> AuthUser {
+        AuthUser {
+            id: "10000000-0000-0000-0000-000000000000".into(),
+            email: "test@user.com".into(),
+        }
+    }
+
+    fn create_request(id_fornecedor: Uuid, id_produto: Uuid) -> CreatePedidoCompraRequest {
+        CreatePedidoCompraRequest {
+            id_fornecedor,
+            id_deposito: None,
+            produtos: vec![CreatePedidoCompraItemRequest {
+                id_produto,
+                quantidade: 8,
+                custo_unitario: BigDecimal::from_str("1.25").unwrap(),
+            }],
+            previsao_entrega: None,
+            observacoes: Some("Entregar pela manhã".into()),
+        }
+    }
+
+    #[actix_rt::test]
+    async fn it_creates_approves_and_receives_a_pedido_de_compra() {
+        let fornecedor = create_fornecedor().unwrap();
+        let produto = create_produto().unwrap();
+        let created = create_pedido_compra(
+            get_data_pool(),
+            Json(create_request(fornecedor.id, produto.id)),
+            auth_user(),
+        )
+        .await
+        .unwrap()
+        .into_inner();
+        assert_eq!(created.valor_total, BigDecimal::from(10));
+        assert_eq!(created.quantidade_pendente, 8);
+
+        let aprovado = aprovar_pedido_compra(Path::from(created.id), get_data_pool(), auth_user())
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(aprovado.status, "aprovado");
+        assert_eq!(aprovado.aprovado_by, Some(auth_user().id));
+
+        let entrega = ReceberPedidoCompraRequest {
+            produtos: vec![ItemRecebimentoRequest {
+                id_produto: produto.id,
+                quantidade: 3,
+                custo_unitario: None,
+            }],
+        };
+        let parcial = receber_pedido_compra(Path::from(created.id), get_data_pool(), Json(entrega), auth_user())
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(parcial.status, "parcial");
+        let params = Query(PendenciasRequest {
+            id_produto: Some(produto.id),
+        });
+        let pendencias = get_pendencias_compra(get_data_pool(), params).await.unwrap().into_inner();
+        assert_eq!(pendencias.0[0].quantidade_pendente, 5);
+
+        let cancelado = cancelar_pedido_compra(Path::from(created.id), get_data_pool(), auth_user())
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(cancelado.status, "cancelado");
+        let found = get_pedido_compra(Path::from(created.id), get_data_pool())
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(found.itens[0].quantidade_recebida, 3);
+
+        delete_pedido(created.id);
+        delete_movimentos(produto.id);
+        delete_fornecedor(&get_pool(), fornecedor.id).unwrap();
+        delete_produto(&get_pool(), produto.id).unwrap();
+    }
+
+    #[actix_rt::test]
+    async fn it_doesnt_create_a_pedido_de_compra_for_an_unknown_fornecedor() {
+        let produto_id = Uuid::new_v4();
+        let fornecedor_id = Uuid::new_v4();
+        let response =
+            create_pedido_compra(get_data_pool(), Json(create_request(fornecedor_id, produto_id)), auth_user()).await;
+        let expected_error = ApiError::NotFound(format!("Fornecedor {} not found", fornecedor_id));
+        assert_eq!(response.unwrap_err(), expected_error);
+    }
+
+    #[actix_rt::test]
+    async fn it_deletes_a_rascunho_pedido_de_compra() {
+        let fornecedor = create_fornecedor().unwrap();
+        let produto = create_produto().unwrap();
+        let created = create_pedido_compra(
+            get_data_pool(),
+            Json(create_request(fornecedor.id, produto.id)),
+            auth_user(),
+        )
+        .await
+        .unwrap()
+        .into_inner();
+        delete_pedido_compra(Path::from(created.id), get_data_pool()).await.unwrap();
+        assert!(get_pedido_compra(Path::from(created.id), get_data_pool()).await.is_err());
+
+        delete_fornecedor(&get_pool(), fornecedor.id).unwrap();
+        delete_produto(&get_pool(), produto.id).unwrap();
+    }
+}
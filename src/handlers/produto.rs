@@ -1,15 +1,20 @@
 use crate::database::PoolType;
 use crate::errors::ApiError;
 use crate::helpers::{respond_json, respond_ok};
+use crate::models::codigo_fiscal::{self, Cest, CstIcms, CstIpi, CstPisCofins, Ncm};
 use crate::models::produto::{create, delete, find, get_all, update, NewProduto, UpdateProduto, Produto};
+use crate::models::produto_custo::{ajustar, find_by_produto, ProdutoCusto};
+use crate::models::user::AuthUser;
 use crate::validate::validate;
 use actix_web::web::{block, Data, HttpResponse, Json, Path};
 use rayon::prelude::*;
 use serde::Serialize;
 use uuid::Uuid;
 use validator::Validate;
+use validator::ValidationError;
 use chrono::{NaiveDateTime, NaiveDate, NaiveTime};
 use bigdecimal::BigDecimal;
+use std::str::FromStr;
 
 #[derive(Debug, Deserialize, Serialize, PartialEq)]
 pub struct ProdutoResponse {
@@ -27,27 +32,56 @@ pub struct ProdutoResponse {
     pub cest: Option<String>,
     pub cod_ipi: Option<String>,
     pub porcentagem_ipi: Option<BigDecimal>,
+    pub aliquota_icms: Option<BigDecimal>,
+    pub aliquota_pis: Option<BigDecimal>,
+    pub aliquota_cofins: Option<BigDecimal>,
+    pub rascunho: bool,
 }
 
 #[derive(Debug, Deserialize, Serialize, PartialEq)]
 pub struct ProdutosResponse(pub Vec<ProdutoResponse>);
 
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub struct ProdutoCustoResponse {
+    pub id: Uuid,
+    pub id_produto: Uuid,
+    pub custo_anterior: Option<BigDecimal>,
+    pub custo_novo: BigDecimal,
+    pub origem: String,
+    pub id_movimento: Option<Uuid>,
+    pub motivo: Option<String>,
+    pub created_by: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub struct ProdutoCustosResponse(pub Vec<ProdutoCustoResponse>);
+
 #[derive(Clone, Debug, Deserialize, Serialize, Validate)]
 pub struct CreateProdutoRequest {
     #[validate(length(min = 1))]
     descricao: String,
     codigo_de_barras: Option<String>,
+    #[validate(custom = "validar_icms")]
     icms: Option<String>,
     unidade_medida: Option<String>,
     preco_venda: Option<BigDecimal>,
     preco_minimo: Option<BigDecimal>,
     custo_medio: Option<BigDecimal>,
+    #[validate(custom = "validar_pis")]
     pis: Option<String>,
+    #[validate(custom = "validar_cofins")]
     cofins: Option<String>,
+    #[validate(custom = "validar_ncm")]
     ncm: Option<String>,
+    #[validate(custom = "validar_cest")]
     cest: Option<String>,
+    #[validate(custom = "validar_cod_ipi")]
     cod_ipi: Option<String>,
     porcentagem_ipi: Option<BigDecimal>,
+    aliquota_icms: Option<BigDecimal>,
+    aliquota_pis: Option<BigDecimal>,
+    aliquota_cofins: Option<BigDecimal>,
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize, Validate)]
@@ -55,17 +89,78 @@ pub struct UpdateProdutoRequest {
     #[validate(length(min = 1))]
     descricao: String,
     codigo_de_barras: Option<String>,
+    #[validate(custom = "validar_icms")]
     icms: Option<String>,
     unidade_medida: Option<String>,
     preco_venda: Option<BigDecimal>,
     preco_minimo: Option<BigDecimal>,
     custo_medio: Option<BigDecimal>,
+    #[validate(custom = "validar_pis")]
     pis: Option<String>,
+    #[validate(custom = "validar_cofins")]
     cofins: Option<String>,
+    #[validate(custom = "validar_ncm")]
     ncm: Option<String>,
+    #[validate(custom = "validar_cest")]
     cest: Option<String>,
+    #[validate(custom = "validar_cod_ipi")]
     cod_ipi: Option<String>,
     porcentagem_ipi: Option<BigDecimal>,
+    aliquota_icms: Option<BigDecimal>,
+    aliquota_pis: Option<BigDecimal>,
+    aliquota_cofins: Option<BigDecimal>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, Validate)]
+pub struct AjusteCustoRequest {
+    custo: BigDecimal,
+    #[validate(length(
+        min = 3, max = 255,
+        message = "O motivo do ajuste deve ter de 3 a 255 caracteres"
+    ))]
+    motivo: String,
+}
+
+/// Check a fiscal code with its newtype, naming the field in the message
+fn validar_codigo<T: FromStr<Err = String>>(campo: &'static str, codigo: &str) -> Result<(), ValidationError> {
+    T::from_str(codigo).map(|_| ()).map_err(|erro| {
+        let mut error = ValidationError::new(campo);
+        error.message = Some(format!("{}: {}", campo, erro).into());
+        error
+    })
+}
+
+fn validar_ncm(ncm: &str) -> Result<(), ValidationError> {
+    validar_codigo::<Ncm>("ncm", ncm)
+}
+
+fn validar_cest(cest: &str) -> Result<(), ValidationError> {
+    validar_codigo::<Cest>("cest", cest)
+}
+
+fn validar_icms(icms: &str) -> Result<(), ValidationError> {
+    validar_codigo::<CstIcms>("icms", icms)
+}
+
+fn validar_cod_ipi(cod_ipi: &str) -> Result<(), ValidationError> {
+    validar_codigo::<CstIpi>("cod_ipi", cod_ipi)
+}
+
+fn validar_pis(pis: &str) -> Result<(), ValidationError> {
+    validar_codigo::<CstPisCofins>("pis", pis)
+}
+
+fn validar_cofins(cofins: &str) -> Result<(), ValidationError> {
+    validar_codigo::<CstPisCofins>("cofins", cofins)
+}
+
+/// Write a validated fiscal code the way it is stored, without the dots
+fn normalizar<T>(campo: &str, codigo: &Option<String>) -> Result<Option<String>, ApiError>
+where
+    T: FromStr<Err = String> + std::fmt::Display,
+{
+    codigo_fiscal::normalizar::<T>(codigo)
+        .map_err(|erro| ApiError::ValidationError(vec![format!("{}: {}", campo, erro)]))
 }
 
 /// Get a produto
@@ -97,19 +192,22 @@ pub async fn create_produto(
         id: produto_id.to_string(),
         descricao: params.descricao.clone(),
         codigo_de_barras: params.codigo_de_barras.clone(),
-        icms: params.icms.clone(),
+        icms: normalizar::<CstIcms>("icms", &params.icms)?,
         unidade_medida: params.unidade_medida.clone(),
         preco_venda: params.preco_venda.clone(),
         preco_minimo: params.preco_minimo.clone(),
         custo_medio: params.custo_medio.clone(),
-        pis: params.pis.clone(),
-        cofins: params.cofins.clone(),
-        ncm: params.ncm.clone(),
-        cest: params.cest.clone(),
-        cod_ipi: params.cod_ipi.clone(),
+        pis: normalizar::<CstPisCofins>("pis", &params.pis)?,
+        cofins: normalizar::<CstPisCofins>("cofins", &params.cofins)?,
+        ncm: normalizar::<Ncm>("ncm", &params.ncm)?,
+        cest: normalizar::<Cest>("cest", &params.cest)?,
+        cod_ipi: normalizar::<CstIpi>("cod_ipi", &params.cod_ipi)?,
         porcentagem_ipi: params.porcentagem_ipi.clone(),
         created_by: produto_id.to_string(),
         updated_by: produto_id.to_string(),
+        aliquota_icms: params.aliquota_icms.clone(),
+        aliquota_pis: params.aliquota_pis.clone(),
+        aliquota_cofins: params.aliquota_cofins.clone(),
     }
     .into();
     let produto = block(move || create(&pool, &new_produto)).await?;
@@ -130,18 +228,21 @@ pub async fn update_produto(
         id: produto_id.to_string(),
         descricao: params.descricao.clone(),
         codigo_de_barras: params.codigo_de_barras.clone(),
-        icms: params.icms.clone(),
+        icms: normalizar::<CstIcms>("icms", &params.icms)?,
         unidade_medida: params.unidade_medida.clone(),
         preco_venda: params.preco_venda.clone(),
         preco_minimo: params.preco_minimo.clone(),
         custo_medio: params.custo_medio.clone(),
-        pis: params.pis.clone(),
-        cofins: params.cofins.clone(),
-        ncm: params.ncm.clone(),
-        cest: params.cest.clone(),
-        cod_ipi: params.cod_ipi.clone(),
+        pis: normalizar::<CstPisCofins>("pis", &params.pis)?,
+        cofins: normalizar::<CstPisCofins>("cofins", &params.cofins)?,
+        ncm: normalizar::<Ncm>("ncm", &params.ncm)?,
+        cest: normalizar::<Cest>("cest", &params.cest)?,
+        cod_ipi: normalizar::<CstIpi>("cod_ipi", &params.cod_ipi)?,
         porcentagem_ipi: params.porcentagem_ipi.clone(),
         updated_by: produto_id.to_string(),
+        aliquota_icms: params.aliquota_icms.clone(),
+        aliquota_pis: params.aliquota_pis.clone(),
+        aliquota_cofins: params.aliquota_cofins.clone(),
     };
     let produto = block(move || update(&pool, &update_produto)).await?;
     respond_json(produto.into())
@@ -156,6 +257,28 @@ pub async fn delete_produto(
     respond_ok()
 }
 
+/// Get the cost history of a produto
+pub async fn get_produto_custos(
+    produto_id: Path<Uuid>,
+    pool: Data<PoolType>,
+) -> Result<Json<ProdutoCustosResponse>, ApiError> {
+    let custos = block(move || find_by_produto(&pool, *produto_id)).await?;
+    respond_json(custos)
+}
+
+/// Adjust the custo_medio of a produto by hand
+pub async fn ajustar_produto_custo(
+    produto_id: Path<Uuid>,
+    pool: Data<PoolType>,
+    params: Json<AjusteCustoRequest>,
+    user: AuthUser,
+) -> Result<Json<ProdutoCustoResponse>, ApiError> {
+    validate(&params)?;
+
+    let custo = block(move || ajustar(&pool, *produto_id, &params.custo, &params.motivo, &user.id)).await?;
+    respond_json(custo)
+}
+
 impl From<Produto> for ProdutoResponse {
     fn from(produto: Produto) -> Self {
         ProdutoResponse {
@@ -173,6 +296,10 @@ impl From<Produto> for ProdutoResponse {
             cest: produto.cest.clone(),
             cod_ipi: produto.cod_ipi.clone(),
             porcentagem_ipi: produto.porcentagem_ipi.clone(),
+            aliquota_icms: produto.aliquota_icms.clone(),
+            aliquota_pis: produto.aliquota_pis.clone(),
+            aliquota_cofins: produto.aliquota_cofins.clone(),
+            rascunho: produto.rascunho,
         }
     }
 }
@@ -183,6 +310,30 @@ impl From<Vec<Produto>> for ProdutosResponse {
     }
 }
 
+impl From<ProdutoCusto> for ProdutoCustoResponse {
+    fn from(custo: ProdutoCusto) -> Self {
+        ProdutoCustoResponse {
+            id: Uuid::parse_str(&custo.id).unwrap(),
+            id_produto: Uuid::parse_str(&custo.id_produto).unwrap(),
+            custo_anterior: custo.custo_anterior,
+            custo_novo: custo.custo_novo,
+            origem: custo.origem,
+            id_movimento: custo
+                .id_movimento
+                .map(|id_movimento| Uuid::parse_str(&id_movimento).unwrap()),
+            motivo: custo.motivo,
+            created_by: custo.created_by,
+            created_at: custo.created_at,
+        }
+    }
+}
+
+impl From<Vec<ProdutoCusto>> for ProdutoCustosResponse {
+    fn from(custos: Vec<ProdutoCusto>) -> Self {
+        ProdutoCustosResponse(custos.into_iter().map(|custo| custo.into()).collect())
+    }
+}
+
 ///Testes
 ///Testes
 ///Testes
@@ -235,17 +386,20 @@ pub mod tests {
         let params = Json(CreateProdutoRequest {
             descricao: "coca cola".into(),
             codigo_de_barras: Some("123456789".into()),
-            icms: Some("18".into()),
+            icms: Some("00".into()),
             unidade_medida: Some("un".into()),
             preco_venda: None,
             preco_minimo: None,
             custo_medio: None,
-            pis: Some("1.65".into()),
-            cofins: Some("7.60".into()),
+            pis: Some("01".into()),
+            cofins: Some("01".into()),
             ncm: Some("22021000".into()),
-            cest: Some("1705600".into()),
-            cod_ipi: Some("999".into()),
+            cest: Some("0300700".into()),
+            cod_ipi: Some("53".into()),
             porcentagem_ipi: None,
+            aliquota_icms: "18.00".parse().ok(),
+            aliquota_pis: "1.65".parse().ok(),
+            aliquota_cofins: "7.60".parse().ok(),
         });
         let response = create_produto(get_data_pool(), Json(params.clone()))
             .await
@@ -255,6 +409,33 @@ pub mod tests {
         delete(&get_data_pool(), response.id);
     }
 
+    #[actix_rt::test]
+    async fn it_rejects_invalid_fiscal_codes() {
+        let params = Json(CreateProdutoRequest {
+            descricao: "coca cola".into(),
+            codigo_de_barras: None,
+            icms: Some("00".into()),
+            unidade_medida: Some("un".into()),
+            preco_venda: None,
+            preco_minimo: None,
+            custo_medio: None,
+            pis: Some("01".into()),
+            cofins: Some("01".into()),
+            ncm: Some("00".into()),
+            cest: None,
+            cod_ipi: None,
+            porcentagem_ipi: None,
+            aliquota_icms: None,
+            aliquota_pis: None,
+            aliquota_cofins: None,
+        });
+        let response = create_produto(get_data_pool(), params).await;
+        let expected_error = ApiError::ValidationError(vec![
+            "ncm: o NCM deve ter 8 dígitos, recebido 00".to_string(),
+        ]);
+        assert_eq!(response.unwrap_err(), expected_error);
+    }
+
     #[actix_rt::test]
     async fn it_updates_a_produto() {
         let first_produto = model_create_produto_by_email("teste_handler_update@teste.com").unwrap();
@@ -262,17 +443,20 @@ pub mod tests {
         let params = Json(UpdateProdutoRequest {
             descricao: "coca cola".into(),
             codigo_de_barras: Some("123456789".into()),
-            icms: Some("18".into()),
+            icms: Some("00".into()),
             unidade_medida: Some("un".into()),
             preco_venda: None,
             preco_minimo: None,
             custo_medio: None,
-            pis: Some("1.65".into()),
-            cofins: Some("7.60".into()),
+            pis: Some("01".into()),
+            cofins: Some("01".into()),
             ncm: Some("22021000".into()),
-            cest: Some("1705600".into()),
-            cod_ipi: Some("999".into()),
+            cest: Some("0300700".into()),
+            cod_ipi: Some("53".into()),
             porcentagem_ipi: None,
+            aliquota_icms: "18.00".parse().ok(),
+            aliquota_pis: "1.65".parse().ok(),
+            aliquota_cofins: "7.60".parse().ok(),
         });
         let response = update_produto(produto_id, get_data_pool(), Json(params.clone()))
             .await
@@ -281,6 +465,49 @@ pub mod tests {
         delete(&get_data_pool(), first_produto.id);
     }
 
+    fn auth_user() -> AuthUser {
+        AuthUser {
+            id: "10000000-0000-0000-0000-000000000000".into(),
+            email: "test@user.com".into(),
+        }
+    }
+
+    #[actix_rt::test]
+    async fn it_adjusts_the_cost_and_lists_its_history() {
+        let created = model_create_produto_by_email("teste_handler_custo@teste.com").unwrap();
+        let params = Json(AjusteCustoRequest {
+            custo: "3.50".parse().unwrap(),
+            motivo: "Inventário anual".into(),
+        });
+        let ajuste = ajustar_produto_custo(Path::from(created.id), get_data_pool(), params, auth_user())
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(ajuste.origem, "ajuste");
+        assert_eq!(ajuste.custo_anterior, created.custo_medio);
+
+        let custos = get_produto_custos(Path::from(created.id), get_data_pool())
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(custos.0.last(), Some(&ajuste));
+        assert_eq!(find(&get_pool(), created.id).unwrap().custo_medio, Some(ajuste.custo_novo));
+        delete(&get_data_pool(), created.id).unwrap();
+    }
+
+    #[actix_rt::test]
+    async fn it_doesnt_adjust_the_cost_without_a_motivo() {
+        let params = Json(AjusteCustoRequest {
+            custo: "3.50".parse().unwrap(),
+            motivo: "".into(),
+        });
+        let response = ajustar_produto_custo(Path::from(Uuid::new_v4()), get_data_pool(), params, auth_user()).await;
+        let expected_error = ApiError::ValidationError(vec![
+            "O motivo do ajuste deve ter de 3 a 255 caracteres".to_string(),
+        ]);
+        assert_eq!(response.unwrap_err(), expected_error);
+    }
+
     #[actix_rt::test]
     async fn it_deletes_a_produto() {
         let created = model_create_produto_by_email("teste_handler_delete@teste.com");
@@ -0,0 +1,215 @@
+use crate::database::PoolType;
+use crate::errors::ApiError;
+use crate::helpers::respond_json;
+use crate::models::titulo_receber::{baixar, create, find, find_by_cliente, NewTituloReceber, TituloReceber};
+use crate::models::user::AuthUser;
+use crate::validate::validate;
+use actix_web::web::{block, Data, Json, Path};
+use bigdecimal::BigDecimal;
+use chrono::{NaiveDateTime, Utc};
+use rayon::prelude::*;
+use serde::Serialize;
+use uuid::Uuid;
+use validator::Validate;
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub struct TituloReceberResponse {
+    pub id: Uuid,
+    pub id_cliente: Uuid,
+    pub id_pedido: Option<Uuid>,
+    pub documento: Option<String>,
+    pub valor: BigDecimal,
+    pub valor_pago: BigDecimal,
+    /// What is still to be paid
+    pub saldo: BigDecimal,
+    pub vencimento: NaiveDateTime,
+    pub vencido: bool,
+    pub pago_at: Option<NaiveDateTime>,
+    pub updated_by: String,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub struct TitulosReceberResponse(pub Vec<TituloReceberResponse>);
+
+#[derive(Clone, Debug, Deserialize, Serialize, Validate)]
+pub struct CreateTituloReceberRequest {
+    /// The pedido the título bills, if any
+    pub id_pedido: Option<Uuid>,
+
+    #[validate(length(
+        min = 1, max = 60,
+        message = "O documento deve ter de 1 a 60 caracteres"
+    ))]
+    pub documento: Option<String>,
+
+    pub valor: BigDecimal,
+
+    pub vencimento: NaiveDateTime,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct BaixaTituloRequest {
+    /// Amount paid, at most what is left of the título
+    pub valor: BigDecimal,
+}
+
+/// Get the títulos of a cliente
+pub async fn get_cliente_titulos(
+    cliente_id: Path<Uuid>,
+    pool: Data<PoolType>,
+) -> Result<Json<TitulosReceberResponse>, ApiError> {
+    let titulos = block(move || find_by_cliente(&pool, *cliente_id)).await?;
+    respond_json(titulos)
+}
+
+/// Get a título
+pub async fn get_titulo(
+    titulo_id: Path<Uuid>,
+    pool: Data<PoolType>,
+) -> Result<Json<TituloReceberResponse>, ApiError> {
+    let titulo = block(move || find(&pool, *titulo_id)).await?;
+    respond_json(titulo)
+}
+
+/// Add a título to a cliente
+pub async fn create_cliente_titulo(
+    cliente_id: Path<Uuid>,
+    pool: Data<PoolType>,
+    params: Json<CreateTituloReceberRequest>,
+    user: AuthUser,
+) -> Result<Json<TituloReceberResponse>, ApiError> {
+    validate(&params)?;
+
+    let new_titulo: TituloReceber = NewTituloReceber {
+        id: Uuid::new_v4().to_string(),
+        id_cliente: cliente_id.to_string(),
+        id_pedido: params.id_pedido.map(|id_pedido| id_pedido.to_string()),
+        documento: params.documento.clone(),
+        valor: params.valor.clone(),
+        vencimento: params.vencimento,
+        created_by: user.id.to_string(),
+    }
+    .into();
+    let titulo = block(move || create(&pool, &new_titulo)).await?;
+    respond_json(titulo)
+}
+
+/// Register a payment of a título
+pub async fn baixar_titulo(
+    titulo_id: Path<Uuid>,
+    pool: Data<PoolType>,
+    params: Json<BaixaTituloRequest>,
+    user: AuthUser,
+) -> Result<Json<TituloReceberResponse>, ApiError> {
+    let titulo = block(move || baixar(&pool, *titulo_id, &params.valor, &user.id)).await?;
+    respond_json(titulo)
+}
+
+impl From<TituloReceber> for TituloReceberResponse {
+    fn from(titulo: TituloReceber) -> Self {
+        let saldo = titulo.saldo();
+        let vencido = titulo.vencido(Utc::now().naive_utc());
+        TituloReceberResponse {
+            id: Uuid::parse_str(&titulo.id).unwrap(),
+            id_cliente: Uuid::parse_str(&titulo.id_cliente).unwrap(),
+            id_pedido: titulo
+                .id_pedido
+                .map(|id_pedido| Uuid::parse_str(&id_pedido).unwrap()),
+            documento: titulo.documento,
+            valor: titulo.valor,
+            valor_pago: titulo.valor_pago,
+            saldo,
+            vencimento: titulo.vencimento,
+            vencido,
+            pago_at: titulo.pago_at,
+            updated_by: titulo.updated_by,
+            updated_at: titulo.updated_at,
+        }
+    }
+}
+
+impl From<Vec<TituloReceber>> for TitulosReceberResponse {
+    fn from(titulos: Vec<TituloReceber>) -> Self {
+        TitulosReceberResponse(titulos.into_par_iter().map(|titulo| titulo.into()).collect())
+    }
+}
+
+///Testes
+///Testes
+///Testes
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::models::cliente::delete as delete_cliente;
+    use crate::models::cliente::tests::create_cliente;
+    use crate::models::titulo_receber::tests::delete_titulos;
+    use crate::tests::helpers::tests::{get_data_pool, get_pool};
+    use std::str::FromStr;
+
+    fn auth_user() -> AuthUser {
+        AuthUser {
+            id: "10000000-0000-0000-0000-000000000000".into(),
+            email: "test@user.com".into(),
+        }
+    }
+
+    fn titulo_request(valor: &str) -> CreateTituloReceberRequest {
+        CreateTituloReceberRequest {
+            id_pedido: None,
+            documento: Some("NF 1234/1".into()),
+            valor: BigDecimal::from_str(valor).unwrap(),
+            vencimento: Utc::now().naive_utc() + chrono::Duration::days(30),
+        }
+    }
+
+    #[actix_rt::test]
+    async fn it_creates_and_pays_a_titulo() {
+        let cliente = create_cliente().unwrap();
+        let response = create_cliente_titulo(
+            Path::from(cliente.id),
+            get_data_pool(),
+            Json(titulo_request("250.00")),
+            auth_user(),
+        )
+        .await
+        .unwrap()
+        .into_inner();
+        assert_eq!(response.saldo, BigDecimal::from(250));
+        assert!(!response.vencido);
+
+        let pago = baixar_titulo(
+            Path::from(response.id),
+            get_data_pool(),
+            Json(BaixaTituloRequest { valor: BigDecimal::from(250) }),
+            auth_user(),
+        )
+        .await
+        .unwrap()
+        .into_inner();
+        assert_eq!(pago.saldo, BigDecimal::from(0));
+        assert!(pago.pago_at.is_some());
+        let titulos = get_cliente_titulos(Path::from(cliente.id), get_data_pool())
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(titulos.0, vec![pago]);
+
+        delete_titulos(cliente.id);
+        delete_cliente(&get_pool(), cliente.id).unwrap();
+    }
+
+    #[actix_rt::test]
+    async fn it_doesnt_create_a_titulo_for_an_unknown_cliente() {
+        let cliente_id = Uuid::new_v4();
+        let response = create_cliente_titulo(
+            Path::from(cliente_id),
+            get_data_pool(),
+            Json(titulo_request("250.00")),
+            auth_user(),
+        )
+        .await;
+        let expected_error = ApiError::NotFound(format!("Cliente {} not found", cliente_id));
+        assert_eq!(response.unwrap_err(), expected_error);
+    }
+}
@@ -2,7 +2,7 @@ use crate::database::PoolType;
 use crate::errors::ApiError;
 use crate::helpers::{respond_json, respond_ok};
 use crate::models::user::{create, delete, find, get_all, update, NewUser, UpdateUser, User};
-use crate::validate::validate;
+use crate::validate::{formatar_cpf, normalizar_cpf, validar_cpf, validate};
 use actix_web::web::{block, Data, HttpResponse, Json, Path};
 use rayon::prelude::*;
 use serde::Serialize;
@@ -16,6 +16,8 @@ pub struct UserResponse {
     pub nome: String,
     pub sobrenome: String,
     pub cpf: Option<String>,
+    /// The cpf with its mask, as in 529.982.247-25
+    pub cpf_formatado: Option<String>,
     pub rg: Option<String>,
     pub data_nascimento: Option<NaiveDateTime>,
     pub sexo: Option<String>,
@@ -41,10 +43,7 @@ pub struct CreateUserRequest {
     ))]
     pub sobrenome: String,
 
-    #[validate(length(
-        min = 11, max = 11,
-        message = "O cpf deve ter 11 caracteres"
-    ))]
+    #[validate(custom = "validar_cpf")]
     pub cpf: Option<String>,
 
     #[validate(length(
@@ -97,10 +96,7 @@ pub struct UpdateUserRequest {
     ))]
     pub sobrenome: String,
 
-    #[validate(length(
-        min = 11, max = 11,
-        message = "O cpf deve ter 11 caracteres"
-    ))]
+    #[validate(custom = "validar_cpf")]
     pub cpf: Option<String>,
 
     #[validate(length(
@@ -162,7 +158,7 @@ pub async fn create_user(
         id: user_id.to_string(),
         nome: params.nome.to_string(),
         sobrenome: params.sobrenome.to_string(),
-        cpf: params.cpf.clone(),
+        cpf: normalizar_cpf(&params.cpf)?,
         rg: params.rg.clone(),
         data_nascimento: params.data_nascimento.clone(),
         sexo: params.sexo.clone(),
@@ -192,7 +188,7 @@ pub async fn update_user(
         id: user_id.to_string(),
         nome: params.nome.to_string(),
         sobrenome: params.sobrenome.to_string(),
-        cpf: params.cpf.clone(),
+        cpf: normalizar_cpf(&params.cpf)?,
         rg: params.rg.clone(),
         data_nascimento: params.data_nascimento.clone(),
         sexo: params.sexo.clone(),
@@ -220,6 +216,7 @@ impl From<User> for UserResponse {
             id: Uuid::parse_str(&user.id).unwrap(),
             nome: user.nome.to_string(),
             sobrenome: user.sobrenome.to_string(),
+            cpf_formatado: user.cpf.as_deref().map(formatar_cpf),
             cpf: user.cpf,
             rg: user.rg,
             data_nascimento: user.data_nascimento,
@@ -287,7 +284,7 @@ pub mod tests {
         let params = Json(CreateUserRequest {
             nome: "Satoshi".into(),
             sobrenome: "Nakamoto".into(),
-            cpf: Some("12345678901".into()),
+            cpf: Some("529.982.247-25".into()),
             rg: Some("123456789".into()),
             data_nascimento: Some(NaiveDate::from_ymd(1990, 1, 1).and_hms(0, 0, 0)),
             sexo: Some("M".into()),
@@ -301,6 +298,8 @@ pub mod tests {
             .unwrap()
             .into_inner();
         assert_eq!(response.nome, params.nome);
+        assert_eq!(response.cpf, Some("52998224725".into()));
+        assert_eq!(response.cpf_formatado, Some("529.982.247-25".into()));
         delete(&get_data_pool(), response.id);
     }
 
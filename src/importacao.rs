@@ -0,0 +1,54 @@
+//! Load reference tables that are too large for a migration
+//!
+//! `cargo run -- importar <tabela> <arquivo>` stores the file through the
+//! model that owns the table and exits without starting the server. Every
+//! import is all or nothing and can be run again to update the table. The
+//! NCM and CEST tables bundled under assets/fiscal are imported when no file
+//! is given.
+
+use crate::config::CONFIG;
+use crate::database::{init_pool, PoolType};
+use crate::errors::ApiError;
+use crate::models::catalogo_fiscal::{importar_cests, importar_ncms, TABELA_CEST, TIPI};
+use crate::models::cep;
+use diesel::pg::PgConnection;
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+
+const USO: &str = "uso: importar <ncm|cest> [arquivo] ou importar cep <arquivo>";
+
+pub fn importar(argumentos: &[String]) -> Result<()> {
+    dotenv::dotenv().ok();
+    env_logger::init();
+
+    let (tabela, arquivo, conteudo) = match argumentos {
+        [tabela, arquivo] => (tabela.as_str(), arquivo.as_str(), fs::read_to_string(arquivo)?),
+        [tabela] if tabela == "ncm" => ("ncm", "assets/fiscal/tipi.csv", TIPI.to_string()),
+        [tabela] if tabela == "cest" => ("cest", "assets/fiscal/cest.csv", TABELA_CEST.to_string()),
+        _ => return Err(Error::new(ErrorKind::InvalidInput, USO)),
+    };
+    let pool: PoolType = init_pool::<PgConnection>(CONFIG.clone())
+        .map_err(|error| Error::new(ErrorKind::Other, error.to_string()))?;
+
+    let importados = match tabela {
+        "ncm" => importar_ncms(&pool, &conteudo).map(|total| format!("{} NCMs importados", total)),
+        "cest" => importar_cests(&pool, &conteudo).map(|total| format!("{} CESTs importados", total)),
+        "cep" => cep::importar(&pool, &conteudo).map(|importacao| {
+            format!("{} CEPs de {} municípios importados", importacao.ceps, importacao.municipios)
+        }),
+        _ => return Err(Error::new(ErrorKind::InvalidInput, USO)),
+    };
+    match importados {
+        Ok(mensagem) => {
+            println!("{}", mensagem);
+            Ok(())
+        }
+        Err(ApiError::ValidationError(errors)) => {
+            for error in errors {
+                eprintln!("{}", error);
+            }
+            Err(Error::new(ErrorKind::InvalidData, format!("{} não foi importado", arquivo)))
+        }
+        Err(error) => Err(Error::new(ErrorKind::Other, format!("{:?}", error))),
+    }
+}
@@ -19,16 +19,23 @@ mod errors;
 mod extractors;
 pub mod handlers;
 mod helpers;
+mod importacao;
 mod middleware;
 mod models;
+mod pdf;
 mod routes;
 mod schema;
 mod server;
 mod state;
 mod tests;
 mod validate;
+mod xsd;
 
 #[actix_rt::main]
 async fn main() -> std::io::Result<()> {
+    let argumentos: Vec<String> = std::env::args().skip(1).collect();
+    if argumentos.first().map(String::as_str) == Some("importar") {
+        return importacao::importar(&argumentos[1..]);
+    }
     server().await
 }
@@ -0,0 +1,367 @@
+use crate::database::PoolType;
+use crate::errors::ApiError;
+use crate::handlers::fiscal::NcmsResponse;
+use crate::models::codigo_fiscal::{Cest, Ncm};
+use crate::schema::{cests, ncms};
+use bigdecimal::BigDecimal;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::upsert::excluded;
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+/// How many NCMs a search returns at most
+const LIMITE_BUSCA: i64 = 20;
+
+/// How many rows go into one INSERT of the import
+const LOTE_IMPORTACAO: usize = 5000;
+
+/// The TIPI shipped with the binary, imported when no file is given
+pub const TIPI: &str = include_str!("../../assets/fiscal/tipi.csv");
+
+/// The CEST table shipped with the binary, imported when no file is given
+pub const TABELA_CEST: &str = include_str!("../../assets/fiscal/cest.csv");
+
+/// A NCM of the catalog, with its TIPI rate
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Queryable, Identifiable, Insertable)]
+#[table_name = "ncms"]
+#[primary_key(codigo)]
+pub struct NcmCatalogo {
+    pub codigo: String,
+    pub descricao: String,
+    pub aliquota_ipi: Option<BigDecimal>,
+}
+
+/// A CEST of the catalog and one of the NCM prefixes it applies to
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Queryable, Identifiable, Insertable)]
+#[table_name = "cests"]
+#[primary_key(codigo, ncm)]
+pub struct CestCatalogo {
+    pub codigo: String,
+    pub ncm: String,
+    pub descricao: String,
+}
+
+impl CestCatalogo {
+    pub fn aplica_se(&self, ncm: &str) -> bool {
+        ncm.starts_with(&self.ncm)
+    }
+}
+
+/// Find NCMs by the start of their code or by part of their description
+///
+/// Each NCM comes with the CESTs that apply to it, so the produto form can
+/// offer only compatible ones.
+pub fn buscar(pool: &PoolType, busca: &str) -> Result<NcmsResponse, ApiError> {
+    use crate::schema::cests::dsl::{cests, ncm as cest_ncm};
+    use crate::schema::ncms::dsl::{codigo, descricao, ncms};
+
+    let mut conn = pool.get()?;
+    let busca = busca.trim();
+    let digitos: String = busca.chars().filter(|c| *c != '.').collect();
+    let query = ncms.order(codigo).limit(LIMITE_BUSCA).into_boxed();
+    let query = if !digitos.is_empty() && digitos.chars().all(|c| c.is_ascii_digit()) {
+        query.filter(codigo.like(format!("{}%", digitos)))
+    } else {
+        query.filter(descricao.ilike(format!("%{}%", busca)))
+    };
+    let encontrados = query.load::<NcmCatalogo>(&mut conn)?;
+    // a CEST is listed under a chapter, heading or item, so only the CESTs
+    // of a prefix of the NCMs found can apply to them
+    let prefixos: Vec<&str> = encontrados
+        .iter()
+        .flat_map(|ncm| (2..=ncm.codigo.len()).map(move |tamanho| &ncm.codigo[..tamanho]))
+        .collect();
+    let candidatos = cests.filter(cest_ncm.eq_any(prefixos)).load::<CestCatalogo>(&mut conn)?;
+
+    Ok(encontrados
+        .into_iter()
+        .map(|ncm| {
+            let aplicaveis = candidatos
+                .iter()
+                .filter(|cest| cest.aplica_se(&ncm.codigo))
+                .cloned()
+                .collect();
+            (ncm, aplicaveis)
+        })
+        .collect::<Vec<(NcmCatalogo, Vec<CestCatalogo>)>>()
+        .into())
+}
+
+/// Load the TIPI into the NCM catalog
+///
+/// Each line is `codigo;descricao;aliquota_ipi`, codes may be written with
+/// dots and the aliquota is `NT` for NCMs outside the IPI. A header line
+/// starting with `codigo` is skipped and NCMs already in the catalog are
+/// replaced. Nothing is stored when a line is invalid.
+pub fn importar_ncms(pool: &PoolType, conteudo: &str) -> Result<usize, ApiError> {
+    let novos = ler_arquivo(conteudo, ler_ncm)?;
+    let novos: Vec<NcmCatalogo> = novos
+        .into_iter()
+        .map(|ncm| (ncm.codigo.clone(), ncm))
+        .collect::<BTreeMap<String, NcmCatalogo>>()
+        .into_values()
+        .collect();
+
+    let mut conn = pool.get()?;
+    conn.transaction::<_, ApiError, _>(|conn| {
+        for lote in novos.chunks(LOTE_IMPORTACAO) {
+            diesel::insert_into(ncms::table)
+                .values(lote)
+                .on_conflict(ncms::codigo)
+                .do_update()
+                .set((
+                    ncms::descricao.eq(excluded(ncms::descricao)),
+                    ncms::aliquota_ipi.eq(excluded(ncms::aliquota_ipi)),
+                ))
+                .execute(conn)?;
+        }
+        Ok(())
+    })?;
+    Ok(novos.len())
+}
+
+/// Load the CEST table into the catalog
+///
+/// Each line is `codigo;ncms;descricao`, where ncms lists the NCM prefixes
+/// the CEST applies to separated by spaces, as in the annex of the Convênio
+/// ICMS 142/18. A header line starting with `codigo` is skipped and
+/// descriptions already in the catalog are replaced. Nothing is stored
+/// when a line is invalid.
+pub fn importar_cests(pool: &PoolType, conteudo: &str) -> Result<usize, ApiError> {
+    let novos: Vec<CestCatalogo> = ler_arquivo(conteudo, ler_cest)?
+        .into_iter()
+        .flatten()
+        .map(|cest| ((cest.codigo.clone(), cest.ncm.clone()), cest))
+        .collect::<BTreeMap<(String, String), CestCatalogo>>()
+        .into_values()
+        .collect();
+
+    let mut conn = pool.get()?;
+    conn.transaction::<_, ApiError, _>(|conn| {
+        for lote in novos.chunks(LOTE_IMPORTACAO) {
+            diesel::insert_into(cests::table)
+                .values(lote)
+                .on_conflict((cests::codigo, cests::ncm))
+                .do_update()
+                .set(cests::descricao.eq(excluded(cests::descricao)))
+                .execute(conn)?;
+        }
+        Ok(())
+    })?;
+    Ok(novos.len())
+}
+
+/// Read every line of an import, collecting one message per bad line
+fn ler_arquivo<T>(conteudo: &str, ler_linha: fn(&str) -> Result<T, String>) -> Result<Vec<T>, ApiError> {
+    let mut errors = Vec::new();
+    let mut lidos = Vec::new();
+    for (posicao, linha) in conteudo.lines().enumerate() {
+        let linha = linha.trim();
+        if linha.is_empty() || (posicao == 0 && linha.to_lowercase().starts_with("codigo")) {
+            continue;
+        }
+        match ler_linha(linha) {
+            Ok(lido) => lidos.push(lido),
+            Err(erro) => errors.push(format!("linha {}: {}", posicao + 1, erro)),
+        }
+    }
+    if errors.is_empty() {
+        Ok(lidos)
+    } else {
+        Err(ApiError::ValidationError(errors))
+    }
+}
+
+fn ler_ncm(linha: &str) -> Result<NcmCatalogo, String> {
+    let campos: Vec<&str> = linha.split(';').map(str::trim).collect();
+    if campos.len() != 3 {
+        return Err(format!("esperados 3 campos separados por ;, recebidos {}", campos.len()));
+    }
+    let codigo = Ncm::from_str(campos[0])?.to_string();
+    if campos[1].is_empty() {
+        return Err(format!("a descrição do NCM {} é obrigatória", codigo));
+    }
+    let aliquota_ipi = match campos[2] {
+        "" | "NT" => None,
+        aliquota => Some(
+            BigDecimal::from_str(&aliquota.replace(',', "."))
+                .map_err(|_| format!("a alíquota de IPI {} não é um número", aliquota))?,
+        ),
+    };
+    Ok(NcmCatalogo {
+        codigo,
+        descricao: campos[1].to_string(),
+        aliquota_ipi,
+    })
+}
+
+fn ler_cest(linha: &str) -> Result<Vec<CestCatalogo>, String> {
+    let campos: Vec<&str> = linha.split(';').map(str::trim).collect();
+    if campos.len() != 3 {
+        return Err(format!("esperados 3 campos separados por ;, recebidos {}", campos.len()));
+    }
+    let codigo = Cest::from_str(campos[0])?.to_string();
+    if campos[2].is_empty() {
+        return Err(format!("a descrição do CEST {} é obrigatória", codigo));
+    }
+    let prefixos: Vec<&str> = campos[1].split_whitespace().collect();
+    if prefixos.is_empty() {
+        return Err(format!("o CEST {} não lista nenhum NCM", codigo));
+    }
+    prefixos
+        .into_iter()
+        .map(|prefixo| {
+            let ncm: String = prefixo.chars().filter(|c| *c != '.').collect();
+            // the table gives chapters, headings and items as well as full codes
+            if ncm.len() < 2 || ncm.len() > 8 || !ncm.chars().all(|c| c.is_ascii_digit()) {
+                return Err(format!("o NCM {} do CEST {} é inválido", prefixo, codigo));
+            }
+            Ok(CestCatalogo {
+                codigo: codigo.clone(),
+                ncm,
+                descricao: campos[2].to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Check the ncm and cest of a produto against the catalog
+///
+/// Returns the catalog entry of the ncm so its TIPI rate can be used.
+pub fn validar_produto(
+    conn: &mut PgConnection,
+    ncm: &Option<String>,
+    cest: &Option<String>,
+) -> Result<Option<NcmCatalogo>, ApiError> {
+    let mut errors = Vec::new();
+
+    let catalogo = match ncm {
+        Some(ncm) => {
+            let catalogo = ncms::table.find(ncm).first::<NcmCatalogo>(conn).optional()?;
+            if catalogo.is_none() {
+                errors.push(format!("ncm: o NCM {} não está no catálogo", ncm));
+            }
+            catalogo
+        }
+        None => None,
+    };
+
+    if let Some(cest) = cest {
+        let aplicacoes = cests::table
+            .filter(cests::codigo.eq(cest))
+            .load::<CestCatalogo>(conn)?;
+        if aplicacoes.is_empty() {
+            errors.push(format!("cest: o CEST {} não está no catálogo", cest));
+        } else {
+            match ncm {
+                Some(ncm) if !aplicacoes.iter().any(|aplicacao| aplicacao.aplica_se(ncm)) => {
+                    errors.push(format!("cest: o CEST {} não se aplica ao NCM {}", cest, ncm))
+                }
+                None => errors.push("cest: informe o NCM do produto para usar um CEST".to_string()),
+                _ => {}
+            }
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(catalogo)
+    } else {
+        Err(ApiError::ValidationError(errors))
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::tests::helpers::tests::get_pool;
+
+    fn codigo(valor: &str) -> Option<String> {
+        Some(valor.to_string())
+    }
+
+    #[test]
+    fn it_reads_a_line_of_the_tipi() {
+        let ncm = ler_ncm("2203.00.00;Cervejas de malte;3,9").unwrap();
+        assert_eq!(ncm.codigo, "22030000");
+        assert_eq!(ncm.aliquota_ipi, Some(BigDecimal::from_str("3.9").unwrap()));
+        assert_eq!(ler_ncm("0101.21.00;Reprodutores de raça pura;NT").unwrap().aliquota_ipi, None);
+        assert_eq!(
+            ler_ncm("2203.00;Cervejas de malte;3,9").unwrap_err(),
+            "o NCM deve ter 8 dígitos, recebido 2203.00"
+        );
+        assert!(ler_ncm("22030000;Cervejas de malte;três").is_err());
+        assert!(ler_ncm("22030000;Cervejas de malte").is_err());
+    }
+
+    #[test]
+    fn it_reads_a_line_of_the_cest_table() {
+        let cests = ler_cest("03.002.00;2201.10.00 2202;Água mineral em copo plástico").unwrap();
+        assert_eq!(cests.len(), 2);
+        assert_eq!(cests[0].codigo, "0300200");
+        assert_eq!(cests[0].ncm, "22011000");
+        assert_eq!(cests[1].ncm, "2202");
+        assert_eq!(
+            ler_cest("0300200;;Água mineral em copo plástico").unwrap_err(),
+            "o CEST 0300200 não lista nenhum NCM"
+        );
+        assert!(ler_cest("030020;2201;Água mineral em copo plástico").is_err());
+    }
+
+    #[test]
+    fn it_reads_the_bundled_tables() {
+        let ncms = ler_arquivo(TIPI, ler_ncm).unwrap();
+        assert!(ncms.iter().any(|ncm| ncm.codigo == "22021000"));
+        let cests = ler_arquivo(TABELA_CEST, ler_cest).unwrap();
+        assert!(cests.iter().flatten().any(|cest| cest.codigo == "0300700" && cest.ncm == "22021000"));
+    }
+
+    #[test]
+    fn it_imports_the_catalog() {
+        let ncms = "codigo;descricao;aliquota_ipi\n2204.21.00;Vinhos em recipientes de capacidade não superior a 2 l;6,5\n";
+        assert_eq!(importar_ncms(&get_pool(), ncms).unwrap(), 1);
+        let cests = "0600100;2204;Vinhos de uvas frescas\n";
+        assert_eq!(importar_cests(&get_pool(), cests).unwrap(), 1);
+        assert_eq!(buscar(&get_pool(), "2204.21").unwrap().0[0].cests[0].codigo, "0600100");
+
+        let invalido = importar_ncms(&get_pool(), "22042100;Vinhos;6,5\n2204;Vinhos;6,5");
+        let expected_error = ApiError::ValidationError(vec![
+            "linha 2: o NCM deve ter 8 dígitos, recebido 2204".to_string(),
+        ]);
+        assert_eq!(invalido.unwrap_err(), expected_error);
+    }
+
+    #[test]
+    fn it_finds_ncms_by_code_or_description() {
+        let por_codigo = buscar(&get_pool(), "2202.10").unwrap();
+        assert_eq!(por_codigo.0[0].codigo, "22021000");
+        assert!(por_codigo.0[0].cests.iter().any(|cest| cest.codigo == "0300700"));
+
+        let por_descricao = buscar(&get_pool(), "cerveja").unwrap();
+        assert_eq!(por_descricao.0[0].codigo, "22030000");
+    }
+
+    #[test]
+    fn it_accepts_a_cataloged_ncm_and_compatible_cest() {
+        let mut conn = get_pool().get().unwrap();
+        let catalogo = validar_produto(&mut conn, &codigo("22021000"), &codigo("0300700")).unwrap();
+        assert_eq!(catalogo.unwrap().codigo, "22021000");
+        assert!(validar_produto(&mut conn, &None, &None).unwrap().is_none());
+    }
+
+    #[test]
+    fn it_rejects_unknown_ncms_and_incompatible_cests() {
+        let mut conn = get_pool().get().unwrap();
+        let desconhecido = validar_produto(&mut conn, &codigo("99999999"), &None);
+        let expected_error = ApiError::ValidationError(vec![
+            "ncm: o NCM 99999999 não está no catálogo".to_string(),
+        ]);
+        assert_eq!(desconhecido.unwrap_err(), expected_error);
+
+        let incompativel = validar_produto(&mut conn, &codigo("22030000"), &codigo("0300700"));
+        let expected_error = ApiError::ValidationError(vec![
+            "cest: o CEST 0300700 não se aplica ao NCM 22030000".to_string(),
+        ]);
+        assert_eq!(incompativel.unwrap_err(), expected_error);
+    }
+}
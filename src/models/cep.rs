@@ -0,0 +1,317 @@
+use crate::database::PoolType;
+use crate::errors::ApiError;
+use crate::handlers::cep::{CepResponse, ImportacaoCepResponse};
+use crate::models::nfe::codigo_uf;
+use crate::models::user_permissao::{has_permissao, IMPORTAR_TABELAS};
+use crate::schema::{ceps, municipios};
+use diesel::prelude::*;
+use diesel::upsert::excluded;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// How many rows go into one INSERT of the import, Postgres takes at most
+/// 65535 parameters per statement
+const LOTE_IMPORTACAO: usize = 5000;
+
+/// Código de Endereçamento Postal, always 8 digits
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cep(String);
+
+/// A município of the IBGE table
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Queryable, Identifiable, Insertable)]
+#[table_name = "municipios"]
+#[primary_key(codigo)]
+pub struct Municipio {
+    pub codigo: String,
+    pub nome: String,
+    pub uf: String,
+}
+
+/// A CEP of the local base
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Queryable, Identifiable, Insertable)]
+#[table_name = "ceps"]
+#[primary_key(cep)]
+pub struct CepBase {
+    pub cep: String,
+    pub logradouro: Option<String>,
+    pub bairro: Option<String>,
+    pub codigo_municipio: String,
+}
+
+/// The address a CEP stands for
+///
+/// CEPs that cover a whole município have no logradouro nor bairro.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EnderecoCep {
+    pub cep: String,
+    pub logradouro: Option<String>,
+    pub bairro: Option<String>,
+    pub codigo_municipio: String,
+    pub municipio: String,
+    pub uf: String,
+}
+
+/// Finds the address of a CEP, in the local base or in an online service
+pub trait ConsultaCep {
+    /// None when the CEP is not known
+    fn consultar(&self, cep: &Cep) -> Result<Option<EnderecoCep>, ApiError>;
+}
+
+/// Looks CEPs up in the base loaded by `importar`
+pub struct ConsultaCepLocal<'a> {
+    pool: &'a PoolType,
+}
+
+impl<'a> ConsultaCepLocal<'a> {
+    pub fn new(pool: &'a PoolType) -> Self {
+        ConsultaCepLocal { pool }
+    }
+}
+
+impl ConsultaCep for ConsultaCepLocal<'_> {
+    fn consultar(&self, cep: &Cep) -> Result<Option<EnderecoCep>, ApiError> {
+        let mut conn = self.pool.get()?;
+        let encontrado = ceps::table
+            .inner_join(municipios::table)
+            .filter(ceps::cep.eq(cep.as_str()))
+            .first::<(CepBase, Municipio)>(&mut conn)
+            .optional()?;
+        Ok(encontrado.map(EnderecoCep::from))
+    }
+}
+
+impl FromStr for Cep {
+    type Err = String;
+
+    fn from_str(cep: &str) -> Result<Self, Self::Err> {
+        let codigo: String = cep.trim().chars().filter(|c| !['.', '-'].contains(c)).collect();
+        if codigo.len() == 8 && codigo.chars().all(|c| c.is_ascii_digit()) {
+            Ok(Cep(codigo))
+        } else {
+            Err(format!("o CEP deve ter 8 dígitos, recebido {}", cep))
+        }
+    }
+}
+
+impl Cep {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for Cep {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl From<(CepBase, Municipio)> for EnderecoCep {
+    fn from((cep, municipio): (CepBase, Municipio)) -> Self {
+        EnderecoCep {
+            cep: cep.cep,
+            logradouro: cep.logradouro,
+            bairro: cep.bairro,
+            codigo_municipio: municipio.codigo,
+            municipio: municipio.nome,
+            uf: municipio.uf,
+        }
+    }
+}
+
+/// Parse a CEP sent by the client, as a validation error
+pub fn ler_cep(cep: &str) -> Result<Cep, ApiError> {
+    Cep::from_str(cep).map_err(|erro| ApiError::ValidationError(vec![format!("cep: {}", erro)]))
+}
+
+/// Find the address of a CEP or error out
+pub fn buscar(consulta: &dyn ConsultaCep, cep: &str) -> Result<CepResponse, ApiError> {
+    let cep = ler_cep(cep)?;
+    match consulta.consultar(&cep)? {
+        Some(endereco) => Ok(endereco.into()),
+        None => Err(ApiError::NotFound(format!("CEP {} not found", cep))),
+    }
+}
+
+/// Load CEPs and their municípios into the local base
+///
+/// Each line is `cep;logradouro;bairro;codigo_ibge;municipio;uf`, the
+/// Correios DNE joined with the IBGE table. A header line starting with
+/// `cep` is skipped and CEPs already in the base are replaced. Nothing is
+/// stored when a line is invalid.
+pub fn importar(pool: &PoolType, conteudo: &str) -> Result<ImportacaoCepResponse, ApiError> {
+    let mut errors = Vec::new();
+    let mut novos_municipios = BTreeMap::new();
+    let mut novos_ceps = BTreeMap::new();
+    for (posicao, linha) in conteudo.lines().enumerate() {
+        let linha = linha.trim();
+        if linha.is_empty() || (posicao == 0 && linha.to_lowercase().starts_with("cep")) {
+            continue;
+        }
+        match ler_linha(linha) {
+            Ok((cep, municipio)) => {
+                novos_municipios.insert(municipio.codigo.clone(), municipio);
+                novos_ceps.insert(cep.cep.clone(), cep);
+            }
+            Err(erro) => errors.push(format!("linha {}: {}", posicao + 1, erro)),
+        }
+    }
+    if !errors.is_empty() {
+        return Err(ApiError::ValidationError(errors));
+    }
+
+    let novos_municipios: Vec<Municipio> = novos_municipios.into_values().collect();
+    let novos_ceps: Vec<CepBase> = novos_ceps.into_values().collect();
+    let mut conn = pool.get()?;
+    conn.transaction::<_, ApiError, _>(|conn| {
+        for lote in novos_municipios.chunks(LOTE_IMPORTACAO) {
+            diesel::insert_into(municipios::table)
+                .values(lote)
+                .on_conflict(municipios::codigo)
+                .do_update()
+                .set((municipios::nome.eq(excluded(municipios::nome)), municipios::uf.eq(excluded(municipios::uf))))
+                .execute(conn)?;
+        }
+        for lote in novos_ceps.chunks(LOTE_IMPORTACAO) {
+            diesel::insert_into(ceps::table)
+                .values(lote)
+                .on_conflict(ceps::cep)
+                .do_update()
+                .set((
+                    ceps::logradouro.eq(excluded(ceps::logradouro)),
+                    ceps::bairro.eq(excluded(ceps::bairro)),
+                    ceps::codigo_municipio.eq(excluded(ceps::codigo_municipio)),
+                ))
+                .execute(conn)?;
+        }
+        Ok(())
+    })?;
+
+    Ok(ImportacaoCepResponse {
+        municipios: novos_municipios.len(),
+        ceps: novos_ceps.len(),
+    })
+}
+
+/// Load a CEP file a user sent, which takes the importar_tabelas permission
+pub fn importar_enviado(pool: &PoolType, conteudo: &str, user_id: &str) -> Result<ImportacaoCepResponse, ApiError> {
+    {
+        let mut conn = pool.get()?;
+        if !has_permissao(&mut conn, user_id, IMPORTAR_TABELAS)? {
+            return Err(ApiError::Unauthorized(format!("User {} cannot import reference tables", user_id)));
+        }
+    }
+    importar(pool, conteudo)
+}
+
+fn ler_linha(linha: &str) -> Result<(CepBase, Municipio), String> {
+    let campos: Vec<&str> = linha.split(';').map(str::trim).collect();
+    if campos.len() != 6 {
+        return Err(format!("esperados 6 campos separados por ;, recebidos {}", campos.len()));
+    }
+    let cep = Cep::from_str(campos[0])?;
+    let codigo_municipio = campos[3];
+    if codigo_municipio.len() != 7 || !codigo_municipio.chars().all(|c| c.is_ascii_digit()) {
+        return Err(format!("o código IBGE deve ter 7 dígitos, recebido {}", codigo_municipio));
+    }
+    if campos[4].is_empty() {
+        return Err("o nome do município é obrigatório".to_string());
+    }
+    let uf = campos[5].to_uppercase();
+    match codigo_uf(&uf) {
+        Some(codigo) if codigo_municipio.starts_with(codigo) => {}
+        Some(_) => return Err(format!("o município {} não fica em {}", codigo_municipio, uf)),
+        None => return Err(format!("a UF {} não existe", campos[5])),
+    }
+
+    let preenchido = |campo: &str| Some(campo.to_string()).filter(|campo| !campo.is_empty());
+    Ok((
+        CepBase {
+            cep: cep.to_string(),
+            logradouro: preenchido(campos[1]),
+            bairro: preenchido(campos[2]),
+            codigo_municipio: codigo_municipio.to_string(),
+        },
+        Municipio {
+            codigo: codigo_municipio.to_string(),
+            nome: campos[4].to_string(),
+            uf,
+        },
+    ))
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::tests::helpers::tests::get_pool;
+
+    /// Answers from a fixed list, for tests that don't need the base
+    pub struct ConsultaCepFixa(pub Vec<EnderecoCep>);
+
+    impl ConsultaCep for ConsultaCepFixa {
+        fn consultar(&self, cep: &Cep) -> Result<Option<EnderecoCep>, ApiError> {
+            Ok(self.0.iter().find(|endereco| endereco.cep == cep.as_str()).cloned())
+        }
+    }
+
+    pub fn praca_da_se() -> EnderecoCep {
+        EnderecoCep {
+            cep: "01001000".to_string(),
+            logradouro: Some("Praça da Sé".to_string()),
+            bairro: Some("Sé".to_string()),
+            codigo_municipio: "3550308".to_string(),
+            municipio: "São Paulo".to_string(),
+            uf: "SP".to_string(),
+        }
+    }
+
+    #[test]
+    fn it_parses_a_cep() {
+        assert_eq!(Cep::from_str("01001-000").unwrap().as_str(), "01001000");
+        assert_eq!(Cep::from_str(" 01.001-000 ").unwrap().to_string(), "01001000");
+        assert_eq!(Cep::from_str("0100100").unwrap_err(), "o CEP deve ter 8 dígitos, recebido 0100100");
+        assert!(Cep::from_str("0100100A").is_err());
+    }
+
+    #[test]
+    fn it_reads_a_line_of_the_import() {
+        let (cep, municipio) = ler_linha("89814-000;;;4204202;Chapecó;sc").unwrap();
+        assert_eq!(cep.cep, "89814000");
+        assert_eq!(cep.logradouro, None);
+        assert_eq!(municipio.uf, "SC");
+
+        assert_eq!(
+            ler_linha("01001000;Praça da Sé;Sé;3550308;São Paulo;RJ").unwrap_err(),
+            "o município 3550308 não fica em RJ"
+        );
+        assert_eq!(
+            ler_linha("01001000;Praça da Sé;Sé;3550308;São Paulo").unwrap_err(),
+            "esperados 6 campos separados por ;, recebidos 5"
+        );
+        assert!(ler_linha("01001000;Praça da Sé;Sé;355030;São Paulo;SP").is_err());
+    }
+
+    #[test]
+    fn it_imports_and_finds_ceps() {
+        let conteudo = "cep;logradouro;bairro;codigo_ibge;municipio;uf\n01001000;Praça da Sé;Sé;3550308;São Paulo;SP\n";
+        let importacao = importar(&get_pool(), conteudo).unwrap();
+        assert_eq!(importacao.ceps, 1);
+        assert_eq!(importacao.municipios, 1);
+
+        let endereco = ConsultaCepLocal::new(&get_pool())
+            .consultar(&Cep::from_str("01001-000").unwrap())
+            .unwrap();
+        assert_eq!(endereco, Some(praca_da_se()));
+        let desconhecido = buscar(&ConsultaCepLocal::new(&get_pool()), "99999999");
+        assert_eq!(desconhecido.unwrap_err(), ApiError::NotFound("CEP 99999999 not found".to_string()));
+    }
+
+    #[test]
+    fn it_imports_nothing_when_a_line_is_invalid() {
+        let conteudo = "01001000;Praça da Sé;Sé;3550308;São Paulo;SP\n0100;;;3550308;São Paulo;SP";
+        let expected_error = ApiError::ValidationError(vec![
+            "linha 2: o CEP deve ter 8 dígitos, recebido 0100".to_string(),
+        ]);
+        assert_eq!(importar(&get_pool(), conteudo).unwrap_err(), expected_error);
+    }
+}
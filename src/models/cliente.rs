@@ -2,15 +2,19 @@ use crate::database::PoolType;
 use crate::errors::ApiError;
 use crate::handlers::cliente::{ClienteResponse, ClientesResponse};
 use crate::schema::clientes;
+use bigdecimal::BigDecimal;
 use chrono::{NaiveDateTime, NaiveDate, NaiveTime, Utc};
+use diesel::pg::PgConnection;
 use diesel::prelude::*;
+use std::fmt;
+use std::str::FromStr;
 use uuid::Uuid;
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Queryable, Identifiable, Insertable)]
 pub struct Cliente {
     pub id: String,
-    pub nome: String,
-    pub sobrenome: String,
+    pub nome: Option<String>,
+    pub sobrenome: Option<String>,
     pub cpf: Option<String>,
     pub rg: Option<String>,
     pub data_nascimento: Option<NaiveDateTime>,
@@ -21,38 +25,170 @@ pub struct Cliente {
     pub created_at: NaiveDateTime,
     pub updated_by: String,
     pub updated_at: NaiveDateTime,
+    pub tipo_pessoa: String,
+    pub cnpj: Option<String>,
+    pub razao_social: Option<String>,
+    pub nome_fantasia: Option<String>,
+    pub inscricao_estadual: Option<String>,
+    /// None when the cliente has no limit, changed only through the credito routes
+    pub limite_credito: Option<BigDecimal>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct NewCliente {
     pub id: String,
-    pub nome: String,
-    pub sobrenome: String,
+    pub tipo_pessoa: TipoPessoa,
+    pub nome: Option<String>,
+    pub sobrenome: Option<String>,
     pub cpf: Option<String>,
     pub rg: Option<String>,
     pub data_nascimento: Option<NaiveDateTime>,
     pub sexo: Option<String>,
     pub estado_civil: Option<String>,
     pub telefone: Option<String>,
+    pub cnpj: Option<String>,
+    pub razao_social: Option<String>,
+    pub nome_fantasia: Option<String>,
+    pub inscricao_estadual: Option<String>,
     pub created_by: String,
     pub updated_by: String,
 }
 
+/// Every field is written, so a cliente changing tipo_pessoa drops the
+/// fields of the other tipo
 #[derive(Clone, Debug, Serialize, Deserialize, AsChangeset)]
 #[table_name = "clientes"]
+#[diesel(treat_none_as_null = true)]
 pub struct UpdateCliente {
     pub id: String,
-    pub nome: String,
-    pub sobrenome: String,
+    pub tipo_pessoa: String,
+    pub nome: Option<String>,
+    pub sobrenome: Option<String>,
     pub cpf: Option<String>,
     pub rg: Option<String>,
     pub data_nascimento: Option<NaiveDateTime>,
     pub sexo: Option<String>,
     pub estado_civil: Option<String>,
     pub telefone: Option<String>,
+    pub cnpj: Option<String>,
+    pub razao_social: Option<String>,
+    pub nome_fantasia: Option<String>,
+    pub inscricao_estadual: Option<String>,
     pub updated_by: String,
 }
 
+/// Whether a cliente is a person or a company
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TipoPessoa {
+    Fisica,
+    Juridica,
+}
+
+impl TipoPessoa {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TipoPessoa::Fisica => "fisica",
+            TipoPessoa::Juridica => "juridica",
+        }
+    }
+}
+
+impl Default for TipoPessoa {
+    fn default() -> Self {
+        TipoPessoa::Fisica
+    }
+}
+
+impl fmt::Display for TipoPessoa {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for TipoPessoa {
+    type Err = ApiError;
+
+    fn from_str(tipo: &str) -> Result<Self, Self::Err> {
+        match tipo {
+            "fisica" => Ok(TipoPessoa::Fisica),
+            "juridica" => Ok(TipoPessoa::Juridica),
+            _ => Err(ApiError::InternalServerError(format!("Unknown tipo de pessoa {}", tipo))),
+        }
+    }
+}
+
+impl Cliente {
+    /// The legal name: nome and sobrenome of a person, razão social of a company
+    pub fn nome_completo(&self) -> String {
+        match self.tipo_pessoa.as_str() {
+            "juridica" => self.razao_social.clone().unwrap_or_default(),
+            _ => format!(
+                "{} {}",
+                self.nome.as_deref().unwrap_or_default(),
+                self.sobrenome.as_deref().unwrap_or_default()
+            )
+            .trim()
+            .to_string(),
+        }
+    }
+
+    /// The name the cliente goes by, the nome fantasia of a company that has one
+    pub fn nome_exibicao(&self) -> String {
+        self.nome_fantasia
+            .clone()
+            .filter(|nome_fantasia| !nome_fantasia.trim().is_empty())
+            .unwrap_or_else(|| self.nome_completo())
+    }
+
+    /// The CPF of a person or the CNPJ of a company
+    pub fn documento(&self) -> Option<&str> {
+        match self.tipo_pessoa.as_str() {
+            "juridica" => self.cnpj.as_deref(),
+            _ => self.cpf.as_deref(),
+        }
+        .map(str::trim)
+    }
+
+    /// Check that the cliente has the fields its tipo_pessoa needs and none
+    /// of the other tipo's
+    pub fn validar_pessoa(&self) -> Result<(), ApiError> {
+        let tipo = TipoPessoa::from_str(&self.tipo_pessoa)?;
+        let pessoa_fisica = [
+            ("nome", self.nome.is_some()),
+            ("sobrenome", self.sobrenome.is_some()),
+            ("cpf", self.cpf.is_some()),
+            ("rg", self.rg.is_some()),
+            ("data_nascimento", self.data_nascimento.is_some()),
+            ("sexo", self.sexo.is_some()),
+            ("estado_civil", self.estado_civil.is_some()),
+        ];
+        let pessoa_juridica = [
+            ("cnpj", self.cnpj.is_some()),
+            ("razao_social", self.razao_social.is_some()),
+            ("nome_fantasia", self.nome_fantasia.is_some()),
+            ("inscricao_estadual", self.inscricao_estadual.is_some()),
+        ];
+
+        let mut errors = Vec::new();
+        // nome and sobrenome, cnpj and razao_social are the required ones
+        let (obrigatorios, proibidos, pessoas, outras) = match tipo {
+            TipoPessoa::Fisica => (&pessoa_fisica[..2], &pessoa_juridica[..], "físicas", "jurídicas"),
+            TipoPessoa::Juridica => (&pessoa_juridica[..2], &pessoa_fisica[..], "jurídicas", "físicas"),
+        };
+        for (campo, _) in obrigatorios.iter().filter(|(_, preenchido)| !preenchido) {
+            errors.push(format!("{}: o campo é obrigatório para pessoas {}", campo, pessoas));
+        }
+        for (campo, _) in proibidos.iter().filter(|(_, preenchido)| *preenchido) {
+            errors.push(format!("{}: apenas pessoas {} têm este campo", campo, outras));
+        }
+        if !errors.is_empty() {
+            return Err(ApiError::ValidationError(errors));
+        }
+        Ok(())
+    }
+}
+
 /// Get all clientes
 pub fn get_all(pool: &PoolType) -> Result<ClientesResponse, ApiError> {
     use crate::schema::clientes::dsl::clientes;
@@ -65,23 +201,28 @@ pub fn get_all(pool: &PoolType) -> Result<ClientesResponse, ApiError> {
 
 /// Find a cliente by the cliente's id or error out
 pub fn find(pool: &PoolType, cliente_id: Uuid) -> Result<ClienteResponse, ApiError> {
+    let mut conn = pool.get()?;
+    Ok(find_cliente(&mut conn, &cliente_id.to_string())?.into())
+}
+
+/// Find a cliente using an existing connection
+pub fn find_cliente(conn: &mut PgConnection, cliente_id: &str) -> Result<Cliente, ApiError> {
     use crate::schema::clientes::dsl::{id, clientes};
 
     let not_found = format!("Cliente {} not found", cliente_id);
-    let mut conn = pool.get()?;
-    let cliente = clientes
-        .filter(id.eq(cliente_id.to_string()))
-        .first::<Cliente>(&mut conn)
-        .map_err(|_| ApiError::NotFound(not_found))?;
-
-    Ok(cliente.into())
+    clientes
+        .filter(id.eq(cliente_id))
+        .first::<Cliente>(conn)
+        .map_err(|_| ApiError::NotFound(not_found))
 }
 
 /// Create a new cliente
 pub fn create(pool: &PoolType, new_cliente: &Cliente) -> Result<ClienteResponse, ApiError> {
     use crate::schema::clientes::dsl::clientes;
 
+    new_cliente.validar_pessoa()?;
     let mut conn = pool.get()?;
+    validar_documento_unico(&mut conn, new_cliente)?;
     diesel::insert_into(clientes).values(new_cliente).execute(&mut conn)?;
     Ok(new_cliente.clone().into())
 }
@@ -91,6 +232,25 @@ pub fn update(pool: &PoolType, update_cliente: &UpdateCliente) -> Result<Cliente
     use crate::schema::clientes::dsl::{id, clientes};
 
     let mut conn = pool.get()?;
+    let atual = find_cliente(&mut conn, &update_cliente.id)?;
+    let atualizado = Cliente {
+        tipo_pessoa: update_cliente.tipo_pessoa.clone(),
+        nome: update_cliente.nome.clone(),
+        sobrenome: update_cliente.sobrenome.clone(),
+        cpf: update_cliente.cpf.clone(),
+        rg: update_cliente.rg.clone(),
+        data_nascimento: update_cliente.data_nascimento,
+        sexo: update_cliente.sexo.clone(),
+        estado_civil: update_cliente.estado_civil.clone(),
+        telefone: update_cliente.telefone.clone(),
+        cnpj: update_cliente.cnpj.clone(),
+        razao_social: update_cliente.razao_social.clone(),
+        nome_fantasia: update_cliente.nome_fantasia.clone(),
+        inscricao_estadual: update_cliente.inscricao_estadual.clone(),
+        ..atual
+    };
+    atualizado.validar_pessoa()?;
+    validar_documento_unico(&mut conn, &atualizado)?;
     diesel::update(clientes)
         .filter(id.eq(update_cliente.id.clone()))
         .set(update_cliente)
@@ -98,6 +258,34 @@ pub fn update(pool: &PoolType, update_cliente: &UpdateCliente) -> Result<Cliente
     find(&pool, Uuid::parse_str(&update_cliente.id)?)
 }
 
+/// Check no other cliente has the cpf or cnpj of a cliente
+///
+/// The unique indexes on both columns have the last word, this only
+/// tells which cliente already has the document.
+pub fn validar_documento_unico(conn: &mut PgConnection, cliente: &Cliente) -> Result<(), ApiError> {
+    use crate::schema::clientes::dsl::{clientes, cnpj, cpf, id};
+
+    for (campo, documento) in [("cpf", &cliente.cpf), ("cnpj", &cliente.cnpj)] {
+        let documento = match documento {
+            Some(documento) => documento,
+            None => continue,
+        };
+        let outro = clientes
+            .filter(id.ne(&cliente.id))
+            .filter(cpf.eq(documento).or(cnpj.eq(documento)))
+            .select(id)
+            .first::<String>(conn)
+            .optional()?;
+        if let Some(outro) = outro {
+            return Err(ApiError::Conflict(format!(
+                "Cliente {} already has the {} {}",
+                outro, campo, documento
+            )));
+        }
+    }
+    Ok(())
+}
+
 /// Delete a cliente
 pub fn delete(pool: &PoolType, cliente_id: Uuid) -> Result<(), ApiError> {
     use crate::schema::clientes::dsl::{id, clientes};
@@ -125,6 +313,12 @@ impl From<NewCliente> for Cliente {
             created_at: Utc::now().naive_utc(),
             updated_by: cliente.updated_by,
             updated_at: Utc::now().naive_utc(),
+            tipo_pessoa: cliente.tipo_pessoa.as_str().to_string(),
+            cnpj: cliente.cnpj,
+            razao_social: cliente.razao_social,
+            nome_fantasia: cliente.nome_fantasia,
+            inscricao_estadual: cliente.inscricao_estadual,
+            limite_credito: None,
         }
     }
 }
@@ -132,6 +326,7 @@ impl From<NewCliente> for Cliente {
 #[cfg(test)]
 pub mod tests {
     use super::*;
+    use crate::models::documento::tests::{cnpj_aleatorio, cpf_aleatorio};
     use crate::tests::helpers::tests::get_pool;
 
     pub fn get_all_clientes() -> Result<ClientesResponse, ApiError> {
@@ -143,14 +338,43 @@ pub mod tests {
         let cliente_id = Uuid::new_v4();
         let new_cliente = NewCliente {
             id: cliente_id.to_string(),
-            nome: "Model".to_string(),
-            sobrenome: "Test".to_string(),
-            cpf: Some("12345678901".to_string()),
+            tipo_pessoa: TipoPessoa::Fisica,
+            nome: Some("Model".to_string()),
+            sobrenome: Some("Test".to_string()),
+            cpf: Some(cpf_aleatorio()),
             rg: Some("123456789".to_string()),
             data_nascimento: Some(NaiveDateTime::new(NaiveDate::from_ymd(1990, 1, 1),NaiveTime::from_hms_milli(0, 0, 0, 0))),
             sexo: Some("M".to_string()),
             estado_civil: Some("Solteiro".to_string()),
             telefone: Some("12345678901".to_string()),
+            cnpj: None,
+            razao_social: None,
+            nome_fantasia: None,
+            inscricao_estadual: None,
+            created_by: cliente_id.to_string(),
+            updated_by: cliente_id.to_string(),
+        };
+        let cliente: Cliente = new_cliente.into();
+        create(&get_pool(), &cliente)
+    }
+
+    pub fn create_empresa() -> Result<ClienteResponse, ApiError> {
+        let cliente_id = Uuid::new_v4();
+        let new_cliente = NewCliente {
+            id: cliente_id.to_string(),
+            tipo_pessoa: TipoPessoa::Juridica,
+            nome: None,
+            sobrenome: None,
+            cpf: None,
+            rg: None,
+            data_nascimento: None,
+            sexo: None,
+            estado_civil: None,
+            telefone: Some("4133334444".to_string()),
+            cnpj: Some(cnpj_aleatorio()),
+            razao_social: Some("Empresa Model Ltda".to_string()),
+            nome_fantasia: Some("Empresa Model".to_string()),
+            inscricao_estadual: Some("9012345678".to_string()),
             created_by: cliente_id.to_string(),
             updated_by: cliente_id.to_string(),
         };
@@ -195,14 +419,19 @@ pub mod tests {
         let created = create_cliente().unwrap();
         let update_cliente = UpdateCliente {
             id: created.id.to_string(),
-            nome: "ModelUpdate".to_string(),
-            sobrenome: "TestUpdate".to_string(),
-            cpf: Some("12345678901".to_string()),
+            tipo_pessoa: "fisica".to_string(),
+            nome: Some("ModelUpdate".to_string()),
+            sobrenome: Some("TestUpdate".to_string()),
+            cpf: created.cpf.clone(),
             rg: Some("123456789".to_string()),
             data_nascimento: Some(NaiveDateTime::new(NaiveDate::from_ymd(1990, 1, 1),NaiveTime::from_hms_milli(0, 0, 0, 0))),
             sexo: Some("M".to_string()),
             estado_civil: Some("Solteiro".to_string()),
             telefone: Some("12345678901".to_string()),
+            cnpj: None,
+            razao_social: None,
+            nome_fantasia: None,
+            inscricao_estadual: None,
             updated_by: created.id.to_string(),
         };
         let updated = update(&get_pool(), &update_cliente);
@@ -217,20 +446,61 @@ pub mod tests {
         let cliente_id = Uuid::new_v4();
         let update_cliente = UpdateCliente {
             id: cliente_id.to_string(),
-            nome: "ModelUpdateFailure".to_string(),
-            sobrenome: "TestUpdateFailure".to_string(),
-            cpf: Some("12345678901".to_string()),
+            tipo_pessoa: "fisica".to_string(),
+            nome: Some("ModelUpdateFailure".to_string()),
+            sobrenome: Some("TestUpdateFailure".to_string()),
+            cpf: Some("52998224725".to_string()),
             rg: Some("123456789".to_string()),
             data_nascimento: Some(NaiveDateTime::new(NaiveDate::from_ymd(1990, 1, 1),NaiveTime::from_hms_milli(0, 0, 0, 0))),
             sexo: Some("M".to_string()),
             estado_civil: Some("Solteiro".to_string()),
             telefone: Some("12345678901".to_string()),
+            cnpj: None,
+            razao_social: None,
+            nome_fantasia: None,
+            inscricao_estadual: None,
             updated_by: cliente_id.to_string(),
         };
         let updated = update(&get_pool(), &update_cliente);
         assert!(updated.is_err());
     }
 
+    #[test]
+    fn it_doesnt_create_a_cliente_with_a_taken_cpf() {
+        let created = create_cliente().unwrap();
+        let cliente_id = Uuid::new_v4();
+        let cliente = Cliente {
+            id: cliente_id.to_string(),
+            ..find_cliente(&mut get_pool().get().unwrap(), &created.id.to_string()).unwrap()
+        };
+        let expected_error = ApiError::Conflict(format!(
+            "Cliente {} already has the cpf {}",
+            created.id,
+            created.cpf.clone().unwrap()
+        ));
+        assert_eq!(create(&get_pool(), &cliente).unwrap_err(), expected_error);
+        delete(&get_pool(), created.id).unwrap();
+    }
+
+    #[test]
+    fn it_checks_the_cnpj_of_a_cliente_with_a_cpf() {
+        let empresa = create_empresa().unwrap();
+        let created = create_cliente().unwrap();
+        let cliente = Cliente {
+            cnpj: empresa.cnpj.clone(),
+            ..find_cliente(&mut get_pool().get().unwrap(), &created.id.to_string()).unwrap()
+        };
+        let response = validar_documento_unico(&mut get_pool().get().unwrap(), &cliente);
+        delete(&get_pool(), created.id).unwrap();
+        delete(&get_pool(), empresa.id).unwrap();
+        let expected_error = ApiError::Conflict(format!(
+            "Cliente {} already has the cnpj {}",
+            empresa.id,
+            empresa.cnpj.unwrap()
+        ));
+        assert_eq!(response.unwrap_err(), expected_error);
+    }
+
     #[test]
     fn it_deletes_a_cliente() {
         let created = create_cliente();
@@ -241,4 +511,56 @@ pub mod tests {
         let cliente = find(&get_pool(), cliente_id);
         assert!(cliente.is_err());
     }
+
+    #[test]
+    fn it_creates_a_pessoa_juridica() {
+        let created = create_empresa().unwrap();
+        assert_eq!(created.tipo_pessoa, "juridica");
+        assert_eq!(created.nome_exibicao, "Empresa Model");
+        assert_eq!(find(&get_pool(), created.id).unwrap(), created);
+        delete(&get_pool(), created.id).unwrap();
+    }
+
+    #[test]
+    fn it_switches_the_fields_with_the_tipo_pessoa() {
+        let cliente_id = Uuid::new_v4().to_string();
+        let mut cliente: Cliente = NewCliente {
+            id: cliente_id.clone(),
+            tipo_pessoa: TipoPessoa::Juridica,
+            nome: Some("Fulano".to_string()),
+            sobrenome: None,
+            cpf: Some("52998224725".to_string()),
+            rg: None,
+            data_nascimento: None,
+            sexo: None,
+            estado_civil: None,
+            telefone: None,
+            cnpj: None,
+            razao_social: Some("Fulano Comercio Ltda".to_string()),
+            nome_fantasia: None,
+            inscricao_estadual: None,
+            created_by: cliente_id.clone(),
+            updated_by: cliente_id,
+        }
+        .into();
+        let expected_error = ApiError::ValidationError(vec![
+            "cnpj: o campo é obrigatório para pessoas jurídicas".to_string(),
+            "nome: apenas pessoas físicas têm este campo".to_string(),
+            "cpf: apenas pessoas físicas têm este campo".to_string(),
+        ]);
+        assert_eq!(cliente.validar_pessoa().unwrap_err(), expected_error);
+        assert_eq!(cliente.nome_exibicao(), "Fulano Comercio Ltda");
+
+        cliente.tipo_pessoa = TipoPessoa::Fisica.to_string();
+        let expected_error = ApiError::ValidationError(vec![
+            "sobrenome: o campo é obrigatório para pessoas físicas".to_string(),
+            "razao_social: apenas pessoas jurídicas têm este campo".to_string(),
+        ]);
+        assert_eq!(cliente.validar_pessoa().unwrap_err(), expected_error);
+        cliente.sobrenome = Some("de Tal".to_string());
+        cliente.razao_social = None;
+        assert!(cliente.validar_pessoa().is_ok());
+        assert_eq!(cliente.nome_exibicao(), "Fulano de Tal");
+        assert_eq!(cliente.documento(), Some("52998224725"));
+    }
 }
@@ -0,0 +1,477 @@
+use crate::database::PoolType;
+use crate::errors::ApiError;
+use crate::handlers::cliente::ClienteResponse;
+use crate::handlers::cliente_duplicado::ClienteDuplicadosResponse;
+use crate::models::cliente::{find_cliente, Cliente, UpdateCliente};
+use crate::models::cliente_endereco::find_principal;
+use crate::schema::clientes_documentos_repetidos;
+use chrono::Utc;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use std::collections::BTreeMap;
+use uuid::Uuid;
+
+/// The cpf or cnpj a cliente gave up for being registered again with it,
+/// kept until the cliente is merged into the one that holds it
+#[derive(Clone, Debug, PartialEq, Queryable, Insertable)]
+#[table_name = "clientes_documentos_repetidos"]
+pub struct DocumentoRepetido {
+    pub id_cliente: String,
+    pub cpf: Option<String>,
+    pub cnpj: Option<String>,
+}
+
+/// Clientes that look like the same person or company
+#[derive(Clone, Debug, PartialEq)]
+pub struct GrupoDuplicado {
+    /// The name every cliente of the group shares once normalized
+    pub nome: String,
+    /// Oldest first, the usual choice to keep when merging
+    pub clientes: Vec<Cliente>,
+}
+
+/// Lowercase a name and drop its accents and punctuation, so that
+/// "José  da Silva" and "jose da silva" compare equal
+pub fn normalizar_nome(nome: &str) -> String {
+    nome.chars()
+        .flat_map(char::to_lowercase)
+        .map(sem_acento)
+        .map(|c| if c.is_alphanumeric() { c } else { ' ' })
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+}
+
+fn sem_acento(c: char) -> char {
+    match c {
+        'á' | 'à' | 'â' | 'ã' | 'ä' => 'a',
+        'é' | 'è' | 'ê' | 'ë' => 'e',
+        'í' | 'ì' | 'î' | 'ï' => 'i',
+        'ó' | 'ò' | 'ô' | 'õ' | 'ö' => 'o',
+        'ú' | 'ù' | 'û' | 'ü' => 'u',
+        'ç' => 'c',
+        'ñ' => 'n',
+        c => c,
+    }
+}
+
+fn telefone(cliente: &Cliente) -> Option<String> {
+    let digitos: String = cliente
+        .telefone
+        .as_deref()
+        .unwrap_or_default()
+        .chars()
+        .filter(char::is_ascii_digit)
+        .collect();
+    Some(digitos).filter(|digitos| !digitos.is_empty())
+}
+
+/// Two clientes with the same normalized name are the same when they also
+/// share the data de nascimento or the telefone
+fn parecidos(cliente: &Cliente, outro: &Cliente) -> bool {
+    let mesmo_nascimento = cliente.data_nascimento.is_some()
+        && cliente.data_nascimento.map(|data| data.date()) == outro.data_nascimento.map(|data| data.date());
+    let mesmo_telefone = telefone(cliente).is_some() && telefone(cliente) == telefone(outro);
+    mesmo_nascimento || mesmo_telefone
+}
+
+/// Group the clientes that look like duplicates of each other
+///
+/// Likeness is transitive, A and C end up in the same group when both look
+/// like B even if they don't look like each other.
+pub fn agrupar(clientes: Vec<Cliente>) -> Vec<GrupoDuplicado> {
+    let mut por_nome: BTreeMap<String, Vec<Cliente>> = BTreeMap::new();
+    for cliente in clientes {
+        let nome = normalizar_nome(&cliente.nome_completo());
+        if !nome.is_empty() {
+            por_nome.entry(nome).or_default().push(cliente);
+        }
+    }
+
+    let mut grupos = Vec::new();
+    for (nome, mut clientes) in por_nome.into_iter().filter(|(_, clientes)| clientes.len() > 1) {
+        clientes.sort_by_key(|cliente| cliente.created_at);
+        // every cliente starts in its own group, likes pull groups together
+        let mut grupo: Vec<usize> = (0..clientes.len()).collect();
+        for i in 0..clientes.len() {
+            for j in i + 1..clientes.len() {
+                if grupo[i] != grupo[j] && parecidos(&clientes[i], &clientes[j]) {
+                    let (de, para) = (grupo[j], grupo[i]);
+                    grupo.iter_mut().filter(|g| **g == de).for_each(|g| *g = para);
+                }
+            }
+        }
+
+        let mut por_grupo: BTreeMap<usize, Vec<Cliente>> = BTreeMap::new();
+        for (cliente, g) in clientes.into_iter().zip(grupo) {
+            por_grupo.entry(g).or_default().push(cliente);
+        }
+        grupos.extend(
+            por_grupo
+                .into_values()
+                .filter(|clientes| clientes.len() > 1)
+                .map(|clientes| GrupoDuplicado { nome: nome.clone(), clientes }),
+        );
+    }
+    grupos
+}
+
+/// Find the clientes that look registered more than once
+pub fn find_duplicados(pool: &PoolType) -> Result<ClienteDuplicadosResponse, ApiError> {
+    use crate::schema::clientes::dsl::clientes;
+
+    let mut conn = pool.get()?;
+    let todos = clientes.load::<Cliente>(&mut conn)?;
+    Ok(agrupar(todos).into())
+}
+
+/// Fill the blanks of a cliente with what its duplicate has
+fn completar(cliente: Cliente, duplicado: Cliente) -> Cliente {
+    Cliente {
+        nome: cliente.nome.or(duplicado.nome),
+        sobrenome: cliente.sobrenome.or(duplicado.sobrenome),
+        cpf: cliente.cpf.or(duplicado.cpf),
+        rg: cliente.rg.or(duplicado.rg),
+        data_nascimento: cliente.data_nascimento.or(duplicado.data_nascimento),
+        sexo: cliente.sexo.or(duplicado.sexo),
+        estado_civil: cliente.estado_civil.or(duplicado.estado_civil),
+        telefone: cliente.telefone.or(duplicado.telefone),
+        cnpj: cliente.cnpj.or(duplicado.cnpj),
+        razao_social: cliente.razao_social.or(duplicado.razao_social),
+        nome_fantasia: cliente.nome_fantasia.or(duplicado.nome_fantasia),
+        inscricao_estadual: cliente.inscricao_estadual.or(duplicado.inscricao_estadual),
+        limite_credito: cliente.limite_credito.or(duplicado.limite_credito),
+        ..cliente
+    }
+}
+
+/// Move everything that points at a duplicate to the cliente that stays
+fn reapontar(conn: &mut PgConnection, duplicado_id: &str, cliente_id: &str) -> Result<(), ApiError> {
+    use crate::schema::{cliente_enderecos, lgpd_registros, orcamentos, pedidos, titulos_receber};
+
+    diesel::update(orcamentos::table.filter(orcamentos::id_cliente.eq(duplicado_id)))
+        .set(orcamentos::id_cliente.eq(cliente_id))
+        .execute(conn)?;
+    diesel::update(pedidos::table.filter(pedidos::id_cliente.eq(duplicado_id)))
+        .set(pedidos::id_cliente.eq(cliente_id))
+        .execute(conn)?;
+    // the cliente keeps its principal endereço when it has one
+    if find_principal(conn, cliente_id)?.is_some() {
+        diesel::update(cliente_enderecos::table.filter(cliente_enderecos::id_cliente.eq(duplicado_id)))
+            .set(cliente_enderecos::principal.eq(false))
+            .execute(conn)?;
+    }
+    diesel::update(cliente_enderecos::table.filter(cliente_enderecos::id_cliente.eq(duplicado_id)))
+        .set(cliente_enderecos::id_cliente.eq(cliente_id))
+        .execute(conn)?;
+    diesel::update(titulos_receber::table.filter(titulos_receber::id_cliente.eq(duplicado_id)))
+        .set(titulos_receber::id_cliente.eq(cliente_id))
+        .execute(conn)?;
+    diesel::update(lgpd_registros::table.filter(lgpd_registros::id_cliente.eq(duplicado_id)))
+        .set(lgpd_registros::id_cliente.eq(cliente_id))
+        .execute(conn)?;
+    Ok(())
+}
+
+/// The document a cliente has parked, if any
+pub fn find_documento_repetido(
+    conn: &mut PgConnection,
+    cliente_id: &str,
+) -> Result<Option<DocumentoRepetido>, ApiError> {
+    use crate::schema::clientes_documentos_repetidos::dsl::{clientes_documentos_repetidos, id_cliente};
+
+    let documento = clientes_documentos_repetidos
+        .filter(id_cliente.eq(cliente_id))
+        .first::<DocumentoRepetido>(conn)
+        .optional()?;
+    Ok(documento)
+}
+
+/// Give a merged cliente without a cpf or cnpj the one either side parked
+///
+/// A document another cliente still holds can't be taken, it stays parked on
+/// the merged cliente instead.
+fn restaurar_documentos(
+    conn: &mut PgConnection,
+    mesclado: &mut Cliente,
+    repetidos: Vec<DocumentoRepetido>,
+) -> Result<(), ApiError> {
+    use crate::schema::clientes::dsl::{clientes, cnpj, cpf, id};
+    use crate::schema::clientes_documentos_repetidos::dsl::{clientes_documentos_repetidos, id_cliente};
+
+    diesel::delete(clientes_documentos_repetidos.filter(id_cliente.eq(&mesclado.id))).execute(conn)?;
+    let mut estacionado = DocumentoRepetido {
+        id_cliente: mesclado.id.clone(),
+        cpf: None,
+        cnpj: None,
+    };
+    for repetido in repetidos {
+        if let (None, Some(documento)) = (&mesclado.cpf, repetido.cpf) {
+            let outros = clientes.filter(cpf.eq(&documento)).filter(id.ne(&mesclado.id));
+            if diesel::select(diesel::dsl::exists(outros)).get_result(conn)? {
+                estacionado.cpf = Some(documento);
+            } else {
+                mesclado.cpf = Some(documento);
+            }
+        }
+        if let (None, Some(documento)) = (&mesclado.cnpj, repetido.cnpj) {
+            let outros = clientes.filter(cnpj.eq(&documento)).filter(id.ne(&mesclado.id));
+            if diesel::select(diesel::dsl::exists(outros)).get_result(conn)? {
+                estacionado.cnpj = Some(documento);
+            } else {
+                mesclado.cnpj = Some(documento);
+            }
+        }
+    }
+    if mesclado.cpf.is_some() {
+        estacionado.cpf = None;
+    }
+    if mesclado.cnpj.is_some() {
+        estacionado.cnpj = None;
+    }
+    if estacionado.cpf.is_some() || estacionado.cnpj.is_some() {
+        diesel::insert_into(clientes_documentos_repetidos)
+            .values(&estacionado)
+            .execute(conn)?;
+    }
+    Ok(())
+}
+
+/// Merge a duplicate into a cliente, all or nothing
+///
+/// The orcamentos, pedidos, enderecos, títulos and LGPD registros of the
+/// duplicate move to the cliente, which keeps its own data and takes the
+/// duplicate's only where it has none, parked documents included. The
+/// duplicate is deleted.
+pub fn mesclar(
+    pool: &PoolType,
+    cliente_id: Uuid,
+    duplicado_id: Uuid,
+    user_id: &str,
+) -> Result<ClienteResponse, ApiError> {
+    use crate::schema::clientes::dsl::{clientes, id, limite_credito, updated_at};
+
+    if cliente_id == duplicado_id {
+        return Err(ApiError::ValidationError(vec![
+            "id_duplicado: um cliente não pode ser mesclado com ele mesmo".to_string(),
+        ]));
+    }
+    let mut conn = pool.get()?;
+    conn.transaction::<_, ApiError, _>(|conn| {
+        let cliente = find_cliente(conn, &cliente_id.to_string())?;
+        let duplicado = find_cliente(conn, &duplicado_id.to_string())?;
+        if cliente.tipo_pessoa != duplicado.tipo_pessoa {
+            return Err(ApiError::ValidationError(vec![format!(
+                "id_duplicado: o cliente {} é pessoa {} e o duplicado pessoa {}",
+                cliente_id, cliente.tipo_pessoa, duplicado.tipo_pessoa
+            )]));
+        }
+
+        let repetidos = [&cliente.id, &duplicado.id]
+            .iter()
+            .map(|cliente_id| find_documento_repetido(conn, cliente_id))
+            .collect::<Result<Vec<_>, ApiError>>()?
+            .into_iter()
+            .flatten()
+            .collect();
+        reapontar(conn, &duplicado.id, &cliente.id)?;
+        // the documents are unique, the duplicate has to go before the
+        // cliente can take them
+        diesel::delete(clientes.filter(id.eq(&duplicado.id))).execute(conn)?;
+        let mut mesclado = completar(cliente, duplicado);
+        restaurar_documentos(conn, &mut mesclado, repetidos)?;
+        let update_cliente = UpdateCliente {
+            id: mesclado.id.clone(),
+            tipo_pessoa: mesclado.tipo_pessoa.clone(),
+            nome: mesclado.nome.clone(),
+            sobrenome: mesclado.sobrenome.clone(),
+            cpf: mesclado.cpf.clone(),
+            rg: mesclado.rg.clone(),
+            data_nascimento: mesclado.data_nascimento,
+            sexo: mesclado.sexo.clone(),
+            estado_civil: mesclado.estado_civil.clone(),
+            telefone: mesclado.telefone.clone(),
+            cnpj: mesclado.cnpj.clone(),
+            razao_social: mesclado.razao_social.clone(),
+            nome_fantasia: mesclado.nome_fantasia.clone(),
+            inscricao_estadual: mesclado.inscricao_estadual.clone(),
+            updated_by: user_id.to_string(),
+        };
+        diesel::update(clientes.filter(id.eq(&mesclado.id)))
+            .set((
+                &update_cliente,
+                limite_credito.eq(&mesclado.limite_credito),
+                updated_at.eq(Utc::now().naive_utc()),
+            ))
+            .execute(conn)?;
+        Ok(find_cliente(conn, &mesclado.id)?.into())
+    })
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::models::cliente::tests::create_cliente;
+    use crate::models::cliente::{create, delete, find, NewCliente, TipoPessoa};
+    use crate::models::documento::tests::cpf_aleatorio;
+    use crate::models::fiscal::ConfigFiscal;
+    use crate::models::lgpd::tests::delete_registros;
+    use crate::models::lgpd::{exportar, find_registros};
+    use crate::models::orcamento::tests::create_orcamento;
+    use crate::models::orcamento::{delete as delete_orcamento, find_header};
+    use crate::tests::helpers::tests::get_pool;
+    use chrono::{NaiveDate, NaiveDateTime};
+
+    fn pessoa(nome: &str, sobrenome: &str, nascimento: Option<NaiveDateTime>, telefone: Option<&str>) -> Cliente {
+        let cliente_id = Uuid::new_v4();
+        NewCliente {
+            id: cliente_id.to_string(),
+            tipo_pessoa: TipoPessoa::Fisica,
+            nome: Some(nome.to_string()),
+            sobrenome: Some(sobrenome.to_string()),
+            cpf: None,
+            rg: None,
+            data_nascimento: nascimento,
+            sexo: None,
+            estado_civil: None,
+            telefone: telefone.map(str::to_string),
+            cnpj: None,
+            razao_social: None,
+            nome_fantasia: None,
+            inscricao_estadual: None,
+            created_by: cliente_id.to_string(),
+            updated_by: cliente_id.to_string(),
+        }
+        .into()
+    }
+
+    fn nascimento() -> Option<NaiveDateTime> {
+        Some(NaiveDate::from_ymd(1985, 3, 12).and_hms(0, 0, 0))
+    }
+
+    #[test]
+    fn it_normalizes_a_name() {
+        assert_eq!(normalizar_nome("  José  da SILVA "), "jose da silva");
+        assert_eq!(normalizar_nome("Conceição D'Ávila"), "conceicao d avila");
+        assert_eq!(normalizar_nome("João-Paulo"), "joao paulo");
+    }
+
+    #[test]
+    fn it_groups_clientes_that_look_alike() {
+        let primeiro = pessoa("José", "da Silva", nascimento(), None);
+        let segundo = pessoa("JOSE", "DA SILVA", None, Some("(41) 99999-0000"));
+        let terceiro = pessoa("jose", "da silva", nascimento(), Some("41999990000"));
+        let homonimo = pessoa("José", "da Silva", None, Some("11988887777"));
+        let outro = pessoa("Maria", "Souza", nascimento(), None);
+
+        let grupos = agrupar(vec![segundo.clone(), homonimo, outro, terceiro.clone(), primeiro.clone()]);
+        assert_eq!(grupos.len(), 1);
+        assert_eq!(grupos[0].nome, "jose da silva");
+        let ids: Vec<&str> = grupos[0].clientes.iter().map(|cliente| cliente.id.as_str()).collect();
+        assert!(ids.contains(&primeiro.id.as_str()));
+        assert!(ids.contains(&segundo.id.as_str()));
+        assert!(ids.contains(&terceiro.id.as_str()));
+        assert_eq!(ids.len(), 3);
+    }
+
+    #[test]
+    fn it_fills_the_blanks_of_a_cliente() {
+        let cliente = pessoa("José", "da Silva", nascimento(), None);
+        let duplicado = pessoa("Jose", "Silva", None, Some("41999990000"));
+        let mesclado = completar(cliente.clone(), duplicado);
+        assert_eq!(mesclado.id, cliente.id);
+        assert_eq!(mesclado.sobrenome, Some("da Silva".to_string()));
+        assert_eq!(mesclado.telefone, Some("41999990000".to_string()));
+    }
+
+    #[test]
+    fn it_merges_a_duplicate_into_a_cliente() {
+        let cliente = pessoa("Mesclar", "Teste", nascimento(), None);
+        create(&get_pool(), &cliente).unwrap();
+        let cliente_id = Uuid::parse_str(&cliente.id).unwrap();
+        let duplicado = create_cliente().unwrap();
+        let orcamento = create_orcamento().unwrap();
+        let mut conn = get_pool().get().unwrap();
+        {
+            use crate::schema::orcamentos::dsl::{id, id_cliente, orcamentos};
+            diesel::update(orcamentos.filter(id.eq(orcamento.id.to_string())))
+                .set(id_cliente.eq(duplicado.id.to_string()))
+                .execute(&mut conn)
+                .unwrap();
+        }
+        exportar(&get_pool(), duplicado.id, &cliente.id, &ConfigFiscal::default()).unwrap();
+
+        let mesclado = mesclar(&get_pool(), cliente_id, duplicado.id, &cliente.id).unwrap();
+        assert_eq!(mesclado.cpf, duplicado.cpf);
+        assert_eq!(mesclado.nome, Some("Mesclar".to_string()));
+        assert!(find(&get_pool(), duplicado.id).is_err());
+        assert_eq!(find_header(&mut conn, orcamento.id).unwrap().id_cliente, cliente.id);
+        assert_eq!(find_registros(&mut conn, &cliente.id).unwrap().len(), 1);
+
+        delete_orcamento(&get_pool(), orcamento.id).unwrap();
+        delete_registros(cliente_id);
+        delete(&get_pool(), cliente_id).unwrap();
+    }
+
+    /// Park a document on a cliente, the way the migration did for repeated ones
+    pub fn estacionar(cliente_id: &str, cpf: &str) {
+        let mut conn = get_pool().get().unwrap();
+        let repetido = DocumentoRepetido {
+            id_cliente: cliente_id.to_string(),
+            cpf: Some(cpf.to_string()),
+            cnpj: None,
+        };
+        diesel::insert_into(clientes_documentos_repetidos::table)
+            .values(&repetido)
+            .execute(&mut conn)
+            .unwrap();
+    }
+
+    #[test]
+    fn it_gives_the_cliente_the_document_its_duplicate_parked() {
+        let cliente = pessoa("Documento", "Estacionado", nascimento(), None);
+        create(&get_pool(), &cliente).unwrap();
+        let cliente_id = Uuid::parse_str(&cliente.id).unwrap();
+        let duplicado = pessoa("Documento", "Estacionado", nascimento(), None);
+        create(&get_pool(), &duplicado).unwrap();
+        let cpf = cpf_aleatorio();
+        estacionar(&duplicado.id, &cpf);
+
+        let mesclado = mesclar(&get_pool(), cliente_id, Uuid::parse_str(&duplicado.id).unwrap(), &cliente.id).unwrap();
+        assert_eq!(mesclado.cpf, Some(cpf));
+        let mut conn = get_pool().get().unwrap();
+        assert_eq!(find_documento_repetido(&mut conn, &cliente.id).unwrap(), None);
+
+        // a cpf another cliente holds stays parked, now on the cliente
+        let titular = create_cliente().unwrap();
+        let duplicado = pessoa("Documento", "Estacionado", nascimento(), None);
+        create(&get_pool(), &duplicado).unwrap();
+        let mut conn = get_pool().get().unwrap();
+        {
+            use crate::schema::clientes::dsl::{clientes, cpf, id};
+            diesel::update(clientes.filter(id.eq(&cliente.id)))
+                .set(cpf.eq(None::<String>))
+                .execute(&mut conn)
+                .unwrap();
+        }
+        estacionar(&duplicado.id, titular.cpf.as_deref().unwrap());
+        let mesclado = mesclar(&get_pool(), cliente_id, Uuid::parse_str(&duplicado.id).unwrap(), &cliente.id).unwrap();
+        assert_eq!(mesclado.cpf, None);
+        let estacionado = find_documento_repetido(&mut conn, &cliente.id).unwrap().unwrap();
+        assert_eq!(estacionado.cpf, titular.cpf);
+
+        delete(&get_pool(), titular.id).unwrap();
+        delete(&get_pool(), cliente_id).unwrap();
+    }
+
+    #[test]
+    fn it_doesnt_merge_a_cliente_into_itself() {
+        let cliente_id = Uuid::new_v4();
+        let mesclado = mesclar(&get_pool(), cliente_id, cliente_id, &cliente_id.to_string());
+        let expected_error = ApiError::ValidationError(vec![
+            "id_duplicado: um cliente não pode ser mesclado com ele mesmo".to_string(),
+        ]);
+        assert_eq!(mesclado.unwrap_err(), expected_error);
+    }
+}
@@ -0,0 +1,473 @@
+use crate::database::PoolType;
+use crate::errors::ApiError;
+use crate::handlers::cliente_endereco::{ClienteEnderecoResponse, ClienteEnderecosResponse};
+use crate::models::cep::{ler_cep, ConsultaCep};
+use crate::models::cliente::find_cliente;
+use crate::models::nfe::codigo_uf;
+use crate::schema::cliente_enderecos;
+use chrono::{NaiveDateTime, Utc};
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use std::fmt;
+use std::str::FromStr;
+use uuid::Uuid;
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Queryable, Identifiable, Insertable)]
+#[table_name = "cliente_enderecos"]
+pub struct ClienteEndereco {
+    pub id: String,
+    pub id_cliente: String,
+    pub tipo: String,
+    pub logradouro: String,
+    pub numero: String,
+    pub complemento: Option<String>,
+    pub bairro: String,
+    pub codigo_municipio: String,
+    pub municipio: String,
+    pub uf: String,
+    pub cep: String,
+    pub created_by: String,
+    pub created_at: NaiveDateTime,
+    pub updated_by: String,
+    pub updated_at: NaiveDateTime,
+    /// The endereço notas are issued to, each cliente has one
+    pub principal: bool,
+}
+
+/// An endereço as sent by the client, the fields left out come from the CEP
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct NewClienteEndereco {
+    pub id: String,
+    pub id_cliente: String,
+    pub tipo: TipoEndereco,
+    pub cep: String,
+    pub logradouro: Option<String>,
+    pub numero: String,
+    pub complemento: Option<String>,
+    pub bairro: Option<String>,
+    pub codigo_municipio: Option<String>,
+    pub municipio: Option<String>,
+    pub uf: Option<String>,
+    /// The first endereço of a cliente is the principal one even if not asked
+    pub principal: bool,
+    pub created_by: String,
+    pub updated_by: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, AsChangeset)]
+#[table_name = "cliente_enderecos"]
+#[diesel(treat_none_as_null = true)]
+pub struct UpdateClienteEndereco {
+    pub id: String,
+    pub tipo: String,
+    pub logradouro: String,
+    pub numero: String,
+    pub complemento: Option<String>,
+    pub bairro: String,
+    pub codigo_municipio: String,
+    pub municipio: String,
+    pub uf: String,
+    pub cep: String,
+    pub updated_by: String,
+}
+
+/// What an endereço of a cliente is used for
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TipoEndereco {
+    Cobranca,
+    Entrega,
+}
+
+impl TipoEndereco {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TipoEndereco::Cobranca => "cobranca",
+            TipoEndereco::Entrega => "entrega",
+        }
+    }
+}
+
+impl fmt::Display for TipoEndereco {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for TipoEndereco {
+    type Err = ApiError;
+
+    fn from_str(tipo: &str) -> Result<Self, Self::Err> {
+        match tipo {
+            "cobranca" => Ok(TipoEndereco::Cobranca),
+            "entrega" => Ok(TipoEndereco::Entrega),
+            _ => Err(ApiError::InternalServerError(format!("Unknown tipo de endereço {}", tipo))),
+        }
+    }
+}
+
+impl NewClienteEndereco {
+    /// Fill the fields left out from the CEP, what was sent wins over the base
+    ///
+    /// The CEP is only looked up when something is missing, so addresses the
+    /// base doesn't know yet can still be registered in full.
+    pub fn completar(self, consulta: &dyn ConsultaCep) -> Result<ClienteEndereco, ApiError> {
+        let cep = ler_cep(&self.cep)?;
+        let completo = self.logradouro.is_some()
+            && self.bairro.is_some()
+            && self.codigo_municipio.is_some()
+            && self.municipio.is_some()
+            && self.uf.is_some();
+        let base = if completo { None } else { consulta.consultar(&cep)? };
+        let base_logradouro = base.as_ref().and_then(|base| base.logradouro.clone());
+        let base_bairro = base.as_ref().and_then(|base| base.bairro.clone());
+
+        let mut errors = Vec::new();
+        let mut exigir = |campo: &str, valor: Option<String>| {
+            if valor.is_none() {
+                errors.push(match &base {
+                    Some(_) => format!("{}: o campo é obrigatório, o CEP {} não o informa", campo, cep),
+                    None => format!("{}: o campo é obrigatório, o CEP {} não está na base", campo, cep),
+                });
+            }
+            valor.unwrap_or_default()
+        };
+        let logradouro = exigir("logradouro", self.logradouro.or(base_logradouro));
+        let bairro = exigir("bairro", self.bairro.or(base_bairro));
+        let codigo_municipio = exigir(
+            "codigo_municipio",
+            self.codigo_municipio.or_else(|| base.as_ref().map(|base| base.codigo_municipio.clone())),
+        );
+        let municipio = exigir(
+            "municipio",
+            self.municipio.or_else(|| base.as_ref().map(|base| base.municipio.clone())),
+        );
+        let uf = exigir("uf", self.uf.or_else(|| base.as_ref().map(|base| base.uf.clone()))).to_uppercase();
+
+        // the IBGE code of a município starts with the code of its UF
+        if let Some(codigo) = codigo_uf(&uf) {
+            if !codigo_municipio.is_empty() && !codigo_municipio.starts_with(codigo) {
+                errors.push(format!("codigo_municipio: o município {} não fica em {}", codigo_municipio, uf));
+            }
+        }
+        if !errors.is_empty() {
+            return Err(ApiError::ValidationError(errors));
+        }
+
+        Ok(ClienteEndereco {
+            id: self.id,
+            id_cliente: self.id_cliente,
+            tipo: self.tipo.as_str().to_string(),
+            logradouro,
+            numero: self.numero,
+            complemento: self.complemento,
+            bairro,
+            codigo_municipio,
+            municipio,
+            uf,
+            cep: cep.to_string(),
+            created_by: self.created_by,
+            created_at: Utc::now().naive_utc(),
+            updated_by: self.updated_by,
+            updated_at: Utc::now().naive_utc(),
+            principal: self.principal,
+        })
+    }
+}
+
+/// Get the enderecos of a cliente, cobrança first
+pub fn get_all(pool: &PoolType, cliente_id: Uuid) -> Result<ClienteEnderecosResponse, ApiError> {
+    use crate::schema::cliente_enderecos::dsl::{cliente_enderecos, created_at, id_cliente, tipo};
+
+    let mut conn = pool.get()?;
+    find_cliente(&mut conn, &cliente_id.to_string())?;
+    let enderecos = cliente_enderecos
+        .filter(id_cliente.eq(cliente_id.to_string()))
+        .order((tipo, created_at))
+        .load::<ClienteEndereco>(&mut conn)?;
+
+    Ok(enderecos.into())
+}
+
+/// Find an endereco of a cliente or error out
+pub fn find(pool: &PoolType, cliente_id: Uuid, endereco_id: Uuid) -> Result<ClienteEnderecoResponse, ApiError> {
+    let mut conn = pool.get()?;
+    Ok(find_endereco(&mut conn, &cliente_id.to_string(), &endereco_id.to_string())?.into())
+}
+
+fn find_endereco(conn: &mut PgConnection, cliente_id: &str, endereco_id: &str) -> Result<ClienteEndereco, ApiError> {
+    use crate::schema::cliente_enderecos::dsl::{cliente_enderecos, id, id_cliente};
+
+    let not_found = format!("Endereco {} of cliente {} not found", endereco_id, cliente_id);
+    cliente_enderecos
+        .filter(id.eq(endereco_id))
+        .filter(id_cliente.eq(cliente_id))
+        .first::<ClienteEndereco>(conn)
+        .map_err(|_| ApiError::NotFound(not_found))
+}
+
+/// The endereço notas are issued to, none only when the cliente has no
+/// endereço at all
+pub fn find_principal(conn: &mut PgConnection, cliente_id: &str) -> Result<Option<ClienteEndereco>, ApiError> {
+    use crate::schema::cliente_enderecos::dsl::{cliente_enderecos, id_cliente, principal};
+
+    let endereco = cliente_enderecos
+        .filter(id_cliente.eq(cliente_id))
+        .filter(principal.eq(true))
+        .first::<ClienteEndereco>(conn)
+        .optional()?;
+    Ok(endereco)
+}
+
+/// Make an endereço the principal one of its cliente, in place of the one
+/// that was
+fn tornar_principal(conn: &mut PgConnection, cliente_id: &str, endereco_id: &str) -> Result<(), ApiError> {
+    use crate::schema::cliente_enderecos::dsl::{cliente_enderecos, id, id_cliente, principal};
+
+    diesel::update(cliente_enderecos)
+        .filter(id_cliente.eq(cliente_id))
+        .filter(principal.eq(true))
+        .set(principal.eq(false))
+        .execute(conn)?;
+    diesel::update(cliente_enderecos)
+        .filter(id.eq(endereco_id))
+        .set(principal.eq(true))
+        .execute(conn)?;
+    Ok(())
+}
+
+/// The NF-e takes the IBGE code of a município, so it has to be a known one
+fn validar_municipio(conn: &mut PgConnection, codigo: &str) -> Result<(), ApiError> {
+    use crate::schema::municipios::dsl::{codigo as codigo_ibge, municipios};
+
+    let cadastrado: bool =
+        diesel::select(diesel::dsl::exists(municipios.filter(codigo_ibge.eq(codigo)))).get_result(conn)?;
+    if !cadastrado {
+        return Err(ApiError::ValidationError(vec![format!(
+            "codigo_municipio: o município {} não está cadastrado",
+            codigo
+        )]));
+    }
+    Ok(())
+}
+
+/// Create an endereco for a cliente, completing it from the CEP
+pub fn create(
+    pool: &PoolType,
+    consulta: &dyn ConsultaCep,
+    new_endereco: NewClienteEndereco,
+) -> Result<ClienteEnderecoResponse, ApiError> {
+    use crate::schema::cliente_enderecos::dsl::cliente_enderecos;
+
+    let mut conn = pool.get()?;
+    find_cliente(&mut conn, &new_endereco.id_cliente)?;
+    let endereco = new_endereco.completar(consulta)?;
+    validar_municipio(&mut conn, &endereco.codigo_municipio)?;
+
+    let endereco = conn.transaction::<_, ApiError, _>(|conn| {
+        // inserted as a plain one, the unique index allows one principal
+        let principal = endereco.principal || find_principal(conn, &endereco.id_cliente)?.is_none();
+        let endereco = ClienteEndereco {
+            principal: false,
+            ..endereco
+        };
+        diesel::insert_into(cliente_enderecos).values(&endereco).execute(conn)?;
+        if principal {
+            tornar_principal(conn, &endereco.id_cliente, &endereco.id)?;
+        }
+        find_endereco(conn, &endereco.id_cliente, &endereco.id)
+    })?;
+    Ok(endereco.into())
+}
+
+/// Replace an endereco of a cliente, completing it from the CEP
+///
+/// An endereço stops being the principal one only when another one takes
+/// its place.
+pub fn update(
+    pool: &PoolType,
+    consulta: &dyn ConsultaCep,
+    new_endereco: NewClienteEndereco,
+) -> Result<ClienteEnderecoResponse, ApiError> {
+    use crate::schema::cliente_enderecos::dsl::{cliente_enderecos, id, updated_at};
+
+    let mut conn = pool.get()?;
+    find_endereco(&mut conn, &new_endereco.id_cliente, &new_endereco.id)?;
+    let endereco = new_endereco.completar(consulta)?;
+    validar_municipio(&mut conn, &endereco.codigo_municipio)?;
+
+    let endereco = conn.transaction::<_, ApiError, _>(|conn| {
+        diesel::update(cliente_enderecos)
+            .filter(id.eq(&endereco.id))
+            .set((&UpdateClienteEndereco::from(endereco.clone()), updated_at.eq(Utc::now().naive_utc())))
+            .execute(conn)?;
+        if endereco.principal {
+            tornar_principal(conn, &endereco.id_cliente, &endereco.id)?;
+        }
+        find_endereco(conn, &endereco.id_cliente, &endereco.id)
+    })?;
+    Ok(endereco.into())
+}
+
+/// Delete an endereco of a cliente, the oldest one left takes the place of
+/// a principal one
+pub fn delete(pool: &PoolType, cliente_id: Uuid, endereco_id: Uuid) -> Result<(), ApiError> {
+    use crate::schema::cliente_enderecos::dsl::{cliente_enderecos, created_at, id, id_cliente, tipo};
+
+    let mut conn = pool.get()?;
+    conn.transaction::<_, ApiError, _>(|conn| {
+        let endereco = find_endereco(conn, &cliente_id.to_string(), &endereco_id.to_string())?;
+        diesel::delete(cliente_enderecos).filter(id.eq(&endereco.id)).execute(conn)?;
+        if endereco.principal {
+            let restante = cliente_enderecos
+                .filter(id_cliente.eq(&endereco.id_cliente))
+                .order((tipo, created_at))
+                .select(id)
+                .first::<String>(conn)
+                .optional()?;
+            if let Some(restante) = restante {
+                tornar_principal(conn, &endereco.id_cliente, &restante)?;
+            }
+        }
+        Ok(())
+    })
+}
+
+impl From<ClienteEndereco> for UpdateClienteEndereco {
+    fn from(endereco: ClienteEndereco) -> Self {
+        UpdateClienteEndereco {
+            id: endereco.id,
+            tipo: endereco.tipo,
+            logradouro: endereco.logradouro,
+            numero: endereco.numero,
+            complemento: endereco.complemento,
+            bairro: endereco.bairro,
+            codigo_municipio: endereco.codigo_municipio,
+            municipio: endereco.municipio,
+            uf: endereco.uf,
+            cep: endereco.cep,
+            updated_by: endereco.updated_by,
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::models::cep::tests::{praca_da_se, ConsultaCepFixa};
+    use crate::models::cliente::delete as delete_cliente;
+    use crate::models::cliente::tests::create_cliente;
+    use crate::tests::helpers::tests::get_pool;
+
+    pub fn new_endereco(cliente_id: Uuid, tipo: TipoEndereco, cep: &str) -> NewClienteEndereco {
+        NewClienteEndereco {
+            id: Uuid::new_v4().to_string(),
+            id_cliente: cliente_id.to_string(),
+            tipo,
+            cep: cep.to_string(),
+            logradouro: None,
+            numero: "100".to_string(),
+            complemento: Some("Sala 2".to_string()),
+            bairro: None,
+            codigo_municipio: None,
+            municipio: None,
+            uf: None,
+            principal: false,
+            created_by: cliente_id.to_string(),
+            updated_by: cliente_id.to_string(),
+        }
+    }
+
+    #[test]
+    fn it_completes_an_endereco_from_the_cep() {
+        let consulta = ConsultaCepFixa(vec![praca_da_se()]);
+        let endereco = new_endereco(Uuid::new_v4(), TipoEndereco::Entrega, "01001-000")
+            .completar(&consulta)
+            .unwrap();
+        assert_eq!(endereco.logradouro, "Praça da Sé");
+        assert_eq!(endereco.codigo_municipio, "3550308");
+        assert_eq!(endereco.uf, "SP");
+        assert_eq!(endereco.cep, "01001000");
+        assert_eq!(endereco.tipo, "entrega");
+
+        let informado = NewClienteEndereco {
+            logradouro: Some("Rua Direita".to_string()),
+            ..new_endereco(Uuid::new_v4(), TipoEndereco::Entrega, "01001000")
+        };
+        assert_eq!(informado.completar(&consulta).unwrap().logradouro, "Rua Direita");
+    }
+
+    #[test]
+    fn it_requires_what_the_cep_doesnt_give() {
+        let consulta = ConsultaCepFixa(vec![]);
+        let response = new_endereco(Uuid::new_v4(), TipoEndereco::Cobranca, "89814000").completar(&consulta);
+        let expected_error = ApiError::ValidationError(vec![
+            "logradouro: o campo é obrigatório, o CEP 89814000 não está na base".to_string(),
+            "bairro: o campo é obrigatório, o CEP 89814000 não está na base".to_string(),
+            "codigo_municipio: o campo é obrigatório, o CEP 89814000 não está na base".to_string(),
+            "municipio: o campo é obrigatório, o CEP 89814000 não está na base".to_string(),
+            "uf: o campo é obrigatório, o CEP 89814000 não está na base".to_string(),
+        ]);
+        assert_eq!(response.unwrap_err(), expected_error);
+
+        let trocado = NewClienteEndereco {
+            uf: Some("rj".to_string()),
+            ..new_endereco(Uuid::new_v4(), TipoEndereco::Cobranca, "01001000")
+        };
+        let expected_error = ApiError::ValidationError(vec![
+            "codigo_municipio: o município 3550308 não fica em RJ".to_string(),
+        ]);
+        assert_eq!(trocado.completar(&ConsultaCepFixa(vec![praca_da_se()])).unwrap_err(), expected_error);
+    }
+
+    #[test]
+    fn it_keeps_the_enderecos_of_a_cliente() {
+        let cliente = create_cliente().unwrap();
+        let consulta = ConsultaCepFixa(vec![praca_da_se()]);
+        let entrega = create(&get_pool(), &consulta, new_endereco(cliente.id, TipoEndereco::Entrega, "01001000")).unwrap();
+        let cobranca = create(&get_pool(), &consulta, new_endereco(cliente.id, TipoEndereco::Cobranca, "01001000")).unwrap();
+
+        let enderecos = get_all(&get_pool(), cliente.id).unwrap().0;
+        assert_eq!(enderecos, vec![cobranca.clone(), entrega.clone()]);
+        assert!(entrega.principal);
+        assert!(!cobranca.principal);
+
+        let alterado = NewClienteEndereco {
+            id: cobranca.id.to_string(),
+            numero: "200".to_string(),
+            principal: true,
+            ..new_endereco(cliente.id, TipoEndereco::Cobranca, "01001000")
+        };
+        assert_eq!(update(&get_pool(), &consulta, alterado).unwrap().numero, "200");
+        let mut conn = get_pool().get().unwrap();
+        let principal = find_principal(&mut conn, &cliente.id.to_string()).unwrap().unwrap();
+        assert_eq!(principal.id, cobranca.id.to_string());
+        assert!(!find(&get_pool(), cliente.id, entrega.id).unwrap().principal);
+
+        delete(&get_pool(), cliente.id, cobranca.id).unwrap();
+        assert!(find(&get_pool(), cliente.id, cobranca.id).is_err());
+        let principal = find_principal(&mut conn, &cliente.id.to_string()).unwrap().unwrap();
+        assert_eq!(principal.id, entrega.id.to_string());
+        delete_cliente(&get_pool(), cliente.id).unwrap();
+        assert!(find_principal(&mut conn, &cliente.id.to_string()).unwrap().is_none());
+    }
+
+    #[test]
+    fn it_doesnt_take_an_unknown_municipio() {
+        let cliente = create_cliente().unwrap();
+        let joinville = NewClienteEndereco {
+            logradouro: Some("Rua do Príncipe".to_string()),
+            bairro: Some("Centro".to_string()),
+            codigo_municipio: Some("4209102".to_string()),
+            municipio: Some("Joinville".to_string()),
+            uf: Some("SC".to_string()),
+            ..new_endereco(cliente.id, TipoEndereco::Entrega, "89201001")
+        };
+        let response = create(&get_pool(), &ConsultaCepFixa(vec![]), joinville);
+        delete_cliente(&get_pool(), cliente.id).unwrap();
+        let expected_error = ApiError::ValidationError(vec![
+            "codigo_municipio: o município 4209102 não está cadastrado".to_string(),
+        ]);
+        assert_eq!(response.unwrap_err(), expected_error);
+    }
+}
@@ -0,0 +1,212 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// ICMS CSTs, the tabela B of the CST
+const CSTS_ICMS: [&str; 11] = ["00", "10", "20", "30", "40", "41", "50", "51", "60", "70", "90"];
+
+/// IPI CSTs, 00 to 49 for entradas and 50 to 99 for saídas
+const CSTS_IPI: [&str; 14] = [
+    "00", "01", "02", "03", "04", "05", "49", "50", "51", "52", "53", "54", "55", "99",
+];
+
+/// PIS and COFINS CSTs, both taxes share the same table
+const CSTS_PIS_COFINS: [&str; 33] = [
+    "01", "02", "03", "04", "05", "06", "07", "08", "09", "49", "50", "51", "52", "53", "54", "55",
+    "56", "60", "61", "62", "63", "64", "65", "66", "67", "70", "71", "72", "73", "74", "75", "98",
+    "99",
+];
+
+/// Nomenclatura Comum do Mercosul code of a produto, always 8 digits
+#[derive(Clone, Debug, PartialEq)]
+pub struct Ncm(String);
+
+/// Código Especificador da Substituição Tributária, always 7 digits
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cest(String);
+
+/// Código de Situação Tributária of the ICMS
+#[derive(Clone, Debug, PartialEq)]
+pub struct CstIcms(String);
+
+/// Código de Situação Tributária of the IPI
+#[derive(Clone, Debug, PartialEq)]
+pub struct CstIpi(String);
+
+/// Código de Situação Tributária of the PIS or the COFINS
+#[derive(Clone, Debug, PartialEq)]
+pub struct CstPisCofins(String);
+
+/// Strip the dots codes are usually written with, as in 2202.10.00
+fn digitos(codigo: &str) -> String {
+    codigo.trim().chars().filter(|c| *c != '.').collect()
+}
+
+fn numerico(codigo: &str, tamanho: usize) -> bool {
+    codigo.len() == tamanho && codigo.chars().all(|c| c.is_ascii_digit())
+}
+
+fn cst(codigo: &str, conhecidos: &[&str]) -> Result<String, String> {
+    let codigo = codigo.trim();
+    if conhecidos.contains(&codigo) {
+        Ok(codigo.to_string())
+    } else {
+        Err(format!("o CST {} não existe", codigo))
+    }
+}
+
+impl FromStr for Ncm {
+    type Err = String;
+
+    fn from_str(ncm: &str) -> Result<Self, Self::Err> {
+        let codigo = digitos(ncm);
+        if numerico(&codigo, 8) {
+            Ok(Ncm(codigo))
+        } else {
+            Err(format!("o NCM deve ter 8 dígitos, recebido {}", ncm))
+        }
+    }
+}
+
+impl FromStr for Cest {
+    type Err = String;
+
+    fn from_str(cest: &str) -> Result<Self, Self::Err> {
+        let codigo = digitos(cest);
+        if numerico(&codigo, 7) {
+            Ok(Cest(codigo))
+        } else {
+            Err(format!("o CEST deve ter 7 dígitos, recebido {}", cest))
+        }
+    }
+}
+
+impl FromStr for CstIcms {
+    type Err = String;
+
+    fn from_str(codigo: &str) -> Result<Self, Self::Err> {
+        cst(codigo, &CSTS_ICMS).map(CstIcms)
+    }
+}
+
+impl FromStr for CstIpi {
+    type Err = String;
+
+    fn from_str(codigo: &str) -> Result<Self, Self::Err> {
+        cst(codigo, &CSTS_IPI).map(CstIpi)
+    }
+}
+
+impl FromStr for CstPisCofins {
+    type Err = String;
+
+    fn from_str(codigo: &str) -> Result<Self, Self::Err> {
+        cst(codigo, &CSTS_PIS_COFINS).map(CstPisCofins)
+    }
+}
+
+impl Ncm {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Cest {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl CstIcms {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl CstIpi {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl CstPisCofins {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for Ncm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl fmt::Display for Cest {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl fmt::Display for CstIcms {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl fmt::Display for CstIpi {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl fmt::Display for CstPisCofins {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Parse an optional code column and write it back the way it is stored
+pub fn normalizar<T>(codigo: &Option<String>) -> Result<Option<String>, String>
+where
+    T: FromStr<Err = String> + fmt::Display,
+{
+    codigo
+        .as_ref()
+        .map(|codigo| T::from_str(codigo).map(|codigo| codigo.to_string()))
+        .transpose()
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    fn it_parses_an_ncm() {
+        assert_eq!(Ncm::from_str("22021000").unwrap().as_str(), "22021000");
+        assert_eq!(Ncm::from_str("2202.10.00").unwrap().as_str(), "22021000");
+        assert!(Ncm::from_str("00").is_err());
+        assert!(Ncm::from_str("2202100A").is_err());
+    }
+
+    #[test]
+    fn it_parses_a_cest() {
+        assert_eq!(Cest::from_str("03.002.00").unwrap().as_str(), "0300200");
+        assert!(Cest::from_str("00").is_err());
+    }
+
+    #[test]
+    fn it_only_accepts_known_csts() {
+        assert!(CstIcms::from_str("60").is_ok());
+        assert!(CstIcms::from_str("01").is_err());
+        assert!(CstIpi::from_str("53").is_ok());
+        assert!(CstIpi::from_str("999").is_err());
+        assert!(CstPisCofins::from_str("01").is_ok());
+        assert!(CstPisCofins::from_str("00").is_err());
+    }
+
+    #[test]
+    fn it_normalizes_an_optional_code() {
+        assert_eq!(normalizar::<Ncm>(&Some("2202.10.00".to_string())), Ok(Some("22021000".to_string())));
+        assert_eq!(normalizar::<Ncm>(&None), Ok(None));
+        assert!(normalizar::<Ncm>(&Some("00".to_string())).is_err());
+    }
+}
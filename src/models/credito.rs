@@ -0,0 +1,235 @@
+use crate::database::PoolType;
+use crate::errors::ApiError;
+use crate::handlers::credito::CreditoResponse;
+use crate::models::cliente::{find_cliente, Cliente};
+use crate::models::titulo_receber::{find_abertos, TituloReceber};
+use crate::models::user_permissao::{has_permissao, LIBERAR_CREDITO};
+use bigdecimal::{BigDecimal, Zero};
+use chrono::{NaiveDateTime, Utc};
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use uuid::Uuid;
+
+/// What a cliente owes and how much more the cliente can buy on credit
+#[derive(Clone, Debug, PartialEq)]
+pub struct PosicaoCredito {
+    pub id_cliente: String,
+    pub limite: Option<BigDecimal>,
+    /// Sum of what is left to pay on every open título
+    pub saldo_aberto: BigDecimal,
+    /// The part of saldo_aberto that is past due
+    pub saldo_vencido: BigDecimal,
+    pub titulos_vencidos: usize,
+}
+
+impl PosicaoCredito {
+    pub fn calcular(cliente: &Cliente, titulos: &[TituloReceber], hoje: NaiveDateTime) -> Self {
+        let abertos: Vec<&TituloReceber> = titulos.iter().filter(|titulo| titulo.pago_at.is_none()).collect();
+        let vencidos: Vec<&&TituloReceber> = abertos.iter().filter(|titulo| titulo.vencido(hoje)).collect();
+        PosicaoCredito {
+            id_cliente: cliente.id.clone(),
+            limite: cliente.limite_credito.clone(),
+            saldo_aberto: abertos.iter().fold(BigDecimal::zero(), |saldo, titulo| saldo + titulo.saldo()),
+            saldo_vencido: vencidos.iter().fold(BigDecimal::zero(), |saldo, titulo| saldo + titulo.saldo()),
+            titulos_vencidos: vencidos.len(),
+        }
+    }
+
+    /// What is left of the limite, None when the cliente has no limit
+    pub fn disponivel(&self) -> Option<BigDecimal> {
+        self.limite.as_ref().map(|limite| limite - &self.saldo_aberto)
+    }
+
+    /// Reasons a sale of `valor` on credit should not go through, empty when it can
+    pub fn bloqueios(&self, valor: &BigDecimal) -> Vec<String> {
+        let mut bloqueios = Vec::new();
+        if self.titulos_vencidos > 0 {
+            bloqueios.push(format!(
+                "Crédito: o cliente tem {} título(s) vencido(s) somando {}",
+                self.titulos_vencidos, self.saldo_vencido
+            ));
+        }
+        if let Some(disponivel) = self.disponivel() {
+            if *valor > disponivel {
+                bloqueios.push(format!(
+                    "Crédito: o valor de {} passa do crédito disponível de {}",
+                    valor.round(2),
+                    disponivel
+                ));
+            }
+        }
+        bloqueios
+    }
+}
+
+/// The credit position of a cliente as of now
+pub fn posicao(conn: &mut PgConnection, cliente_id: &str) -> Result<PosicaoCredito, ApiError> {
+    let cliente = find_cliente(conn, cliente_id)?;
+    let titulos = find_abertos(conn, cliente_id)?;
+    Ok(PosicaoCredito::calcular(&cliente, &titulos, Utc::now().naive_utc()))
+}
+
+/// Check a cliente can buy `valor` on credit
+///
+/// Clientes with overdue títulos, or whose open balance plus `valor` passes
+/// their limite_credito, are only sold to by users holding the
+/// LIBERAR_CREDITO permission.
+pub fn verificar(
+    conn: &mut PgConnection,
+    cliente_id: &str,
+    valor: &BigDecimal,
+    user_id: &str,
+) -> Result<(), ApiError> {
+    let bloqueios = posicao(conn, cliente_id)?.bloqueios(valor);
+    if bloqueios.is_empty() || has_permissao(conn, user_id, LIBERAR_CREDITO)? {
+        Ok(())
+    } else {
+        Err(ApiError::ValidationError(bloqueios))
+    }
+}
+
+/// Get the credit position of a cliente
+pub fn find(pool: &PoolType, cliente_id: Uuid) -> Result<CreditoResponse, ApiError> {
+    let mut conn = pool.get()?;
+    Ok(posicao(&mut conn, &cliente_id.to_string())?.into())
+}
+
+/// Set or, with None, remove the limite_credito of a cliente
+pub fn definir_limite(
+    pool: &PoolType,
+    cliente_id: Uuid,
+    limite: Option<BigDecimal>,
+    user_id: &str,
+) -> Result<CreditoResponse, ApiError> {
+    use crate::schema::clientes::dsl::{clientes, id, limite_credito, updated_at, updated_by};
+
+    let mut conn = pool.get()?;
+    find_cliente(&mut conn, &cliente_id.to_string())?;
+    if !has_permissao(&mut conn, user_id, LIBERAR_CREDITO)? {
+        return Err(ApiError::Unauthorized(format!("User {} cannot change credit limits", user_id)));
+    }
+    if let Some(limite) = &limite {
+        if *limite < BigDecimal::zero() {
+            return Err(ApiError::ValidationError(vec![
+                "limite_credito: o limite não pode ser negativo".to_string(),
+            ]));
+        }
+    }
+
+    diesel::update(clientes)
+        .filter(id.eq(cliente_id.to_string()))
+        .set((
+            limite_credito.eq(limite.map(|limite| limite.round(2))),
+            updated_by.eq(user_id),
+            updated_at.eq(Utc::now().naive_utc()),
+        ))
+        .execute(&mut conn)?;
+    find(pool, cliente_id)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::models::cliente::delete as delete_cliente;
+    use crate::models::cliente::tests::create_cliente;
+    use crate::models::cliente::{NewCliente, TipoPessoa};
+    use crate::models::titulo_receber::create as create_titulo;
+    use crate::models::titulo_receber::tests::{delete_titulos, new_titulo};
+    use crate::models::user::delete as delete_user;
+    use crate::models::user::tests::create_user_by_email;
+    use crate::models::user_permissao::revoke;
+    use crate::models::user_permissao::tests::grant_permissao;
+    use crate::tests::helpers::tests::get_pool;
+    use chrono::Duration;
+    use std::str::FromStr;
+
+    fn cliente(limite: Option<&str>) -> Cliente {
+        let cliente_id = Uuid::new_v4();
+        let cliente: Cliente = NewCliente {
+            id: cliente_id.to_string(),
+            tipo_pessoa: TipoPessoa::Fisica,
+            nome: Some("Crédito".to_string()),
+            sobrenome: None,
+            cpf: None,
+            rg: None,
+            data_nascimento: None,
+            sexo: None,
+            estado_civil: None,
+            telefone: None,
+            cnpj: None,
+            razao_social: None,
+            nome_fantasia: None,
+            inscricao_estadual: None,
+            created_by: cliente_id.to_string(),
+            updated_by: cliente_id.to_string(),
+        }
+        .into();
+        Cliente {
+            limite_credito: limite.map(|limite| BigDecimal::from_str(limite).unwrap()),
+            ..cliente
+        }
+    }
+
+    #[test]
+    fn it_blocks_a_sale_past_the_limite() {
+        let hoje = Utc::now().naive_utc();
+        let cliente = cliente(Some("1000.00"));
+        let cliente_id = Uuid::parse_str(&cliente.id).unwrap();
+        let titulos = vec![
+            new_titulo(cliente_id, "300.00", hoje + Duration::days(10)),
+            new_titulo(cliente_id, "200.00", hoje),
+        ];
+        let posicao = PosicaoCredito::calcular(&cliente, &titulos, hoje);
+        assert_eq!(posicao.saldo_aberto, BigDecimal::from(500));
+        assert_eq!(posicao.disponivel(), Some(BigDecimal::from(500)));
+        assert!(posicao.bloqueios(&BigDecimal::from(500)).is_empty());
+        assert_eq!(
+            posicao.bloqueios(&BigDecimal::from_str("500.01").unwrap()),
+            vec!["Crédito: o valor de 500.01 passa do crédito disponível de 500.00".to_string()],
+        );
+    }
+
+    #[test]
+    fn it_blocks_a_cliente_with_overdue_titulos() {
+        let hoje = Utc::now().naive_utc();
+        let cliente = cliente(None);
+        let cliente_id = Uuid::parse_str(&cliente.id).unwrap();
+        let titulos = vec![
+            new_titulo(cliente_id, "150.00", hoje - Duration::days(3)),
+            new_titulo(cliente_id, "300.00", hoje + Duration::days(10)),
+        ];
+        let posicao = PosicaoCredito::calcular(&cliente, &titulos, hoje);
+        assert_eq!(posicao.disponivel(), None);
+        assert_eq!(posicao.saldo_vencido, BigDecimal::from(150));
+        assert_eq!(
+            posicao.bloqueios(&BigDecimal::zero()),
+            vec!["Crédito: o cliente tem 1 título(s) vencido(s) somando 150.00".to_string()],
+        );
+    }
+
+    #[test]
+    fn it_lets_a_permitted_user_sell_past_the_credito() {
+        let user = create_user_by_email("teste_model_liberar_credito@teste.com").unwrap();
+        let user_id = user.id.to_string();
+        let created = create_cliente().unwrap();
+        let vencido = new_titulo(created.id, "80.00", Utc::now().naive_utc() - Duration::days(5));
+        create_titulo(&get_pool(), &vencido).unwrap();
+
+        let mut conn = get_pool().get().unwrap();
+        let cliente_id = created.id.to_string();
+        assert!(verificar(&mut conn, &cliente_id, &BigDecimal::from(10), &user_id).is_err());
+        assert!(definir_limite(&get_pool(), created.id, Some(BigDecimal::from(100)), &user_id).is_err());
+        grant_permissao(&user_id, LIBERAR_CREDITO);
+        assert!(verificar(&mut conn, &cliente_id, &BigDecimal::from(10), &user_id).is_ok());
+
+        let credito = definir_limite(&get_pool(), created.id, Some(BigDecimal::from(100)), &user_id).unwrap();
+        assert_eq!(credito.limite_credito, Some(BigDecimal::from(100)));
+        assert_eq!(credito.credito_disponivel, Some(BigDecimal::from(20)));
+        assert!(credito.bloqueado);
+
+        revoke(&get_pool(), user.id, LIBERAR_CREDITO).unwrap();
+        delete_titulos(created.id);
+        delete_cliente(&get_pool(), created.id).unwrap();
+        delete_user(&get_pool(), user.id).unwrap();
+    }
+}
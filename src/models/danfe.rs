@@ -0,0 +1,474 @@
+//! DANFE, the printable companion of a NF-e
+//!
+//! Read back from the XML that was issued, so the PDF shows exactly what was
+//! sent to SEFAZ.
+
+use crate::errors::ApiError;
+use crate::models::nfe::AMBIENTE_HOMOLOGACAO;
+use crate::pdf::{formatar_cep, formatar_cnpj, numero, Documento, A4_ALTURA, A4_LARGURA};
+use crate::validate::formatar_cpf;
+use bigdecimal::{BigDecimal, Zero};
+use chrono::DateTime;
+use std::str::FromStr;
+
+const MARGEM: f64 = 5.0;
+const LARGURA_UTIL: f64 = A4_LARGURA - 2.0 * MARGEM;
+const ALTURA_LINHA_ITEM: f64 = 4.0;
+const FIM_ITENS: f64 = A4_ALTURA - 30.0;
+const FIM_ITENS_CONTINUACAO: f64 = A4_ALTURA - MARGEM;
+/// Where the first item row starts, below the table header
+const INICIO_ITENS: f64 = 124.0;
+const INICIO_ITENS_CONTINUACAO: f64 = 44.0;
+
+/// Columns of the items table, title and width
+const COLUNAS: [(&str, f64); 12] = [
+    ("CÓDIGO", 22.0),
+    ("DESCRIÇÃO DO PRODUTO", 58.0),
+    ("NCM", 14.0),
+    ("CST", 8.0),
+    ("CFOP", 9.0),
+    ("UN", 8.0),
+    ("QTD", 13.0),
+    ("V. UNIT.", 16.0),
+    ("V. TOTAL", 16.0),
+    ("BC ICMS", 15.0),
+    ("V. ICMS", 12.0),
+    ("V. IPI", 9.0),
+];
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct EmitenteDanfe {
+    pub nome: String,
+    pub cnpj: String,
+    pub ie: String,
+    pub endereco: String,
+    pub municipio: String,
+    pub cep: String,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ItemDanfe {
+    pub codigo: String,
+    pub descricao: String,
+    pub ncm: String,
+    pub cst: String,
+    pub cfop: String,
+    pub unidade: String,
+    pub quantidade: BigDecimal,
+    pub valor_unitario: BigDecimal,
+    pub valor_total: BigDecimal,
+    pub base_icms: BigDecimal,
+    pub valor_icms: BigDecimal,
+    pub valor_ipi: BigDecimal,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TotaisDanfe {
+    pub base_icms: BigDecimal,
+    pub valor_icms: BigDecimal,
+    pub valor_produtos: BigDecimal,
+    pub desconto: BigDecimal,
+    pub valor_ipi: BigDecimal,
+    pub valor_pis: BigDecimal,
+    pub valor_cofins: BigDecimal,
+    pub valor_nota: BigDecimal,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Danfe {
+    pub chave: String,
+    pub numero: String,
+    pub serie: String,
+    pub emissao: String,
+    pub homologacao: bool,
+    pub natureza: String,
+    pub protocolo: Option<String>,
+    pub emitente: EmitenteDanfe,
+    pub destinatario_nome: String,
+    pub destinatario_documento: String,
+    pub itens: Vec<ItemDanfe>,
+    pub totais: TotaisDanfe,
+}
+
+impl Danfe {
+    /// Read the DANFE fields from the XML of a nota
+    pub fn from_xml(xml: &str, protocolo: Option<String>) -> Result<Self, ApiError> {
+        let documento = roxmltree::Document::parse(xml)
+            .map_err(|error| ApiError::InternalServerError(format!("XML da NF-e inválido: {}", error)))?;
+        let raiz = documento.root_element();
+        let grupo = |tag: &str| {
+            raiz.descendants()
+                .find(|no| no.is_element() && no.tag_name().name() == tag)
+                .ok_or_else(|| ApiError::InternalServerError(format!("Grupo {} ausente na NF-e", tag)))
+        };
+        let inf = grupo("infNFe")?;
+        let ide = grupo("ide")?;
+        let emit = grupo("emit")?;
+        let ender = grupo("enderEmit")?;
+        let dest = grupo("dest")?;
+        let totais = grupo("ICMSTot")?;
+
+        let emissao = DateTime::parse_from_rfc3339(&texto(ide, "dhEmi"))
+            .map(|emissao| emissao.format("%d/%m/%Y %H:%M").to_string())
+            .unwrap_or_default();
+        let documento_destinatario = match texto(dest, "CNPJ") {
+            cnpj if !cnpj.is_empty() => formatar_cnpj(&cnpj),
+            _ => formatar_cpf(&texto(dest, "CPF")),
+        };
+
+        let itens = inf
+            .children()
+            .filter(|no| no.has_tag_name("det"))
+            .map(|det| {
+                let prod = filho(det, "prod");
+                let imposto = filho(det, "imposto");
+                let icms = imposto.and_then(|imposto| filho(imposto, "ICMS")).and_then(|icms| icms.first_element_child());
+                let ipi = imposto.and_then(|imposto| filho(imposto, "IPI")).and_then(|ipi| filho(ipi, "IPITrib"));
+                let prod_texto = |tag: &str| prod.map(|prod| texto(prod, tag)).unwrap_or_default();
+                ItemDanfe {
+                    codigo: prod_texto("cProd"),
+                    descricao: prod_texto("xProd"),
+                    ncm: prod_texto("NCM"),
+                    cst: icms
+                        .map(|icms| format!("{}{}", texto(icms, "orig"), texto(icms, "CST")))
+                        .unwrap_or_default(),
+                    cfop: prod_texto("CFOP"),
+                    unidade: prod_texto("uCom"),
+                    quantidade: valor(prod, "qCom"),
+                    valor_unitario: valor(prod, "vUnCom"),
+                    valor_total: valor(prod, "vProd"),
+                    base_icms: valor(icms, "vBC"),
+                    valor_icms: valor(icms, "vICMS"),
+                    valor_ipi: valor(ipi, "vIPI"),
+                }
+            })
+            .collect();
+
+        Ok(Danfe {
+            chave: inf.attribute("Id").unwrap_or_default().trim_start_matches("NFe").to_string(),
+            numero: texto(ide, "nNF"),
+            serie: texto(ide, "serie"),
+            emissao,
+            homologacao: texto(ide, "tpAmb") == AMBIENTE_HOMOLOGACAO.to_string(),
+            natureza: texto(ide, "natOp"),
+            protocolo,
+            emitente: EmitenteDanfe {
+                nome: texto(emit, "xNome"),
+                cnpj: formatar_cnpj(&texto(emit, "CNPJ")),
+                ie: texto(emit, "IE"),
+                endereco: format!("{}, {} - {}", texto(ender, "xLgr"), texto(ender, "nro"), texto(ender, "xBairro")),
+                municipio: format!("{} - {}", texto(ender, "xMun"), texto(ender, "UF")),
+                cep: formatar_cep(&texto(ender, "CEP")),
+            },
+            destinatario_nome: texto(dest, "xNome"),
+            destinatario_documento: documento_destinatario,
+            itens,
+            totais: TotaisDanfe {
+                base_icms: valor(Some(totais), "vBC"),
+                valor_icms: valor(Some(totais), "vICMS"),
+                valor_produtos: valor(Some(totais), "vProd"),
+                desconto: valor(Some(totais), "vDesc"),
+                valor_ipi: valor(Some(totais), "vIPI"),
+                valor_pis: valor(Some(totais), "vPIS"),
+                valor_cofins: valor(Some(totais), "vCOFINS"),
+                valor_nota: valor(Some(totais), "vNF"),
+            },
+        })
+    }
+
+    /// How many items fit on each sheet, the first one has the other boxes
+    fn folhas(&self) -> Vec<usize> {
+        let primeira = ((FIM_ITENS - INICIO_ITENS) / ALTURA_LINHA_ITEM) as usize;
+        let demais = ((FIM_ITENS_CONTINUACAO - INICIO_ITENS_CONTINUACAO) / ALTURA_LINHA_ITEM) as usize;
+        let mut folhas = vec![self.itens.len().min(primeira)];
+        let mut restantes = self.itens.len().saturating_sub(primeira);
+        while restantes > 0 {
+            folhas.push(restantes.min(demais));
+            restantes = restantes.saturating_sub(demais);
+        }
+        folhas
+    }
+}
+
+/// Lay out the DANFE on A4 portrait sheets
+pub fn renderizar(danfe: &Danfe) -> Result<Vec<u8>, ApiError> {
+    let mut documento = Documento::new(&format!("DANFE {}", danfe.chave), A4_LARGURA, A4_ALTURA)?;
+    let folhas = danfe.folhas();
+    let mut itens = danfe.itens.iter();
+
+    for (indice, quantidade) in folhas.iter().enumerate() {
+        if indice > 0 {
+            documento.nova_pagina();
+        }
+        let inicio = if indice == 0 {
+            canhoto(&documento, danfe);
+            let y = cabecalho(&documento, danfe, 23.0, indice + 1, folhas.len());
+            quadros(&documento, danfe, y)
+        } else {
+            cabecalho(&documento, danfe, MARGEM, indice + 1, folhas.len()) + 2.0
+        };
+        tabela_itens(&documento, inicio, itens.by_ref().take(*quantidade));
+        if indice == 0 {
+            dados_adicionais(&documento, danfe);
+        }
+    }
+
+    documento.salvar()
+}
+
+/// Receipt the destinatário signs and returns
+fn canhoto(documento: &Documento, danfe: &Danfe) {
+    let largura_recibo = LARGURA_UTIL - 40.0;
+    documento.caixa(MARGEM, MARGEM, LARGURA_UTIL, 16.0);
+    documento.linha(MARGEM + largura_recibo, MARGEM, MARGEM + largura_recibo, MARGEM + 16.0);
+    documento.linha(MARGEM, MARGEM + 8.0, MARGEM + largura_recibo, MARGEM + 8.0);
+    documento.linha(MARGEM + 40.0, MARGEM + 8.0, MARGEM + 40.0, MARGEM + 16.0);
+    let recebemos = format!(
+        "RECEBEMOS DE {} OS PRODUTOS CONSTANTES DA NOTA FISCAL INDICADA AO LADO",
+        danfe.emitente.nome
+    );
+    documento.texto(&documento.ajustar(&recebemos, 6.0, largura_recibo - 2.0, false), 6.0, MARGEM + 1.0, MARGEM + 4.0, false);
+    rotulo(documento, "DATA DE RECEBIMENTO", MARGEM, MARGEM + 8.0);
+    rotulo(documento, "IDENTIFICAÇÃO E ASSINATURA DO RECEBEDOR", MARGEM + 40.0, MARGEM + 8.0);
+    let centro = MARGEM + largura_recibo + 20.0;
+    documento.texto_centro("NF-e", 10.0, centro, MARGEM + 5.0, true);
+    documento.texto_centro(&format!("Nº {}", numero_formatado(&danfe.numero)), 8.0, centro, MARGEM + 10.0, true);
+    documento.texto_centro(&format!("Série {}", danfe.serie), 8.0, centro, MARGEM + 14.0, true);
+    documento.linha(MARGEM, MARGEM + 17.5, MARGEM + LARGURA_UTIL, MARGEM + 17.5);
+}
+
+/// Emitente, DANFE title and chave de acesso, repeated on every sheet
+fn cabecalho(documento: &Documento, danfe: &Danfe, y: f64, folha: usize, folhas: usize) -> f64 {
+    let emitente = &danfe.emitente;
+    documento.caixa(MARGEM, y, LARGURA_UTIL, 32.0);
+    documento.linha(MARGEM + 80.0, y, MARGEM + 80.0, y + 32.0);
+    documento.linha(MARGEM + 115.0, y, MARGEM + 115.0, y + 32.0);
+
+    let nome = documento.quebrar(&emitente.nome, 10.0, 76.0, true);
+    for (linha, texto) in nome.iter().take(2).enumerate() {
+        documento.texto_centro(texto, 10.0, MARGEM + 40.0, y + 7.0 + 4.5 * linha as f64, true);
+    }
+    let mut linha_y = y + 18.0;
+    for texto in [&emitente.endereco, &emitente.municipio, &format!("CEP {}", emitente.cep)].iter() {
+        documento.texto_centro(&documento.ajustar(texto, 7.0, 76.0, false), 7.0, MARGEM + 40.0, linha_y, false);
+        linha_y += 3.5;
+    }
+
+    let centro = MARGEM + 97.5;
+    documento.texto_centro("DANFE", 12.0, centro, y + 6.0, true);
+    documento.texto_centro("Documento Auxiliar da", 6.0, centro, y + 9.5, false);
+    documento.texto_centro("Nota Fiscal Eletrônica", 6.0, centro, y + 12.0, false);
+    documento.texto("0 - ENTRADA", 6.0, MARGEM + 83.0, y + 16.0, false);
+    documento.texto("1 - SAÍDA", 6.0, MARGEM + 83.0, y + 19.0, false);
+    documento.caixa(MARGEM + 104.0, y + 14.0, 6.0, 6.0);
+    documento.texto_centro("1", 8.0, MARGEM + 107.0, y + 18.5, true);
+    documento.texto_centro(&format!("Nº {}", numero_formatado(&danfe.numero)), 8.0, centro, y + 24.0, true);
+    documento.texto_centro(&format!("SÉRIE {}", danfe.serie), 8.0, centro, y + 27.5, true);
+    documento.texto_centro(&format!("FOLHA {}/{}", folha, folhas), 7.0, centro, y + 30.5, false);
+
+    let direita = MARGEM + 117.0;
+    documento.codigo_de_barras(&danfe.chave, direita, y + 2.0, 78.0, 11.0);
+    documento.linha(MARGEM + 115.0, y + 15.0, MARGEM + LARGURA_UTIL, y + 15.0);
+    rotulo(documento, "CHAVE DE ACESSO", MARGEM + 115.0, y + 15.0);
+    documento.texto(&formatar_chave(&danfe.chave), 7.5, direita, y + 21.0, true);
+    documento.linha(MARGEM + 115.0, y + 23.0, MARGEM + LARGURA_UTIL, y + 23.0);
+    documento.texto("Consulta de autenticidade no portal nacional da NF-e", 6.0, direita, y + 26.5, false);
+    documento.texto("www.nfe.fazenda.gov.br/portal ou no site da Sefaz Autorizadora", 6.0, direita, y + 29.5, false);
+
+    y + 32.0
+}
+
+/// Natureza, protocolo, destinatário and tax totals on the first sheet
+fn quadros(documento: &Documento, danfe: &Danfe, y: f64) -> f64 {
+    campo(documento, "NATUREZA DA OPERAÇÃO", &danfe.natureza, MARGEM, y, 115.0);
+    let protocolo = danfe
+        .protocolo
+        .clone()
+        .unwrap_or_else(|| "NF-e ainda não autorizada".to_string());
+    campo(documento, "PROTOCOLO DE AUTORIZAÇÃO DE USO", &protocolo, MARGEM + 115.0, y, 85.0);
+    let y = y + 8.0;
+    campo(documento, "INSCRIÇÃO ESTADUAL", &danfe.emitente.ie, MARGEM, y, 100.0);
+    campo(documento, "CNPJ", &danfe.emitente.cnpj, MARGEM + 100.0, y, 100.0);
+
+    let y = y + 10.0;
+    documento.texto("DESTINATÁRIO / REMETENTE", 7.0, MARGEM, y - 0.5, true);
+    campo(documento, "NOME / RAZÃO SOCIAL", &danfe.destinatario_nome, MARGEM, y, 120.0);
+    campo(documento, "CNPJ / CPF", &danfe.destinatario_documento, MARGEM + 120.0, y, 40.0);
+    campo(documento, "DATA DE EMISSÃO", &danfe.emissao, MARGEM + 160.0, y, 40.0);
+
+    let y = y + 11.0;
+    let totais = &danfe.totais;
+    documento.texto("CÁLCULO DO IMPOSTO", 7.0, MARGEM, y - 0.5, true);
+    let linhas = [
+        [
+            ("BASE DE CÁLCULO DO ICMS", &totais.base_icms),
+            ("VALOR DO ICMS", &totais.valor_icms),
+            ("VALOR DO PIS", &totais.valor_pis),
+            ("VALOR DA COFINS", &totais.valor_cofins),
+            ("VALOR TOTAL DOS PRODUTOS", &totais.valor_produtos),
+        ],
+        [
+            ("VALOR DO FRETE", &BigDecimal::zero()),
+            ("VALOR DO SEGURO", &BigDecimal::zero()),
+            ("DESCONTO", &totais.desconto),
+            ("VALOR DO IPI", &totais.valor_ipi),
+            ("VALOR TOTAL DA NOTA", &totais.valor_nota),
+        ],
+    ];
+    for (linha, campos) in linhas.iter().enumerate() {
+        for (coluna, (titulo, valor)) in campos.iter().enumerate() {
+            let x = MARGEM + 40.0 * coluna as f64;
+            let y = y + 8.0 * linha as f64;
+            documento.caixa(x, y, 40.0, 8.0);
+            rotulo(documento, titulo, x, y);
+            documento.texto_direita(&numero(valor, 2), 8.0, x + 39.0, y + 6.5, false);
+        }
+    }
+
+    let y = y + 19.0;
+    documento.texto("DADOS DOS PRODUTOS / SERVIÇOS", 7.0, MARGEM, y - 0.5, true);
+    y
+}
+
+fn tabela_itens<'a>(documento: &Documento, y: f64, itens: impl Iterator<Item = &'a ItemDanfe>) {
+    let mut x = MARGEM;
+    for (titulo, largura) in COLUNAS.iter() {
+        documento.caixa(x, y, *largura, 5.0);
+        documento.texto_centro(titulo, 5.5, x + largura / 2.0, y + 3.5, true);
+        x += largura;
+    }
+
+    let mut linha_y = y + 5.0;
+    for item in itens {
+        let valores = [
+            item.codigo.clone(),
+            item.descricao.clone(),
+            item.ncm.clone(),
+            item.cst.clone(),
+            item.cfop.clone(),
+            item.unidade.clone(),
+            numero(&item.quantidade, 4),
+            numero(&item.valor_unitario, 2),
+            numero(&item.valor_total, 2),
+            numero(&item.base_icms, 2),
+            numero(&item.valor_icms, 2),
+            numero(&item.valor_ipi, 2),
+        ];
+        let mut x = MARGEM;
+        for (coluna, ((_, largura), valor)) in COLUNAS.iter().zip(valores.iter()).enumerate() {
+            let texto = documento.ajustar(valor, 6.0, largura - 1.5, false);
+            if coluna >= 6 {
+                documento.texto_direita(&texto, 6.0, x + largura - 0.8, linha_y + 3.0, false);
+            } else {
+                documento.texto(&texto, 6.0, x + 0.8, linha_y + 3.0, false);
+            }
+            x += largura;
+        }
+        documento.linha(MARGEM, linha_y + ALTURA_LINHA_ITEM, MARGEM + LARGURA_UTIL, linha_y + ALTURA_LINHA_ITEM);
+        linha_y += ALTURA_LINHA_ITEM;
+    }
+}
+
+fn dados_adicionais(documento: &Documento, danfe: &Danfe) {
+    let y = FIM_ITENS + 3.0;
+    documento.texto("DADOS ADICIONAIS", 7.0, MARGEM, y - 0.5, true);
+    documento.caixa(MARGEM, y, LARGURA_UTIL, A4_ALTURA - MARGEM - y);
+    rotulo(documento, "INFORMAÇÕES COMPLEMENTARES", MARGEM, y);
+    let mut informacoes = Vec::new();
+    if danfe.homologacao {
+        informacoes.push("EMITIDA EM AMBIENTE DE HOMOLOGAÇÃO - SEM VALOR FISCAL".to_string());
+    }
+    informacoes.push(format!(
+        "Valor aproximado dos tributos federais: PIS R$ {} e COFINS R$ {}.",
+        numero(&danfe.totais.valor_pis, 2),
+        numero(&danfe.totais.valor_cofins, 2)
+    ));
+    for (linha, texto) in informacoes.iter().enumerate() {
+        documento.texto(texto, 7.0, MARGEM + 1.0, y + 7.0 + 3.5 * linha as f64, linha == 0 && danfe.homologacao);
+    }
+}
+
+/// Small title in the top left corner of a box
+fn rotulo(documento: &Documento, titulo: &str, x: f64, y: f64) {
+    documento.texto(titulo, 5.0, x + 1.0, y + 2.3, false);
+}
+
+/// Box with a title and a value
+fn campo(documento: &Documento, titulo: &str, valor: &str, x: f64, y: f64, largura: f64) {
+    documento.caixa(x, y, largura, 8.0);
+    rotulo(documento, titulo, x, y);
+    documento.texto(&documento.ajustar(valor, 8.0, largura - 2.0, false), 8.0, x + 1.0, y + 6.5, false);
+}
+
+fn filho<'a, 'input>(no: roxmltree::Node<'a, 'input>, tag: &str) -> Option<roxmltree::Node<'a, 'input>> {
+    no.children().find(|filho| filho.is_element() && filho.tag_name().name() == tag)
+}
+
+fn texto(no: roxmltree::Node, tag: &str) -> String {
+    filho(no, tag).and_then(|filho| filho.text()).unwrap_or_default().to_string()
+}
+
+fn valor(no: Option<roxmltree::Node>, tag: &str) -> BigDecimal {
+    no.and_then(|no| BigDecimal::from_str(&texto(no, tag)).ok())
+        .unwrap_or_else(BigDecimal::zero)
+}
+
+/// The chave in groups of four digits, as printed under the barcode
+fn formatar_chave(chave: &str) -> String {
+    chave
+        .as_bytes()
+        .chunks(4)
+        .map(|grupo| String::from_utf8_lossy(grupo).to_string())
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+/// The numero in groups of three digits, `000.000.078`
+fn numero_formatado(numero: &str) -> String {
+    let numero = format!("{:0>9}", numero);
+    format!("{}.{}.{}", &numero[0..3], &numero[3..6], &numero[6..9])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::nfe::gerar_xml;
+    use crate::models::nfe::tests::dados;
+
+    fn danfe() -> Danfe {
+        Danfe::from_xml(&gerar_xml(&dados()).unwrap(), Some("935230000000001".to_string())).unwrap()
+    }
+
+    #[test]
+    fn it_reads_the_danfe_from_the_xml() {
+        let danfe = danfe();
+        assert_eq!(danfe.chave, dados().chave().unwrap());
+        assert_eq!(danfe.emissao, "05/07/2023 10:30");
+        assert_eq!(danfe.emitente.cnpj, "11.222.333/0001-81");
+        assert_eq!(danfe.destinatario_documento, "529.982.247-25");
+        assert_eq!(danfe.itens.len(), 2);
+        assert_eq!(danfe.itens[0].cst, "000");
+        assert_eq!(danfe.totais.valor_nota, BigDecimal::from_str("43.96").unwrap());
+        assert!(danfe.homologacao);
+    }
+
+    #[test]
+    fn it_formats_the_chave_and_numero() {
+        assert_eq!(formatar_chave("12345678"), "1234 5678");
+        assert_eq!(numero_formatado("78"), "000.000.078");
+    }
+
+    #[test]
+    fn it_renders_the_danfe_over_as_many_sheets_as_needed() {
+        let mut danfe = danfe();
+        assert_eq!(danfe.folhas(), vec![2]);
+        assert!(renderizar(&danfe).unwrap().starts_with(b"%PDF"));
+
+        let item = danfe.itens[0].clone();
+        danfe.itens = vec![item; 100];
+        let folhas = danfe.folhas();
+        assert!(folhas.len() > 1);
+        assert_eq!(folhas.iter().sum::<usize>(), 100);
+        assert!(renderizar(&danfe).unwrap().starts_with(b"%PDF"));
+    }
+}
@@ -0,0 +1,129 @@
+use crate::database::PoolType;
+use crate::errors::ApiError;
+use crate::handlers::deposito::{DepositoResponse, DepositosResponse};
+use crate::schema::depositos;
+use chrono::{NaiveDateTime, Utc};
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use uuid::Uuid;
+
+/// Depósito that sales take their stock from
+pub const DEPOSITO_PADRAO: &str = "00000000-0000-0000-0000-000000000000";
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Queryable, Identifiable, Insertable)]
+pub struct Deposito {
+    pub id: String,
+    pub nome: String,
+    pub created_by: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct NewDeposito {
+    pub id: String,
+    pub nome: String,
+    pub created_by: String,
+}
+
+/// Get all depositos
+pub fn get_all(pool: &PoolType) -> Result<DepositosResponse, ApiError> {
+    use crate::schema::depositos::dsl::{depositos, nome};
+
+    let mut conn = pool.get()?;
+    let all_depositos = depositos.order(nome).load::<Deposito>(&mut conn)?;
+
+    Ok(all_depositos.into())
+}
+
+/// Find a deposito by the deposito's id or error out
+pub fn find(pool: &PoolType, deposito_id: Uuid) -> Result<DepositoResponse, ApiError> {
+    let mut conn = pool.get()?;
+    Ok(find_deposito(&mut conn, &deposito_id.to_string())?.into())
+}
+
+/// Find a deposito using an existing connection
+pub fn find_deposito(conn: &mut PgConnection, deposito_id: &str) -> Result<Deposito, ApiError> {
+    use crate::schema::depositos::dsl::{depositos, id};
+
+    let not_found = format!("Deposito {} not found", deposito_id);
+    depositos
+        .filter(id.eq(deposito_id))
+        .first::<Deposito>(conn)
+        .map_err(|_| ApiError::NotFound(not_found))
+}
+
+/// Create a new deposito
+pub fn create(pool: &PoolType, new_deposito: &Deposito) -> Result<DepositoResponse, ApiError> {
+    use crate::schema::depositos::dsl::depositos;
+
+    let mut conn = pool.get()?;
+    diesel::insert_into(depositos).values(new_deposito).execute(&mut conn)?;
+    Ok(new_deposito.clone().into())
+}
+
+impl From<NewDeposito> for Deposito {
+    fn from(deposito: NewDeposito) -> Self {
+        Deposito {
+            id: deposito.id,
+            nome: deposito.nome,
+            created_by: deposito.created_by,
+            created_at: Utc::now().naive_utc(),
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::tests::helpers::tests::get_pool;
+
+    /// Remove a deposito created by a test, its movimentos must be gone already
+    pub fn delete_deposito(deposito_id: Uuid) {
+        use crate::schema::depositos::dsl::{depositos, id};
+
+        let mut conn = get_pool().get().unwrap();
+        diesel::delete(depositos)
+            .filter(id.eq(deposito_id.to_string()))
+            .execute(&mut conn)
+            .unwrap();
+    }
+
+    pub fn create_deposito() -> Result<DepositoResponse, ApiError> {
+        let deposito_id = Uuid::new_v4();
+        let new_deposito = NewDeposito {
+            id: deposito_id.to_string(),
+            nome: format!("Depósito {}", deposito_id),
+            created_by: deposito_id.to_string(),
+        };
+        let deposito: Deposito = new_deposito.into();
+        create(&get_pool(), &deposito)
+    }
+
+    #[test]
+    fn it_finds_the_default_deposito() {
+        let deposito = find(&get_pool(), Uuid::parse_str(DEPOSITO_PADRAO).unwrap()).unwrap();
+        assert_eq!(deposito.nome, "Depósito principal");
+        assert!(get_all(&get_pool()).unwrap().0.contains(&deposito));
+    }
+
+    #[test]
+    fn it_doesnt_find_a_deposito() {
+        let deposito_id = Uuid::new_v4();
+        let expected_error = ApiError::NotFound(format!("Deposito {} not found", deposito_id));
+        assert_eq!(find(&get_pool(), deposito_id).unwrap_err(), expected_error);
+    }
+
+    #[test]
+    fn it_creates_a_deposito_with_a_unique_nome() {
+        let created = create_deposito().unwrap();
+        assert_eq!(find(&get_pool(), created.id).unwrap(), created);
+        let repetido: Deposito = NewDeposito {
+            id: Uuid::new_v4().to_string(),
+            nome: created.nome.clone(),
+            created_by: created.id.to_string(),
+        }
+        .into();
+        assert!(create(&get_pool(), &repetido).is_err());
+        delete_deposito(created.id);
+    }
+}
@@ -0,0 +1,126 @@
+use crate::errors::ApiError;
+use bigdecimal::{BigDecimal, Zero};
+use std::fmt;
+use std::str::FromStr;
+
+/// How the value of a discount is applied
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TipoDesconto {
+    Percentual,
+    Valor,
+}
+
+impl TipoDesconto {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TipoDesconto::Percentual => "percentual",
+            TipoDesconto::Valor => "valor",
+        }
+    }
+
+    /// Parse the tipo_desconto column, where NULL means no discount
+    pub fn from_column(tipo: &Option<String>) -> Result<Option<Self>, ApiError> {
+        tipo.as_ref().map(|tipo| TipoDesconto::from_str(tipo)).transpose()
+    }
+}
+
+impl fmt::Display for TipoDesconto {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for TipoDesconto {
+    type Err = ApiError;
+
+    fn from_str(tipo: &str) -> Result<Self, Self::Err> {
+        match tipo {
+            "percentual" => Ok(TipoDesconto::Percentual),
+            "valor" => Ok(TipoDesconto::Valor),
+            _ => Err(ApiError::InternalServerError(format!("Unknown tipo de desconto {}", tipo))),
+        }
+    }
+}
+
+/// Amount a discount takes off `bruto`, rounded to cents
+pub fn calcular(bruto: &BigDecimal, tipo: Option<TipoDesconto>, valor: &BigDecimal) -> BigDecimal {
+    match tipo {
+        Some(TipoDesconto::Percentual) => (bruto * valor / BigDecimal::from(100)).round(2),
+        Some(TipoDesconto::Valor) => valor.clone(),
+        None => BigDecimal::zero(),
+    }
+}
+
+/// Describe what is wrong with a discount over `bruto`, if anything
+pub fn validar(bruto: &BigDecimal, tipo: Option<TipoDesconto>, valor: &BigDecimal) -> Option<String> {
+    if *valor < BigDecimal::zero() {
+        return Some("o desconto não pode ser negativo".to_string());
+    }
+    match tipo {
+        Some(TipoDesconto::Percentual) if *valor > BigDecimal::from(100) => {
+            Some("o desconto percentual não pode passar de 100%".to_string())
+        }
+        Some(TipoDesconto::Valor) if valor > bruto => {
+            Some(format!("o desconto de {} é maior que o valor bruto de {}", valor, bruto))
+        }
+        _ => None,
+    }
+}
+
+/// Split a discount across `valores` in proportion to each of them
+///
+/// Every share is rounded to cents and the last one absorbs the rounding, so
+/// the shares always add up to `desconto`.
+pub fn ratear(desconto: &BigDecimal, valores: &[BigDecimal]) -> Vec<BigDecimal> {
+    let total = valores.iter().fold(BigDecimal::zero(), |total, valor| total + valor);
+    if total.is_zero() || valores.is_empty() {
+        return valores.iter().map(|_| BigDecimal::zero()).collect();
+    }
+
+    let mut restante = desconto.clone();
+    let mut rateio: Vec<BigDecimal> = valores[..valores.len() - 1]
+        .iter()
+        .map(|valor| {
+            let parte = (desconto * valor / &total).round(2);
+            restante -= &parte;
+            parte
+        })
+        .collect();
+    rateio.push(restante);
+    rateio
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    fn decimal(valor: &str) -> BigDecimal {
+        BigDecimal::from_str(valor).unwrap()
+    }
+
+    #[test]
+    fn it_calculates_a_discount() {
+        let bruto = decimal("15.98");
+        assert_eq!(calcular(&bruto, Some(TipoDesconto::Percentual), &decimal("10")), decimal("1.60"));
+        assert_eq!(calcular(&bruto, Some(TipoDesconto::Valor), &decimal("2.50")), decimal("2.50"));
+        assert_eq!(calcular(&bruto, None, &decimal("0")), decimal("0"));
+    }
+
+    #[test]
+    fn it_rejects_invalid_discounts() {
+        let bruto = decimal("10.00");
+        assert!(validar(&bruto, Some(TipoDesconto::Percentual), &decimal("100")).is_none());
+        assert!(validar(&bruto, Some(TipoDesconto::Percentual), &decimal("100.01")).is_some());
+        assert!(validar(&bruto, Some(TipoDesconto::Valor), &decimal("10.01")).is_some());
+        assert!(validar(&bruto, Some(TipoDesconto::Valor), &decimal("-1")).is_some());
+    }
+
+    #[test]
+    fn it_splits_a_discount_without_losing_cents() {
+        let valores = vec![decimal("10.00"), decimal("10.00"), decimal("10.00")];
+        let rateio = ratear(&decimal("1.00"), &valores);
+        assert_eq!(rateio, vec![decimal("0.33"), decimal("0.33"), decimal("0.34")]);
+        assert_eq!(ratear(&decimal("1.00"), &[decimal("0")]), vec![decimal("0")]);
+    }
+}
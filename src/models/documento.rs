@@ -0,0 +1,175 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// Cadastro de Pessoas Físicas, 11 digits with two check digits
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cpf(String);
+
+/// Cadastro Nacional da Pessoa Jurídica, 14 digits with two check digits
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cnpj(String);
+
+/// Strip the dots, slash and dash documents are usually written with
+fn digitos(documento: &str) -> String {
+    documento
+        .trim()
+        .chars()
+        .filter(|c| !['.', '/', '-'].contains(c))
+        .collect()
+}
+
+/// Check digit of the modulo 11 used by the Receita Federal
+fn digito(base: &[u32], pesos: &[u32]) -> u32 {
+    let soma: u32 = base.iter().zip(pesos.iter()).map(|(digito, peso)| digito * peso).sum();
+    match soma % 11 {
+        resto if resto < 2 => 0,
+        resto => 11 - resto,
+    }
+}
+
+impl FromStr for Cpf {
+    type Err = String;
+
+    fn from_str(cpf: &str) -> Result<Self, Self::Err> {
+        let numero = digitos(cpf);
+        let algarismos: Vec<u32> = numero.chars().filter_map(|c| c.to_digit(10)).collect();
+        if numero.len() != 11 || algarismos.len() != 11 {
+            return Err(format!("o CPF deve ter 11 dígitos, recebido {}", cpf));
+        }
+        let pesos = [11, 10, 9, 8, 7, 6, 5, 4, 3, 2];
+        let primeiro = digito(&algarismos[..9], &pesos[1..]);
+        let segundo = digito(&algarismos[..10], &pesos);
+        // repeated digits pass the check but are never issued
+        if algarismos.iter().all(|algarismo| *algarismo == algarismos[0])
+            || algarismos[9] != primeiro
+            || algarismos[10] != segundo
+        {
+            return Err(format!("o CPF {} é inválido", cpf));
+        }
+        Ok(Cpf(numero))
+    }
+}
+
+impl Cpf {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for Cpf {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl FromStr for Cnpj {
+    type Err = String;
+
+    fn from_str(cnpj: &str) -> Result<Self, Self::Err> {
+        let numero = digitos(cnpj);
+        let algarismos: Vec<u32> = numero.chars().filter_map(|c| c.to_digit(10)).collect();
+        if numero.len() != 14 || algarismos.len() != 14 {
+            return Err(format!("o CNPJ deve ter 14 dígitos, recebido {}", cnpj));
+        }
+        let pesos = [6, 5, 4, 3, 2, 9, 8, 7, 6, 5, 4, 3, 2];
+        let primeiro = digito(&algarismos[..12], &pesos[1..]);
+        let segundo = digito(&algarismos[..13], &pesos);
+        // repeated digits pass the check but are never issued
+        if algarismos.iter().all(|algarismo| *algarismo == algarismos[0])
+            || algarismos[12] != primeiro
+            || algarismos[13] != segundo
+        {
+            return Err(format!("o CNPJ {} é inválido", cnpj));
+        }
+        Ok(Cnpj(numero))
+    }
+}
+
+impl Cnpj {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for Cnpj {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    /// A valid CPF nobody else has, clientes can't share one
+    pub fn cpf_aleatorio() -> String {
+        let mut algarismos: Vec<u32> = Uuid::new_v4().as_bytes()[..9]
+            .iter()
+            .map(|byte| u32::from(*byte) % 10)
+            .collect();
+        // repeated digits are never valid
+        algarismos[0] = (algarismos[1] + 1) % 10;
+        let pesos = [11, 10, 9, 8, 7, 6, 5, 4, 3, 2];
+        algarismos.push(digito(&algarismos, &pesos[1..]));
+        algarismos.push(digito(&algarismos, &pesos));
+        algarismos.iter().map(|algarismo| algarismo.to_string()).collect()
+    }
+
+    /// A valid CNPJ nobody else has, for tables where it is unique
+    pub fn cnpj_aleatorio() -> String {
+        let mut algarismos: Vec<u32> = Uuid::new_v4().as_bytes()[..12]
+            .iter()
+            .map(|byte| u32::from(*byte) % 10)
+            .collect();
+        algarismos[0] = (algarismos[1] + 1) % 10;
+        let pesos = [6, 5, 4, 3, 2, 9, 8, 7, 6, 5, 4, 3, 2];
+        algarismos.push(digito(&algarismos, &pesos[1..]));
+        algarismos.push(digito(&algarismos, &pesos));
+        algarismos.iter().map(|algarismo| algarismo.to_string()).collect()
+    }
+
+    #[test]
+    fn it_parses_a_cpf() {
+        assert_eq!(Cpf::from_str("52998224725").unwrap().as_str(), "52998224725");
+        assert_eq!(Cpf::from_str("529.982.247-25").unwrap().as_str(), "52998224725");
+        assert_eq!(Cpf::from_str(" 086.737.239-73 ").unwrap().to_string(), "08673723973");
+    }
+
+    #[test]
+    fn it_checks_the_digits_of_a_cpf() {
+        assert_eq!(Cpf::from_str("52998224726").unwrap_err(), "o CPF 52998224726 é inválido");
+        assert!(Cpf::from_str("12345678901").is_err());
+        assert!(Cpf::from_str("00000000000").is_err());
+        assert!(Cpf::from_str("11111111111").is_err());
+        assert_eq!(
+            Cpf::from_str("5299822472").unwrap_err(),
+            "o CPF deve ter 11 dígitos, recebido 5299822472"
+        );
+        assert!(Cpf::from_str("abcdefghijk").is_err());
+        assert!(Cpf::from_str(&cpf_aleatorio()).is_ok());
+    }
+
+    #[test]
+    fn it_parses_a_cnpj() {
+        assert_eq!(Cnpj::from_str("11222333000181").unwrap().as_str(), "11222333000181");
+        assert_eq!(Cnpj::from_str("11.222.333/0001-81").unwrap().as_str(), "11222333000181");
+        assert_eq!(Cnpj::from_str("12345678000195").unwrap().to_string(), "12345678000195");
+    }
+
+    #[test]
+    fn it_checks_the_digits_of_a_cnpj() {
+        assert_eq!(
+            Cnpj::from_str("11222333000182").unwrap_err(),
+            "o CNPJ 11222333000182 é inválido"
+        );
+        assert!(Cnpj::from_str("11111111111111").is_err());
+        assert!(Cnpj::from_str("00000000000000").is_err());
+        assert_eq!(
+            Cnpj::from_str("1122233300018").unwrap_err(),
+            "o CNPJ deve ter 14 dígitos, recebido 1122233300018"
+        );
+        assert!(Cnpj::from_str("1122233300018A").is_err());
+        assert!(Cnpj::from_str(&cnpj_aleatorio()).is_ok());
+    }
+}
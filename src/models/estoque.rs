@@ -0,0 +1,560 @@
+//! Stock ledger
+//!
+//! Every change of stock is a row in estoque_movimentos holding the signed
+//! quantity it adds to one depósito, so the balance of a produto is always
+//! the sum of its movimentos and nothing else has to be kept in sync.
+
+use crate::config::Config;
+use crate::database::PoolType;
+use crate::errors::ApiError;
+use crate::handlers::estoque::{EstoqueMovimentosResponse, SaldoProdutoResponse, SaldosResponse};
+use crate::models::deposito::{find_deposito, DEPOSITO_PADRAO};
+use crate::models::pedido_produto::PedidoProduto;
+use crate::models::produto_custo::{self, validar_custo};
+use crate::schema::estoque_movimentos;
+use bigdecimal::BigDecimal;
+use chrono::{NaiveDateTime, Utc};
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+use uuid::Uuid;
+
+/// Kind of a stock movement
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TipoMovimento {
+    Entrada,
+    Saida,
+    /// Inventory count correction, the quantity carries its own sign
+    Ajuste,
+    /// Goods moved between depósitos, posted as a pair of opposite rows
+    Transferencia,
+}
+
+impl TipoMovimento {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TipoMovimento::Entrada => "entrada",
+            TipoMovimento::Saida => "saida",
+            TipoMovimento::Ajuste => "ajuste",
+            TipoMovimento::Transferencia => "transferencia",
+        }
+    }
+}
+
+impl fmt::Display for TipoMovimento {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for TipoMovimento {
+    type Err = ApiError;
+
+    fn from_str(tipo: &str) -> Result<Self, Self::Err> {
+        match tipo {
+            "entrada" => Ok(TipoMovimento::Entrada),
+            "saida" => Ok(TipoMovimento::Saida),
+            "ajuste" => Ok(TipoMovimento::Ajuste),
+            "transferencia" => Ok(TipoMovimento::Transferencia),
+            _ => Err(ApiError::InternalServerError(format!("Unknown tipo de movimento {}", tipo))),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Queryable, Identifiable, Insertable)]
+pub struct EstoqueMovimento {
+    pub id: String,
+    pub id_produto: String,
+    pub id_deposito: String,
+    pub tipo: String,
+    pub quantidade: i32,
+    pub id_documento: Option<String>,
+    pub observacao: Option<String>,
+    pub created_by: String,
+    pub created_at: NaiveDateTime,
+    pub custo_unitario: Option<BigDecimal>,
+}
+
+/// A movement as it is asked for, before it becomes rows of the ledger
+///
+/// `quantidade` is what is moved and must be positive, except for ajustes
+/// where a negative quantity takes stock out. Transferências move it from
+/// `id_deposito` to `id_deposito_destino`. Only entradas take a
+/// `custo_unitario`, which goes into the custo_medio of the produto.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct NewEstoqueMovimento {
+    pub id_produto: String,
+    pub id_deposito: String,
+    pub id_deposito_destino: Option<String>,
+    pub tipo: TipoMovimento,
+    pub quantidade: i32,
+    pub custo_unitario: Option<BigDecimal>,
+    pub id_documento: Option<String>,
+    pub observacao: Option<String>,
+    pub created_by: String,
+}
+
+/// Balance of a produto in one depósito: its id, nome and quantity
+pub type SaldoDeposito = (String, String, i64);
+
+/// Stock rules read from the configuration
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ConfigEstoque {
+    /// Refuse movements that would leave a depósito with a negative balance
+    pub bloquear_negativo: bool,
+}
+
+impl ConfigEstoque {
+    pub fn from_config(config: &Config) -> Self {
+        ConfigEstoque {
+            bloquear_negativo: config.estoque_bloquear_negativo,
+        }
+    }
+}
+
+/// Balance of every produto with movimentos, per depósito
+pub fn get_saldos(pool: &PoolType) -> Result<SaldosResponse, ApiError> {
+    let mut conn = pool.get()?;
+    let saldos = saldos(&mut conn, None)?;
+
+    let mut por_produto: BTreeMap<String, Vec<SaldoDeposito>> = BTreeMap::new();
+    for (id_produto, id_deposito, deposito, quantidade) in saldos {
+        por_produto
+            .entry(id_produto)
+            .or_insert_with(Vec::new)
+            .push((id_deposito, deposito, quantidade));
+    }
+
+    Ok(por_produto.into_iter().collect::<Vec<_>>().into())
+}
+
+/// Balance of a produto, per depósito
+pub fn find_saldo(pool: &PoolType, produto_id: Uuid) -> Result<SaldoProdutoResponse, ApiError> {
+    let mut conn = pool.get()?;
+    find_produto(&mut conn, &produto_id.to_string())?;
+    let depositos = saldos(&mut conn, Some(&produto_id.to_string()))?
+        .into_iter()
+        .map(|(_, id_deposito, deposito, quantidade)| (id_deposito, deposito, quantidade))
+        .collect();
+
+    Ok((produto_id.to_string(), depositos).into())
+}
+
+/// Movimentos of a produto, oldest first
+pub fn find_movimentos(pool: &PoolType, produto_id: Uuid) -> Result<EstoqueMovimentosResponse, ApiError> {
+    use crate::schema::estoque_movimentos::dsl::{created_at, estoque_movimentos, id_produto};
+
+    let mut conn = pool.get()?;
+    find_produto(&mut conn, &produto_id.to_string())?;
+    let movimentos = estoque_movimentos
+        .filter(id_produto.eq(produto_id.to_string()))
+        .order(created_at)
+        .load::<EstoqueMovimento>(&mut conn)?;
+
+    Ok(movimentos.into())
+}
+
+/// Post a movement asked for through the API
+pub fn movimentar(
+    pool: &PoolType,
+    new_movimento: &NewEstoqueMovimento,
+    config: &ConfigEstoque,
+) -> Result<EstoqueMovimentosResponse, ApiError> {
+    let mut conn = pool.get()?;
+    conn.transaction::<_, ApiError, _>(|conn| {
+        let movimentos = linhas(new_movimento)?;
+        find_produto(conn, &new_movimento.id_produto)?;
+        find_deposito(conn, &new_movimento.id_deposito)?;
+        if let Some(destino) = &new_movimento.id_deposito_destino {
+            find_deposito(conn, destino)?;
+        }
+        lancar(conn, &movimentos, config)?;
+        Ok(movimentos.into())
+    })
+}
+
+/// Turn a movement into the ledger rows it stands for
+fn linhas(new_movimento: &NewEstoqueMovimento) -> Result<Vec<EstoqueMovimento>, ApiError> {
+    let quantidade = new_movimento.quantidade;
+    let valida = match new_movimento.tipo {
+        TipoMovimento::Ajuste => quantidade != 0,
+        _ => quantidade > 0,
+    };
+    if !valida {
+        return Err(ApiError::ValidationError(vec![match new_movimento.tipo {
+            TipoMovimento::Ajuste => "A quantidade do ajuste não pode ser zero".to_string(),
+            _ => "A quantidade deve ser maior que zero".to_string(),
+        }]));
+    }
+    if let Some(custo_unitario) = &new_movimento.custo_unitario {
+        if new_movimento.tipo != TipoMovimento::Entrada {
+            return Err(ApiError::ValidationError(vec![
+                "Apenas entradas têm custo unitário".to_string(),
+            ]));
+        }
+        validar_custo("custo_unitario", custo_unitario)?;
+    }
+
+    let linha = |id_deposito: &str, quantidade: i32, id_documento: Option<String>| EstoqueMovimento {
+        id: Uuid::new_v4().to_string(),
+        id_produto: new_movimento.id_produto.clone(),
+        id_deposito: id_deposito.to_string(),
+        tipo: new_movimento.tipo.as_str().to_string(),
+        quantidade,
+        id_documento,
+        observacao: new_movimento.observacao.clone(),
+        created_by: new_movimento.created_by.clone(),
+        created_at: Utc::now().naive_utc(),
+        custo_unitario: new_movimento.custo_unitario.clone(),
+    };
+    let origem = new_movimento.id_deposito.as_str();
+    let documento = new_movimento.id_documento.clone();
+    match (new_movimento.tipo, &new_movimento.id_deposito_destino) {
+        (TipoMovimento::Transferencia, Some(destino)) if destino != origem => {
+            // both rows share a documento so the pair can be told apart
+            let documento = documento.or_else(|| Some(Uuid::new_v4().to_string()));
+            Ok(vec![
+                linha(origem, -quantidade, documento.clone()),
+                linha(destino, quantidade, documento),
+            ])
+        }
+        (TipoMovimento::Transferencia, Some(_)) => Err(ApiError::ValidationError(vec![
+            "O depósito de destino deve ser diferente do de origem".to_string(),
+        ])),
+        (TipoMovimento::Transferencia, None) => Err(ApiError::ValidationError(vec![
+            "A transferência precisa de um depósito de destino".to_string(),
+        ])),
+        (_, Some(_)) => Err(ApiError::ValidationError(vec![
+            "Apenas transferências têm depósito de destino".to_string(),
+        ])),
+        (TipoMovimento::Saida, None) => Ok(vec![linha(origem, -quantidade, documento)]),
+        (_, None) => Ok(vec![linha(origem, quantidade, documento)]),
+    }
+}
+
+/// Take the lines of a pedido out of the default depósito
+///
+/// Runs inside the transaction converting the orcamento, so a sale without
+/// stock is refused as a whole when negative stock is blocked.
+pub fn baixar_pedido(
+    conn: &mut PgConnection,
+    pedido_id: &str,
+    itens: &[PedidoProduto],
+    user_id: &str,
+    config: &ConfigEstoque,
+) -> Result<(), ApiError> {
+    let movimentos: Vec<EstoqueMovimento> = itens
+        .iter()
+        .filter(|item| item.quantidade > 0)
+        .map(|item| EstoqueMovimento {
+            id: Uuid::new_v4().to_string(),
+            id_produto: item.id_produto.clone(),
+            id_deposito: DEPOSITO_PADRAO.to_string(),
+            tipo: TipoMovimento::Saida.as_str().to_string(),
+            quantidade: -item.quantidade,
+            id_documento: Some(pedido_id.to_string()),
+            observacao: Some(format!("Venda do pedido {}", pedido_id)),
+            created_by: user_id.to_string(),
+            created_at: Utc::now().naive_utc(),
+            custo_unitario: None,
+        })
+        .collect();
+    lancar(conn, &movimentos, config)
+}
+
+/// Put back the stock the saídas of a pedido took out
+pub fn estornar_pedido(conn: &mut PgConnection, pedido_id: &str, user_id: &str) -> Result<(), ApiError> {
+    use crate::schema::estoque_movimentos::dsl::{estoque_movimentos, id_documento, tipo};
+
+    let saidas = estoque_movimentos
+        .filter(id_documento.eq(pedido_id))
+        .filter(tipo.eq(TipoMovimento::Saida.as_str()))
+        .load::<EstoqueMovimento>(conn)?;
+    let estornos: Vec<EstoqueMovimento> = saidas
+        .into_iter()
+        .map(|saida| EstoqueMovimento {
+            id: Uuid::new_v4().to_string(),
+            tipo: TipoMovimento::Entrada.as_str().to_string(),
+            quantidade: -saida.quantidade,
+            observacao: Some(format!("Estorno do pedido {}", pedido_id)),
+            created_by: user_id.to_string(),
+            created_at: Utc::now().naive_utc(),
+            ..saida
+        })
+        .collect();
+    // giving stock back can't make a balance negative
+    lancar(conn, &estornos, &ConfigEstoque::default())
+}
+
+/// Insert ledger rows, refusing them if a balance they lower goes negative
+/// when that is blocked, and move the custo_medio of the produtos their
+/// costed entradas bring in
+///
+/// The produtos are locked first, so concurrent movements of the same
+/// produto can't both pass the check on the same balance nor average their
+/// costs against it.
+pub fn lancar(
+    conn: &mut PgConnection,
+    movimentos: &[EstoqueMovimento],
+    config: &ConfigEstoque,
+) -> Result<(), ApiError> {
+    use crate::schema::estoque_movimentos::dsl::estoque_movimentos;
+    use crate::schema::produtos::dsl::{descricao, id, produtos};
+
+    if movimentos.is_empty() {
+        return Ok(());
+    }
+    let ids: Vec<&str> = movimentos.iter().map(|movimento| movimento.id_produto.as_str()).collect();
+    let descricoes: BTreeMap<String, String> = produtos
+        .filter(id.eq_any(ids))
+        .select((id, descricao))
+        .for_update()
+        .load::<(String, String)>(conn)?
+        .into_iter()
+        .collect();
+    let custos = produto_custo::custos_das_entradas(conn, movimentos)?;
+    diesel::insert_into(estoque_movimentos).values(movimentos).execute(conn)?;
+    produto_custo::aplicar(conn, &custos)?;
+
+    if !config.bloquear_negativo {
+        return Ok(());
+    }
+    let mut reduzidos: Vec<(&str, &str)> = movimentos
+        .iter()
+        .filter(|movimento| movimento.quantidade < 0)
+        .map(|movimento| (movimento.id_produto.as_str(), movimento.id_deposito.as_str()))
+        .collect();
+    reduzidos.sort_unstable();
+    reduzidos.dedup();
+    let mut errors = Vec::new();
+    for (id_produto, id_deposito) in reduzidos {
+        let saldo = saldo(conn, id_produto, id_deposito)?;
+        if saldo < 0 {
+            let deposito = find_deposito(conn, id_deposito)?;
+            errors.push(format!(
+                "Estoque insuficiente de {} no {}: faltam {}",
+                descricoes.get(id_produto).map(String::as_str).unwrap_or(id_produto),
+                deposito.nome,
+                -saldo
+            ));
+        }
+    }
+    if !errors.is_empty() {
+        return Err(ApiError::ValidationError(errors));
+    }
+    Ok(())
+}
+
+/// Balance of a produto in a depósito
+pub fn saldo(conn: &mut PgConnection, produto_id: &str, deposito_id: &str) -> Result<i64, ApiError> {
+    use crate::schema::estoque_movimentos::dsl::{estoque_movimentos, id_deposito, id_produto, quantidade};
+
+    let saldo = estoque_movimentos
+        .filter(id_produto.eq(produto_id))
+        .filter(id_deposito.eq(deposito_id))
+        .select(diesel::dsl::sum(quantidade))
+        .first::<Option<i64>>(conn)?;
+    Ok(saldo.unwrap_or(0))
+}
+
+/// Balance of a produto summed over every depósito
+pub fn saldo_total(conn: &mut PgConnection, produto_id: &str) -> Result<i64, ApiError> {
+    use crate::schema::estoque_movimentos::dsl::{estoque_movimentos, id_produto, quantidade};
+
+    let saldo = estoque_movimentos
+        .filter(id_produto.eq(produto_id))
+        .select(diesel::dsl::sum(quantidade))
+        .first::<Option<i64>>(conn)?;
+    Ok(saldo.unwrap_or(0))
+}
+
+/// Balances grouped by produto and depósito, of one produto or of all of them
+fn saldos(
+    conn: &mut PgConnection,
+    produto_id: Option<&str>,
+) -> Result<Vec<(String, String, String, i64)>, ApiError> {
+    use crate::schema::depositos::dsl::{depositos, id, nome};
+    use crate::schema::estoque_movimentos::dsl::{estoque_movimentos, id_deposito, id_produto, quantidade};
+
+    let mut query = estoque_movimentos
+        .group_by((id_produto, id_deposito))
+        .select((id_produto, id_deposito, diesel::dsl::sum(quantidade)))
+        .into_boxed();
+    if let Some(produto_id) = produto_id {
+        query = query.filter(id_produto.eq(produto_id.to_string()));
+    }
+    let saldos = query.load::<(String, String, Option<i64>)>(conn)?;
+    let nomes: BTreeMap<String, String> = depositos
+        .select((id, nome))
+        .load::<(String, String)>(conn)?
+        .into_iter()
+        .collect();
+
+    let mut saldos: Vec<(String, String, String, i64)> = saldos
+        .into_iter()
+        .map(|(produto, deposito, saldo)| {
+            let nome_deposito = nomes.get(&deposito).cloned().unwrap_or_default();
+            (produto, deposito, nome_deposito, saldo.unwrap_or(0))
+        })
+        .collect();
+    saldos.sort_by(|a, b| (&a.0, &a.2).cmp(&(&b.0, &b.2)));
+    Ok(saldos)
+}
+
+fn find_produto(conn: &mut PgConnection, produto_id: &str) -> Result<(), ApiError> {
+    use crate::schema::produtos::dsl::{id, produtos};
+
+    let not_found = format!("Produto {} not found", produto_id);
+    produtos
+        .filter(id.eq(produto_id))
+        .select(id)
+        .first::<String>(conn)
+        .map(|_| ())
+        .map_err(|_| ApiError::NotFound(not_found))
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::models::deposito::tests::{create_deposito, delete_deposito};
+    use crate::models::produto::delete as delete_produto;
+    use crate::models::produto::tests::create_produto;
+    use crate::tests::helpers::tests::get_pool;
+
+    /// Remove the movimentos of a produto created by a test, so it can be deleted
+    pub fn delete_movimentos(produto_id: Uuid) {
+        use crate::schema::estoque_movimentos::dsl::{estoque_movimentos, id_produto};
+
+        let mut conn = get_pool().get().unwrap();
+        diesel::delete(estoque_movimentos)
+            .filter(id_produto.eq(produto_id.to_string()))
+            .execute(&mut conn)
+            .unwrap();
+    }
+
+    /// Remove the movimentos a test's pedido or nota de entrada left behind
+    pub fn delete_movimentos_do_documento(documento_id: Uuid) {
+        use crate::schema::estoque_movimentos::dsl::{estoque_movimentos, id_documento};
+
+        let mut conn = get_pool().get().unwrap();
+        diesel::delete(estoque_movimentos)
+            .filter(id_documento.eq(documento_id.to_string()))
+            .execute(&mut conn)
+            .unwrap();
+    }
+
+    pub fn movimento(produto_id: Uuid, tipo: TipoMovimento, quantidade: i32) -> NewEstoqueMovimento {
+        NewEstoqueMovimento {
+            id_produto: produto_id.to_string(),
+            id_deposito: DEPOSITO_PADRAO.to_string(),
+            id_deposito_destino: None,
+            tipo,
+            quantidade,
+            custo_unitario: None,
+            id_documento: None,
+            observacao: None,
+            created_by: produto_id.to_string(),
+        }
+    }
+
+    fn bloqueado() -> ConfigEstoque {
+        ConfigEstoque { bloquear_negativo: true }
+    }
+
+    #[test]
+    fn it_signs_the_rows_of_each_tipo() {
+        let produto_id = Uuid::new_v4();
+        let quantidades = |tipo, quantidade| {
+            linhas(&movimento(produto_id, tipo, quantidade))
+                .map(|linhas| linhas.iter().map(|linha| linha.quantidade).collect::<Vec<i32>>())
+        };
+        assert_eq!(quantidades(TipoMovimento::Entrada, 5).unwrap(), vec![5]);
+        assert_eq!(quantidades(TipoMovimento::Saida, 5).unwrap(), vec![-5]);
+        assert_eq!(quantidades(TipoMovimento::Ajuste, -3).unwrap(), vec![-3]);
+        assert!(quantidades(TipoMovimento::Saida, -5).is_err());
+        assert!(quantidades(TipoMovimento::Ajuste, 0).is_err());
+        assert!(quantidades(TipoMovimento::Transferencia, 5).is_err());
+
+        let mut transferencia = movimento(produto_id, TipoMovimento::Transferencia, 5);
+        transferencia.id_deposito_destino = Some(Uuid::new_v4().to_string());
+        let pares = linhas(&transferencia).unwrap();
+        assert_eq!(pares.iter().map(|linha| linha.quantidade).collect::<Vec<i32>>(), vec![-5, 5]);
+        assert_eq!(pares[0].id_documento, pares[1].id_documento);
+        transferencia.id_deposito_destino = Some(DEPOSITO_PADRAO.to_string());
+        assert!(linhas(&transferencia).is_err());
+
+        let mut saida = movimento(produto_id, TipoMovimento::Saida, 5);
+        saida.custo_unitario = Some(BigDecimal::from(2));
+        assert!(linhas(&saida).is_err());
+    }
+
+    #[test]
+    fn it_keeps_the_cost_of_an_entrada_in_its_row() {
+        let mut entrada = movimento(Uuid::new_v4(), TipoMovimento::Entrada, 5);
+        entrada.custo_unitario = Some(BigDecimal::from(2));
+        assert_eq!(linhas(&entrada).unwrap()[0].custo_unitario, Some(BigDecimal::from(2)));
+        entrada.custo_unitario = Some(BigDecimal::from(-2));
+        let expected_error = ApiError::ValidationError(vec![
+            "custo_unitario: o custo não pode ser negativo, recebido -2".to_string(),
+        ]);
+        assert_eq!(linhas(&entrada).unwrap_err(), expected_error);
+    }
+
+    #[test]
+    fn it_keeps_the_balance_per_deposito() {
+        let produto = create_produto().unwrap();
+        let deposito = create_deposito().unwrap();
+        movimentar(&get_pool(), &movimento(produto.id, TipoMovimento::Entrada, 10), &bloqueado()).unwrap();
+        movimentar(&get_pool(), &movimento(produto.id, TipoMovimento::Saida, 3), &bloqueado()).unwrap();
+        let mut transferencia = movimento(produto.id, TipoMovimento::Transferencia, 4);
+        transferencia.id_deposito_destino = Some(deposito.id.to_string());
+        movimentar(&get_pool(), &transferencia, &bloqueado()).unwrap();
+
+        let saldo = find_saldo(&get_pool(), produto.id).unwrap();
+        assert_eq!(saldo.total, 3);
+        let por_deposito: Vec<(Uuid, i64)> =
+            saldo.depositos.iter().map(|saldo| (saldo.id_deposito, saldo.quantidade)).collect();
+        assert!(por_deposito.contains(&(Uuid::parse_str(DEPOSITO_PADRAO).unwrap(), 3)));
+        assert!(por_deposito.contains(&(deposito.id, 4)));
+        assert_eq!(find_movimentos(&get_pool(), produto.id).unwrap().0.len(), 4);
+        assert!(get_saldos(&get_pool()).unwrap().0.contains(&saldo));
+
+        delete_movimentos(produto.id);
+        delete_deposito(deposito.id);
+        delete_produto(&get_pool(), produto.id).unwrap();
+    }
+
+    #[test]
+    fn it_blocks_negative_stock_when_configured() {
+        let produto = create_produto().unwrap();
+        movimentar(&get_pool(), &movimento(produto.id, TipoMovimento::Entrada, 2), &bloqueado()).unwrap();
+
+        let saida = movimento(produto.id, TipoMovimento::Saida, 3);
+        let blocked = movimentar(&get_pool(), &saida, &bloqueado());
+        let expected_error = ApiError::ValidationError(vec![format!(
+            "Estoque insuficiente de {} no Depósito principal: faltam 1",
+            produto.descricao
+        )]);
+        assert_eq!(blocked.unwrap_err(), expected_error);
+        assert_eq!(find_saldo(&get_pool(), produto.id).unwrap().total, 2);
+
+        movimentar(&get_pool(), &saida, &ConfigEstoque::default()).unwrap();
+        assert_eq!(find_saldo(&get_pool(), produto.id).unwrap().total, -1);
+
+        delete_movimentos(produto.id);
+        delete_produto(&get_pool(), produto.id).unwrap();
+    }
+
+    #[test]
+    fn it_doesnt_move_an_unknown_produto() {
+        let produto_id = Uuid::new_v4();
+        let moved = movimentar(&get_pool(), &movimento(produto_id, TipoMovimento::Entrada, 1), &bloqueado());
+        let expected_error = ApiError::NotFound(format!("Produto {} not found", produto_id));
+        assert_eq!(moved.unwrap_err(), expected_error);
+        assert!(find_saldo(&get_pool(), produto_id).is_err());
+    }
+}
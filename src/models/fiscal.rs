@@ -0,0 +1,374 @@
+use crate::config::Config;
+use crate::models::cliente::{Cliente, TipoPessoa};
+use crate::models::cliente_endereco::ClienteEndereco;
+use crate::models::orcamento_produto::OrcamentoProduto;
+use crate::models::pedido_produto::PedidoProduto;
+use crate::models::produto::Produto;
+use bigdecimal::{BigDecimal, Zero};
+
+/// States where an interstate sale to the rest of the country is taxed at 7%
+///
+/// Sales from the South and Southeast, Espírito Santo excluded, to the North,
+/// Northeast, Center-West and Espírito Santo use the lower rate, every other
+/// interstate sale uses 12%.
+const UFS_SUL_SUDESTE: [&str; 6] = ["MG", "PR", "RJ", "RS", "SC", "SP"];
+
+/// ICMS CSTs that do not charge ICMS on the sale itself
+const CST_ICMS_SEM_DEBITO: [&str; 6] = ["30", "40", "41", "50", "51", "60"];
+
+/// PIS/COFINS CSTs of sales that are not taxed at an ad valorem rate
+const CST_PIS_COFINS_SEM_DEBITO: [&str; 6] = ["04", "05", "06", "07", "08", "09"];
+
+/// The operation a tax is computed for
+#[derive(Clone, Debug, PartialEq)]
+pub struct ContextoFiscal {
+    pub uf_origem: Option<String>,
+    pub uf_destino: Option<String>,
+    pub consumidor_final: bool,
+}
+
+impl Default for ContextoFiscal {
+    /// A sale inside the state to the final consumer
+    fn default() -> Self {
+        ContextoFiscal {
+            uf_origem: None,
+            uf_destino: None,
+            consumidor_final: true,
+        }
+    }
+}
+
+impl ContextoFiscal {
+    pub fn interestadual(&self) -> bool {
+        match (&self.uf_origem, &self.uf_destino) {
+            (Some(origem), Some(destino)) => !origem.eq_ignore_ascii_case(destino),
+            _ => false,
+        }
+    }
+
+    /// ICMS rate of an interstate sale, or None when the sale is internal
+    fn aliquota_interestadual(&self) -> Option<BigDecimal> {
+        if !self.interestadual() {
+            return None;
+        }
+        let sul_sudeste = |uf: &Option<String>| {
+            uf.as_ref()
+                .map(|uf| UFS_SUL_SUDESTE.contains(&uf.to_uppercase().as_str()))
+                .unwrap_or(false)
+        };
+        if sul_sudeste(&self.uf_origem) && !sul_sudeste(&self.uf_destino) {
+            Some(BigDecimal::from(7))
+        } else {
+            Some(BigDecimal::from(12))
+        }
+    }
+}
+
+/// Where sales leave from, read from the configuration
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ConfigFiscal {
+    pub uf_origem: Option<String>,
+}
+
+impl ConfigFiscal {
+    pub fn from_config(config: &Config) -> Self {
+        ConfigFiscal {
+            uf_origem: config
+                .emitente_uf
+                .as_ref()
+                .map(|uf| uf.trim().to_uppercase())
+                .filter(|uf| !uf.is_empty()),
+        }
+    }
+}
+
+/// The sale of the emitente to a cliente
+///
+/// The sale goes to the UF of the cliente's principal endereço, a cliente
+/// without one is taxed as a sale inside the state. Only a pessoa jurídica
+/// that is a contribuinte of the ICMS buys as something other than the final
+/// consumer.
+pub fn contexto(config: &ConfigFiscal, cliente: &Cliente, endereco: Option<&ClienteEndereco>) -> ContextoFiscal {
+    let juridica = cliente.tipo_pessoa == TipoPessoa::Juridica.as_str();
+    ContextoFiscal {
+        uf_origem: config.uf_origem.clone(),
+        uf_destino: endereco.map(|endereco| endereco.uf.clone()),
+        consumidor_final: !(juridica && contribuinte(&cliente.inscricao_estadual)),
+    }
+}
+
+/// Whether an inscrição estadual makes its holder a contribuinte of the ICMS,
+/// the ones exempt from it are written as ISENTO
+pub fn contribuinte(inscricao_estadual: &Option<String>) -> bool {
+    let inscricao_estadual = inscricao_estadual.as_deref().map(str::trim).unwrap_or_default();
+    !inscricao_estadual.eq_ignore_ascii_case("ISENTO") && inscricao_estadual.chars().any(|c| c.is_ascii_digit())
+}
+
+/// Tax classification of a produto, as kept on the produto and on the lines
+/// that snapshot it
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DadosFiscais {
+    pub cst_icms: Option<String>,
+    pub aliquota_icms: Option<BigDecimal>,
+    pub porcentagem_ipi: Option<BigDecimal>,
+    pub cst_pis: Option<String>,
+    pub aliquota_pis: Option<BigDecimal>,
+    pub cst_cofins: Option<String>,
+    pub aliquota_cofins: Option<BigDecimal>,
+}
+
+impl From<&Produto> for DadosFiscais {
+    fn from(produto: &Produto) -> Self {
+        DadosFiscais {
+            cst_icms: produto.icms.clone(),
+            aliquota_icms: produto.aliquota_icms.clone(),
+            porcentagem_ipi: produto.porcentagem_ipi.clone(),
+            cst_pis: produto.pis.clone(),
+            aliquota_pis: produto.aliquota_pis.clone(),
+            cst_cofins: produto.cofins.clone(),
+            aliquota_cofins: produto.aliquota_cofins.clone(),
+        }
+    }
+}
+
+impl From<&OrcamentoProduto> for DadosFiscais {
+    fn from(orcamento_produto: &OrcamentoProduto) -> Self {
+        DadosFiscais {
+            cst_icms: orcamento_produto.icms.clone(),
+            aliquota_icms: orcamento_produto.aliquota_icms.clone(),
+            porcentagem_ipi: orcamento_produto.porcentagem_ipi.clone(),
+            cst_pis: orcamento_produto.pis.clone(),
+            aliquota_pis: orcamento_produto.aliquota_pis.clone(),
+            cst_cofins: orcamento_produto.cofins.clone(),
+            aliquota_cofins: orcamento_produto.aliquota_cofins.clone(),
+        }
+    }
+}
+
+impl From<&PedidoProduto> for DadosFiscais {
+    fn from(pedido_produto: &PedidoProduto) -> Self {
+        DadosFiscais {
+            cst_icms: pedido_produto.icms.clone(),
+            aliquota_icms: pedido_produto.aliquota_icms.clone(),
+            porcentagem_ipi: pedido_produto.porcentagem_ipi.clone(),
+            cst_pis: pedido_produto.pis.clone(),
+            aliquota_pis: pedido_produto.aliquota_pis.clone(),
+            cst_cofins: pedido_produto.cofins.clone(),
+            aliquota_cofins: pedido_produto.aliquota_cofins.clone(),
+        }
+    }
+}
+
+/// One tax over one line
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct Imposto {
+    pub base_calculo: BigDecimal,
+    pub aliquota: BigDecimal,
+    pub valor: BigDecimal,
+}
+
+impl Imposto {
+    fn zero() -> Self {
+        Imposto {
+            base_calculo: BigDecimal::zero(),
+            aliquota: BigDecimal::zero(),
+            valor: BigDecimal::zero(),
+        }
+    }
+
+    fn calcular(base_calculo: BigDecimal, aliquota: BigDecimal) -> Self {
+        let base_calculo = arredondar(&base_calculo);
+        let valor = arredondar(&(&base_calculo * &aliquota / BigDecimal::from(100)));
+        Imposto { base_calculo, aliquota, valor }
+    }
+}
+
+/// Taxes over one line
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct Impostos {
+    pub icms: Imposto,
+    pub ipi: Imposto,
+    pub pis: Imposto,
+    pub cofins: Imposto,
+}
+
+/// Taxes over a whole document, summed line by line
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct TotalImpostos {
+    pub base_calculo_icms: BigDecimal,
+    pub valor_icms: BigDecimal,
+    pub valor_ipi: BigDecimal,
+    pub valor_pis: BigDecimal,
+    pub valor_cofins: BigDecimal,
+}
+
+impl TotalImpostos {
+    pub fn somar<'a>(impostos: impl IntoIterator<Item = &'a Impostos>) -> Self {
+        impostos.into_iter().fold(
+            TotalImpostos {
+                base_calculo_icms: BigDecimal::zero(),
+                valor_icms: BigDecimal::zero(),
+                valor_ipi: BigDecimal::zero(),
+                valor_pis: BigDecimal::zero(),
+                valor_cofins: BigDecimal::zero(),
+            },
+            |total, impostos| TotalImpostos {
+                base_calculo_icms: total.base_calculo_icms + &impostos.icms.base_calculo,
+                valor_icms: total.valor_icms + &impostos.icms.valor,
+                valor_ipi: total.valor_ipi + &impostos.ipi.valor,
+                valor_pis: total.valor_pis + &impostos.pis.valor,
+                valor_cofins: total.valor_cofins + &impostos.cofins.valor,
+            },
+        )
+    }
+}
+
+/// Round half up to cents, the way tax amounts are stated on documents
+pub fn arredondar(valor: &BigDecimal) -> BigDecimal {
+    valor.round(2).with_scale(2)
+}
+
+/// Compute ICMS, IPI, PIS and COFINS over `valor`, the net value of a line
+///
+/// IPI is charged on top of the price and enters the ICMS base when the buyer
+/// is the final consumer. PIS and COFINS are charged over the value without
+/// the ICMS. A missing CST means the tax is due at the produto's rate.
+pub fn calcular(dados: &DadosFiscais, valor: &BigDecimal, contexto: &ContextoFiscal) -> Impostos {
+    let ipi = match &dados.porcentagem_ipi {
+        Some(porcentagem) => Imposto::calcular(valor.clone(), porcentagem.clone()),
+        None => Imposto::zero(),
+    };
+
+    let icms = if tributado(&dados.cst_icms, &CST_ICMS_SEM_DEBITO) {
+        let base_calculo = if contexto.consumidor_final { valor + &ipi.valor } else { valor.clone() };
+        let aliquota = contexto
+            .aliquota_interestadual()
+            .or_else(|| dados.aliquota_icms.clone())
+            .unwrap_or_else(BigDecimal::zero);
+        Imposto::calcular(base_calculo, aliquota)
+    } else {
+        Imposto::zero()
+    };
+
+    let base_pis_cofins = valor - &icms.valor;
+    let pis_cofins = |cst: &Option<String>, aliquota: &Option<BigDecimal>| match aliquota {
+        Some(aliquota) if tributado(cst, &CST_PIS_COFINS_SEM_DEBITO) => {
+            Imposto::calcular(base_pis_cofins.clone(), aliquota.clone())
+        }
+        _ => Imposto::zero(),
+    };
+    let pis = pis_cofins(&dados.cst_pis, &dados.aliquota_pis);
+    let cofins = pis_cofins(&dados.cst_cofins, &dados.aliquota_cofins);
+
+    Impostos { icms, ipi, pis, cofins }
+}
+
+fn tributado(cst: &Option<String>, sem_debito: &[&str]) -> bool {
+    cst.as_ref().map(|cst| !sem_debito.contains(&cst.as_str())).unwrap_or(true)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn decimal(valor: &str) -> BigDecimal {
+        BigDecimal::from_str(valor).unwrap()
+    }
+
+    fn dados() -> DadosFiscais {
+        DadosFiscais {
+            cst_icms: Some("00".to_string()),
+            aliquota_icms: Some(decimal("18")),
+            porcentagem_ipi: None,
+            cst_pis: Some("01".to_string()),
+            aliquota_pis: Some(decimal("1.65")),
+            cst_cofins: Some("01".to_string()),
+            aliquota_cofins: Some(decimal("7.60")),
+        }
+    }
+
+    fn interestadual(origem: &str, destino: &str) -> ContextoFiscal {
+        ContextoFiscal {
+            uf_origem: Some(origem.to_string()),
+            uf_destino: Some(destino.to_string()),
+            consumidor_final: true,
+        }
+    }
+
+    #[test]
+    fn it_rounds_half_up_to_cents() {
+        assert_eq!(arredondar(&decimal("1.005")), decimal("1.01"));
+        assert_eq!(arredondar(&decimal("1.004")), decimal("1.00"));
+        assert_eq!(arredondar(&decimal("2")).to_string(), "2.00");
+    }
+
+    #[test]
+    fn it_calculates_the_taxes_of_an_internal_sale() {
+        let impostos = calcular(&dados(), &decimal("100.00"), &ContextoFiscal::default());
+        assert_eq!(impostos.icms.base_calculo, decimal("100.00"));
+        assert_eq!(impostos.icms.valor, decimal("18.00"));
+        assert_eq!(impostos.ipi.valor, decimal("0"));
+        assert_eq!(impostos.pis.base_calculo, decimal("82.00"));
+        assert_eq!(impostos.pis.valor, decimal("1.35"));
+        assert_eq!(impostos.cofins.valor, decimal("6.23"));
+    }
+
+    #[test]
+    fn it_adds_the_ipi_to_the_icms_base_of_the_final_consumer() {
+        let dados = DadosFiscais { porcentagem_ipi: Some(decimal("10")), ..dados() };
+        let impostos = calcular(&dados, &decimal("100.00"), &ContextoFiscal::default());
+        assert_eq!(impostos.ipi.valor, decimal("10.00"));
+        assert_eq!(impostos.icms.base_calculo, decimal("110.00"));
+        assert_eq!(impostos.icms.valor, decimal("19.80"));
+
+        let contribuinte = ContextoFiscal { consumidor_final: false, ..ContextoFiscal::default() };
+        let impostos = calcular(&dados, &decimal("100.00"), &contribuinte);
+        assert_eq!(impostos.icms.base_calculo, decimal("100.00"));
+    }
+
+    #[test]
+    fn it_tells_the_contribuintes_of_the_icms() {
+        assert!(contribuinte(&Some("110.042.490.114".to_string())));
+        assert!(!contribuinte(&Some("ISENTO".to_string())));
+        assert!(!contribuinte(&Some(" isento ".to_string())));
+        assert!(!contribuinte(&Some("".to_string())));
+        assert!(!contribuinte(&None));
+    }
+
+    #[test]
+    fn it_uses_the_interstate_icms_rate() {
+        let valor = decimal("100.00");
+        assert_eq!(calcular(&dados(), &valor, &interestadual("SP", "BA")).icms.aliquota, decimal("7"));
+        assert_eq!(calcular(&dados(), &valor, &interestadual("SP", "ES")).icms.aliquota, decimal("7"));
+        assert_eq!(calcular(&dados(), &valor, &interestadual("SP", "RJ")).icms.aliquota, decimal("12"));
+        assert_eq!(calcular(&dados(), &valor, &interestadual("BA", "SP")).icms.aliquota, decimal("12"));
+        assert_eq!(calcular(&dados(), &valor, &interestadual("SP", "sp")).icms.aliquota, decimal("18"));
+    }
+
+    #[test]
+    fn it_skips_taxes_the_cst_does_not_charge() {
+        let dados = DadosFiscais {
+            cst_icms: Some("60".to_string()),
+            cst_pis: Some("04".to_string()),
+            cst_cofins: Some("06".to_string()),
+            ..dados()
+        };
+        let impostos = calcular(&dados, &decimal("100.00"), &ContextoFiscal::default());
+        assert_eq!(impostos.icms, Imposto::zero());
+        assert_eq!(impostos.pis, Imposto::zero());
+        assert_eq!(impostos.cofins, Imposto::zero());
+    }
+
+    #[test]
+    fn it_sums_the_taxes_of_every_line() {
+        let linhas = vec![
+            calcular(&dados(), &decimal("100.00"), &ContextoFiscal::default()),
+            calcular(&dados(), &decimal("50.00"), &ContextoFiscal::default()),
+        ];
+        let total = TotalImpostos::somar(&linhas);
+        assert_eq!(total.base_calculo_icms, decimal("150.00"));
+        assert_eq!(total.valor_icms, decimal("27.00"));
+        assert_eq!(total.valor_pis, decimal("2.03"));
+    }
+}
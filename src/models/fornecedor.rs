@@ -0,0 +1,374 @@
+use crate::database::PoolType;
+use crate::errors::ApiError;
+use crate::handlers::fornecedor::{
+    FornecedorProdutoResponse, FornecedorProdutosResponse, FornecedorResponse, FornecedoresResponse,
+};
+use crate::schema::{fornecedor_produtos, fornecedores};
+use chrono::{NaiveDateTime, Utc};
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use uuid::Uuid;
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Queryable, Identifiable, Insertable)]
+#[table_name = "fornecedores"]
+pub struct Fornecedor {
+    pub id: String,
+    pub cnpj: String,
+    pub razao_social: String,
+    pub nome_fantasia: Option<String>,
+    pub inscricao_estadual: Option<String>,
+    pub created_by: String,
+    pub created_at: NaiveDateTime,
+    pub updated_by: String,
+    pub updated_at: NaiveDateTime,
+    pub contato: Option<String>,
+    pub email: Option<String>,
+    pub telefone: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct NewFornecedor {
+    pub id: String,
+    pub cnpj: String,
+    pub razao_social: String,
+    pub nome_fantasia: Option<String>,
+    pub inscricao_estadual: Option<String>,
+    pub contato: Option<String>,
+    pub email: Option<String>,
+    pub telefone: Option<String>,
+    pub created_by: String,
+    pub updated_by: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, AsChangeset)]
+#[table_name = "fornecedores"]
+pub struct UpdateFornecedor {
+    pub id: String,
+    pub cnpj: String,
+    pub razao_social: String,
+    pub nome_fantasia: Option<String>,
+    pub inscricao_estadual: Option<String>,
+    pub contato: Option<String>,
+    pub email: Option<String>,
+    pub telefone: Option<String>,
+    pub updated_by: String,
+}
+
+/// The code a fornecedor uses for one of our produtos
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Queryable, Insertable)]
+#[table_name = "fornecedor_produtos"]
+pub struct FornecedorProduto {
+    pub id_fornecedor: String,
+    pub codigo: String,
+    pub id_produto: String,
+    pub created_by: String,
+    pub created_at: NaiveDateTime,
+    /// The fornecedor the produto is bought from by default
+    pub preferencial: bool,
+}
+
+/// Get all fornecedores
+pub fn get_all(pool: &PoolType) -> Result<FornecedoresResponse, ApiError> {
+    use crate::schema::fornecedores::dsl::{fornecedores, razao_social};
+
+    let mut conn = pool.get()?;
+    let all_fornecedores = fornecedores.order(razao_social).load::<Fornecedor>(&mut conn)?;
+
+    Ok(all_fornecedores.into())
+}
+
+/// Find a fornecedor by the fornecedor's id or error out
+pub fn find(pool: &PoolType, fornecedor_id: Uuid) -> Result<FornecedorResponse, ApiError> {
+    let mut conn = pool.get()?;
+    Ok(find_fornecedor(&mut conn, &fornecedor_id.to_string())?.into())
+}
+
+/// Find a fornecedor using an existing connection
+pub fn find_fornecedor(conn: &mut PgConnection, fornecedor_id: &str) -> Result<Fornecedor, ApiError> {
+    use crate::schema::fornecedores::dsl::{fornecedores, id};
+
+    let not_found = format!("Fornecedor {} not found", fornecedor_id);
+    fornecedores
+        .filter(id.eq(fornecedor_id))
+        .first::<Fornecedor>(conn)
+        .map_err(|_| ApiError::NotFound(not_found))
+}
+
+/// Find a fornecedor by its CNPJ, digits only
+pub fn find_by_cnpj(conn: &mut PgConnection, fornecedor_cnpj: &str) -> Result<Option<Fornecedor>, ApiError> {
+    use crate::schema::fornecedores::dsl::{cnpj, fornecedores};
+
+    let fornecedor = fornecedores
+        .filter(cnpj.eq(fornecedor_cnpj))
+        .first::<Fornecedor>(conn)
+        .optional()?;
+    Ok(fornecedor)
+}
+
+/// Create a new fornecedor
+pub fn create(pool: &PoolType, new_fornecedor: &Fornecedor) -> Result<FornecedorResponse, ApiError> {
+    let mut conn = pool.get()?;
+    registrar(&mut conn, new_fornecedor)?;
+    Ok(new_fornecedor.clone().into())
+}
+
+/// Insert a fornecedor using an existing connection
+pub fn registrar(conn: &mut PgConnection, new_fornecedor: &Fornecedor) -> Result<(), ApiError> {
+    use crate::schema::fornecedores::dsl::fornecedores;
+
+    diesel::insert_into(fornecedores).values(new_fornecedor).execute(conn)?;
+    Ok(())
+}
+
+/// Update a fornecedor
+pub fn update(pool: &PoolType, update_fornecedor: &UpdateFornecedor) -> Result<FornecedorResponse, ApiError> {
+    use crate::schema::fornecedores::dsl::{fornecedores, id, updated_at};
+
+    let mut conn = pool.get()?;
+    find_fornecedor(&mut conn, &update_fornecedor.id)?;
+    diesel::update(fornecedores)
+        .filter(id.eq(update_fornecedor.id.clone()))
+        .set((update_fornecedor, updated_at.eq(Utc::now().naive_utc())))
+        .execute(&mut conn)?;
+    find(pool, Uuid::parse_str(&update_fornecedor.id)?)
+}
+
+/// Delete a fornecedor, unless notas de entrada or pedidos de compra refer to it
+pub fn delete(pool: &PoolType, fornecedor_id: Uuid) -> Result<(), ApiError> {
+    use crate::schema::fornecedores::dsl::{fornecedores, id};
+    use crate::schema::notas_entrada::dsl::{id_fornecedor, notas_entrada};
+    use crate::schema::pedidos_compra::dsl::{id_fornecedor as id_fornecedor_pedido, pedidos_compra};
+
+    let mut conn = pool.get()?;
+    let notas = notas_entrada
+        .filter(id_fornecedor.eq(fornecedor_id.to_string()))
+        .count()
+        .get_result::<i64>(&mut conn)?;
+    let pedidos = pedidos_compra
+        .filter(id_fornecedor_pedido.eq(fornecedor_id.to_string()))
+        .count()
+        .get_result::<i64>(&mut conn)?;
+    let mut errors = Vec::new();
+    if notas > 0 {
+        errors.push(format!(
+            "O fornecedor tem {} nota(s) de entrada e não pode ser excluído",
+            notas
+        ));
+    }
+    if pedidos > 0 {
+        errors.push(format!(
+            "O fornecedor tem {} pedido(s) de compra e não pode ser excluído",
+            pedidos
+        ));
+    }
+    if !errors.is_empty() {
+        return Err(ApiError::ValidationError(errors));
+    }
+    diesel::delete(fornecedores)
+        .filter(id.eq(fornecedor_id.to_string()))
+        .execute(&mut conn)?;
+    Ok(())
+}
+
+/// The produtos a fornecedor supplies, by their code at the fornecedor
+pub fn find_produtos(pool: &PoolType, fornecedor_id: Uuid) -> Result<FornecedorProdutosResponse, ApiError> {
+    use crate::schema::fornecedor_produtos::dsl::{codigo, fornecedor_produtos, id_fornecedor};
+
+    let mut conn = pool.get()?;
+    find_fornecedor(&mut conn, &fornecedor_id.to_string())?;
+    let vinculos = fornecedor_produtos
+        .filter(id_fornecedor.eq(fornecedor_id.to_string()))
+        .order(codigo)
+        .load::<FornecedorProduto>(&mut conn)?;
+
+    Ok(vinculos.into())
+}
+
+/// The fornecedores of a produto, the preferred one first
+pub fn find_by_produto(pool: &PoolType, produto_id: Uuid) -> Result<FornecedorProdutosResponse, ApiError> {
+    use crate::schema::fornecedor_produtos::dsl::{created_at, fornecedor_produtos, id_produto, preferencial};
+
+    let mut conn = pool.get()?;
+    let vinculos = fornecedor_produtos
+        .filter(id_produto.eq(produto_id.to_string()))
+        .order((preferencial.desc(), created_at))
+        .load::<FornecedorProduto>(&mut conn)?;
+
+    Ok(vinculos.into())
+}
+
+/// The produto a fornecedor code stands for, if it was ever linked
+pub fn find_produto_by_codigo(
+    conn: &mut PgConnection,
+    fornecedor_id: &str,
+    codigo_fornecedor: &str,
+) -> Result<Option<String>, ApiError> {
+    use crate::schema::fornecedor_produtos::dsl::{codigo, fornecedor_produtos, id_fornecedor, id_produto};
+
+    let produto_id = fornecedor_produtos
+        .filter(id_fornecedor.eq(fornecedor_id))
+        .filter(codigo.eq(codigo_fornecedor))
+        .select(id_produto)
+        .first::<String>(conn)
+        .optional()?;
+    Ok(produto_id)
+}
+
+/// Link a fornecedor code to a produto, keeping the link it already has
+pub fn vincular_produto(conn: &mut PgConnection, vinculo: &FornecedorProduto) -> Result<(), ApiError> {
+    use crate::schema::fornecedor_produtos::dsl::fornecedor_produtos;
+
+    diesel::insert_into(fornecedor_produtos)
+        .values(vinculo)
+        .on_conflict_do_nothing()
+        .execute(conn)?;
+    Ok(())
+}
+
+/// Link a fornecedor code to a produto, replacing what the code pointed to
+///
+/// Making the fornecedor the preferred one of the produto takes that place
+/// from any other fornecedor.
+pub fn salvar_produto(pool: &PoolType, vinculo: &FornecedorProduto) -> Result<FornecedorProdutoResponse, ApiError> {
+    use crate::schema::fornecedor_produtos::dsl::{codigo, fornecedor_produtos, id_fornecedor, id_produto, preferencial};
+    use crate::schema::produtos::dsl::{id, produtos};
+
+    let mut conn = pool.get()?;
+    conn.transaction::<_, ApiError, _>(|conn| {
+        find_fornecedor(conn, &vinculo.id_fornecedor)?;
+        let not_found = format!("Produto {} not found", vinculo.id_produto);
+        produtos
+            .filter(id.eq(&vinculo.id_produto))
+            .select(id)
+            .first::<String>(conn)
+            .map_err(|_| ApiError::NotFound(not_found))?;
+
+        if vinculo.preferencial {
+            diesel::update(fornecedor_produtos)
+                .filter(id_produto.eq(&vinculo.id_produto))
+                .set(preferencial.eq(false))
+                .execute(conn)?;
+        }
+        diesel::insert_into(fornecedor_produtos)
+            .values(vinculo)
+            .on_conflict((id_fornecedor, codigo))
+            .do_update()
+            .set((id_produto.eq(&vinculo.id_produto), preferencial.eq(vinculo.preferencial)))
+            .execute(conn)?;
+        Ok(vinculo.clone().into())
+    })
+}
+
+/// Unlink a fornecedor code
+pub fn remover_produto(pool: &PoolType, fornecedor_id: Uuid, codigo_fornecedor: &str) -> Result<(), ApiError> {
+    use crate::schema::fornecedor_produtos::dsl::{codigo, fornecedor_produtos, id_fornecedor};
+
+    let mut conn = pool.get()?;
+    let removidos = diesel::delete(fornecedor_produtos)
+        .filter(id_fornecedor.eq(fornecedor_id.to_string()))
+        .filter(codigo.eq(codigo_fornecedor))
+        .execute(&mut conn)?;
+    if removidos == 0 {
+        return Err(ApiError::NotFound(format!(
+            "Codigo {} of fornecedor {} not found",
+            codigo_fornecedor, fornecedor_id
+        )));
+    }
+    Ok(())
+}
+
+impl From<NewFornecedor> for Fornecedor {
+    fn from(fornecedor: NewFornecedor) -> Self {
+        Fornecedor {
+            id: fornecedor.id,
+            cnpj: fornecedor.cnpj,
+            razao_social: fornecedor.razao_social,
+            nome_fantasia: fornecedor.nome_fantasia,
+            inscricao_estadual: fornecedor.inscricao_estadual,
+            created_by: fornecedor.created_by,
+            created_at: Utc::now().naive_utc(),
+            updated_by: fornecedor.updated_by,
+            updated_at: Utc::now().naive_utc(),
+            contato: fornecedor.contato,
+            email: fornecedor.email,
+            telefone: fornecedor.telefone,
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::models::documento::tests::cnpj_aleatorio;
+    use crate::models::produto::delete as delete_produto;
+    use crate::models::produto::tests::create_produto;
+    use crate::tests::helpers::tests::get_pool;
+
+    /// A fornecedor whose CNPJ doesn't collide with the ones of other tests
+    pub fn create_fornecedor() -> Result<FornecedorResponse, ApiError> {
+        let fornecedor_id = Uuid::new_v4();
+        let new_fornecedor = NewFornecedor {
+            id: fornecedor_id.to_string(),
+            cnpj: cnpj_aleatorio(),
+            razao_social: "Fornecedor Model Ltda".to_string(),
+            nome_fantasia: Some("Fornecedor Model".to_string()),
+            inscricao_estadual: Some("1234567890".to_string()),
+            contato: Some("Maria".to_string()),
+            email: Some("compras@fornecedor.com".to_string()),
+            telefone: Some("4133334444".to_string()),
+            created_by: fornecedor_id.to_string(),
+            updated_by: fornecedor_id.to_string(),
+        };
+        let fornecedor: Fornecedor = new_fornecedor.into();
+        create(&get_pool(), &fornecedor)
+    }
+
+    fn vinculo(fornecedor_id: Uuid, codigo: &str, produto_id: Uuid, preferencial: bool) -> FornecedorProduto {
+        FornecedorProduto {
+            id_fornecedor: fornecedor_id.to_string(),
+            codigo: codigo.to_string(),
+            id_produto: produto_id.to_string(),
+            created_by: fornecedor_id.to_string(),
+            created_at: Utc::now().naive_utc(),
+            preferencial,
+        }
+    }
+
+    #[test]
+    fn it_creates_and_finds_a_fornecedor() {
+        let created = create_fornecedor().unwrap();
+        assert_eq!(find(&get_pool(), created.id).unwrap(), created);
+        assert!(get_all(&get_pool()).unwrap().0.contains(&created));
+        delete(&get_pool(), created.id).unwrap();
+        assert!(find(&get_pool(), created.id).is_err());
+    }
+
+    #[test]
+    fn it_doesnt_find_a_fornecedor() {
+        let fornecedor_id = Uuid::new_v4();
+        let expected_error = ApiError::NotFound(format!("Fornecedor {} not found", fornecedor_id));
+        assert_eq!(find(&get_pool(), fornecedor_id).unwrap_err(), expected_error);
+    }
+
+    #[test]
+    fn it_keeps_one_preferred_fornecedor_per_produto() {
+        let produto = create_produto().unwrap();
+        let primeiro = create_fornecedor().unwrap();
+        let segundo = create_fornecedor().unwrap();
+        salvar_produto(&get_pool(), &vinculo(primeiro.id, "A-1", produto.id, true)).unwrap();
+        salvar_produto(&get_pool(), &vinculo(segundo.id, "B-1", produto.id, true)).unwrap();
+
+        let fornecedores = find_by_produto(&get_pool(), produto.id).unwrap().0;
+        assert_eq!(fornecedores.len(), 2);
+        assert_eq!(fornecedores[0].id_fornecedor, segundo.id);
+        assert!(fornecedores[0].preferencial);
+        assert!(!fornecedores[1].preferencial);
+        assert_eq!(find_produtos(&get_pool(), primeiro.id).unwrap().0[0].codigo, "A-1");
+
+        remover_produto(&get_pool(), primeiro.id, "A-1").unwrap();
+        assert!(remover_produto(&get_pool(), primeiro.id, "A-1").is_err());
+        delete(&get_pool(), primeiro.id).unwrap();
+        delete(&get_pool(), segundo.id).unwrap();
+        delete_produto(&get_pool(), produto.id).unwrap();
+    }
+}
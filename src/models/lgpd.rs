@@ -0,0 +1,273 @@
+use crate::database::PoolType;
+use crate::errors::ApiError;
+use crate::handlers::cliente::ClienteResponse;
+use crate::handlers::lgpd::{LgpdExportResponse, NotaFiscalExportResponse, OrcamentoExportResponse};
+use crate::models::cliente::{self, find_cliente, TipoPessoa};
+use crate::models::cliente_duplicado::find_documento_repetido;
+use crate::models::cliente_endereco;
+use crate::models::fiscal::ConfigFiscal;
+use crate::models::nota_fiscal;
+use crate::models::orcamento;
+use crate::models::orcamento_historico::find_by_orcamento;
+use crate::models::pedido;
+use crate::models::titulo_receber;
+use crate::schema::lgpd_registros;
+use chrono::{NaiveDateTime, Utc};
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use std::fmt;
+use std::str::FromStr;
+use uuid::Uuid;
+
+/// Name an anonymized pessoa física is left with, nome and sobrenome are
+/// required for them
+pub const NOME_ANONIMIZADO: &str = "Cliente";
+pub const SOBRENOME_ANONIMIZADO: &str = "Anonimizado";
+
+/// A request of a cliente under the LGPD, answered by a user
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Queryable, Identifiable, Insertable)]
+#[table_name = "lgpd_registros"]
+pub struct LgpdRegistro {
+    pub id: String,
+    pub id_cliente: String,
+    pub operacao: String,
+    pub created_by: String,
+    pub created_at: NaiveDateTime,
+}
+
+/// What was done with the personal data of a cliente
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum OperacaoLgpd {
+    Exportacao,
+    Anonimizacao,
+}
+
+impl OperacaoLgpd {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OperacaoLgpd::Exportacao => "exportacao",
+            OperacaoLgpd::Anonimizacao => "anonimizacao",
+        }
+    }
+}
+
+impl fmt::Display for OperacaoLgpd {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// Record that a user handled a request of a cliente
+fn registrar(
+    conn: &mut PgConnection,
+    cliente_id: &str,
+    operacao: OperacaoLgpd,
+    user_id: &str,
+) -> Result<(), ApiError> {
+    use crate::schema::lgpd_registros::dsl::lgpd_registros;
+
+    let registro = LgpdRegistro {
+        id: Uuid::new_v4().to_string(),
+        id_cliente: cliente_id.to_string(),
+        operacao: operacao.as_str().to_string(),
+        created_by: user_id.to_string(),
+        created_at: Utc::now().naive_utc(),
+    };
+    diesel::insert_into(lgpd_registros).values(&registro).execute(conn)?;
+    Ok(())
+}
+
+/// The requests of a cliente answered so far, oldest first
+pub fn find_registros(conn: &mut PgConnection, cliente_id: &str) -> Result<Vec<LgpdRegistro>, ApiError> {
+    use crate::schema::lgpd_registros::dsl::{created_at, id_cliente, lgpd_registros};
+
+    let registros = lgpd_registros
+        .filter(id_cliente.eq(cliente_id))
+        .order(created_at)
+        .load::<LgpdRegistro>(conn)?;
+    Ok(registros)
+}
+
+/// Gather every personal data held on a cliente, recording who asked for it
+///
+/// The export itself is recorded before the registros are read, so the
+/// bundle lists the request it answers.
+pub fn exportar(
+    pool: &PoolType,
+    cliente_id: Uuid,
+    user_id: &str,
+    config: &ConfigFiscal,
+) -> Result<LgpdExportResponse, ApiError> {
+    let cliente = cliente::find(pool, cliente_id)?;
+    let enderecos = cliente_endereco::get_all(pool, cliente_id)?.0;
+    let titulos = titulo_receber::find_by_cliente(pool, cliente_id)?.0;
+    let orcamentos = orcamento::find_by_cliente(pool, cliente_id, config)?.0;
+    let pedidos = pedido::find_by_cliente(pool, cliente_id)?.0;
+
+    let mut conn = pool.get()?;
+    let documento_repetido = find_documento_repetido(&mut conn, &cliente_id.to_string())?;
+    let orcamentos = orcamentos
+        .into_iter()
+        .map(|orcamento| {
+            let historico = find_by_orcamento(&mut conn, &orcamento.id.to_string())?;
+            Ok(OrcamentoExportResponse { orcamento, historico })
+        })
+        .collect::<Result<Vec<OrcamentoExportResponse>, ApiError>>()?;
+    let notas_fiscais = nota_fiscal::find_by_cliente(&mut conn, &cliente_id.to_string())?
+        .into_iter()
+        .map(|nota| NotaFiscalExportResponse {
+            xml: nota.xml.clone(),
+            nota: nota.into(),
+        })
+        .collect();
+    registrar(&mut conn, &cliente_id.to_string(), OperacaoLgpd::Exportacao, user_id)?;
+    let registros = find_registros(&mut conn, &cliente_id.to_string())?;
+
+    Ok(LgpdExportResponse {
+        gerado_at: Utc::now().naive_utc(),
+        gerado_by: user_id.to_string(),
+        cliente,
+        documento_repetido: documento_repetido.map(|documento| documento.into()),
+        enderecos,
+        orcamentos,
+        pedidos,
+        notas_fiscais,
+        titulos,
+        registros: registros.into_iter().map(|registro| registro.into()).collect(),
+    })
+}
+
+/// Scrub the personal data of a cliente, recording who asked for it
+///
+/// A pessoa física keeps a placeholder name so it still validates. The
+/// cliente row stays so orcamentos, pedidos, títulos and notas fiscais
+/// keep pointing at it, while its endereços and any parked cpf or cnpj are
+/// deleted. Notas fiscais are fiscal documents the law requires to be kept,
+/// their XML is left as issued.
+pub fn anonimizar(pool: &PoolType, cliente_id: Uuid, user_id: &str) -> Result<ClienteResponse, ApiError> {
+    use crate::schema::clientes::dsl::{
+        clientes, cpf, data_nascimento, id, nome, rg, sobrenome, telefone, updated_at, updated_by,
+    };
+    use crate::schema::{cliente_enderecos, clientes_documentos_repetidos};
+
+    let mut conn = pool.get()?;
+    conn.transaction::<_, ApiError, _>(|conn| {
+        let cliente = find_cliente(conn, &cliente_id.to_string())?;
+        let (anonimo_nome, anonimo_sobrenome) = match TipoPessoa::from_str(&cliente.tipo_pessoa)? {
+            TipoPessoa::Fisica => (Some(NOME_ANONIMIZADO), Some(SOBRENOME_ANONIMIZADO)),
+            TipoPessoa::Juridica => (None, None),
+        };
+        diesel::update(clientes.filter(id.eq(&cliente.id)))
+            .set((
+                nome.eq(anonimo_nome),
+                sobrenome.eq(anonimo_sobrenome),
+                cpf.eq(None::<String>),
+                rg.eq(None::<String>),
+                telefone.eq(None::<String>),
+                data_nascimento.eq(None::<NaiveDateTime>),
+                updated_by.eq(user_id),
+                updated_at.eq(Utc::now().naive_utc()),
+            ))
+            .execute(conn)?;
+        diesel::delete(cliente_enderecos::table.filter(cliente_enderecos::id_cliente.eq(&cliente.id)))
+            .execute(conn)?;
+        diesel::delete(
+            clientes_documentos_repetidos::table.filter(clientes_documentos_repetidos::id_cliente.eq(&cliente.id)),
+        )
+        .execute(conn)?;
+        registrar(conn, &cliente.id, OperacaoLgpd::Anonimizacao, user_id)?;
+        Ok(find_cliente(conn, &cliente.id)?.into())
+    })
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::handlers::lgpd::DocumentoRepetidoResponse;
+    use crate::models::cep::tests::{praca_da_se, ConsultaCepFixa};
+    use crate::models::cliente::delete as delete_cliente;
+    use crate::models::cliente::tests::create_cliente;
+    use crate::models::cliente_duplicado::tests::estacionar;
+    use crate::models::cliente_endereco::tests::new_endereco;
+    use crate::models::cliente_endereco::{create as create_endereco, find_principal, TipoEndereco};
+    use crate::models::documento::tests::cpf_aleatorio;
+    use crate::tests::helpers::tests::get_pool;
+
+    /// Registros outlive their cliente, tests clean them up themselves
+    pub fn delete_registros(cliente_id: Uuid) {
+        use crate::schema::lgpd_registros::dsl::{id_cliente, lgpd_registros};
+
+        let mut conn = get_pool().get().unwrap();
+        diesel::delete(lgpd_registros)
+            .filter(id_cliente.eq(cliente_id.to_string()))
+            .execute(&mut conn)
+            .unwrap();
+    }
+
+    #[test]
+    fn it_exports_the_data_of_a_cliente() {
+        let user_id = "10000000-0000-0000-0000-000000000000";
+        let created = create_cliente().unwrap();
+        let consulta = ConsultaCepFixa(vec![praca_da_se()]);
+        let endereco =
+            create_endereco(&get_pool(), &consulta, new_endereco(created.id, TipoEndereco::Entrega, "01001000")).unwrap();
+        let repetido = cpf_aleatorio();
+        estacionar(&created.id.to_string(), &repetido);
+        let export = exportar(&get_pool(), created.id, user_id, &ConfigFiscal::default()).unwrap();
+        assert_eq!(export.cliente, created);
+        assert_eq!(export.enderecos, vec![endereco]);
+        let expected_documento = DocumentoRepetidoResponse {
+            cpf: Some(repetido),
+            cnpj: None,
+        };
+        assert_eq!(export.documento_repetido, Some(expected_documento));
+        assert!(export.orcamentos.is_empty());
+        assert!(export.pedidos.is_empty());
+        assert!(export.notas_fiscais.is_empty());
+        assert_eq!(export.registros.len(), 1);
+        assert_eq!(export.registros[0].operacao, "exportacao");
+        assert_eq!(export.registros[0].created_by, user_id);
+
+        delete_registros(created.id);
+        delete_cliente(&get_pool(), created.id).unwrap();
+    }
+
+    #[test]
+    fn it_anonymizes_a_cliente() {
+        let user_id = "10000000-0000-0000-0000-000000000000";
+        let created = create_cliente().unwrap();
+        let consulta = ConsultaCepFixa(vec![praca_da_se()]);
+        create_endereco(&get_pool(), &consulta, new_endereco(created.id, TipoEndereco::Entrega, "01001000")).unwrap();
+        estacionar(&created.id.to_string(), &cpf_aleatorio());
+        let anonimo = anonimizar(&get_pool(), created.id, user_id).unwrap();
+        assert_eq!(anonimo.id, created.id);
+        assert_eq!(anonimo.nome.as_deref(), Some(NOME_ANONIMIZADO));
+        assert_eq!(anonimo.sobrenome.as_deref(), Some(SOBRENOME_ANONIMIZADO));
+        assert_eq!(anonimo.cpf, None);
+        assert_eq!(anonimo.rg, None);
+        assert_eq!(anonimo.telefone, None);
+        assert_eq!(anonimo.data_nascimento, None);
+        assert_eq!(anonimo.estado_civil, created.estado_civil);
+
+        let mut conn = get_pool().get().unwrap();
+        assert!(find_cliente(&mut conn, &created.id.to_string()).unwrap().validar_pessoa().is_ok());
+        assert_eq!(find_principal(&mut conn, &created.id.to_string()).unwrap(), None);
+        assert_eq!(find_documento_repetido(&mut conn, &created.id.to_string()).unwrap(), None);
+        let registros = find_registros(&mut conn, &created.id.to_string()).unwrap();
+        assert_eq!(registros.len(), 1);
+        assert_eq!(registros[0].operacao, OperacaoLgpd::Anonimizacao.as_str());
+        assert_eq!(registros[0].created_by, user_id);
+
+        delete_registros(created.id);
+        delete_cliente(&get_pool(), created.id).unwrap();
+    }
+
+    #[test]
+    fn it_doesnt_anonymize_an_unknown_cliente() {
+        let cliente_id = Uuid::new_v4();
+        let anonimo = anonimizar(&get_pool(), cliente_id, "10000000-0000-0000-0000-000000000000");
+        let expected_error = ApiError::NotFound(format!("Cliente {} not found", cliente_id));
+        assert_eq!(anonimo.unwrap_err(), expected_error);
+    }
+}
@@ -2,4 +2,29 @@ pub mod user;
 pub mod cliente;
 pub mod produto;
 pub mod orcamento_produto;
-pub mod orcamento;
\ No newline at end of file
+pub mod orcamento;
+pub mod orcamento_pdf;
+pub mod orcamento_historico;
+pub mod pedido_produto;
+pub mod pedido;
+pub mod desconto;
+pub mod user_permissao;
+pub mod fiscal;
+pub mod codigo_fiscal;
+pub mod catalogo_fiscal;
+pub mod nfe;
+pub mod nota_fiscal;
+pub mod danfe;
+pub mod deposito;
+pub mod estoque;
+pub mod produto_custo;
+pub mod fornecedor;
+pub mod nota_entrada;
+pub mod documento;
+pub mod pedido_compra;
+pub mod cep;
+pub mod cliente_endereco;
+pub mod titulo_receber;
+pub mod credito;
+pub mod cliente_duplicado;
+pub mod lgpd;
\ No newline at end of file
@@ -0,0 +1,985 @@
+//! NF-e 4.00 layout: building the XML of a nota fiscal and checking it
+//!
+//! Signing and sending the XML to SEFAZ sit behind the `Assinador` and
+//! `Transmissor` traits, with local stand-ins for tests and homologação.
+
+use crate::config::Config;
+use crate::errors::ApiError;
+use crate::models::documento::Cnpj;
+use crate::models::fiscal::{self, Imposto, Impostos};
+use bigdecimal::{BigDecimal, Zero};
+use chrono::{DateTime, FixedOffset};
+use crate::xsd;
+use std::str::FromStr;
+
+pub const VERSAO: &str = "4.00";
+const NAMESPACE: &str = "http://www.portalfiscal.inf.br/nfe";
+const MODELO: &str = "55";
+const VERSAO_PROCESSO: &str = "erp-faculdade 0.1";
+const NATUREZA_OPERACAO: &str = "VENDA DE MERCADORIA";
+const TIPO_EMISSAO_NORMAL: u8 = 1;
+const MAXIMO_ITENS: usize = 990;
+
+/// Schema of the 4.00 layout the generated XML is checked against
+const SCHEMA_NFE: &str = include_str!("../../assets/nfe/nfe_v4.00.xsd");
+
+/// Name SEFAZ requires for the destinatário of notas issued in homologação
+const NOME_HOMOLOGACAO: &str = "NF-E EMITIDA EM AMBIENTE DE HOMOLOGACAO - SEM VALOR FISCAL";
+
+pub const AMBIENTE_PRODUCAO: u8 = 1;
+pub const AMBIENTE_HOMOLOGACAO: u8 = 2;
+
+/// IBGE codes of the states, used as cUF
+const CODIGOS_UF: [(&str, &str); 27] = [
+    ("RO", "11"), ("AC", "12"), ("AM", "13"), ("RR", "14"), ("PA", "15"), ("AP", "16"),
+    ("TO", "17"), ("MA", "21"), ("PI", "22"), ("CE", "23"), ("RN", "24"), ("PB", "25"),
+    ("PE", "26"), ("AL", "27"), ("SE", "28"), ("BA", "29"), ("MG", "31"), ("ES", "32"),
+    ("RJ", "33"), ("SP", "35"), ("PR", "41"), ("SC", "42"), ("RS", "43"), ("MS", "50"),
+    ("MT", "51"), ("GO", "52"), ("DF", "53"),
+];
+
+/// ICMS CSTs that need substituição tributária data the produtos do not carry
+const CST_ICMS_COM_ST: [&str; 3] = ["10", "30", "70"];
+
+/// ICMS CSTs without ICMS on the sale, all written in the ICMS40 group
+const CST_ICMS_40: [&str; 3] = ["40", "41", "50"];
+
+/// The company issuing the notas, read from the EMITENTE_* settings
+#[derive(Clone, Debug, PartialEq)]
+pub struct Emitente {
+    pub cnpj: String,
+    pub razao_social: String,
+    pub nome_fantasia: Option<String>,
+    pub ie: String,
+    pub crt: u8,
+    pub logradouro: String,
+    pub numero: String,
+    pub bairro: String,
+    pub codigo_municipio: String,
+    pub municipio: String,
+    pub uf: String,
+    pub cep: String,
+    pub telefone: Option<String>,
+}
+
+/// Settings the NF-e needs besides the emitente
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConfigNfe {
+    pub emitente: Emitente,
+    pub ambiente: u8,
+    pub serie: i32,
+}
+
+impl ConfigNfe {
+    /// Read the emitente from the configuration, naming every missing setting
+    pub fn from_config(config: &Config) -> Result<Self, ApiError> {
+        let mut faltando = Vec::new();
+        let mut exigir = |valor: &Option<String>, nome: &'static str| match valor {
+            Some(valor) if !valor.trim().is_empty() => valor.trim().to_string(),
+            _ => {
+                faltando.push(nome);
+                String::new()
+            }
+        };
+        let emitente = Emitente {
+            cnpj: exigir(&config.emitente_cnpj, "EMITENTE_CNPJ"),
+            razao_social: exigir(&config.emitente_razao_social, "EMITENTE_RAZAO_SOCIAL"),
+            nome_fantasia: config.emitente_nome_fantasia.clone(),
+            ie: exigir(&config.emitente_ie, "EMITENTE_IE"),
+            crt: config.emitente_crt.unwrap_or(3),
+            logradouro: exigir(&config.emitente_logradouro, "EMITENTE_LOGRADOURO"),
+            numero: exigir(&config.emitente_numero, "EMITENTE_NUMERO"),
+            bairro: exigir(&config.emitente_bairro, "EMITENTE_BAIRRO"),
+            codigo_municipio: exigir(&config.emitente_codigo_municipio, "EMITENTE_CODIGO_MUNICIPIO"),
+            municipio: exigir(&config.emitente_municipio, "EMITENTE_MUNICIPIO"),
+            uf: exigir(&config.emitente_uf, "EMITENTE_UF"),
+            cep: exigir(&config.emitente_cep, "EMITENTE_CEP"),
+            telefone: config.emitente_telefone.clone(),
+        };
+        if !faltando.is_empty() {
+            return Err(ApiError::BadRequest(format!(
+                "Emitente da NF-e não configurado, faltam {}",
+                faltando.join(", ")
+            )));
+        }
+        let cnpj = Cnpj::from_str(&emitente.cnpj)
+            .map_err(|error| ApiError::BadRequest(format!("EMITENTE_CNPJ: {}", error)))?;
+        Ok(ConfigNfe {
+            emitente: Emitente {
+                cnpj: cnpj.to_string(),
+                ..emitente
+            },
+            ambiente: config.nfe_ambiente,
+            serie: config.nfe_serie,
+        })
+    }
+}
+
+impl Emitente {
+    /// The cUF and the CNPJ digits the chave is built from, or what is wrong
+    /// with them
+    fn identificacao(&self) -> Result<(&'static str, Cnpj), Vec<String>> {
+        match (codigo_uf(&self.uf), Cnpj::from_str(&self.cnpj)) {
+            (Some(uf), Ok(cnpj)) => Ok((uf, cnpj)),
+            (uf, cnpj) => {
+                let mut errors = Vec::new();
+                if let Err(error) = cnpj {
+                    errors.push(format!("Emitente: {}", error));
+                }
+                if uf.is_none() {
+                    errors.push(format!("Emitente: UF {} desconhecida", self.uf));
+                }
+                Err(errors)
+            }
+        }
+    }
+}
+
+/// Who the nota is issued to, `documento` is a CPF or a CNPJ
+///
+/// Only companies carry an `inscricao_estadual`, the ones that have it are
+/// contribuintes of the ICMS. The nota needs the documento and the endereço.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Destinatario {
+    pub nome: String,
+    pub documento: Option<String>,
+    pub inscricao_estadual: Option<String>,
+    pub endereco: Option<Endereco>,
+}
+
+/// The endereço of the destinatário, written as enderDest
+#[derive(Clone, Debug, PartialEq)]
+pub struct Endereco {
+    pub logradouro: String,
+    pub numero: String,
+    pub complemento: Option<String>,
+    pub bairro: String,
+    pub codigo_municipio: String,
+    pub municipio: String,
+    pub uf: String,
+    pub cep: String,
+}
+
+/// A line of the nota, with the taxes already computed
+#[derive(Clone, Debug, PartialEq)]
+pub struct ItemNfe {
+    pub codigo: String,
+    pub codigo_de_barras: Option<String>,
+    pub descricao: String,
+    pub ncm: Option<String>,
+    pub cest: Option<String>,
+    pub unidade: Option<String>,
+    pub quantidade: i32,
+    pub valor_unitario: BigDecimal,
+    pub desconto: BigDecimal,
+    pub cst_icms: Option<String>,
+    pub cst_ipi: Option<String>,
+    pub cst_pis: Option<String>,
+    pub cst_cofins: Option<String>,
+    pub impostos: Impostos,
+}
+
+impl ItemNfe {
+    pub fn valor_bruto(&self) -> BigDecimal {
+        &self.valor_unitario * BigDecimal::from(self.quantidade)
+    }
+}
+
+/// Everything that goes into one nota
+#[derive(Clone, Debug, PartialEq)]
+pub struct DadosNfe {
+    pub config: ConfigNfe,
+    pub destinatario: Destinatario,
+    pub numero: i32,
+    pub codigo_numerico: u32,
+    pub emissao: DateTime<FixedOffset>,
+    pub itens: Vec<ItemNfe>,
+}
+
+impl DadosNfe {
+    pub fn interestadual(&self) -> bool {
+        self.destinatario
+            .endereco
+            .as_ref()
+            .map(|endereco| !endereco.uf.eq_ignore_ascii_case(&self.config.emitente.uf))
+            .unwrap_or(false)
+    }
+
+    /// The 44 digit chave de acesso of the nota
+    pub fn chave(&self) -> Result<String, ApiError> {
+        let (uf, cnpj) = self.config.emitente.identificacao().map_err(ApiError::ValidationError)?;
+        Ok(chave_acesso(
+            uf,
+            &self.emissao,
+            cnpj.as_str(),
+            self.config.serie,
+            self.numero,
+            self.codigo_numerico,
+        ))
+    }
+}
+
+pub fn codigo_uf(uf: &str) -> Option<&'static str> {
+    CODIGOS_UF
+        .iter()
+        .find(|(sigla, _)| sigla.eq_ignore_ascii_case(uf))
+        .map(|(_, codigo)| *codigo)
+}
+
+/// Mod 11 check digit of the chave de acesso, weights 2 to 9 from the right
+pub fn digito_verificador(base: &str) -> u32 {
+    let soma: u32 = base
+        .chars()
+        .rev()
+        .filter_map(|c| c.to_digit(10))
+        .enumerate()
+        .map(|(posicao, digito)| digito * (2 + posicao as u32 % 8))
+        .sum();
+    match soma % 11 {
+        0 | 1 => 0,
+        resto => 11 - resto,
+    }
+}
+
+pub fn chave_acesso(
+    codigo_uf: &str,
+    emissao: &DateTime<FixedOffset>,
+    cnpj: &str,
+    serie: i32,
+    numero: i32,
+    codigo_numerico: u32,
+) -> String {
+    let base = format!(
+        "{}{}{}{}{:03}{:09}{}{:08}",
+        codigo_uf,
+        emissao.format("%y%m"),
+        cnpj,
+        MODELO,
+        serie,
+        numero,
+        TIPO_EMISSAO_NORMAL,
+        codigo_numerico,
+    );
+    format!("{}{}", base, digito_verificador(&base))
+}
+
+/// CFOP of a sale, 5xxx inside the state and 6xxx to other states
+fn cfop(item: &ItemNfe, interestadual: bool) -> &'static str {
+    let substituicao = item.cst_icms.as_deref() == Some("60");
+    match (interestadual, substituicao) {
+        (false, false) => "5102",
+        (false, true) => "5405",
+        (true, false) => "6102",
+        (true, true) => "6404",
+    }
+}
+
+/// What is missing from the data to issue the nota, one message per problem
+fn pendencias(dados: &DadosNfe) -> Vec<String> {
+    let mut errors = Vec::new();
+    if let Err(mut emitente) = dados.config.emitente.identificacao() {
+        errors.append(&mut emitente);
+    }
+    match &dados.destinatario.documento {
+        Some(documento) if documento.len() == 11 || documento.len() == 14 => {}
+        _ => errors.push(format!(
+            "Destinatário {}: informe o CPF ou CNPJ para emitir a NF-e",
+            dados.destinatario.nome
+        )),
+    }
+    if dados.destinatario.endereco.is_none() {
+        errors.push(format!(
+            "Destinatário {}: cadastre o endereço principal para emitir a NF-e",
+            dados.destinatario.nome
+        ));
+    }
+    if dados.itens.is_empty() {
+        errors.push("A NF-e precisa de ao menos um item".to_string());
+    }
+    if dados.itens.len() > MAXIMO_ITENS {
+        errors.push(format!("A NF-e aceita no máximo {} itens", MAXIMO_ITENS));
+    }
+    for item in &dados.itens {
+        let mut exigir = |valor: &Option<String>, campo: &str| {
+            if valor.is_none() {
+                errors.push(format!("Produto {}: informe o {} para emitir a NF-e", item.descricao, campo));
+            }
+        };
+        exigir(&item.ncm, "NCM");
+        exigir(&item.cst_icms, "CST de ICMS");
+        exigir(&item.cst_pis, "CST de PIS");
+        exigir(&item.cst_cofins, "CST de COFINS");
+        if let Some(cst) = &item.cst_icms {
+            if CST_ICMS_COM_ST.contains(&cst.as_str()) || cst == "20" {
+                errors.push(format!(
+                    "Produto {}: o CST de ICMS {} ainda não é suportado na NF-e",
+                    item.descricao, cst
+                ));
+            }
+        }
+    }
+    errors
+}
+
+/// Build the XML of a nota and check it against the layout
+pub fn gerar_xml(dados: &DadosNfe) -> Result<String, ApiError> {
+    let errors = pendencias(dados);
+    let (documento, endereco) = match (&dados.destinatario.documento, &dados.destinatario.endereco) {
+        (Some(documento), Some(endereco)) if errors.is_empty() => (documento, endereco),
+        _ => return Err(ApiError::ValidationError(errors)),
+    };
+
+    let emitente = &dados.config.emitente;
+    let (uf, cnpj) = emitente.identificacao().map_err(ApiError::ValidationError)?;
+    let chave = dados.chave()?;
+    let interestadual = dados.interestadual();
+    let consumidor_final = !fiscal::contribuinte(&dados.destinatario.inscricao_estadual);
+    let homologacao = dados.config.ambiente == AMBIENTE_HOMOLOGACAO;
+    let mut xml = Xml::default();
+
+    xml.abrir_com(&format!("NFe xmlns=\"{}\"", NAMESPACE));
+    xml.abrir_com(&format!("infNFe versao=\"{}\" Id=\"NFe{}\"", VERSAO, chave));
+
+    xml.abrir("ide");
+    xml.campo("cUF", uf);
+    xml.campo("cNF", &format!("{:08}", dados.codigo_numerico));
+    xml.campo("natOp", NATUREZA_OPERACAO);
+    xml.campo("mod", MODELO);
+    xml.campo("serie", &dados.config.serie.to_string());
+    xml.campo("nNF", &dados.numero.to_string());
+    xml.campo("dhEmi", &dados.emissao.format("%Y-%m-%dT%H:%M:%S%:z").to_string());
+    xml.campo("tpNF", "1");
+    xml.campo("idDest", if interestadual { "2" } else { "1" });
+    xml.campo("cMunFG", &emitente.codigo_municipio);
+    xml.campo("tpImp", "1");
+    xml.campo("tpEmis", &TIPO_EMISSAO_NORMAL.to_string());
+    xml.campo("cDV", &chave.chars().last().map(String::from).unwrap_or_default());
+    xml.campo("tpAmb", &dados.config.ambiente.to_string());
+    xml.campo("finNFe", "1");
+    xml.campo("indFinal", if consumidor_final { "1" } else { "0" });
+    xml.campo("indPres", "1");
+    xml.campo("procEmi", "0");
+    xml.campo("verProc", VERSAO_PROCESSO);
+    xml.fechar("ide");
+
+    xml.abrir("emit");
+    xml.campo("CNPJ", cnpj.as_str());
+    xml.campo("xNome", &emitente.razao_social);
+    xml.opcional("xFant", &emitente.nome_fantasia);
+    xml.abrir("enderEmit");
+    xml.campo("xLgr", &emitente.logradouro);
+    xml.campo("nro", &emitente.numero);
+    xml.campo("xBairro", &emitente.bairro);
+    xml.campo("cMun", &emitente.codigo_municipio);
+    xml.campo("xMun", &emitente.municipio);
+    xml.campo("UF", &emitente.uf.to_uppercase());
+    xml.campo("CEP", &emitente.cep);
+    xml.campo("cPais", "1058");
+    xml.campo("xPais", "BRASIL");
+    xml.opcional("fone", &emitente.telefone);
+    xml.fechar("enderEmit");
+    xml.campo("IE", &emitente.ie);
+    xml.campo("CRT", &emitente.crt.to_string());
+    xml.fechar("emit");
+
+    xml.abrir("dest");
+    xml.campo(if documento.len() == 14 { "CNPJ" } else { "CPF" }, documento);
+    xml.campo("xNome", if homologacao { NOME_HOMOLOGACAO } else { &dados.destinatario.nome });
+    xml.abrir("enderDest");
+    xml.campo("xLgr", &endereco.logradouro);
+    xml.campo("nro", &endereco.numero);
+    xml.opcional("xCpl", &endereco.complemento);
+    xml.campo("xBairro", &endereco.bairro);
+    xml.campo("cMun", &endereco.codigo_municipio);
+    xml.campo("xMun", &endereco.municipio);
+    xml.campo("UF", &endereco.uf.to_uppercase());
+    xml.campo("CEP", &endereco.cep);
+    xml.campo("cPais", "1058");
+    xml.campo("xPais", "BRASIL");
+    xml.fechar("enderDest");
+    escrever_contribuinte(&mut xml, &dados.destinatario.inscricao_estadual);
+    xml.fechar("dest");
+
+    for (posicao, item) in dados.itens.iter().enumerate() {
+        escrever_item(&mut xml, posicao + 1, item, interestadual);
+    }
+
+    let soma = |valor: &dyn Fn(&ItemNfe) -> BigDecimal| {
+        dados.itens.iter().fold(BigDecimal::zero(), |total, item| total + valor(item))
+    };
+    let total_produtos = soma(&|item| item.valor_bruto());
+    let total_desconto = soma(&|item| item.desconto.clone());
+    let total_ipi = soma(&|item| item.impostos.ipi.valor.clone());
+    let total_nota = &total_produtos - &total_desconto + &total_ipi;
+    let zero = BigDecimal::zero();
+
+    xml.abrir("total");
+    xml.abrir("ICMSTot");
+    xml.decimal("vBC", &soma(&|item| item.impostos.icms.base_calculo.clone()), 2);
+    xml.decimal("vICMS", &soma(&|item| item.impostos.icms.valor.clone()), 2);
+    xml.decimal("vICMSDeson", &zero, 2);
+    xml.decimal("vFCP", &zero, 2);
+    xml.decimal("vBCST", &zero, 2);
+    xml.decimal("vST", &zero, 2);
+    xml.decimal("vFCPST", &zero, 2);
+    xml.decimal("vFCPSTRet", &zero, 2);
+    xml.decimal("vProd", &total_produtos, 2);
+    xml.decimal("vFrete", &zero, 2);
+    xml.decimal("vSeg", &zero, 2);
+    xml.decimal("vDesc", &total_desconto, 2);
+    xml.decimal("vII", &zero, 2);
+    xml.decimal("vIPI", &total_ipi, 2);
+    xml.decimal("vIPIDevol", &zero, 2);
+    xml.decimal("vPIS", &soma(&|item| item.impostos.pis.valor.clone()), 2);
+    xml.decimal("vCOFINS", &soma(&|item| item.impostos.cofins.valor.clone()), 2);
+    xml.decimal("vOutro", &zero, 2);
+    xml.decimal("vNF", &total_nota, 2);
+    xml.fechar("ICMSTot");
+    xml.fechar("total");
+
+    xml.abrir("transp");
+    xml.campo("modFrete", "9");
+    xml.fechar("transp");
+
+    xml.abrir("pag");
+    xml.abrir("detPag");
+    xml.campo("tPag", "99");
+    xml.campo("xPag", "A combinar");
+    xml.decimal("vPag", &total_nota, 2);
+    xml.fechar("detPag");
+    xml.fechar("pag");
+
+    xml.fechar("infNFe");
+    xml.fechar("NFe");
+
+    let xml = xml.0;
+    validar_xml(&xml)?;
+    Ok(xml)
+}
+
+/// indIEDest 1 with the IE for contribuintes, 2 for the ones exempt from
+/// the inscrição and 9 for everyone else
+fn escrever_contribuinte(xml: &mut Xml, inscricao_estadual: &Option<String>) {
+    let inscricao_estadual = inscricao_estadual.as_deref().map(str::trim).unwrap_or_default();
+    let digitos: String = inscricao_estadual.chars().filter(char::is_ascii_digit).collect();
+    if inscricao_estadual.eq_ignore_ascii_case("ISENTO") {
+        xml.campo("indIEDest", "2");
+    } else if digitos.is_empty() {
+        xml.campo("indIEDest", "9");
+    } else {
+        xml.campo("indIEDest", "1");
+        xml.campo("IE", &digitos);
+    }
+}
+
+fn escrever_item(xml: &mut Xml, numero: usize, item: &ItemNfe, interestadual: bool) {
+    let gtin = item
+        .codigo_de_barras
+        .clone()
+        .filter(|codigo| [8, 12, 13, 14].contains(&codigo.len()) && codigo.chars().all(|c| c.is_ascii_digit()))
+        .unwrap_or_else(|| "SEM GTIN".to_string());
+    let unidade = item.unidade.clone().unwrap_or_else(|| "UN".to_string()).to_uppercase();
+    let quantidade = BigDecimal::from(item.quantidade);
+
+    xml.abrir_com(&format!("det nItem=\"{}\"", numero));
+    xml.abrir("prod");
+    xml.campo("cProd", &item.codigo);
+    xml.campo("cEAN", &gtin);
+    xml.campo("xProd", &item.descricao);
+    xml.campo("NCM", item.ncm.as_deref().unwrap_or_default());
+    xml.opcional("CEST", &item.cest);
+    xml.campo("CFOP", cfop(item, interestadual));
+    xml.campo("uCom", &unidade);
+    xml.decimal("qCom", &quantidade, 4);
+    xml.decimal("vUnCom", &item.valor_unitario, 2);
+    xml.decimal("vProd", &item.valor_bruto(), 2);
+    xml.campo("cEANTrib", &gtin);
+    xml.campo("uTrib", &unidade);
+    xml.decimal("qTrib", &quantidade, 4);
+    xml.decimal("vUnTrib", &item.valor_unitario, 2);
+    if !item.desconto.is_zero() {
+        xml.decimal("vDesc", &item.desconto, 2);
+    }
+    xml.campo("indTot", "1");
+    xml.fechar("prod");
+
+    xml.abrir("imposto");
+    escrever_icms(xml, item.cst_icms.as_deref().unwrap_or_default(), &item.impostos.icms);
+    if let Some(cst) = &item.cst_ipi {
+        escrever_ipi(xml, cst, &item.impostos.ipi);
+    }
+    escrever_pis_cofins(xml, "PIS", item.cst_pis.as_deref().unwrap_or_default(), &item.impostos.pis);
+    escrever_pis_cofins(xml, "COFINS", item.cst_cofins.as_deref().unwrap_or_default(), &item.impostos.cofins);
+    xml.fechar("imposto");
+    xml.fechar("det");
+}
+
+fn escrever_icms(xml: &mut Xml, cst: &str, icms: &Imposto) {
+    let grupo = if CST_ICMS_40.contains(&cst) { "ICMS40".to_string() } else { format!("ICMS{}", cst) };
+    xml.abrir("ICMS");
+    xml.abrir(&grupo);
+    xml.campo("orig", "0");
+    xml.campo("CST", cst);
+    if cst == "00" || cst == "90" {
+        xml.campo("modBC", "3");
+        xml.decimal("vBC", &icms.base_calculo, 2);
+        xml.decimal("pICMS", &icms.aliquota, 2);
+        xml.decimal("vICMS", &icms.valor, 2);
+    }
+    xml.fechar(&grupo);
+    xml.fechar("ICMS");
+}
+
+fn escrever_ipi(xml: &mut Xml, cst: &str, ipi: &Imposto) {
+    xml.abrir("IPI");
+    xml.campo("cEnq", "999");
+    if ["00", "49", "50", "99"].contains(&cst) {
+        xml.abrir("IPITrib");
+        xml.campo("CST", cst);
+        xml.decimal("vBC", &ipi.base_calculo, 2);
+        xml.decimal("pIPI", &ipi.aliquota, 2);
+        xml.decimal("vIPI", &ipi.valor, 2);
+        xml.fechar("IPITrib");
+    } else {
+        xml.abrir("IPINT");
+        xml.campo("CST", cst);
+        xml.fechar("IPINT");
+    }
+    xml.fechar("IPI");
+}
+
+/// PIS and COFINS share the layout, only the tag names change
+fn escrever_pis_cofins(xml: &mut Xml, tributo: &str, cst: &str, imposto: &Imposto) {
+    let (grupo, com_valores) = match cst {
+        "01" | "02" => ("Aliq", true),
+        "04" | "05" | "06" | "07" | "08" | "09" => ("NT", false),
+        _ => ("Outr", true),
+    };
+    let grupo = format!("{}{}", tributo, grupo);
+    xml.abrir(tributo);
+    xml.abrir(&grupo);
+    xml.campo("CST", cst);
+    if com_valores {
+        xml.decimal("vBC", &imposto.base_calculo, 2);
+        xml.decimal(&format!("p{}", tributo), &imposto.aliquota, 4);
+        xml.decimal(&format!("v{}", tributo), &imposto.valor, 2);
+    }
+    xml.fechar(&grupo);
+    xml.fechar(tributo);
+}
+
+/// Minimal writer, the layout has no mixed content so tags and text suffice
+#[derive(Default)]
+struct Xml(String);
+
+impl Xml {
+    fn abrir(&mut self, tag: &str) {
+        self.0.push_str(&format!("<{}>", tag));
+    }
+
+    /// Open a tag that carries attributes, written as they should appear
+    fn abrir_com(&mut self, tag_com_atributos: &str) {
+        self.0.push_str(&format!("<{}>", tag_com_atributos));
+    }
+
+    fn fechar(&mut self, tag: &str) {
+        self.0.push_str(&format!("</{}>", tag));
+    }
+
+    fn campo(&mut self, tag: &str, valor: &str) {
+        self.0.push_str(&format!("<{}>{}</{}>", tag, escapar(valor.trim()), tag));
+    }
+
+    fn opcional(&mut self, tag: &str, valor: &Option<String>) {
+        if let Some(valor) = valor.as_ref().filter(|valor| !valor.trim().is_empty()) {
+            self.campo(tag, valor);
+        }
+    }
+
+    fn decimal(&mut self, tag: &str, valor: &BigDecimal, casas: i64) {
+        self.campo(tag, &valor.round(casas).with_scale(casas).to_string());
+    }
+}
+
+fn escapar(valor: &str) -> String {
+    valor
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// Check a NF-e XML against the bundled 4.00 schema and the rules it
+/// cannot express
+///
+/// Past the schema, the chave de acesso must carry its check digit, the items
+/// must be numbered in sequence and the ICMSTot totals must add up to the
+/// items. A signed XML passes too, the schema takes the Signature as optional.
+pub fn validar_xml(xml: &str) -> Result<(), ApiError> {
+    xsd::validar(SCHEMA_NFE, xml)?;
+    let documento = roxmltree::Document::parse(xml)
+        .map_err(|error| ApiError::ValidationError(vec![format!("XML da NF-e inválido: {}", error)]))?;
+    let mut errors = Vec::new();
+
+    // the schema guarantees the groups and formats read below
+    let inf = documento.root_element().first_element_child().unwrap();
+    let chave = inf.attribute("Id").unwrap_or_default().trim_start_matches("NFe");
+    if digito_verificador(&chave[..43]).to_string() != chave[43..] {
+        errors.push(format!("infNFe: dígito verificador da chave {} inválido", chave));
+    }
+
+    let itens: Vec<roxmltree::Node> = inf.children().filter(|node| node.has_tag_name("det")).collect();
+    let mut somas: Vec<(&str, BigDecimal)> = ["vProd", "vDesc", "vBC", "vICMS", "vIPI", "vPIS", "vCOFINS"]
+        .iter()
+        .map(|campo| (*campo, BigDecimal::zero()))
+        .collect();
+    for (posicao, det) in itens.iter().enumerate() {
+        if det.attribute("nItem") != Some((posicao + 1).to_string().as_str()) {
+            errors.push(format!("det {}: nItem fora de sequência", posicao + 1));
+        }
+        let prod = filho(*det, "prod");
+        let imposto = filho(*det, "imposto");
+        let valor = |no: Option<roxmltree::Node>, campo: &str| {
+            no.and_then(|no| texto(no, campo))
+                .and_then(|valor| BigDecimal::from_str(valor).ok())
+                .unwrap_or_else(BigDecimal::zero)
+        };
+        let icms = imposto
+            .and_then(|imposto| filho(imposto, "ICMS"))
+            .and_then(|icms| icms.first_element_child());
+        let ipi = imposto
+            .and_then(|imposto| filho(imposto, "IPI"))
+            .and_then(|ipi| filho(ipi, "IPITrib"));
+        let pis = imposto
+            .and_then(|imposto| filho(imposto, "PIS"))
+            .and_then(|pis| pis.first_element_child());
+        let cofins = imposto
+            .and_then(|imposto| filho(imposto, "COFINS"))
+            .and_then(|cofins| cofins.first_element_child());
+        let valores = [
+            valor(prod, "vProd"),
+            valor(prod, "vDesc"),
+            valor(icms, "vBC"),
+            valor(icms, "vICMS"),
+            valor(ipi, "vIPI"),
+            valor(pis, "vPIS"),
+            valor(cofins, "vCOFINS"),
+        ];
+        for ((_, soma), valor) in somas.iter_mut().zip(valores.iter()) {
+            *soma += valor;
+        }
+    }
+
+    let totais = descendente(inf, "ICMSTot").unwrap();
+    let total = |campo: &str| texto(totais, campo).and_then(|valor| BigDecimal::from_str(valor).ok());
+    for (campo, soma) in somas.iter() {
+        if total(campo).as_ref() != Some(soma) {
+            errors.push(format!("ICMSTot: {} não confere com a soma dos itens, {}", campo, soma));
+        }
+    }
+    let esperado =
+        total("vProd").unwrap_or_default() - total("vDesc").unwrap_or_default() + total("vIPI").unwrap_or_default();
+    if total("vNF") != Some(esperado) {
+        errors.push("ICMSTot: vNF deve ser vProd - vDesc + vIPI".to_string());
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(ApiError::ValidationError(errors))
+    }
+}
+
+fn filho<'a, 'input>(no: roxmltree::Node<'a, 'input>, tag: &str) -> Option<roxmltree::Node<'a, 'input>> {
+    no.children().find(|filho| filho.is_element() && filho.tag_name().name() == tag)
+}
+
+fn descendente<'a, 'input>(no: roxmltree::Node<'a, 'input>, tag: &str) -> Option<roxmltree::Node<'a, 'input>> {
+    no.descendants().find(|filho| filho.is_element() && filho.tag_name().name() == tag)
+}
+
+fn texto<'a>(no: roxmltree::Node<'a, '_>, tag: &str) -> Option<&'a str> {
+    filho(no, tag).and_then(|filho| filho.text())
+}
+
+/// Signs the XML of a nota with the emitente's certificate
+pub trait Assinador {
+    fn assinar(&self, xml: &str) -> Result<String, ApiError>;
+}
+
+/// What SEFAZ answered to a nota
+#[derive(Clone, Debug, PartialEq)]
+pub struct Retorno {
+    pub autorizada: bool,
+    pub protocolo: Option<String>,
+    pub motivo: String,
+}
+
+/// Sends a signed nota to SEFAZ for authorization
+pub trait Transmissor {
+    fn transmitir(&self, xml: &str, chave: &str) -> Result<Retorno, ApiError>;
+}
+
+/// Leaves the XML unsigned, for tests and homologação without a certificate
+pub struct AssinadorLocal;
+
+impl Assinador for AssinadorLocal {
+    fn assinar(&self, xml: &str) -> Result<String, ApiError> {
+        Ok(xml.to_string())
+    }
+}
+
+/// Answers like SEFAZ without leaving the machine
+///
+/// Authorizes every nota that passes `validar_xml` with a protocol derived
+/// from the chave, and rejects the others with the validation messages.
+pub struct TransmissorLocal;
+
+impl Transmissor for TransmissorLocal {
+    fn transmitir(&self, xml: &str, chave: &str) -> Result<Retorno, ApiError> {
+        Ok(match validar_xml(xml) {
+            Ok(()) => Retorno {
+                autorizada: true,
+                protocolo: Some(format!("9{}", &chave[chave.len().saturating_sub(14)..])),
+                motivo: "Autorizado o uso da NF-e".to_string(),
+            },
+            Err(ApiError::ValidationError(errors)) => Retorno {
+                autorizada: false,
+                protocolo: None,
+                motivo: errors.join("; "),
+            },
+            Err(error) => return Err(error),
+        })
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::models::fiscal::{calcular, ContextoFiscal, DadosFiscais};
+    use chrono::TimeZone;
+
+    pub fn config() -> ConfigNfe {
+        ConfigNfe {
+            emitente: Emitente {
+                cnpj: "11222333000181".to_string(),
+                razao_social: "ERP FACULDADE LTDA".to_string(),
+                nome_fantasia: Some("ERP Faculdade".to_string()),
+                ie: "110042490114".to_string(),
+                crt: 3,
+                logradouro: "Rua das Flores".to_string(),
+                numero: "100".to_string(),
+                bairro: "Centro".to_string(),
+                codigo_municipio: "3550308".to_string(),
+                municipio: "Sao Paulo".to_string(),
+                uf: "SP".to_string(),
+                cep: "01001000".to_string(),
+                telefone: None,
+            },
+            ambiente: AMBIENTE_HOMOLOGACAO,
+            serie: 1,
+        }
+    }
+
+    fn item(cst_icms: &str) -> ItemNfe {
+        let dados = DadosFiscais {
+            cst_icms: Some(cst_icms.to_string()),
+            aliquota_icms: Some(BigDecimal::from(18)),
+            porcentagem_ipi: Some(BigDecimal::from(10)),
+            cst_pis: Some("01".to_string()),
+            aliquota_pis: Some(BigDecimal::from_str("1.65").unwrap()),
+            cst_cofins: Some("01".to_string()),
+            aliquota_cofins: Some(BigDecimal::from_str("7.60").unwrap()),
+        };
+        let valor = BigDecimal::from_str("19.98").unwrap();
+        ItemNfe {
+            codigo: "00000000-0000-0000-0000-000000000000".to_string(),
+            codigo_de_barras: Some("7894900011517".to_string()),
+            descricao: "Refrigerante & cia".to_string(),
+            ncm: Some("22021000".to_string()),
+            cest: Some("0300700".to_string()),
+            unidade: Some("un".to_string()),
+            quantidade: 2,
+            valor_unitario: BigDecimal::from_str("10.99").unwrap(),
+            desconto: BigDecimal::from_str("2.00").unwrap(),
+            cst_icms: dados.cst_icms.clone(),
+            cst_ipi: Some("50".to_string()),
+            cst_pis: dados.cst_pis.clone(),
+            cst_cofins: dados.cst_cofins.clone(),
+            impostos: calcular(&dados, &valor, &ContextoFiscal::default()),
+        }
+    }
+
+    pub fn dados() -> DadosNfe {
+        DadosNfe {
+            config: config(),
+            destinatario: Destinatario {
+                nome: "Fulano de Tal".to_string(),
+                documento: Some("52998224725".to_string()),
+                inscricao_estadual: None,
+                endereco: Some(Endereco {
+                    logradouro: "Praça da Sé".to_string(),
+                    numero: "100".to_string(),
+                    complemento: Some("Sala 2".to_string()),
+                    bairro: "Sé".to_string(),
+                    codigo_municipio: "3550308".to_string(),
+                    municipio: "São Paulo".to_string(),
+                    uf: "SP".to_string(),
+                    cep: "01001000".to_string(),
+                }),
+            },
+            numero: 78,
+            codigo_numerico: 12345678,
+            emissao: FixedOffset::west_opt(3 * 3600)
+                .unwrap()
+                .with_ymd_and_hms(2023, 7, 5, 10, 30, 0)
+                .unwrap(),
+            itens: vec![item("00"), item("60")],
+        }
+    }
+
+    #[test]
+    fn it_calculates_the_check_digit_of_the_chave() {
+        assert_eq!(digito_verificador("5206043300991100250655012000000780026730161"), 5);
+        let chave = dados().chave().unwrap();
+        assert_eq!(chave.len(), 44);
+        assert_eq!(&chave[0..6], "352307");
+        assert_eq!(&chave[20..22], "55");
+    }
+
+    #[test]
+    fn it_builds_a_valid_nfe() {
+        let xml = gerar_xml(&dados()).unwrap();
+        assert!(xml.starts_with("<NFe xmlns=\"http://www.portalfiscal.inf.br/nfe\">"));
+        assert!(xml.contains(&format!("Id=\"NFe{}\"", dados().chave().unwrap())));
+        assert!(xml.contains("<cUF>35</cUF>"));
+        assert!(xml.contains(concat!(
+            "<enderDest><xLgr>Praça da Sé</xLgr><nro>100</nro><xCpl>Sala 2</xCpl><xBairro>Sé</xBairro>",
+            "<cMun>3550308</cMun><xMun>São Paulo</xMun><UF>SP</UF><CEP>01001000</CEP>",
+            "<cPais>1058</cPais><xPais>BRASIL</xPais></enderDest><indIEDest>9</indIEDest></dest>"
+        )));
+        assert!(xml.contains("<xProd>Refrigerante &amp; cia</xProd>"));
+        assert!(xml.contains("<xNome>NF-E EMITIDA EM AMBIENTE DE HOMOLOGACAO - SEM VALOR FISCAL</xNome>"));
+        assert!(xml.contains("<CFOP>5102</CFOP>"));
+        assert!(xml.contains("<ICMS60><orig>0</orig><CST>60</CST></ICMS60>"));
+        assert!(xml.contains("<vProd>43.96</vProd>"));
+        assert!(xml.contains("<vDesc>4.00</vDesc><vII>0.00</vII><vIPI>4.00</vIPI>"));
+        assert!(xml.contains("<vNF>43.96</vNF>"));
+        assert_eq!(validar_xml(&xml), Ok(()));
+    }
+
+    #[test]
+    fn it_lists_what_is_missing_to_issue_a_nfe() {
+        let mut dados = dados();
+        dados.destinatario.documento = None;
+        dados.destinatario.endereco = None;
+        dados.itens[0].ncm = None;
+        let expected_error = ApiError::ValidationError(vec![
+            "Destinatário Fulano de Tal: informe o CPF ou CNPJ para emitir a NF-e".to_string(),
+            "Destinatário Fulano de Tal: cadastre o endereço principal para emitir a NF-e".to_string(),
+            "Produto Refrigerante & cia: informe o NCM para emitir a NF-e".to_string(),
+        ]);
+        assert_eq!(gerar_xml(&dados).unwrap_err(), expected_error);
+    }
+
+    #[test]
+    fn it_reads_the_emitente_from_the_config() {
+        let mut config = crate::config::CONFIG.clone();
+        config.emitente_cnpj = Some("11.222.333/0001-81".to_string());
+        config.emitente_razao_social = Some("ERP FACULDADE LTDA".to_string());
+        config.emitente_ie = Some("110042490114".to_string());
+        config.emitente_logradouro = Some("Rua das Flores".to_string());
+        config.emitente_numero = Some("100".to_string());
+        config.emitente_bairro = Some("Centro".to_string());
+        config.emitente_codigo_municipio = Some("3550308".to_string());
+        config.emitente_municipio = Some("Sao Paulo".to_string());
+        config.emitente_uf = Some("SP".to_string());
+        config.emitente_cep = Some("01001000".to_string());
+        config.nfe_ambiente = AMBIENTE_PRODUCAO;
+        assert_eq!(ConfigNfe::from_config(&config).unwrap().emitente.cnpj, "11222333000181");
+
+        config.emitente_cnpj = Some("11222333000180".to_string());
+        let expected_error = ApiError::BadRequest("EMITENTE_CNPJ: o CNPJ 11222333000180 é inválido".to_string());
+        assert_eq!(ConfigNfe::from_config(&config).unwrap_err(), expected_error);
+    }
+
+    #[test]
+    fn it_builds_the_chave_from_the_digits_of_the_cnpj() {
+        let mut dados = dados();
+        dados.config.emitente.cnpj = "11.222.333/0001-81".to_string();
+        let xml = gerar_xml(&dados).unwrap();
+        assert!(xml.contains("<CNPJ>11222333000181</CNPJ>"));
+        assert_eq!(&dados.chave().unwrap()[6..20], "11222333000181");
+
+        dados.config.emitente.cnpj = "1122233300018".to_string();
+        dados.config.emitente.uf = "XX".to_string();
+        let expected_error = ApiError::ValidationError(vec![
+            "Emitente: o CNPJ deve ter 14 dígitos, recebido 1122233300018".to_string(),
+            "Emitente: UF XX desconhecida".to_string(),
+        ]);
+        assert_eq!(gerar_xml(&dados).unwrap_err(), expected_error);
+        assert_eq!(dados.chave().unwrap_err(), expected_error);
+    }
+
+    #[test]
+    fn it_tells_contribuintes_of_the_icms_apart() {
+        let mut dados = dados();
+        dados.destinatario.documento = Some("11444777000161".to_string());
+        dados.destinatario.inscricao_estadual = Some("110.042.490.114".to_string());
+        let xml = gerar_xml(&dados).unwrap();
+        assert!(xml.contains("<indIEDest>1</indIEDest><IE>110042490114</IE></dest>"));
+
+        dados.destinatario.inscricao_estadual = Some("isento".to_string());
+        let xml = gerar_xml(&dados).unwrap();
+        assert!(xml.contains("<indIEDest>2</indIEDest></dest>"));
+    }
+
+    #[test]
+    fn it_checks_the_xml_against_the_bundled_schema() {
+        let xml = gerar_xml(&dados()).unwrap().replacen("<CFOP>5102</CFOP>", "<CFOP>4102</CFOP>", 1);
+        let expected_error =
+            ApiError::ValidationError(vec!["XSD NFe/infNFe/det[1]/prod/CFOP: valor 4102 fora do formato".to_string()]);
+        assert_eq!(validar_xml(&xml).unwrap_err(), expected_error);
+
+        let xml = gerar_xml(&dados()).unwrap().replacen("<transp>", "<cobr/><transp>", 1);
+        let expected_error =
+            ApiError::ValidationError(vec!["XSD NFe/infNFe: transp ausente, encontrado cobr".to_string()]);
+        assert_eq!(validar_xml(&xml).unwrap_err(), expected_error);
+
+        // a CSOSN of the Simples Nacional has no group in the layout this module writes
+        let mut dados = dados();
+        dados.itens[1].cst_icms = Some("101".to_string());
+        let expected_error = ApiError::ValidationError(vec![
+            "XSD NFe/infNFe/det[2]/imposto/ICMS: esperado um de ICMS00, ICMS40, ICMS51, ICMS60, ICMS90, encontrado ICMS101"
+                .to_string(),
+        ]);
+        assert_eq!(gerar_xml(&dados).unwrap_err(), expected_error);
+    }
+
+    #[test]
+    fn it_takes_the_signature_as_part_of_the_nfe() {
+        let assinado = gerar_xml(&dados()).unwrap().replace(
+            "</infNFe></NFe>",
+            "</infNFe><Signature xmlns=\"http://www.w3.org/2000/09/xmldsig#\"><SignedInfo/></Signature></NFe>",
+        );
+        assert_eq!(validar_xml(&assinado), Ok(()));
+    }
+
+    #[test]
+    fn it_rejects_an_xml_whose_totals_do_not_add_up() {
+        let xml = gerar_xml(&dados()).unwrap().replacen("<vICMS>", "<vICMS>1", 1);
+        assert!(validar_xml(&xml).is_err());
+        let xml = gerar_xml(&dados()).unwrap().replacen("<vNF>43.96</vNF>", "<vNF>47.96</vNF>", 1);
+        assert!(validar_xml(&xml).is_err());
+    }
+
+    #[test]
+    fn it_authorizes_with_the_local_stand_ins() {
+        let dados = dados();
+        let xml = AssinadorLocal.assinar(&gerar_xml(&dados).unwrap()).unwrap();
+        let retorno = TransmissorLocal.transmitir(&xml, &dados.chave().unwrap()).unwrap();
+        assert!(retorno.autorizada);
+        assert_eq!(retorno.protocolo.unwrap().len(), 15);
+    }
+}
@@ -0,0 +1,686 @@
+//! Import of purchase NF-e
+//!
+//! The XML a fornecedor sends with the goods is read into a NotaCompra,
+//! each item is matched to one of our produtos and, unless it is only a
+//! preview, the nota becomes a stock entrada whose costs feed custo_medio.
+
+use crate::config::Config;
+use crate::database::PoolType;
+use crate::errors::ApiError;
+use crate::handlers::nota_entrada::{ImportacaoResponse, NotaEntradaResponse};
+use crate::models::catalogo_fiscal::validar_produto;
+use crate::models::deposito::find_deposito;
+use crate::models::estoque::{lancar, ConfigEstoque, EstoqueMovimento, TipoMovimento};
+use crate::models::fornecedor::{
+    find_by_cnpj, find_produto_by_codigo, registrar, vincular_produto, Fornecedor, FornecedorProduto, NewFornecedor,
+};
+use crate::models::nfe::digito_verificador;
+use crate::models::produto::Produto;
+use crate::schema::notas_entrada;
+use bigdecimal::{BigDecimal, ToPrimitive, Zero};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use std::collections::HashMap;
+use std::str::FromStr;
+use uuid::Uuid;
+
+/// Decimal places of the unit cost taken from the nota
+const CASAS_CUSTO: i64 = 4;
+const TAMANHO_DESCRICAO: usize = 100;
+const TAMANHO_CODIGO: usize = 60;
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Queryable, Identifiable, Insertable)]
+#[table_name = "notas_entrada"]
+pub struct NotaEntrada {
+    pub id: String,
+    pub chave: String,
+    pub serie: i32,
+    pub numero: i32,
+    pub id_fornecedor: String,
+    pub emissao: NaiveDateTime,
+    pub valor_total: BigDecimal,
+    pub xml: String,
+    pub created_by: String,
+    pub created_at: NaiveDateTime,
+}
+
+/// Who issued a purchase nota, as it comes in the emit group
+#[derive(Clone, Debug, PartialEq)]
+pub struct EmitenteCompra {
+    pub cnpj: String,
+    pub razao_social: String,
+    pub nome_fantasia: Option<String>,
+    pub inscricao_estadual: Option<String>,
+}
+
+/// An item of a purchase nota
+///
+/// `custo_unitario` is what each unit cost us: the value of the item plus
+/// frete, seguro, outras despesas, IPI and ICMS-ST, less the desconto.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ItemCompra {
+    pub numero: u32,
+    pub codigo: String,
+    pub codigo_de_barras: Option<String>,
+    pub descricao: String,
+    pub ncm: Option<String>,
+    pub cest: Option<String>,
+    pub unidade: Option<String>,
+    pub quantidade: i32,
+    pub custo_unitario: BigDecimal,
+}
+
+/// The fields of a purchase NF-e the import uses
+#[derive(Clone, Debug, PartialEq)]
+pub struct NotaCompra {
+    pub chave: String,
+    pub serie: i32,
+    pub numero: i32,
+    pub emissao: NaiveDateTime,
+    pub emitente: EmitenteCompra,
+    pub destinatario_cnpj: Option<String>,
+    pub valor_total: BigDecimal,
+    pub itens: Vec<ItemCompra>,
+}
+
+/// How an item of the nota was matched to a produto
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Correspondencia {
+    /// Through the code the fornecedor gives the produto
+    Fornecedor,
+    CodigoDeBarras,
+    /// Nothing matched, the import creates a rascunho
+    Novo,
+}
+
+impl Correspondencia {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Correspondencia::Fornecedor => "fornecedor",
+            Correspondencia::CodigoDeBarras => "codigo_de_barras",
+            Correspondencia::Novo => "novo",
+        }
+    }
+}
+
+/// What an import did, or would do when it is a preview
+#[derive(Clone, Debug, PartialEq)]
+pub struct Importacao {
+    pub id: Option<String>,
+    pub nota: NotaCompra,
+    pub id_fornecedor: Option<String>,
+    pub itens: Vec<(ItemCompra, Option<String>, Correspondencia)>,
+}
+
+/// Import rules read from the configuration
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ConfigImportacao {
+    /// Our CNPJ, notas issued to anyone else are refused when it is set
+    pub cnpj: Option<String>,
+}
+
+impl ConfigImportacao {
+    pub fn from_config(config: &Config) -> Self {
+        ConfigImportacao {
+            cnpj: config
+                .emitente_cnpj
+                .as_ref()
+                .map(|cnpj| digitos(cnpj))
+                .filter(|cnpj| !cnpj.is_empty()),
+        }
+    }
+}
+
+impl NotaCompra {
+    /// Read a purchase nota from its XML, with or without the nfeProc envelope
+    pub fn from_xml(xml: &str) -> Result<Self, ApiError> {
+        let invalido = |mensagem: String| ApiError::ValidationError(vec![mensagem]);
+        let documento = roxmltree::Document::parse(xml)
+            .map_err(|error| invalido(format!("XML da NF-e inválido: {}", error)))?;
+        let raiz = documento.root_element();
+        let inf = grupo(raiz, "infNFe")?;
+        let ide = grupo(inf, "ide")?;
+        let emit = grupo(inf, "emit")?;
+        let totais = grupo(inf, "ICMSTot")?;
+
+        let chave = inf.attribute("Id").unwrap_or_default().trim_start_matches("NFe").to_string();
+        if chave.len() != 44 || !chave.chars().all(|c| c.is_ascii_digit()) {
+            return Err(invalido("infNFe: o Id deve ser NFe seguido da chave de 44 dígitos".to_string()));
+        }
+        if digito_verificador(&chave[..43]).to_string() != chave[43..] {
+            return Err(invalido(format!("infNFe: dígito verificador da chave {} inválido", chave)));
+        }
+        let cnpj = texto(emit, "CNPJ")
+            .ok_or_else(|| invalido("emit: apenas fornecedores com CNPJ podem ser importados".to_string()))?;
+        let emissao = texto(ide, "dhEmi")
+            .and_then(|emissao| DateTime::parse_from_rfc3339(emissao).ok())
+            .map(|emissao| emissao.naive_local())
+            .ok_or_else(|| invalido("ide: dhEmi ausente ou inválida".to_string()))?;
+        let inteiro = |campo: &str| {
+            texto(ide, campo)
+                .and_then(|valor| valor.parse::<i32>().ok())
+                .ok_or_else(|| invalido(format!("ide: {} ausente ou inválido", campo)))
+        };
+
+        let mut errors = Vec::new();
+        let itens: Vec<ItemCompra> = inf
+            .children()
+            .filter(|no| no.has_tag_name("det"))
+            .filter_map(|det| match item(det) {
+                Ok(item) => Some(item),
+                Err(erro) => {
+                    errors.push(erro);
+                    None
+                }
+            })
+            .collect();
+        if itens.is_empty() && errors.is_empty() {
+            errors.push("A NF-e não tem itens".to_string());
+        }
+        if !errors.is_empty() {
+            return Err(ApiError::ValidationError(errors));
+        }
+
+        Ok(NotaCompra {
+            chave,
+            serie: inteiro("serie")?,
+            numero: inteiro("nNF")?,
+            emissao,
+            emitente: EmitenteCompra {
+                cnpj: cnpj.to_string(),
+                razao_social: texto(emit, "xNome").unwrap_or(cnpj).to_string(),
+                nome_fantasia: texto(emit, "xFant").map(|nome| nome.to_string()),
+                inscricao_estadual: texto(emit, "IE").map(|ie| ie.to_string()),
+            },
+            destinatario_cnpj: descendente(inf, "dest")
+                .and_then(|dest| texto(dest, "CNPJ"))
+                .map(|cnpj| cnpj.to_string()),
+            valor_total: valor(Some(totais), "vNF"),
+            itens,
+        })
+    }
+}
+
+/// Read an item, refusing the quantities the stock can't hold
+fn item(det: roxmltree::Node) -> Result<ItemCompra, String> {
+    let numero = det.attribute("nItem").and_then(|numero| numero.parse::<u32>().ok()).unwrap_or(0);
+    let prod = filho(det, "prod").ok_or_else(|| format!("Item {}: grupo prod ausente", numero))?;
+    let imposto = filho(det, "imposto");
+    let quantidade = texto(prod, "qCom")
+        .and_then(|quantidade| BigDecimal::from_str(quantidade).ok())
+        .ok_or_else(|| format!("Item {}: qCom ausente ou inválida", numero))?;
+    let inteira = quantidade
+        .to_i32()
+        .filter(|inteira| BigDecimal::from(*inteira) == quantidade && *inteira > 0)
+        .ok_or_else(|| format!("Item {}: a quantidade {} deve ser inteira e positiva", numero, quantidade))?;
+
+    let icms = imposto
+        .and_then(|imposto| filho(imposto, "ICMS"))
+        .and_then(|icms| icms.first_element_child());
+    let ipi = imposto
+        .and_then(|imposto| filho(imposto, "IPI"))
+        .and_then(|ipi| filho(ipi, "IPITrib"));
+    let custo_total = valor(Some(prod), "vProd")
+        + valor(Some(prod), "vFrete")
+        + valor(Some(prod), "vSeg")
+        + valor(Some(prod), "vOutro")
+        - valor(Some(prod), "vDesc")
+        + valor(ipi, "vIPI")
+        + valor(icms, "vICMSST");
+
+    Ok(ItemCompra {
+        numero,
+        codigo: texto(prod, "cProd").unwrap_or_default().chars().take(TAMANHO_CODIGO).collect(),
+        codigo_de_barras: texto(prod, "cEAN")
+            .filter(|ean| [8, 12, 13, 14].contains(&ean.len()) && ean.chars().all(|c| c.is_ascii_digit()))
+            .map(|ean| ean.to_string()),
+        descricao: texto(prod, "xProd").unwrap_or_default().chars().take(TAMANHO_DESCRICAO).collect(),
+        ncm: texto(prod, "NCM").map(|ncm| ncm.to_string()),
+        cest: texto(prod, "CEST").map(|cest| cest.to_string()),
+        unidade: texto(prod, "uCom").map(|unidade| unidade.to_uppercase().chars().take(2).collect()),
+        quantidade: inteira,
+        custo_unitario: (custo_total / BigDecimal::from(inteira)).round(CASAS_CUSTO),
+    })
+}
+
+/// Find an imported nota by its id or error out
+pub fn find(pool: &PoolType, nota_id: Uuid) -> Result<NotaEntradaResponse, ApiError> {
+    use crate::schema::notas_entrada::dsl::{id, notas_entrada};
+
+    let not_found = format!("Nota de entrada {} not found", nota_id);
+    let mut conn = pool.get()?;
+    let nota = notas_entrada
+        .filter(id.eq(nota_id.to_string()))
+        .first::<NotaEntrada>(&mut conn)
+        .map_err(|_| ApiError::NotFound(not_found))?;
+
+    Ok(nota.into())
+}
+
+/// Import a purchase nota into a depósito
+///
+/// A preview matches the items the same way and writes nothing. Otherwise
+/// the fornecedor is registered when it is new, unmatched items become
+/// rascunho produtos, the fornecedor codes are linked to the produtos so the
+/// next nota matches by them, and each item is posted as a costed entrada.
+pub fn importar(
+    pool: &PoolType,
+    xml: &str,
+    deposito_id: &str,
+    config: &ConfigImportacao,
+    preview: bool,
+    user_id: &str,
+) -> Result<ImportacaoResponse, ApiError> {
+    let nota = NotaCompra::from_xml(xml)?;
+    if let Some(cnpj) = &config.cnpj {
+        if nota.destinatario_cnpj.as_ref() != Some(cnpj) {
+            return Err(ApiError::ValidationError(vec![format!(
+                "A NF-e {} não foi emitida para o CNPJ {}",
+                nota.chave, cnpj
+            )]));
+        }
+    }
+
+    let mut conn = pool.get()?;
+    conn.transaction::<_, ApiError, _>(|conn| {
+        find_deposito(conn, deposito_id)?;
+        if find_by_chave(conn, &nota.chave)?.is_some() {
+            return Err(ApiError::ValidationError(vec![format!(
+                "A NF-e {} já foi importada",
+                nota.chave
+            )]));
+        }
+        let fornecedor = find_by_cnpj(conn, &nota.emitente.cnpj)?;
+        let mut itens = Vec::new();
+        for item in &nota.itens {
+            let (produto_id, correspondencia) = corresponder(conn, fornecedor.as_ref(), item)?;
+            itens.push((item.clone(), produto_id, correspondencia));
+        }
+        if preview {
+            return Ok(Importacao {
+                id: None,
+                id_fornecedor: fornecedor.map(|fornecedor| fornecedor.id),
+                nota: nota.clone(),
+                itens,
+            }
+            .into());
+        }
+
+        let fornecedor = match fornecedor {
+            Some(fornecedor) => fornecedor,
+            None => {
+                let fornecedor: Fornecedor = NewFornecedor {
+                    id: Uuid::new_v4().to_string(),
+                    cnpj: nota.emitente.cnpj.clone(),
+                    razao_social: nota.emitente.razao_social.clone(),
+                    nome_fantasia: nota.emitente.nome_fantasia.clone(),
+                    inscricao_estadual: nota.emitente.inscricao_estadual.clone(),
+                    contato: None,
+                    email: None,
+                    telefone: None,
+                    created_by: user_id.to_string(),
+                    updated_by: user_id.to_string(),
+                }
+                .into();
+                registrar(conn, &fornecedor)?;
+                fornecedor
+            }
+        };
+        let nota_id = Uuid::new_v4().to_string();
+        diesel::insert_into(notas_entrada::table)
+            .values(&NotaEntrada {
+                id: nota_id.clone(),
+                chave: nota.chave.clone(),
+                serie: nota.serie,
+                numero: nota.numero,
+                id_fornecedor: fornecedor.id.clone(),
+                emissao: nota.emissao,
+                valor_total: nota.valor_total.clone(),
+                xml: xml.to_string(),
+                created_by: user_id.to_string(),
+                created_at: Utc::now().naive_utc(),
+            })
+            .execute(conn)?;
+
+        let mut movimentos = Vec::new();
+        // lines repeating an unmatched code share the rascunho of the first one
+        let mut rascunhos: HashMap<String, String> = HashMap::new();
+        for (item, produto_id, _) in itens.iter_mut() {
+            let id_produto = match produto_id {
+                Some(id_produto) => id_produto.clone(),
+                None => match rascunhos.get(&item.codigo) {
+                    Some(id_produto) => id_produto.clone(),
+                    None => {
+                        let id_produto = criar_rascunho(conn, item, user_id)?;
+                        rascunhos.insert(item.codigo.clone(), id_produto.clone());
+                        id_produto
+                    }
+                },
+            };
+            vincular_produto(
+                conn,
+                &FornecedorProduto {
+                    id_fornecedor: fornecedor.id.clone(),
+                    codigo: item.codigo.clone(),
+                    id_produto: id_produto.clone(),
+                    created_by: user_id.to_string(),
+                    created_at: Utc::now().naive_utc(),
+                    preferencial: false,
+                },
+            )?;
+            movimentos.push(EstoqueMovimento {
+                id: Uuid::new_v4().to_string(),
+                id_produto: id_produto.clone(),
+                id_deposito: deposito_id.to_string(),
+                tipo: TipoMovimento::Entrada.as_str().to_string(),
+                quantidade: item.quantidade,
+                id_documento: Some(nota_id.clone()),
+                observacao: Some(format!(
+                    "Entrada da NF-e {}/{} de {}",
+                    nota.numero, nota.serie, fornecedor.razao_social
+                )),
+                created_by: user_id.to_string(),
+                created_at: Utc::now().naive_utc(),
+                custo_unitario: Some(item.custo_unitario.clone()),
+            });
+            *produto_id = Some(id_produto);
+        }
+        // entradas only add stock, there is no balance to block
+        lancar(conn, &movimentos, &ConfigEstoque::default())?;
+
+        Ok(Importacao {
+            id: Some(nota_id),
+            id_fornecedor: Some(fornecedor.id),
+            nota: nota.clone(),
+            itens,
+        }
+        .into())
+    })
+}
+
+/// Match an item by the fornecedor code first, then by codigo_de_barras
+fn corresponder(
+    conn: &mut PgConnection,
+    fornecedor: Option<&Fornecedor>,
+    item: &ItemCompra,
+) -> Result<(Option<String>, Correspondencia), ApiError> {
+    use crate::schema::produtos::dsl::{codigo_de_barras, created_at, id, produtos};
+
+    if let Some(fornecedor) = fornecedor {
+        if let Some(produto_id) = find_produto_by_codigo(conn, &fornecedor.id, &item.codigo)? {
+            return Ok((Some(produto_id), Correspondencia::Fornecedor));
+        }
+    }
+    if let Some(ean) = &item.codigo_de_barras {
+        let produto_id = produtos
+            .filter(codigo_de_barras.eq(ean))
+            .order(created_at)
+            .select(id)
+            .first::<String>(conn)
+            .optional()?;
+        if produto_id.is_some() {
+            return Ok((produto_id, Correspondencia::CodigoDeBarras));
+        }
+    }
+    Ok((None, Correspondencia::Novo))
+}
+
+/// Create a produto for an item nothing matched, flagged for review
+///
+/// The ncm and cest of the nota are kept only when the catalog accepts them.
+fn criar_rascunho(conn: &mut PgConnection, item: &ItemCompra, user_id: &str) -> Result<String, ApiError> {
+    use crate::schema::produtos::dsl::produtos;
+
+    let (ncm, cest) = if validar_produto(conn, &item.ncm, &item.cest).is_ok() {
+        (item.ncm.clone(), item.cest.clone())
+    } else if validar_produto(conn, &item.ncm, &None).is_ok() {
+        (item.ncm.clone(), None)
+    } else {
+        (None, None)
+    };
+    let produto_id = Uuid::new_v4().to_string();
+    let rascunho = Produto {
+        id: produto_id.clone(),
+        descricao: item.descricao.clone(),
+        codigo_de_barras: item.codigo_de_barras.clone(),
+        icms: None,
+        unidade_medida: item.unidade.clone().filter(|unidade| !unidade.is_empty()),
+        preco_venda: None,
+        preco_minimo: None,
+        custo_medio: None,
+        pis: None,
+        cofins: None,
+        ncm,
+        cest,
+        cod_ipi: None,
+        porcentagem_ipi: None,
+        created_by: user_id.to_string(),
+        created_at: Utc::now().naive_utc(),
+        updated_by: user_id.to_string(),
+        updated_at: Utc::now().naive_utc(),
+        aliquota_icms: None,
+        aliquota_pis: None,
+        aliquota_cofins: None,
+        rascunho: true,
+    };
+    diesel::insert_into(produtos).values(&rascunho).execute(conn)?;
+    Ok(produto_id)
+}
+
+fn find_by_chave(conn: &mut PgConnection, nota_chave: &str) -> Result<Option<NotaEntrada>, ApiError> {
+    use crate::schema::notas_entrada::dsl::{chave, notas_entrada};
+
+    let nota = notas_entrada
+        .filter(chave.eq(nota_chave))
+        .first::<NotaEntrada>(conn)
+        .optional()?;
+    Ok(nota)
+}
+
+fn digitos(valor: &str) -> String {
+    valor.chars().filter(|c| c.is_ascii_digit()).collect()
+}
+
+fn grupo<'a, 'input>(no: roxmltree::Node<'a, 'input>, tag: &str) -> Result<roxmltree::Node<'a, 'input>, ApiError> {
+    descendente(no, tag).ok_or_else(|| ApiError::ValidationError(vec![format!("Grupo {} ausente na NF-e", tag)]))
+}
+
+fn filho<'a, 'input>(no: roxmltree::Node<'a, 'input>, tag: &str) -> Option<roxmltree::Node<'a, 'input>> {
+    no.children().find(|filho| filho.is_element() && filho.tag_name().name() == tag)
+}
+
+fn descendente<'a, 'input>(no: roxmltree::Node<'a, 'input>, tag: &str) -> Option<roxmltree::Node<'a, 'input>> {
+    no.descendants().find(|filho| filho.is_element() && filho.tag_name().name() == tag)
+}
+
+fn texto<'a>(no: roxmltree::Node<'a, '_>, tag: &str) -> Option<&'a str> {
+    filho(no, tag).and_then(|filho| filho.text()).map(str::trim).filter(|texto| !texto.is_empty())
+}
+
+fn valor(no: Option<roxmltree::Node>, tag: &str) -> BigDecimal {
+    no.and_then(|no| texto(no, tag))
+        .and_then(|valor| BigDecimal::from_str(valor).ok())
+        .unwrap_or_else(BigDecimal::zero)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::models::deposito::DEPOSITO_PADRAO;
+    use crate::models::estoque::tests::delete_movimentos;
+    use crate::models::produto::delete as delete_produto;
+    use crate::models::produto::find as find_produto;
+    use crate::models::produto::tests::create_produto;
+    use crate::tests::helpers::tests::get_pool;
+
+    pub const CNPJ_EMPRESA: &str = "11222333000181";
+
+    /// A purchase nota with two items: one identified by its EAN, one without
+    pub fn xml_compra(numero: u32, cnpj_fornecedor: &str, ean: &str, codigo_novo: &str) -> String {
+        let base = format!("412307{}55001{:09}1{:08}", cnpj_fornecedor, numero, numero);
+        let chave = format!("{}{}", base, digito_verificador(&base));
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<nfeProc xmlns="http://www.portalfiscal.inf.br/nfe" versao="4.00">
+<NFe><infNFe Id="NFe{chave}" versao="4.00">
+<ide><serie>1</serie><nNF>{numero}</nNF><dhEmi>2023-07-09T10:30:00-03:00</dhEmi></ide>
+<emit><CNPJ>{cnpj}</CNPJ><xNome>Distribuidora Teste Ltda</xNome><xFant>Distribuidora</xFant><IE>1234567890</IE></emit>
+<dest><CNPJ>{destinatario}</CNPJ><xNome>Empresa</xNome></dest>
+<det nItem="1"><prod><cProd>REF-1</cProd><cEAN>{ean}</cEAN><xProd>Refrigerante lata</xProd><NCM>22021000</NCM><uCom>un</uCom><qCom>10.0000</qCom><vUnCom>2.00</vUnCom><vProd>20.00</vProd><vFrete>1.00</vFrete><vDesc>0.50</vDesc></prod>
+<imposto><ICMS><ICMS10><CST>10</CST><vICMSST>0.30</vICMSST></ICMS10></ICMS><IPI><cEnq>999</cEnq><IPITrib><CST>50</CST><vIPI>0.20</vIPI></IPITrib></IPI></imposto></det>
+<det nItem="2"><prod><cProd>{codigo_novo}</cProd><cEAN>SEM GTIN</cEAN><xProd>Copo descartavel</xProd><NCM>99999999</NCM><uCom>PCT</uCom><qCom>3</qCom><vUnCom>5.00</vUnCom><vProd>15.00</vProd></prod>
+<imposto><ICMS><ICMS00><CST>00</CST></ICMS00></ICMS></imposto></det>
+<total><ICMSTot><vNF>36.00</vNF></ICMSTot></total>
+</infNFe></NFe>
+</nfeProc>"#,
+            chave = chave,
+            numero = numero,
+            cnpj = cnpj_fornecedor,
+            destinatario = CNPJ_EMPRESA,
+            ean = ean,
+            codigo_novo = codigo_novo,
+        )
+    }
+
+    /// Remove what an import created: the nota, its fornecedor and the rascunhos
+    pub fn delete_importacao(importacao: &ImportacaoResponse) {
+        use crate::schema::fornecedores::dsl::{fornecedores, id as fornecedor_id};
+        use crate::schema::notas_entrada::dsl::{id, notas_entrada};
+
+        let mut conn = get_pool().get().unwrap();
+        for item in &importacao.itens {
+            delete_movimentos(item.id_produto.unwrap());
+            if item.correspondencia == "novo" {
+                delete_produto(&get_pool(), item.id_produto.unwrap()).unwrap();
+            }
+        }
+        diesel::delete(notas_entrada)
+            .filter(id.eq(importacao.id.unwrap().to_string()))
+            .execute(&mut conn)
+            .unwrap();
+        diesel::delete(fornecedores)
+            .filter(fornecedor_id.eq(importacao.fornecedor.id.unwrap().to_string()))
+            .execute(&mut conn)
+            .unwrap();
+    }
+
+    fn config() -> ConfigImportacao {
+        ConfigImportacao {
+            cnpj: Some(CNPJ_EMPRESA.to_string()),
+        }
+    }
+
+    #[test]
+    fn it_reads_a_purchase_nota() {
+        let nota = NotaCompra::from_xml(&xml_compra(1, "99888777000166", "7891234567895", "NOVO-1")).unwrap();
+        assert_eq!(nota.numero, 1);
+        assert_eq!(nota.emitente.cnpj, "99888777000166");
+        assert_eq!(nota.destinatario_cnpj, Some(CNPJ_EMPRESA.to_string()));
+        assert_eq!(nota.emissao.to_string(), "2023-07-09 10:30:00");
+        assert_eq!(nota.itens.len(), 2);
+
+        let refrigerante = &nota.itens[0];
+        assert_eq!(refrigerante.quantidade, 10);
+        assert_eq!(refrigerante.codigo_de_barras, Some("7891234567895".to_string()));
+        assert_eq!(refrigerante.unidade, Some("UN".to_string()));
+        // (20.00 + 1.00 - 0.50 + 0.20 IPI + 0.30 ST) / 10
+        assert_eq!(refrigerante.custo_unitario, BigDecimal::from_str("2.1").unwrap());
+        let copo = &nota.itens[1];
+        assert_eq!(copo.codigo_de_barras, None);
+        assert_eq!(copo.custo_unitario, BigDecimal::from(5));
+    }
+
+    #[test]
+    fn it_refuses_a_nota_it_cant_stock() {
+        let xml = xml_compra(1, "99888777000166", "", "NOVO-1").replace("<qCom>3</qCom>", "<qCom>2.5</qCom>");
+        let expected_error = ApiError::ValidationError(vec![
+            "Item 2: a quantidade 2.5 deve ser inteira e positiva".to_string(),
+        ]);
+        assert_eq!(NotaCompra::from_xml(&xml).unwrap_err(), expected_error);
+
+        let xml = xml_compra(1, "99888777000166", "", "NOVO-1");
+        let chave = NotaCompra::from_xml(&xml).unwrap().chave;
+        let digito = (chave[43..].parse::<u32>().unwrap() + 1) % 10;
+        let xml = xml.replace(&chave, &format!("{}{}", &chave[..43], digito));
+        assert!(NotaCompra::from_xml(&xml).is_err());
+        assert!(NotaCompra::from_xml("<NFe>").is_err());
+    }
+
+    #[test]
+    fn it_imports_a_purchase_nota_after_a_preview() {
+        let produto = create_produto().unwrap();
+        let ean = format!("{:013}", rand_ean());
+        let mut conn = get_pool().get().unwrap();
+        {
+            use crate::schema::produtos::dsl::{codigo_de_barras, id, produtos};
+            diesel::update(produtos)
+                .filter(id.eq(produto.id.to_string()))
+                .set(codigo_de_barras.eq(&ean))
+                .execute(&mut conn)
+                .unwrap();
+        }
+        let numero = (rand_ean() % 999_999_999) as u32;
+        let xml = xml_compra(numero, "99888777000166", &ean, &format!("NOVO-{}", numero));
+
+        let preview = importar(&get_pool(), &xml, DEPOSITO_PADRAO, &config(), true, "test").unwrap();
+        assert_eq!(preview.id, None);
+        assert_eq!(preview.itens[0].id_produto, Some(produto.id));
+        assert_eq!(preview.itens[0].correspondencia, "codigo_de_barras");
+        assert_eq!(preview.itens[1].correspondencia, "novo");
+        assert!(find_by_chave(&mut conn, &preview.chave).unwrap().is_none());
+
+        let importacao = importar(&get_pool(), &xml, DEPOSITO_PADRAO, &config(), false, "test").unwrap();
+        assert!(importacao.id.is_some());
+        let rascunho = find_produto(&get_pool(), importacao.itens[1].id_produto.unwrap()).unwrap();
+        assert!(rascunho.rascunho);
+        assert_eq!(rascunho.custo_medio, Some(BigDecimal::from(5)));
+        assert_eq!(find(&get_pool(), importacao.id.unwrap()).unwrap().chave, importacao.chave);
+
+        let repetida = importar(&get_pool(), &xml, DEPOSITO_PADRAO, &config(), true, "test");
+        let expected_error = ApiError::ValidationError(vec![format!("A NF-e {} já foi importada", importacao.chave)]);
+        assert_eq!(repetida.unwrap_err(), expected_error);
+        let fornecedor = find_by_cnpj(&mut conn, "99888777000166").unwrap().unwrap();
+        let vinculado = find_produto_by_codigo(&mut conn, &fornecedor.id, "REF-1").unwrap();
+        assert_eq!(vinculado, Some(produto.id.to_string()));
+
+        delete_importacao(&importacao);
+        delete_produto(&get_pool(), produto.id).unwrap();
+    }
+
+    #[test]
+    fn it_creates_one_rascunho_per_unmatched_code() {
+        let numero = (rand_ean() % 999_999_999) as u32;
+        let codigo = format!("NOVO-{}", numero);
+        let xml = xml_compra(numero, "99888777000166", "", &codigo);
+        let inicio = xml.find(r#"<det nItem="2">"#).unwrap();
+        let fim = xml.find("<total>").unwrap();
+        let repetido = xml[inicio..fim].replace(r#"nItem="2""#, r#"nItem="3""#);
+        let xml = format!("{}{}{}", &xml[..fim], repetido, &xml[fim..]);
+
+        let importacao = importar(&get_pool(), &xml, DEPOSITO_PADRAO, &config(), false, "test").unwrap();
+        assert_eq!(importacao.itens.len(), 3);
+        assert_eq!(importacao.itens[1].correspondencia, "novo");
+        assert_eq!(importacao.itens[1].id_produto, importacao.itens[2].id_produto);
+        let mut conn = get_pool().get().unwrap();
+        let fornecedor = find_by_cnpj(&mut conn, "99888777000166").unwrap().unwrap();
+        let vinculado = find_produto_by_codigo(&mut conn, &fornecedor.id, &codigo).unwrap();
+        assert_eq!(vinculado, importacao.itens[1].id_produto.map(|id| id.to_string()));
+
+        delete_importacao(&importacao);
+    }
+
+    #[test]
+    fn it_refuses_a_nota_issued_to_someone_else() {
+        let xml = xml_compra(1, "99888777000166", "", "NOVO-1");
+        let config = ConfigImportacao {
+            cnpj: Some("00111222000133".to_string()),
+        };
+        let imported = importar(&get_pool(), &xml, DEPOSITO_PADRAO, &config, true, "test");
+        assert!(imported.is_err());
+    }
+
+    fn rand_ean() -> u64 {
+        u64::from_str_radix(&Uuid::new_v4().to_simple().to_string()[..12], 16).unwrap() % 10_000_000_000_000
+    }
+}
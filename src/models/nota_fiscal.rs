@@ -0,0 +1,477 @@
+use crate::database::PoolType;
+use crate::errors::ApiError;
+use crate::handlers::nota_fiscal::NotaFiscalResponse;
+use crate::models::cliente::{Cliente, TipoPessoa};
+use crate::models::cliente_endereco::find_principal;
+use crate::models::danfe::{self, Danfe};
+use crate::models::fiscal::{self, ConfigFiscal, ContextoFiscal, DadosFiscais};
+use crate::models::nfe::{self, Assinador, ConfigNfe, DadosNfe, Destinatario, Endereco, ItemNfe, Transmissor};
+use crate::models::orcamento::{find_itens as find_orcamento_itens, lock_header, valores_itens, OrcamentoStatus};
+use crate::models::pedido::{find_itens as find_pedido_itens, Pedido};
+use crate::schema::notas_fiscais;
+use chrono::{FixedOffset, NaiveDateTime, Utc};
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::sql_types::{BigInt, Text};
+use std::str::FromStr;
+use uuid::Uuid;
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Queryable, Identifiable, Insertable)]
+#[table_name = "notas_fiscais"]
+pub struct NotaFiscal {
+    pub id: String,
+    pub id_pedido: Option<String>,
+    pub id_orcamento: Option<String>,
+    pub serie: i32,
+    pub numero: i32,
+    pub chave: String,
+    pub ambiente: i16,
+    pub status: String,
+    pub xml: String,
+    pub protocolo: Option<String>,
+    pub motivo: Option<String>,
+    pub created_by: String,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+/// Status of a nota whose XML was built but not sent yet
+pub const NOTA_GERADA: &str = "gerada";
+pub const NOTA_AUTORIZADA: &str = "autorizada";
+pub const NOTA_REJEITADA: &str = "rejeitada";
+
+/// Size of the motivo column, SEFAZ messages can be longer
+const TAMANHO_MOTIVO: usize = 255;
+
+sql_function!(fn nextval(sequencia: Text) -> BigInt);
+
+/// Issue the nota of a pedido
+///
+/// Lines are invoiced as they were sold: price, discount, codes and taxes
+/// come from the line, not from the produto as it is today.
+pub fn gerar_de_pedido(
+    pool: &PoolType,
+    pedido_id: Uuid,
+    config: &ConfigNfe,
+    user_id: &str,
+) -> Result<NotaFiscalResponse, ApiError> {
+    let mut conn = pool.get()?;
+    conn.transaction::<_, ApiError, _>(|conn| gerar_pedido(conn, pedido_id, config, user_id))
+}
+
+fn gerar_pedido(
+    conn: &mut PgConnection,
+    pedido_id: Uuid,
+    config: &ConfigNfe,
+    user_id: &str,
+) -> Result<NotaFiscalResponse, ApiError> {
+    use crate::schema::pedidos::dsl::{id, pedidos};
+
+    let not_found = format!("Pedido {} not found", pedido_id);
+    let pedido = pedidos
+        .filter(id.eq(pedido_id.to_string()))
+        .first::<Pedido>(conn)
+        .map_err(|_| ApiError::NotFound(not_found))?;
+    ensure_sem_nota(conn, Some(&pedido.id), pedido.id_orcamento.as_deref())?;
+
+    let (destinatario, contexto) = destinatario(conn, &pedido.id_cliente, config)?;
+    let itens = find_pedido_itens(conn, &pedido.id)?
+        .into_iter()
+        .map(|(pedido_produto, produto)| {
+            let bruto = &pedido_produto.preco_unitario * bigdecimal::BigDecimal::from(pedido_produto.quantidade);
+            let impostos = fiscal::calcular(
+                &DadosFiscais::from(&pedido_produto),
+                &(bruto - &pedido_produto.desconto),
+                &contexto,
+            );
+            ItemNfe {
+                codigo: produto.id,
+                codigo_de_barras: produto.codigo_de_barras,
+                descricao: pedido_produto.descricao,
+                ncm: pedido_produto.ncm,
+                cest: pedido_produto.cest,
+                unidade: pedido_produto.unidade_medida,
+                quantidade: pedido_produto.quantidade,
+                valor_unitario: pedido_produto.preco_unitario,
+                desconto: pedido_produto.desconto,
+                cst_icms: pedido_produto.icms,
+                cst_ipi: pedido_produto.cod_ipi,
+                cst_pis: pedido_produto.pis,
+                cst_cofins: pedido_produto.cofins,
+                impostos,
+            }
+        })
+        .collect();
+
+    emitir(conn, config, destinatario, itens, Some(pedido.id), pedido.id_orcamento, user_id)
+}
+
+/// Issue the nota of an orcamento
+///
+/// An aprovado orcamento is invoiced from its lines as quoted, a convertido
+/// one through the pedido it became.
+pub fn gerar_de_orcamento(
+    pool: &PoolType,
+    orcamento_id: Uuid,
+    config: &ConfigNfe,
+    user_id: &str,
+) -> Result<NotaFiscalResponse, ApiError> {
+    let mut conn = pool.get()?;
+    conn.transaction::<_, ApiError, _>(|conn| {
+        let orcamento = lock_header(conn, orcamento_id)?;
+        let status = OrcamentoStatus::from_str(&orcamento.status)?;
+        match (status, &orcamento.id_pedido) {
+            (OrcamentoStatus::Convertido, Some(pedido_id)) => {
+                return gerar_pedido(conn, Uuid::parse_str(pedido_id)?, config, user_id);
+            }
+            (OrcamentoStatus::Aprovado, _) => {}
+            _ => {
+                return Err(ApiError::Conflict(format!(
+                    "Orcamento {} is {} and only aprovado or convertido orcamentos can be invoiced",
+                    orcamento_id, status
+                )))
+            }
+        }
+        ensure_sem_nota(conn, None, Some(&orcamento.id))?;
+
+        let (destinatario, contexto) = destinatario(conn, &orcamento.id_cliente, config)?;
+        let itens = find_orcamento_itens(conn, &orcamento.id)?;
+        let valores = valores_itens(&orcamento, &itens)?;
+        let itens = itens
+            .into_iter()
+            .zip(valores)
+            .map(|((orcamento_produto, produto), valor)| {
+                let impostos = valor.impostos(&orcamento_produto, &contexto);
+                ItemNfe {
+                    codigo: produto.id,
+                    codigo_de_barras: produto.codigo_de_barras,
+                    descricao: orcamento_produto.descricao,
+                    ncm: orcamento_produto.ncm,
+                    cest: orcamento_produto.cest,
+                    unidade: orcamento_produto.unidade_medida,
+                    quantidade: orcamento_produto.quantidade,
+                    valor_unitario: orcamento_produto.preco_unitario,
+                    desconto: valor.desconto + valor.rateio,
+                    cst_icms: orcamento_produto.icms,
+                    cst_ipi: orcamento_produto.cod_ipi,
+                    cst_pis: orcamento_produto.pis,
+                    cst_cofins: orcamento_produto.cofins,
+                    impostos,
+                }
+            })
+            .collect();
+
+        emitir(conn, config, destinatario, itens, None, Some(orcamento.id), user_id)
+    })
+}
+
+/// The cliente the nota is issued to, with the sale its lines are taxed for
+fn destinatario(
+    conn: &mut PgConnection,
+    cliente_id: &str,
+    config: &ConfigNfe,
+) -> Result<(Destinatario, ContextoFiscal), ApiError> {
+    use crate::schema::clientes::dsl::{clientes, id};
+
+    let cliente = clientes.filter(id.eq(cliente_id)).first::<Cliente>(conn)?;
+    let endereco = find_principal(conn, cliente_id)?;
+    let contexto = fiscal::contexto(
+        &ConfigFiscal { uf_origem: Some(config.emitente.uf.clone()) },
+        &cliente,
+        endereco.as_ref(),
+    );
+    let inscricao_estadual = match TipoPessoa::from_str(&cliente.tipo_pessoa)? {
+        TipoPessoa::Juridica => cliente.inscricao_estadual.clone(),
+        TipoPessoa::Fisica => None,
+    };
+    let destinatario = Destinatario {
+        nome: cliente.nome_completo(),
+        documento: cliente.documento().map(str::to_string),
+        inscricao_estadual,
+        endereco: endereco.map(|endereco| Endereco {
+            logradouro: endereco.logradouro,
+            numero: endereco.numero,
+            complemento: endereco.complemento,
+            bairro: endereco.bairro,
+            codigo_municipio: endereco.codigo_municipio,
+            municipio: endereco.municipio,
+            uf: endereco.uf,
+            cep: endereco.cep,
+        }),
+    };
+    Ok((destinatario, contexto))
+}
+
+/// A pedido or orcamento gets a single nota, unless SEFAZ rejected it
+fn ensure_sem_nota(
+    conn: &mut PgConnection,
+    pedido_id: Option<&str>,
+    orcamento_id: Option<&str>,
+) -> Result<(), ApiError> {
+    use crate::schema::notas_fiscais::dsl::{chave, id_orcamento, id_pedido, notas_fiscais, status};
+
+    let existente = notas_fiscais
+        .filter(status.ne(NOTA_REJEITADA))
+        .filter(id_pedido.eq(pedido_id).or(id_orcamento.eq(orcamento_id)))
+        .select(chave)
+        .first::<String>(conn)
+        .optional()?;
+    match existente {
+        Some(existente) => Err(ApiError::Conflict(format!("NF-e {} was already issued for this sale", existente))),
+        None => Ok(()),
+    }
+}
+
+fn emitir(
+    conn: &mut PgConnection,
+    config: &ConfigNfe,
+    destinatario: Destinatario,
+    itens: Vec<ItemNfe>,
+    pedido_id: Option<String>,
+    orcamento_id: Option<String>,
+    user_id: &str,
+) -> Result<NotaFiscalResponse, ApiError> {
+    let numero = diesel::select(nextval("notas_fiscais_numero_seq")).get_result::<i64>(conn)?;
+    let brasilia = FixedOffset::west_opt(3 * 3600).unwrap();
+    let nota_id = Uuid::new_v4();
+    let dados = DadosNfe {
+        config: config.clone(),
+        destinatario,
+        numero: numero as i32,
+        // cNF must not be guessable from the numero, so it comes from the id
+        codigo_numerico: u32::from_be_bytes([
+            nota_id.as_bytes()[0],
+            nota_id.as_bytes()[1],
+            nota_id.as_bytes()[2],
+            nota_id.as_bytes()[3],
+        ]) % 100_000_000,
+        emissao: Utc::now().with_timezone(&brasilia),
+        itens,
+    };
+    let xml = nfe::gerar_xml(&dados)?;
+
+    let nota = NotaFiscal {
+        id: nota_id.to_string(),
+        id_pedido: pedido_id,
+        id_orcamento: orcamento_id,
+        serie: config.serie,
+        numero: dados.numero,
+        chave: dados.chave()?,
+        ambiente: config.ambiente as i16,
+        status: NOTA_GERADA.to_string(),
+        xml,
+        protocolo: None,
+        motivo: None,
+        created_by: user_id.to_string(),
+        created_at: Utc::now().naive_utc(),
+        updated_at: Utc::now().naive_utc(),
+    };
+    diesel::insert_into(notas_fiscais::table).values(&nota).execute(conn)?;
+    Ok(nota.into())
+}
+
+/// Find a nota by its id or error out
+pub fn find(pool: &PoolType, nota_id: Uuid) -> Result<NotaFiscalResponse, ApiError> {
+    let mut conn = pool.get()?;
+    Ok(find_nota(&mut conn, nota_id)?.into())
+}
+
+/// The XML of a nota, as it was issued or signed
+pub fn find_xml(pool: &PoolType, nota_id: Uuid) -> Result<String, ApiError> {
+    let mut conn = pool.get()?;
+    Ok(find_nota(&mut conn, nota_id)?.xml)
+}
+
+/// The DANFE of a nota as a PDF
+pub fn find_danfe(pool: &PoolType, nota_id: Uuid) -> Result<Vec<u8>, ApiError> {
+    let mut conn = pool.get()?;
+    let nota = find_nota(&mut conn, nota_id)?;
+    danfe::renderizar(&Danfe::from_xml(&nota.xml, nota.protocolo)?)
+}
+
+/// The notas of a cliente, issued from its pedidos or orcamentos, oldest first
+pub fn find_by_cliente(conn: &mut PgConnection, cliente_id: &str) -> Result<Vec<NotaFiscal>, ApiError> {
+    use crate::schema::notas_fiscais::dsl::{created_at, id_orcamento, id_pedido, notas_fiscais};
+    use crate::schema::{orcamentos, pedidos};
+
+    let do_cliente_pedidos = pedidos::table
+        .filter(pedidos::id_cliente.eq(cliente_id))
+        .select(pedidos::id.nullable());
+    let do_cliente_orcamentos = orcamentos::table
+        .filter(orcamentos::id_cliente.eq(cliente_id))
+        .select(orcamentos::id.nullable());
+    let notas = notas_fiscais
+        .filter(id_pedido.eq_any(do_cliente_pedidos).or(id_orcamento.eq_any(do_cliente_orcamentos)))
+        .order(created_at)
+        .load::<NotaFiscal>(conn)?;
+    Ok(notas)
+}
+
+fn find_nota(conn: &mut PgConnection, nota_id: Uuid) -> Result<NotaFiscal, ApiError> {
+    use crate::schema::notas_fiscais::dsl::{id, notas_fiscais};
+
+    let not_found = format!("Nota fiscal {} not found", nota_id);
+    notas_fiscais
+        .filter(id.eq(nota_id.to_string()))
+        .first::<NotaFiscal>(conn)
+        .map_err(|_| ApiError::NotFound(not_found))
+}
+
+/// Find a nota and lock it for the current transaction
+fn lock_nota(conn: &mut PgConnection, nota_id: Uuid) -> Result<NotaFiscal, ApiError> {
+    use crate::schema::notas_fiscais::dsl::{id, notas_fiscais};
+
+    let not_found = format!("Nota fiscal {} not found", nota_id);
+    notas_fiscais
+        .filter(id.eq(nota_id.to_string()))
+        .for_update()
+        .first::<NotaFiscal>(conn)
+        .map_err(|_| ApiError::NotFound(not_found))
+}
+
+/// Sign a nota and send it for authorization, recording what SEFAZ answered
+///
+/// The signed XML is checked against the schema first, a nota that fails
+/// stays gerada.
+pub fn transmitir(
+    pool: &PoolType,
+    nota_id: Uuid,
+    assinador: &dyn Assinador,
+    transmissor: &dyn Transmissor,
+) -> Result<NotaFiscalResponse, ApiError> {
+    use crate::schema::notas_fiscais::dsl::{id, motivo, notas_fiscais, protocolo, status, updated_at, xml};
+
+    let mut conn = pool.get()?;
+    conn.transaction::<_, ApiError, _>(|conn| {
+        // held until the answer is stored, so a nota is sent only once
+        let nota = lock_nota(conn, nota_id)?;
+        if nota.status != NOTA_GERADA {
+            return Err(ApiError::Conflict(format!(
+                "Nota fiscal {} is {} and cannot be transmitted again",
+                nota_id, nota.status
+            )));
+        }
+
+        let assinado = assinador.assinar(&nota.xml)?;
+        nfe::validar_xml(&assinado)?;
+        let retorno = transmissor.transmitir(&assinado, &nota.chave)?;
+        let novo_status = if retorno.autorizada { NOTA_AUTORIZADA } else { NOTA_REJEITADA };
+        let novo_motivo: String = retorno.motivo.chars().take(TAMANHO_MOTIVO).collect();
+
+        let nota = diesel::update(notas_fiscais)
+            .filter(id.eq(&nota.id))
+            .set((
+                status.eq(novo_status),
+                xml.eq(assinado),
+                protocolo.eq(retorno.protocolo),
+                motivo.eq(Some(novo_motivo)),
+                updated_at.eq(Utc::now().naive_utc()),
+            ))
+            .get_result::<NotaFiscal>(conn)?;
+        Ok(nota.into())
+    })
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::models::cep::tests::{praca_da_se, ConsultaCepFixa};
+    use crate::models::cliente::delete as delete_cliente;
+    use crate::models::cliente::tests::create_cliente;
+    use crate::models::cliente_endereco::tests::new_endereco;
+    use crate::models::cliente_endereco::{create as create_endereco, TipoEndereco};
+    use crate::models::estoque::tests::delete_movimentos_do_documento;
+    use crate::models::estoque::ConfigEstoque;
+    use crate::models::nfe::tests::config;
+    use crate::models::nfe::{AssinadorLocal, TransmissorLocal};
+    use crate::models::orcamento::delete as delete_orcamento;
+    use crate::models::orcamento::tests::{create_orcamento, create_orcamento_for_cliente};
+    use crate::models::orcamento::{converter, transition, SYSTEM_USER_ID};
+    use crate::models::pedido::delete as delete_pedido;
+    use crate::tests::helpers::tests::get_pool;
+
+    /// Remove a nota created by a test, so its pedido can be deleted
+    pub fn delete_nota(nota_id: Uuid) {
+        let mut conn = get_pool().get().unwrap();
+        diesel::delete(notas_fiscais::table.find(nota_id.to_string()))
+            .execute(&mut conn)
+            .unwrap();
+    }
+
+    #[test]
+    fn it_doesnt_find_a_nota_fiscal() {
+        let nota_id = Uuid::new_v4();
+        let expected_error = ApiError::NotFound(format!("Nota fiscal {} not found", nota_id));
+        assert_eq!(find(&get_pool(), nota_id).unwrap_err(), expected_error);
+    }
+
+    #[test]
+    fn it_only_invoices_aprovado_orcamentos() {
+        let created = create_orcamento().unwrap();
+        let rascunho = gerar_de_orcamento(&get_pool(), created.id, &config(), SYSTEM_USER_ID);
+        let expected_error = ApiError::Conflict(format!(
+            "Orcamento {} is rascunho and only aprovado or convertido orcamentos can be invoiced",
+            created.id
+        ));
+        assert_eq!(rascunho.unwrap_err(), expected_error);
+        delete_orcamento(&get_pool(), created.id).unwrap();
+    }
+
+    #[test]
+    fn it_issues_and_transmits_the_nota_of_a_pedido() {
+        let cliente = create_cliente().unwrap();
+        let consulta = ConsultaCepFixa(vec![praca_da_se()]);
+        create_endereco(&get_pool(), &consulta, new_endereco(cliente.id, TipoEndereco::Entrega, "01001000")).unwrap();
+        let created = create_orcamento_for_cliente(cliente.id).unwrap();
+        let config_fiscal = ConfigFiscal::default();
+        transition(&get_pool(), created.id, OrcamentoStatus::Enviado, SYSTEM_USER_ID, &config_fiscal).unwrap();
+        transition(&get_pool(), created.id, OrcamentoStatus::Aprovado, SYSTEM_USER_ID, &config_fiscal).unwrap();
+        let pedido = converter(&get_pool(), created.id, SYSTEM_USER_ID, &ConfigEstoque::default()).unwrap();
+
+        let itens = find_pedido_itens(&mut get_pool().get().unwrap(), &pedido.id.to_string()).unwrap();
+        let (pedido_produto, produto) = &itens[0];
+        assert_eq!(pedido_produto.descricao, produto.descricao);
+        assert_eq!(pedido_produto.cod_ipi, produto.cod_ipi);
+
+        let nota = gerar_de_pedido(&get_pool(), pedido.id, &config(), SYSTEM_USER_ID).unwrap();
+        assert_eq!(nota.status, NOTA_GERADA);
+        assert_eq!(nota.id_orcamento, Some(created.id));
+        let xml = find_xml(&get_pool(), nota.id).unwrap();
+        assert!(xml.contains(&format!("Id=\"NFe{}\"", nota.chave)));
+        assert!(xml.contains("<enderDest><xLgr>Praça da Sé</xLgr><nro>100</nro><xCpl>Sala 2</xCpl>"));
+        assert!(find_danfe(&get_pool(), nota.id).unwrap().starts_with(b"%PDF"));
+        let notas = find_by_cliente(&mut get_pool().get().unwrap(), &created.id_cliente.to_string()).unwrap();
+        assert!(notas.iter().any(|emitida| emitida.id == nota.id.to_string()));
+
+        // the orcamento it came from is the same sale
+        assert!(gerar_de_orcamento(&get_pool(), created.id, &config(), SYSTEM_USER_ID).is_err());
+
+        let transmitida = transmitir(&get_pool(), nota.id, &AssinadorLocal, &TransmissorLocal).unwrap();
+        assert_eq!(transmitida.status, NOTA_AUTORIZADA);
+        assert!(transmitida.protocolo.is_some());
+        assert!(transmitir(&get_pool(), nota.id, &AssinadorLocal, &TransmissorLocal).is_err());
+
+        delete_nota(nota.id);
+        delete_pedido(&get_pool(), pedido.id).unwrap();
+        delete_movimentos_do_documento(pedido.id);
+        delete_orcamento(&get_pool(), created.id).unwrap();
+        delete_cliente(&get_pool(), cliente.id).unwrap();
+    }
+
+    #[test]
+    fn it_needs_the_endereco_of_the_cliente() {
+        let cliente = create_cliente().unwrap();
+        let created = create_orcamento_for_cliente(cliente.id).unwrap();
+        let config_fiscal = ConfigFiscal::default();
+        transition(&get_pool(), created.id, OrcamentoStatus::Enviado, SYSTEM_USER_ID, &config_fiscal).unwrap();
+        transition(&get_pool(), created.id, OrcamentoStatus::Aprovado, SYSTEM_USER_ID, &config_fiscal).unwrap();
+
+        let response = gerar_de_orcamento(&get_pool(), created.id, &config(), SYSTEM_USER_ID);
+        let expected_error = ApiError::ValidationError(vec![
+            "Destinatário Model Test: cadastre o endereço principal para emitir a NF-e".to_string(),
+        ]);
+        assert_eq!(response.unwrap_err(), expected_error);
+        delete_orcamento(&get_pool(), created.id).unwrap();
+        delete_cliente(&get_pool(), cliente.id).unwrap();
+    }
+}
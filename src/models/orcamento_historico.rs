@@ -0,0 +1,102 @@
+use crate::errors::ApiError;
+use crate::handlers::orcamento::OrcamentoHistoricoResponse;
+use crate::schema::orcamento_historicos;
+use chrono::{NaiveDateTime, Utc};
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use uuid::Uuid;
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Queryable, Identifiable, Insertable)]
+pub struct OrcamentoHistorico {
+    pub id: String,
+    pub id_orcamento: String,
+    pub status_anterior: Option<String>,
+    pub status_novo: String,
+    pub created_by: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct NewOrcamentoHistorico {
+    pub id: String,
+    pub id_orcamento: String,
+    pub status_anterior: Option<String>,
+    pub status_novo: String,
+    pub created_by: String,
+}
+
+/// Get the status history of a orcamento, oldest first
+pub fn find_by_orcamento(
+    conn: &mut PgConnection,
+    orcamento_id: &str,
+) -> Result<Vec<OrcamentoHistoricoResponse>, ApiError> {
+    use crate::schema::orcamento_historicos::dsl::{created_at, id_orcamento, orcamento_historicos};
+
+    let historico = orcamento_historicos
+        .filter(id_orcamento.eq(orcamento_id))
+        .order(created_at)
+        .load::<OrcamentoHistorico>(conn)?;
+
+    Ok(historico.into_iter().map(|historico| historico.into()).collect())
+}
+
+/// Record a status change of a orcamento
+///
+/// Takes a connection so it can join the transaction that changed the status.
+pub fn create(conn: &mut PgConnection, new_historico: &OrcamentoHistorico) -> Result<(), ApiError> {
+    use crate::schema::orcamento_historicos::dsl::orcamento_historicos;
+
+    diesel::insert_into(orcamento_historicos)
+        .values(new_historico)
+        .execute(conn)?;
+    Ok(())
+}
+
+/// Delete the history of a orcamento
+pub fn delete_by_orcamento(conn: &mut PgConnection, orcamento_id: &str) -> Result<(), ApiError> {
+    use crate::schema::orcamento_historicos::dsl::{id_orcamento, orcamento_historicos};
+
+    diesel::delete(orcamento_historicos)
+        .filter(id_orcamento.eq(orcamento_id))
+        .execute(conn)?;
+    Ok(())
+}
+
+impl NewOrcamentoHistorico {
+    pub fn new(id_orcamento: &str, status_anterior: Option<&str>, status_novo: &str, created_by: &str) -> Self {
+        NewOrcamentoHistorico {
+            id: Uuid::new_v4().to_string(),
+            id_orcamento: id_orcamento.to_string(),
+            status_anterior: status_anterior.map(|status| status.to_string()),
+            status_novo: status_novo.to_string(),
+            created_by: created_by.to_string(),
+        }
+    }
+}
+
+impl From<NewOrcamentoHistorico> for OrcamentoHistorico {
+    fn from(historico: NewOrcamentoHistorico) -> Self {
+        OrcamentoHistorico {
+            id: historico.id,
+            id_orcamento: historico.id_orcamento,
+            status_anterior: historico.status_anterior,
+            status_novo: historico.status_novo,
+            created_by: historico.created_by,
+            created_at: Utc::now().naive_utc(),
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::tests::helpers::tests::get_pool;
+
+    #[test]
+    fn it_finds_the_history_of_a_orcamento() {
+        let mut conn = get_pool().get().unwrap();
+        let historico = find_by_orcamento(&mut conn, "00000000-0000-0000-0000-000000000000").unwrap();
+        assert!(!historico.is_empty());
+        assert_eq!(historico[0].status_novo, "rascunho");
+    }
+}
@@ -0,0 +1,418 @@
+//! Orçamento printed to be sent to the cliente
+//!
+//! The company header is the emitente of the notas fiscais plus the
+//! EMPRESA_* settings, so quotes and notas carry the same company data.
+
+use crate::config::Config;
+use crate::errors::ApiError;
+use crate::models::cliente::Cliente;
+use crate::models::fiscal::ContextoFiscal;
+use crate::models::orcamento::{valores_itens, Orcamento, OrcamentoItem};
+use crate::pdf::{formatar_cep, formatar_cnpj, numero, Documento, A4_ALTURA, A4_LARGURA};
+use crate::validate::formatar_cpf;
+use bigdecimal::{BigDecimal, Zero};
+use std::fs;
+
+const MARGEM: f64 = 15.0;
+const LARGURA_UTIL: f64 = A4_LARGURA - 2.0 * MARGEM;
+const ALTURA_LINHA_ITEM: f64 = 6.0;
+const FIM_PAGINA: f64 = A4_ALTURA - MARGEM;
+const ALTURA_CABECALHO: f64 = 30.0;
+const LARGURA_LOGO: f64 = 40.0;
+const LARGURA_TOTAIS: f64 = 70.0;
+
+/// Columns of the items table, title and width
+const COLUNAS: [(&str, f64); 7] = [
+    ("ITEM", 10.0),
+    ("DESCRIÇÃO", 76.0),
+    ("UN", 12.0),
+    ("QTD", 14.0),
+    ("V. UNIT.", 22.0),
+    ("DESCONTO", 22.0),
+    ("V. TOTAL", 24.0),
+];
+
+/// Company shown on the top of every page
+#[derive(Clone, Debug, PartialEq)]
+pub struct Empresa {
+    pub nome: String,
+    pub razao_social: Option<String>,
+    pub cnpj: Option<String>,
+    pub endereco: Option<String>,
+    pub contatos: Vec<String>,
+    /// Contents of the PNG or JPEG logo
+    pub logo: Option<Vec<u8>>,
+}
+
+impl Empresa {
+    /// Read the header from the configuration, every field is optional but a
+    /// logo that is configured and can't be read is an error
+    pub fn from_config(config: &Config) -> Result<Self, ApiError> {
+        let preenchido = |valor: &Option<String>| {
+            valor
+                .as_ref()
+                .map(|valor| valor.trim().to_string())
+                .filter(|valor| !valor.is_empty())
+        };
+        let razao_social = preenchido(&config.emitente_razao_social);
+        let nome = preenchido(&config.emitente_nome_fantasia)
+            .or_else(|| razao_social.clone())
+            .unwrap_or_default();
+
+        let logradouro = [&config.emitente_logradouro, &config.emitente_numero, &config.emitente_bairro]
+            .iter()
+            .filter_map(|parte| preenchido(parte))
+            .collect::<Vec<String>>();
+        let cidade = match (preenchido(&config.emitente_municipio), preenchido(&config.emitente_uf)) {
+            (Some(municipio), Some(uf)) => Some(format!("{}/{}", municipio, uf)),
+            (municipio, uf) => municipio.or(uf),
+        };
+        let endereco = vec![
+            Some(logradouro.join(", ")).filter(|logradouro| !logradouro.is_empty()),
+            cidade,
+            preenchido(&config.emitente_cep).map(|cep| format!("CEP {}", formatar_cep(&cep))),
+        ]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<String>>()
+        .join(" - ");
+
+        let contatos = vec![
+            preenchido(&config.emitente_telefone).map(|telefone| format!("Tel. {}", telefone)),
+            preenchido(&config.empresa_email),
+            preenchido(&config.empresa_site),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+
+        let logo = match preenchido(&config.empresa_logo) {
+            Some(caminho) => Some(fs::read(&caminho).map_err(|error| {
+                ApiError::InternalServerError(format!("Não foi possível ler o logo {}: {}", caminho, error))
+            })?),
+            None => None,
+        };
+
+        Ok(Empresa {
+            // the razão social is only repeated when the header shows the nome fantasia
+            razao_social: razao_social.filter(|razao_social| razao_social != &nome),
+            nome,
+            cnpj: preenchido(&config.emitente_cnpj).map(|cnpj| formatar_cnpj(&cnpj)),
+            endereco: Some(endereco).filter(|endereco| !endereco.is_empty()),
+            contatos,
+            logo,
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ItemOrcamentoPdf {
+    pub descricao: String,
+    pub unidade: String,
+    pub quantidade: i32,
+    pub valor_unitario: BigDecimal,
+    pub desconto: BigDecimal,
+    pub valor_total: BigDecimal,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct OrcamentoPdf {
+    pub numero: String,
+    pub emissao: String,
+    pub validade: Option<String>,
+    pub cliente_nome: String,
+    /// CPF or CNPJ, with its label
+    pub cliente_documento: Option<String>,
+    pub cliente_telefone: Option<String>,
+    pub itens: Vec<ItemOrcamentoPdf>,
+    pub subtotal: BigDecimal,
+    pub desconto: BigDecimal,
+    pub valor_ipi: BigDecimal,
+    pub total: BigDecimal,
+    pub observacoes: Option<String>,
+}
+
+impl OrcamentoPdf {
+    /// Gather what is printed, with the same values the API returns for the orcamento
+    pub fn new(
+        orcamento: &Orcamento,
+        cliente: &Cliente,
+        itens: &[OrcamentoItem],
+        contexto: &ContextoFiscal,
+    ) -> Result<Self, ApiError> {
+        let valores = valores_itens(orcamento, itens)?;
+        // IPI is charged on top of the price, so the cliente pays it as well
+        let valor_ipi = itens
+            .iter()
+            .zip(&valores)
+            .fold(BigDecimal::zero(), |total, ((orcamento_produto, _), valor)| {
+                total + valor.impostos(orcamento_produto, contexto).ipi.valor
+            });
+        let itens: Vec<ItemOrcamentoPdf> = itens
+            .iter()
+            .zip(&valores)
+            .map(|((orcamento_produto, _), valores)| ItemOrcamentoPdf {
+                descricao: orcamento_produto.descricao.clone(),
+                unidade: orcamento_produto.unidade_medida.clone().unwrap_or_default(),
+                quantidade: orcamento_produto.quantidade,
+                valor_unitario: orcamento_produto.preco_unitario.clone(),
+                desconto: &valores.desconto + &valores.rateio,
+                valor_total: &valores.liquido - &valores.rateio,
+            })
+            .collect();
+        let subtotal = valores.iter().fold(BigDecimal::zero(), |total, valor| total + &valor.bruto);
+        let desconto = itens.iter().fold(BigDecimal::zero(), |total, item| total + &item.desconto);
+        let total = &subtotal - &desconto + &valor_ipi;
+
+        Ok(OrcamentoPdf {
+            numero: orcamento.id.chars().take(8).collect::<String>().to_uppercase(),
+            emissao: orcamento.created_at.format("%d/%m/%Y").to_string(),
+            validade: orcamento.validade.map(|validade| validade.format("%d/%m/%Y").to_string()),
+            cliente_nome: cliente.nome_completo(),
+            cliente_documento: cliente.documento().map(|documento| match documento.len() {
+                14 => format!("CNPJ {}", formatar_cnpj(documento)),
+                _ => format!("CPF {}", formatar_cpf(documento)),
+            }),
+            cliente_telefone: cliente.telefone.clone(),
+            itens,
+            subtotal,
+            desconto,
+            valor_ipi,
+            total,
+            observacoes: orcamento.observacoes.clone().filter(|observacoes| !observacoes.trim().is_empty()),
+        })
+    }
+}
+
+/// Lay out the orcamento on A4 portrait pages, the items flow onto as many
+/// pages as needed and the totals follow the last one
+pub fn renderizar(empresa: &Empresa, orcamento: &OrcamentoPdf) -> Result<Vec<u8>, ApiError> {
+    let mut documento = Documento::new(&format!("Orçamento {}", orcamento.numero), A4_LARGURA, A4_ALTURA)?;
+
+    let mut y = cabecalho(&documento, empresa, orcamento)?;
+    y = cliente(&documento, orcamento, y);
+    y = titulos_itens(&documento, y);
+    for (indice, item) in orcamento.itens.iter().enumerate() {
+        if y + ALTURA_LINHA_ITEM > FIM_PAGINA {
+            documento.nova_pagina();
+            y = titulos_itens(&documento, cabecalho(&documento, empresa, orcamento)?);
+        }
+        y = linha_item(&documento, indice + 1, item, y);
+    }
+
+    let observacoes = orcamento
+        .observacoes
+        .as_ref()
+        .map(|observacoes| documento.quebrar(observacoes, 8.0, LARGURA_UTIL - 2.0, false))
+        .unwrap_or_default();
+    let altura_rodape = 36.0 + 4.0 * observacoes.len() as f64;
+    if y + altura_rodape > FIM_PAGINA {
+        documento.nova_pagina();
+        y = cabecalho(&documento, empresa, orcamento)?;
+    }
+    rodape(&documento, orcamento, &observacoes, y + 4.0);
+
+    documento.salvar()
+}
+
+/// Logo, company data and the orcamento number, repeated on every page
+fn cabecalho(documento: &Documento, empresa: &Empresa, orcamento: &OrcamentoPdf) -> Result<f64, ApiError> {
+    let x = match &empresa.logo {
+        Some(logo) => {
+            documento.imagem(logo, MARGEM, MARGEM, LARGURA_LOGO, ALTURA_CABECALHO - 6.0)?;
+            MARGEM + LARGURA_LOGO + 5.0
+        }
+        None => MARGEM,
+    };
+    let largura_empresa = LARGURA_UTIL - (x - MARGEM) - 50.0;
+    documento.texto(&documento.ajustar(&empresa.nome, 13.0, largura_empresa, true), 13.0, x, MARGEM + 5.0, true);
+    let linhas = vec![
+        match (&empresa.razao_social, &empresa.cnpj) {
+            (Some(razao_social), Some(cnpj)) => Some(format!("{} - CNPJ {}", razao_social, cnpj)),
+            (Some(razao_social), None) => Some(razao_social.clone()),
+            (None, Some(cnpj)) => Some(format!("CNPJ {}", cnpj)),
+            (None, None) => None,
+        },
+        empresa.endereco.clone(),
+        Some(empresa.contatos.join(" · ")).filter(|contatos| !contatos.is_empty()),
+    ];
+    for (linha, texto) in linhas.into_iter().flatten().enumerate() {
+        let texto = documento.ajustar(&texto, 8.0, largura_empresa, false);
+        documento.texto(&texto, 8.0, x, MARGEM + 10.5 + 4.0 * linha as f64, false);
+    }
+
+    let direita = MARGEM + LARGURA_UTIL;
+    documento.texto_direita("ORÇAMENTO", 14.0, direita, MARGEM + 5.0, true);
+    documento.texto_direita(&format!("Nº {}", orcamento.numero), 9.0, direita, MARGEM + 11.0, true);
+    documento.texto_direita(&format!("Emissão: {}", orcamento.emissao), 8.0, direita, MARGEM + 16.0, false);
+
+    let y = MARGEM + ALTURA_CABECALHO;
+    documento.linha(MARGEM, y - 2.0, direita, y - 2.0);
+    Ok(y)
+}
+
+/// Who the orcamento is for
+fn cliente(documento: &Documento, orcamento: &OrcamentoPdf, y: f64) -> f64 {
+    documento.caixa(MARGEM, y, LARGURA_UTIL, 14.0);
+    documento.texto("CLIENTE", 6.0, MARGEM + 1.5, y + 3.0, false);
+    let nome = documento.ajustar(&orcamento.cliente_nome, 10.0, LARGURA_UTIL - 3.0, true);
+    documento.texto(&nome, 10.0, MARGEM + 1.5, y + 7.5, true);
+    let contato = vec![
+        orcamento.cliente_documento.clone(),
+        orcamento.cliente_telefone.as_ref().map(|telefone| format!("Tel. {}", telefone)),
+    ]
+    .into_iter()
+    .flatten()
+    .collect::<Vec<String>>()
+    .join("    ");
+    documento.texto(&contato, 8.0, MARGEM + 1.5, y + 12.0, false);
+    y + 18.0
+}
+
+/// Header row of the items table, returns where the first item goes
+fn titulos_itens(documento: &Documento, y: f64) -> f64 {
+    documento.caixa(MARGEM, y, LARGURA_UTIL, ALTURA_LINHA_ITEM);
+    let mut x = MARGEM;
+    for (indice, (titulo, largura)) in COLUNAS.iter().enumerate() {
+        if indice < 3 {
+            documento.texto(titulo, 7.0, x + 1.0, y + 4.2, true);
+        } else {
+            documento.texto_direita(titulo, 7.0, x + largura - 1.0, y + 4.2, true);
+        }
+        x += largura;
+    }
+    y + ALTURA_LINHA_ITEM
+}
+
+fn linha_item(documento: &Documento, posicao: usize, item: &ItemOrcamentoPdf, y: f64) -> f64 {
+    let valores = [
+        posicao.to_string(),
+        documento.ajustar(&item.descricao, 8.0, COLUNAS[1].1 - 2.0, false),
+        item.unidade.clone(),
+        item.quantidade.to_string(),
+        numero(&item.valor_unitario, 2),
+        numero(&item.desconto, 2),
+        numero(&item.valor_total, 2),
+    ];
+    let mut x = MARGEM;
+    for (indice, (valor, (_, largura))) in valores.iter().zip(COLUNAS.iter()).enumerate() {
+        if indice < 3 {
+            documento.texto(valor, 8.0, x + 1.0, y + 4.2, false);
+        } else {
+            documento.texto_direita(valor, 8.0, x + largura - 1.0, y + 4.2, false);
+        }
+        x += largura;
+    }
+    documento.linha(MARGEM, y + ALTURA_LINHA_ITEM, MARGEM + LARGURA_UTIL, y + ALTURA_LINHA_ITEM);
+    y + ALTURA_LINHA_ITEM
+}
+
+/// Totals, validity and the notes, after the last item
+fn rodape(documento: &Documento, orcamento: &OrcamentoPdf, observacoes: &[String], y: f64) {
+    let x = MARGEM + LARGURA_UTIL - LARGURA_TOTAIS;
+    let direita = MARGEM + LARGURA_UTIL;
+    let totais = [
+        ("Subtotal", &orcamento.subtotal),
+        ("Descontos", &orcamento.desconto),
+        ("IPI", &orcamento.valor_ipi),
+    ];
+    for (linha, (titulo, valor)) in totais.iter().enumerate() {
+        let base = y + 4.0 + 5.0 * linha as f64;
+        documento.texto(titulo, 8.0, x + 1.5, base, false);
+        documento.texto_direita(&format!("R$ {}", numero(valor, 2)), 8.0, direita - 1.5, base, false);
+    }
+    documento.caixa(x, y + 16.0, LARGURA_TOTAIS, 8.0);
+    documento.texto("TOTAL", 10.0, x + 1.5, y + 21.7, true);
+    documento.texto_direita(&format!("R$ {}", numero(&orcamento.total, 2)), 10.0, direita - 1.5, y + 21.7, true);
+
+    let validade = match &orcamento.validade {
+        Some(validade) => format!("Orçamento válido até {}", validade),
+        None => "Orçamento sem data de validade".to_string(),
+    };
+    documento.texto(&validade, 8.0, MARGEM, y + 4.0, true);
+
+    if !observacoes.is_empty() {
+        let inicio = y + 30.0;
+        documento.texto("OBSERVAÇÕES", 7.0, MARGEM, inicio, true);
+        for (linha, texto) in observacoes.iter().enumerate() {
+            documento.texto(texto, 8.0, MARGEM + 1.0, inicio + 4.5 + 4.0 * linha as f64, false);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn empresa() -> Empresa {
+        Empresa {
+            nome: "ERP Faculdade".to_string(),
+            razao_social: Some("ERP FACULDADE LTDA".to_string()),
+            cnpj: Some("11.222.333/0001-81".to_string()),
+            endereco: Some("Rua das Flores, 100, Centro - Sao Paulo/SP - CEP 01001-000".to_string()),
+            contatos: vec!["Tel. 1133334444".to_string(), "contato@erpfaculdade.com.br".to_string()],
+            logo: None,
+        }
+    }
+
+    fn orcamento() -> OrcamentoPdf {
+        OrcamentoPdf {
+            numero: "1A2B3C4D".to_string(),
+            emissao: "05/07/2023".to_string(),
+            validade: Some("20/07/2023".to_string()),
+            cliente_nome: "Nikolas Lunkes".to_string(),
+            cliente_documento: Some("CPF 086.737.239-73".to_string()),
+            cliente_telefone: None,
+            itens: vec![ItemOrcamentoPdf {
+                descricao: "Pote plástico 500ml".to_string(),
+                unidade: "UN".to_string(),
+                quantidade: 2,
+                valor_unitario: BigDecimal::from(20),
+                desconto: BigDecimal::from(4),
+                valor_total: BigDecimal::from(36),
+            }],
+            subtotal: BigDecimal::from(40),
+            desconto: BigDecimal::from(4),
+            valor_ipi: BigDecimal::from_str("1.80").unwrap(),
+            total: BigDecimal::from_str("37.80").unwrap(),
+            observacoes: Some("Entrega em até 5 dias úteis após a aprovação.".to_string()),
+        }
+    }
+
+    #[test]
+    fn it_reads_the_header_from_the_config() {
+        let mut config = crate::config::CONFIG.clone();
+        config.emitente_nome_fantasia = Some("ERP Faculdade".to_string());
+        config.emitente_razao_social = Some("ERP FACULDADE LTDA".to_string());
+        config.emitente_cnpj = Some("11222333000181".to_string());
+        config.empresa_logo = None;
+        let empresa = Empresa::from_config(&config).unwrap();
+        assert_eq!(empresa.nome, "ERP Faculdade");
+        assert_eq!(empresa.razao_social, Some("ERP FACULDADE LTDA".to_string()));
+        assert_eq!(empresa.cnpj, Some("11.222.333/0001-81".to_string()));
+
+        config.empresa_logo = Some("/nao/existe/logo.png".to_string());
+        assert!(Empresa::from_config(&config).is_err());
+    }
+
+    #[test]
+    fn it_renders_the_orcamento() {
+        assert!(renderizar(&empresa(), &orcamento()).unwrap().starts_with(b"%PDF"));
+    }
+
+    #[test]
+    fn it_renders_long_orcamentos_over_many_pages() {
+        let mut orcamento = orcamento();
+        orcamento.itens = vec![orcamento.itens[0].clone(); 80];
+        orcamento.observacoes = Some("Observação longa. ".repeat(60));
+        assert!(renderizar(&empresa(), &orcamento).unwrap().starts_with(b"%PDF"));
+    }
+
+    #[test]
+    fn it_refuses_a_logo_that_is_not_an_image() {
+        let mut empresa = empresa();
+        empresa.logo = Some(b"nao e uma imagem".to_vec());
+        assert!(renderizar(&empresa, &orcamento()).is_err());
+    }
+}
@@ -1,10 +1,13 @@
 use crate::auth::hash;
 use crate::database::PoolType;
 use crate::errors::ApiError;
+use crate::models::orcamento::ensure_rascunho;
+use crate::models::produto::Produto;
 //use crate::handlers::orcamento_produto::{OrcamentoProdutoResponse, OrcamentoProdutosResponse};
 use crate::schema::orcamento_produtos;
 use crate::handlers::orcamento_produto::{OrcamentoProdutoResponse};
 use chrono::{NaiveDateTime, NaiveDate, NaiveTime, Utc};
+use diesel::pg::PgConnection;
 use diesel::prelude::*;
 use bigdecimal::BigDecimal;
 use uuid::Uuid;
@@ -17,6 +20,21 @@ pub struct OrcamentoProduto {
     pub quantidade: i32,
     pub created_by: String,
     pub created_at: NaiveDateTime,
+    pub tipo_desconto: Option<String>,
+    pub valor_desconto: BigDecimal,
+    pub preco_unitario: BigDecimal,
+    pub descricao: String,
+    pub unidade_medida: Option<String>,
+    pub ncm: Option<String>,
+    pub cest: Option<String>,
+    pub icms: Option<String>,
+    pub pis: Option<String>,
+    pub cofins: Option<String>,
+    pub porcentagem_ipi: Option<BigDecimal>,
+    pub aliquota_icms: Option<BigDecimal>,
+    pub aliquota_pis: Option<BigDecimal>,
+    pub aliquota_cofins: Option<BigDecimal>,
+    pub cod_ipi: Option<String>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -26,6 +44,17 @@ pub struct NewOrcamentoProduto {
     pub id_orcamento: String,
     pub quantidade: i32,
     pub created_by: String,
+    pub tipo_desconto: Option<String>,
+    pub valor_desconto: BigDecimal,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, AsChangeset)]
+#[table_name = "orcamento_produtos"]
+#[diesel(treat_none_as_null = true)]
+pub struct UpdateOrcamentoProduto {
+    pub quantidade: i32,
+    pub tipo_desconto: Option<String>,
+    pub valor_desconto: BigDecimal,
 }
 
 /// Find a orcamento_produto by the orcamento_produto's id or error out
@@ -42,26 +71,72 @@ pub fn find(pool: &PoolType, orcamento_produto_id: Uuid) -> Result<OrcamentoProd
     Ok(orcamento_produto.into())
 }
 
-/// Create a new orcamento_produto
+/// Copy the price, description and tax data of the produto onto a line
+///
+/// Lines keep what the produto looked like when they were added, so later
+/// changes to the produto do not rewrite existing orcamentos.
+pub fn snapshot(conn: &mut PgConnection, item: &OrcamentoProduto) -> Result<OrcamentoProduto, ApiError> {
+    use crate::schema::produtos::dsl::{id, produtos};
+
+    let not_found = format!("Produto {} not found", item.id_produto);
+    let produto = produtos
+        .filter(id.eq(&item.id_produto))
+        .first::<Produto>(conn)
+        .map_err(|_| ApiError::NotFound(not_found))?;
+
+    Ok(OrcamentoProduto {
+        preco_unitario: produto.preco_venda.unwrap_or_default(),
+        descricao: produto.descricao,
+        unidade_medida: produto.unidade_medida,
+        ncm: produto.ncm,
+        cest: produto.cest,
+        icms: produto.icms,
+        pis: produto.pis,
+        cofins: produto.cofins,
+        porcentagem_ipi: produto.porcentagem_ipi,
+        aliquota_icms: produto.aliquota_icms,
+        aliquota_pis: produto.aliquota_pis,
+        aliquota_cofins: produto.aliquota_cofins,
+        cod_ipi: produto.cod_ipi,
+        ..item.clone()
+    })
+}
+
+/// Create a new orcamento_produto on a orcamento that is still a rascunho
 pub fn create(pool: &PoolType, new_orcamento_produto: &OrcamentoProduto) -> Result<OrcamentoProdutoResponse, ApiError> {
     use crate::schema::orcamento_produtos::dsl::orcamento_produtos;
 
     let mut conn = pool.get()?;
-    diesel::insert_into(orcamento_produtos).values(new_orcamento_produto).execute(&mut conn)?;
-    Ok(new_orcamento_produto.clone().into())
+    conn.transaction::<_, ApiError, _>(|conn| {
+        ensure_rascunho(conn, Uuid::parse_str(&new_orcamento_produto.id_orcamento)?)?;
+        let new_orcamento_produto = snapshot(conn, new_orcamento_produto)?;
+        diesel::insert_into(orcamento_produtos).values(&new_orcamento_produto).execute(conn)?;
+        Ok(new_orcamento_produto.into())
+    })
 }
 
-/// Delete a orcamento_produto
+/// Delete a orcamento_produto from a orcamento that is still a rascunho
 pub fn delete(pool: &PoolType, orcamento_produto_id: Uuid) -> Result<(), ApiError> {
-    use crate::schema::orcamento_produtos::dsl::{id, orcamento_produtos};
+    use crate::schema::orcamento_produtos::dsl::{id, id_orcamento, orcamento_produtos};
 
     let mut conn = pool.get()?;
-    diesel::delete(orcamento_produtos)
-        .filter(id.eq(orcamento_produto_id.to_string()))
-        .execute(&mut conn)?;
-    Ok(())
+    conn.transaction::<_, ApiError, _>(|conn| {
+        let orcamento_id: Option<String> = orcamento_produtos
+            .filter(id.eq(orcamento_produto_id.to_string()))
+            .select(id_orcamento)
+            .first(conn)
+            .optional()?;
+        if let Some(orcamento_id) = orcamento_id {
+            ensure_rascunho(conn, Uuid::parse_str(&orcamento_id)?)?;
+        }
+        diesel::delete(orcamento_produtos)
+            .filter(id.eq(orcamento_produto_id.to_string()))
+            .execute(conn)?;
+        Ok(())
+    })
 }
 
+/// The produto snapshot starts empty and is filled by `snapshot` on insert
 impl From<NewOrcamentoProduto> for OrcamentoProduto {
     fn from(orcamento_produto: NewOrcamentoProduto) -> Self {
         OrcamentoProduto {
@@ -71,6 +146,21 @@ impl From<NewOrcamentoProduto> for OrcamentoProduto {
             quantidade: orcamento_produto.quantidade,
             created_by: orcamento_produto.created_by,
             created_at: Utc::now().naive_utc(),
+            tipo_desconto: orcamento_produto.tipo_desconto,
+            valor_desconto: orcamento_produto.valor_desconto,
+            preco_unitario: BigDecimal::from(0),
+            descricao: String::new(),
+            unidade_medida: None,
+            ncm: None,
+            cest: None,
+            icms: None,
+            pis: None,
+            cofins: None,
+            porcentagem_ipi: None,
+            aliquota_icms: None,
+            aliquota_pis: None,
+            aliquota_cofins: None,
+            cod_ipi: None,
         }
     }
 }
@@ -92,6 +182,8 @@ pub mod tests {
             id_orcamento: "00000000-0000-0000-0000-000000000000".to_string(),
             quantidade: 1,
             created_by: orcamento_produto_id.to_string(),
+            tipo_desconto: None,
+            valor_desconto: BigDecimal::from(0),
         };
         let orcamento_produto: OrcamentoProduto = new_orcamento_produto.into();
         create(&get_pool(), &orcamento_produto)
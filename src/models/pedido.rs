@@ -0,0 +1,199 @@
+use crate::database::PoolType;
+use crate::errors::ApiError;
+use crate::handlers::pedido::{PedidoResponse, PedidosResponse};
+use crate::models::estoque::estornar_pedido;
+use crate::models::orcamento::{desfazer_conversao, SYSTEM_USER_ID};
+use crate::models::pedido_produto::PedidoProduto;
+use crate::models::produto::Produto;
+use crate::schema::pedidos;
+use chrono::{NaiveDateTime, Utc};
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Queryable, Identifiable, Insertable)]
+pub struct Pedido {
+    pub id: String,
+    pub id_cliente: String,
+    pub id_orcamento: Option<String>,
+    pub status: String,
+    pub created_by: String,
+    pub created_at: NaiveDateTime,
+    pub updated_by: String,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct NewPedido {
+    pub id: String,
+    pub id_cliente: String,
+    pub id_orcamento: Option<String>,
+    pub created_by: String,
+}
+
+/// Status a pedido starts in
+pub const PEDIDO_ABERTO: &str = "aberto";
+
+/// A pedido line joined with the produto it references
+pub type PedidoItem = (PedidoProduto, Produto);
+
+/// Get all pedidos
+pub fn get_all(pool: &PoolType) -> Result<PedidosResponse, ApiError> {
+    use crate::schema::pedidos::dsl::{created_at, pedidos};
+
+    let mut conn = pool.get()?;
+    let all_pedidos = pedidos.order(created_at).load::<Pedido>(&mut conn)?;
+
+    Ok(with_itens(&mut conn, all_pedidos)?.into())
+}
+
+/// Get the pedidos of a cliente, oldest first
+pub fn find_by_cliente(pool: &PoolType, cliente_id: Uuid) -> Result<PedidosResponse, ApiError> {
+    use crate::schema::pedidos::dsl::{created_at, id_cliente, pedidos};
+
+    let mut conn = pool.get()?;
+    let cliente_pedidos = pedidos
+        .filter(id_cliente.eq(cliente_id.to_string()))
+        .order(created_at)
+        .load::<Pedido>(&mut conn)?;
+
+    Ok(with_itens(&mut conn, cliente_pedidos)?.into())
+}
+
+/// Load the lines of many pedidos at once and pair them with their headers
+fn with_itens(
+    conn: &mut PgConnection,
+    headers: Vec<Pedido>,
+) -> Result<Vec<(Pedido, Vec<PedidoItem>)>, ApiError> {
+    use crate::schema::pedido_produtos::dsl::{created_at, id_pedido, pedido_produtos};
+    use crate::schema::produtos::dsl::produtos;
+
+    let ids: Vec<&str> = headers.iter().map(|pedido| pedido.id.as_str()).collect();
+    let all_itens = pedido_produtos
+        .inner_join(produtos)
+        .filter(id_pedido.eq_any(ids))
+        .order(created_at)
+        .load::<PedidoItem>(conn)?;
+
+    let mut itens_por_pedido: HashMap<String, Vec<PedidoItem>> = HashMap::new();
+    for item in all_itens {
+        itens_por_pedido
+            .entry(item.0.id_pedido.clone())
+            .or_insert_with(Vec::new)
+            .push(item);
+    }
+
+    Ok(headers
+        .into_iter()
+        .map(|pedido| {
+            let itens = itens_por_pedido.remove(&pedido.id).unwrap_or_default();
+            (pedido, itens)
+        })
+        .collect())
+}
+
+/// Find a pedido by the pedido's id or error out
+pub fn find(pool: &PoolType, pedido_id: Uuid) -> Result<PedidoResponse, ApiError> {
+    let mut conn = pool.get()?;
+    find_with_itens(&mut conn, pedido_id)
+}
+
+/// Find a pedido with its lines using an existing connection
+pub fn find_with_itens(conn: &mut PgConnection, pedido_id: Uuid) -> Result<PedidoResponse, ApiError> {
+    use crate::schema::pedidos::dsl::{id, pedidos};
+
+    let not_found = format!("Pedido {} not found", pedido_id);
+    let pedido = pedidos
+        .filter(id.eq(pedido_id.to_string()))
+        .first::<Pedido>(conn)
+        .map_err(|_| ApiError::NotFound(not_found))?;
+    let itens = find_itens(conn, &pedido.id)?;
+
+    Ok((pedido, itens).into())
+}
+
+/// Load the lines of a pedido joined with their produtos
+pub fn find_itens(conn: &mut PgConnection, pedido_id: &str) -> Result<Vec<PedidoItem>, ApiError> {
+    use crate::schema::pedido_produtos::dsl::{created_at, id_pedido, pedido_produtos};
+    use crate::schema::produtos::dsl::produtos;
+
+    let itens = pedido_produtos
+        .inner_join(produtos)
+        .filter(id_pedido.eq(pedido_id))
+        .order(created_at)
+        .load::<PedidoItem>(conn)?;
+
+    Ok(itens)
+}
+
+/// Insert a pedido and its lines
+///
+/// Takes a connection so it can join the transaction converting a orcamento.
+pub fn create(
+    conn: &mut PgConnection,
+    new_pedido: &Pedido,
+    new_itens: &[PedidoProduto],
+) -> Result<(), ApiError> {
+    use crate::schema::pedido_produtos::dsl::pedido_produtos;
+    use crate::schema::pedidos::dsl::pedidos;
+
+    diesel::insert_into(pedidos).values(new_pedido).execute(conn)?;
+    diesel::insert_into(pedido_produtos).values(new_itens).execute(conn)?;
+    Ok(())
+}
+
+/// Delete a pedido along with its lines, putting the orcamento it came from
+/// back to aprovado and the stock it took out back in
+pub fn delete(pool: &PoolType, pedido_id: Uuid) -> Result<(), ApiError> {
+    use crate::schema::pedido_produtos::dsl::{id_pedido, pedido_produtos};
+    use crate::schema::pedidos::dsl::{id, pedidos};
+
+    let mut conn = pool.get()?;
+    conn.transaction::<_, ApiError, _>(|conn| {
+        desfazer_conversao(conn, &pedido_id.to_string(), SYSTEM_USER_ID)?;
+        estornar_pedido(conn, &pedido_id.to_string(), SYSTEM_USER_ID)?;
+        diesel::delete(pedido_produtos)
+            .filter(id_pedido.eq(pedido_id.to_string()))
+            .execute(conn)?;
+        diesel::delete(pedidos)
+            .filter(id.eq(pedido_id.to_string()))
+            .execute(conn)?;
+        Ok(())
+    })
+}
+
+impl From<NewPedido> for Pedido {
+    fn from(pedido: NewPedido) -> Self {
+        Pedido {
+            id: pedido.id,
+            id_cliente: pedido.id_cliente,
+            id_orcamento: pedido.id_orcamento,
+            status: PEDIDO_ABERTO.to_string(),
+            created_by: pedido.created_by.clone(),
+            created_at: Utc::now().naive_utc(),
+            updated_by: pedido.created_by,
+            updated_at: Utc::now().naive_utc(),
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::tests::helpers::tests::get_pool;
+
+    #[test]
+    fn it_gets_all_pedidos() {
+        let pedidos = get_all(&get_pool());
+        assert!(pedidos.is_ok());
+    }
+
+    #[test]
+    fn it_doesnt_find_a_pedido() {
+        let pedido_id = Uuid::new_v4();
+        let not_found_pedido = find(&get_pool(), pedido_id);
+        let expected_error = ApiError::NotFound(format!("Pedido {} not found", pedido_id));
+        assert_eq!(not_found_pedido.unwrap_err(), expected_error);
+    }
+}
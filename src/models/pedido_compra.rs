@@ -0,0 +1,750 @@
+//! Pedidos de compra
+//!
+//! What was ordered from a fornecedor. Once aprovado, a pedido de compra is
+//! received in one or more deliveries, each posting costed entradas to the
+//! depósito of the pedido, until every line has arrived or what is still
+//! missing is cancelado.
+
+use crate::database::PoolType;
+use crate::errors::ApiError;
+use crate::handlers::pedido_compra::{PedidoCompraResponse, PedidosCompraResponse, PendenciasResponse};
+use crate::models::deposito::find_deposito;
+use crate::models::estoque::{lancar, ConfigEstoque, EstoqueMovimento, TipoMovimento};
+use crate::models::fornecedor::find_fornecedor;
+use crate::models::produto::Produto;
+use crate::models::produto_custo::validar_custo;
+use crate::schema::{pedido_compra_itens, pedidos_compra};
+use bigdecimal::BigDecimal;
+use chrono::{NaiveDateTime, Utc};
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::str::FromStr;
+use uuid::Uuid;
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Queryable, Identifiable, Insertable)]
+#[table_name = "pedidos_compra"]
+pub struct PedidoCompra {
+    pub id: String,
+    pub id_fornecedor: String,
+    pub id_deposito: String,
+    pub status: String,
+    pub previsao_entrega: Option<NaiveDateTime>,
+    pub observacoes: Option<String>,
+    pub aprovado_by: Option<String>,
+    pub aprovado_at: Option<NaiveDateTime>,
+    pub created_by: String,
+    pub created_at: NaiveDateTime,
+    pub updated_by: String,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct NewPedidoCompra {
+    pub id: String,
+    pub id_fornecedor: String,
+    pub id_deposito: String,
+    pub previsao_entrega: Option<NaiveDateTime>,
+    pub observacoes: Option<String>,
+    pub created_by: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Queryable, Identifiable, Insertable)]
+#[table_name = "pedido_compra_itens"]
+pub struct PedidoCompraItem {
+    pub id: String,
+    pub id_pedido_compra: String,
+    pub id_produto: String,
+    pub quantidade: i32,
+    pub quantidade_recebida: i32,
+    pub custo_unitario: BigDecimal,
+    pub created_by: String,
+    pub created_at: NaiveDateTime,
+}
+
+impl PedidoCompraItem {
+    /// What is still to be received of the line
+    pub fn pendente(&self) -> i32 {
+        self.quantidade - self.quantidade_recebida
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct NewPedidoCompraItem {
+    pub id: String,
+    pub id_pedido_compra: String,
+    pub id_produto: String,
+    pub quantidade: i32,
+    pub custo_unitario: BigDecimal,
+    pub created_by: String,
+}
+
+/// A pedido de compra line joined with the produto it references
+pub type PedidoCompraLinha = (PedidoCompraItem, Produto);
+
+/// What is still to be received of a produto, line by line of the open
+/// pedidos de compra, the ones expected first
+pub type Pendencia = (Produto, Vec<(PedidoCompra, PedidoCompraItem)>);
+
+/// Lifecycle of a pedido de compra
+///
+/// rascunho -> aprovado -> parcial/recebido as deliveries arrive, a parcial
+/// pedido staying parcial until its last line is received. Anything not yet
+/// recebido can be cancelado, which only gives up on what is still missing.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum PedidoCompraStatus {
+    Rascunho,
+    Aprovado,
+    Parcial,
+    Recebido,
+    Cancelado,
+}
+
+impl PedidoCompraStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PedidoCompraStatus::Rascunho => "rascunho",
+            PedidoCompraStatus::Aprovado => "aprovado",
+            PedidoCompraStatus::Parcial => "parcial",
+            PedidoCompraStatus::Recebido => "recebido",
+            PedidoCompraStatus::Cancelado => "cancelado",
+        }
+    }
+
+    /// Whether a pedido de compra in this status may move to `destino`
+    pub fn can_transition_to(&self, destino: PedidoCompraStatus) -> bool {
+        use PedidoCompraStatus::*;
+
+        match (self, destino) {
+            (Rascunho, Aprovado) => true,
+            (Aprovado, Parcial) | (Aprovado, Recebido) => true,
+            (Parcial, Parcial) | (Parcial, Recebido) => true,
+            (Rascunho, Cancelado) | (Aprovado, Cancelado) | (Parcial, Cancelado) => true,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for PedidoCompraStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for PedidoCompraStatus {
+    type Err = ApiError;
+
+    fn from_str(status: &str) -> Result<Self, Self::Err> {
+        match status {
+            "rascunho" => Ok(PedidoCompraStatus::Rascunho),
+            "aprovado" => Ok(PedidoCompraStatus::Aprovado),
+            "parcial" => Ok(PedidoCompraStatus::Parcial),
+            "recebido" => Ok(PedidoCompraStatus::Recebido),
+            "cancelado" => Ok(PedidoCompraStatus::Cancelado),
+            _ => Err(ApiError::InternalServerError(format!("Unknown pedido de compra status {}", status))),
+        }
+    }
+}
+
+/// A produto arriving in a delivery
+///
+/// `custo_unitario` is what the fornecedor charged when it differs from the
+/// cost the pedido was placed at.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ItemRecebimento {
+    pub id_produto: String,
+    pub quantidade: i32,
+    pub custo_unitario: Option<BigDecimal>,
+}
+
+/// Get all pedidos de compra
+pub fn get_all(pool: &PoolType) -> Result<PedidosCompraResponse, ApiError> {
+    use crate::schema::pedidos_compra::dsl::{created_at, pedidos_compra};
+
+    let mut conn = pool.get()?;
+    let all_pedidos = pedidos_compra.order(created_at).load::<PedidoCompra>(&mut conn)?;
+
+    Ok(with_itens(&mut conn, all_pedidos)?.into())
+}
+
+/// Load the lines of many pedidos de compra at once and pair them with their headers
+fn with_itens(
+    conn: &mut PgConnection,
+    headers: Vec<PedidoCompra>,
+) -> Result<Vec<(PedidoCompra, Vec<PedidoCompraLinha>)>, ApiError> {
+    use crate::schema::pedido_compra_itens::dsl::{created_at, id_pedido_compra, pedido_compra_itens};
+    use crate::schema::produtos::dsl::produtos;
+
+    let ids: Vec<&str> = headers.iter().map(|pedido| pedido.id.as_str()).collect();
+    let all_itens = pedido_compra_itens
+        .inner_join(produtos)
+        .filter(id_pedido_compra.eq_any(ids))
+        .order(created_at)
+        .load::<PedidoCompraLinha>(conn)?;
+
+    let mut itens_por_pedido: HashMap<String, Vec<PedidoCompraLinha>> = HashMap::new();
+    for item in all_itens {
+        itens_por_pedido
+            .entry(item.0.id_pedido_compra.clone())
+            .or_insert_with(Vec::new)
+            .push(item);
+    }
+
+    Ok(headers
+        .into_iter()
+        .map(|pedido| {
+            let itens = itens_por_pedido.remove(&pedido.id).unwrap_or_default();
+            (pedido, itens)
+        })
+        .collect())
+}
+
+/// Find a pedido de compra by its id or error out
+pub fn find(pool: &PoolType, pedido_id: Uuid) -> Result<PedidoCompraResponse, ApiError> {
+    let mut conn = pool.get()?;
+    find_with_itens(&mut conn, pedido_id)
+}
+
+/// Find a pedido de compra with its lines using an existing connection
+fn find_with_itens(conn: &mut PgConnection, pedido_id: Uuid) -> Result<PedidoCompraResponse, ApiError> {
+    let pedido = find_header(conn, pedido_id)?;
+    let itens = find_itens(conn, &pedido.id)?;
+
+    Ok((pedido, itens).into())
+}
+
+/// Find the header of a pedido de compra
+fn find_header(conn: &mut PgConnection, pedido_id: Uuid) -> Result<PedidoCompra, ApiError> {
+    use crate::schema::pedidos_compra::dsl::{id, pedidos_compra};
+
+    let not_found = format!("Pedido de compra {} not found", pedido_id);
+    pedidos_compra
+        .filter(id.eq(pedido_id.to_string()))
+        .first::<PedidoCompra>(conn)
+        .map_err(|_| ApiError::NotFound(not_found))
+}
+
+/// Find and lock the header of a pedido de compra that is about to change
+fn lock_header(conn: &mut PgConnection, pedido_id: Uuid) -> Result<PedidoCompra, ApiError> {
+    use crate::schema::pedidos_compra::dsl::{id, pedidos_compra};
+
+    let not_found = format!("Pedido de compra {} not found", pedido_id);
+    pedidos_compra
+        .filter(id.eq(pedido_id.to_string()))
+        .for_update()
+        .first::<PedidoCompra>(conn)
+        .map_err(|_| ApiError::NotFound(not_found))
+}
+
+/// Load the lines of a pedido de compra joined with their produtos
+fn find_itens(conn: &mut PgConnection, pedido_id: &str) -> Result<Vec<PedidoCompraLinha>, ApiError> {
+    use crate::schema::pedido_compra_itens::dsl::{created_at, id_pedido_compra, pedido_compra_itens};
+    use crate::schema::produtos::dsl::produtos;
+
+    let itens = pedido_compra_itens
+        .inner_join(produtos)
+        .filter(id_pedido_compra.eq(pedido_id))
+        .order(created_at)
+        .load::<PedidoCompraLinha>(conn)?;
+
+    Ok(itens)
+}
+
+/// Create a rascunho pedido de compra with its lines
+pub fn create(
+    pool: &PoolType,
+    new_pedido: &PedidoCompra,
+    new_itens: &[PedidoCompraItem],
+) -> Result<PedidoCompraResponse, ApiError> {
+    use crate::schema::pedido_compra_itens::dsl::pedido_compra_itens;
+    use crate::schema::pedidos_compra::dsl::pedidos_compra;
+    use crate::schema::produtos::dsl::{id, produtos};
+
+    validar_itens(new_itens)?;
+    let mut conn = pool.get()?;
+    conn.transaction::<_, ApiError, _>(|conn| {
+        find_fornecedor(conn, &new_pedido.id_fornecedor)?;
+        find_deposito(conn, &new_pedido.id_deposito)?;
+        let ids: Vec<&str> = new_itens.iter().map(|item| item.id_produto.as_str()).collect();
+        let existentes: HashSet<String> = produtos
+            .filter(id.eq_any(&ids))
+            .select(id)
+            .load::<String>(conn)?
+            .into_iter()
+            .collect();
+        if let Some(faltante) = ids.iter().find(|produto_id| !existentes.contains(**produto_id)) {
+            return Err(ApiError::NotFound(format!("Produto {} not found", faltante)));
+        }
+
+        diesel::insert_into(pedidos_compra).values(new_pedido).execute(conn)?;
+        diesel::insert_into(pedido_compra_itens).values(new_itens).execute(conn)?;
+        find_with_itens(conn, Uuid::parse_str(&new_pedido.id)?)
+    })
+}
+
+/// Check the lines of a new pedido de compra
+fn validar_itens(itens: &[PedidoCompraItem]) -> Result<(), ApiError> {
+    let mut errors = Vec::new();
+    let mut vistos = HashSet::new();
+    for item in itens {
+        if item.quantidade <= 0 {
+            errors.push(format!(
+                "A quantidade do produto {} deve ser maior que zero",
+                item.id_produto
+            ));
+        }
+        if let Err(ApiError::ValidationError(mut erros)) = validar_custo("custo_unitario", &item.custo_unitario) {
+            errors.append(&mut erros);
+        }
+        if !vistos.insert(item.id_produto.as_str()) {
+            errors.push(format!(
+                "O produto {} aparece mais de uma vez no pedido de compra",
+                item.id_produto
+            ));
+        }
+    }
+    if !errors.is_empty() {
+        return Err(ApiError::ValidationError(errors));
+    }
+    Ok(())
+}
+
+/// Approve a rascunho pedido de compra, so it can be received
+pub fn aprovar(pool: &PoolType, pedido_id: Uuid, user_id: &str) -> Result<PedidoCompraResponse, ApiError> {
+    use crate::schema::pedidos_compra::dsl::{aprovado_at, aprovado_by};
+
+    let mut conn = pool.get()?;
+    conn.transaction::<_, ApiError, _>(|conn| {
+        transition(conn, pedido_id, PedidoCompraStatus::Aprovado, user_id)?;
+        diesel::update(pedidos_compra::table.find(pedido_id.to_string()))
+            .set((aprovado_by.eq(user_id), aprovado_at.eq(Utc::now().naive_utc())))
+            .execute(conn)?;
+        find_with_itens(conn, pedido_id)
+    })
+}
+
+/// Cancel what is still to be received of a pedido de compra
+///
+/// Deliveries already received stay in stock.
+pub fn cancelar(pool: &PoolType, pedido_id: Uuid, user_id: &str) -> Result<PedidoCompraResponse, ApiError> {
+    let mut conn = pool.get()?;
+    conn.transaction::<_, ApiError, _>(|conn| {
+        transition(conn, pedido_id, PedidoCompraStatus::Cancelado, user_id)?;
+        find_with_itens(conn, pedido_id)
+    })
+}
+
+/// Move a pedido de compra to a new status, recording who moved it
+fn transition(
+    conn: &mut PgConnection,
+    pedido_id: Uuid,
+    destino: PedidoCompraStatus,
+    user_id: &str,
+) -> Result<PedidoCompra, ApiError> {
+    use crate::schema::pedidos_compra::dsl::{status, updated_at, updated_by};
+
+    let pedido = lock_header(conn, pedido_id)?;
+    let atual = PedidoCompraStatus::from_str(&pedido.status)?;
+    if !atual.can_transition_to(destino) {
+        return Err(ApiError::Conflict(format!(
+            "Pedido de compra {} cannot go from {} to {}",
+            pedido_id, atual, destino
+        )));
+    }
+    diesel::update(pedidos_compra::table.find(&pedido.id))
+        .set((
+            status.eq(destino.as_str()),
+            updated_by.eq(user_id),
+            updated_at.eq(Utc::now().naive_utc()),
+        ))
+        .execute(conn)?;
+    Ok(pedido)
+}
+
+/// Receive a delivery of an aprovado pedido de compra
+///
+/// Every produto that arrived becomes an entrada in the depósito of the
+/// pedido at the cost it was bought for, moving the custo_medio of the
+/// produto. An empty delivery receives everything still missing. The pedido
+/// becomes recebido once nothing is missing, parcial otherwise.
+pub fn receber(
+    pool: &PoolType,
+    pedido_id: Uuid,
+    recebimento: &[ItemRecebimento],
+    user_id: &str,
+) -> Result<PedidoCompraResponse, ApiError> {
+    use crate::schema::pedido_compra_itens::dsl::quantidade_recebida;
+
+    let mut conn = pool.get()?;
+    conn.transaction::<_, ApiError, _>(|conn| {
+        let pedido = lock_header(conn, pedido_id)?;
+        let atual = PedidoCompraStatus::from_str(&pedido.status)?;
+        if !atual.can_transition_to(PedidoCompraStatus::Recebido) {
+            return Err(ApiError::Conflict(format!(
+                "Pedido de compra {} is {} and cannot be received",
+                pedido_id, atual
+            )));
+        }
+        let mut itens = find_itens(conn, &pedido.id)?;
+        let recebidos = a_receber(&itens, recebimento)?;
+
+        let movimentos: Vec<EstoqueMovimento> = recebidos
+            .iter()
+            .map(|(indice, quantidade, custo)| EstoqueMovimento {
+                id: Uuid::new_v4().to_string(),
+                id_produto: itens[*indice].0.id_produto.clone(),
+                id_deposito: pedido.id_deposito.clone(),
+                tipo: TipoMovimento::Entrada.as_str().to_string(),
+                quantidade: *quantidade,
+                id_documento: Some(pedido.id.clone()),
+                observacao: Some(format!("Recebimento do pedido de compra {}", pedido.id)),
+                created_by: user_id.to_string(),
+                created_at: Utc::now().naive_utc(),
+                custo_unitario: Some(custo.clone()),
+            })
+            .collect();
+        // entradas can't make a balance negative
+        lancar(conn, &movimentos, &ConfigEstoque::default())?;
+
+        for (indice, quantidade, _) in recebidos {
+            let item = &mut itens[indice].0;
+            item.quantidade_recebida += quantidade;
+            diesel::update(pedido_compra_itens::table.find(&item.id))
+                .set(quantidade_recebida.eq(item.quantidade_recebida))
+                .execute(conn)?;
+        }
+        let destino = if itens.iter().all(|(item, _)| item.pendente() == 0) {
+            PedidoCompraStatus::Recebido
+        } else {
+            PedidoCompraStatus::Parcial
+        };
+        transition(conn, pedido_id, destino, user_id)?;
+        find_with_itens(conn, pedido_id)
+    })
+}
+
+/// Match a delivery to the lines of the pedido de compra
+///
+/// Gives the index of each line received with the quantity that arrived and
+/// its cost, refusing produtos the pedido doesn't have and more than is
+/// still missing.
+fn a_receber(
+    itens: &[PedidoCompraLinha],
+    recebimento: &[ItemRecebimento],
+) -> Result<Vec<(usize, i32, BigDecimal)>, ApiError> {
+    if recebimento.is_empty() {
+        return Ok(itens
+            .iter()
+            .enumerate()
+            .filter(|(_, (item, _))| item.pendente() > 0)
+            .map(|(indice, (item, _))| (indice, item.pendente(), item.custo_unitario.clone()))
+            .collect());
+    }
+
+    let mut errors = Vec::new();
+    let mut recebidos: Vec<(usize, i32, BigDecimal)> = Vec::new();
+    for recebido in recebimento {
+        let indice = match itens.iter().position(|(item, _)| item.id_produto == recebido.id_produto) {
+            Some(indice) => indice,
+            None => {
+                errors.push(format!(
+                    "O produto {} não está no pedido de compra",
+                    recebido.id_produto
+                ));
+                continue;
+            }
+        };
+        let (item, produto) = &itens[indice];
+        if recebidos.iter().any(|(outro, _, _)| *outro == indice) {
+            errors.push(format!("O produto {} foi informado mais de uma vez", produto.descricao));
+        } else if recebido.quantidade <= 0 {
+            errors.push(format!(
+                "A quantidade recebida de {} deve ser maior que zero",
+                produto.descricao
+            ));
+        } else if recebido.quantidade > item.pendente() {
+            errors.push(format!(
+                "A quantidade recebida de {} é maior que a pendente: pendente {}, recebida {}",
+                produto.descricao,
+                item.pendente(),
+                recebido.quantidade
+            ));
+        }
+        let custo = recebido.custo_unitario.clone().unwrap_or_else(|| item.custo_unitario.clone());
+        if let Err(ApiError::ValidationError(mut erros)) = validar_custo("custo_unitario", &custo) {
+            errors.append(&mut erros);
+        }
+        recebidos.push((indice, recebido.quantidade, custo));
+    }
+    if !errors.is_empty() {
+        return Err(ApiError::ValidationError(errors));
+    }
+    Ok(recebidos)
+}
+
+/// What is still to be received of each produto from the pedidos de compra
+/// aprovado or parcial, of one produto or of all of them
+pub fn pendentes(pool: &PoolType, produto_id: Option<Uuid>) -> Result<PendenciasResponse, ApiError> {
+    use crate::schema::pedido_compra_itens::dsl::{id_produto, pedido_compra_itens, quantidade, quantidade_recebida};
+    use crate::schema::pedidos_compra::dsl::{created_at, previsao_entrega, status};
+    use crate::schema::produtos::dsl::produtos;
+
+    let mut conn = pool.get()?;
+    let abertos = vec![PedidoCompraStatus::Aprovado.as_str(), PedidoCompraStatus::Parcial.as_str()];
+    let mut query = pedido_compra_itens
+        .inner_join(pedidos_compra::table)
+        .inner_join(produtos)
+        .filter(status.eq_any(abertos))
+        .filter(quantidade.gt(quantidade_recebida))
+        .order((previsao_entrega.asc().nulls_last(), created_at))
+        .into_boxed();
+    if let Some(produto_id) = produto_id {
+        query = query.filter(id_produto.eq(produto_id.to_string()));
+    }
+    let linhas = query.load::<(PedidoCompraItem, PedidoCompra, Produto)>(&mut conn)?;
+
+    let mut por_produto: BTreeMap<String, Pendencia> = BTreeMap::new();
+    for (item, pedido, produto) in linhas {
+        por_produto
+            .entry(produto.id.clone())
+            .or_insert_with(|| (produto, Vec::new()))
+            .1
+            .push((pedido, item));
+    }
+
+    Ok(por_produto.into_values().collect::<Vec<_>>().into())
+}
+
+/// Delete a pedido de compra, which only a rascunho can be
+pub fn delete(pool: &PoolType, pedido_id: Uuid) -> Result<(), ApiError> {
+    let mut conn = pool.get()?;
+    conn.transaction::<_, ApiError, _>(|conn| {
+        let pedido = lock_header(conn, pedido_id)?;
+        if pedido.status != PedidoCompraStatus::Rascunho.as_str() {
+            return Err(ApiError::Conflict(format!(
+                "Pedido de compra {} is {} and can no longer be deleted",
+                pedido_id, pedido.status
+            )));
+        }
+        diesel::delete(pedidos_compra::table.find(&pedido.id)).execute(conn)?;
+        Ok(())
+    })
+}
+
+impl From<NewPedidoCompra> for PedidoCompra {
+    fn from(pedido: NewPedidoCompra) -> Self {
+        PedidoCompra {
+            id: pedido.id,
+            id_fornecedor: pedido.id_fornecedor,
+            id_deposito: pedido.id_deposito,
+            status: PedidoCompraStatus::Rascunho.as_str().to_string(),
+            previsao_entrega: pedido.previsao_entrega,
+            observacoes: pedido.observacoes,
+            aprovado_by: None,
+            aprovado_at: None,
+            created_by: pedido.created_by.clone(),
+            created_at: Utc::now().naive_utc(),
+            updated_by: pedido.created_by,
+            updated_at: Utc::now().naive_utc(),
+        }
+    }
+}
+
+impl From<NewPedidoCompraItem> for PedidoCompraItem {
+    fn from(item: NewPedidoCompraItem) -> Self {
+        PedidoCompraItem {
+            id: item.id,
+            id_pedido_compra: item.id_pedido_compra,
+            id_produto: item.id_produto,
+            quantidade: item.quantidade,
+            quantidade_recebida: 0,
+            custo_unitario: item.custo_unitario,
+            created_by: item.created_by,
+            created_at: Utc::now().naive_utc(),
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::models::deposito::DEPOSITO_PADRAO;
+    use crate::models::estoque::tests::delete_movimentos;
+    use crate::models::fornecedor::delete as delete_fornecedor;
+    use crate::models::fornecedor::tests::create_fornecedor;
+    use crate::models::produto::delete as delete_produto;
+    use crate::models::produto::tests::create_produto;
+    use crate::models::produto::{find as find_produto, NewProduto};
+    use crate::tests::helpers::tests::get_pool;
+
+    pub fn new_pedido(fornecedor_id: Uuid, itens: &[(Uuid, i32, &str)]) -> (PedidoCompra, Vec<PedidoCompraItem>) {
+        let pedido_id = Uuid::new_v4().to_string();
+        let pedido: PedidoCompra = NewPedidoCompra {
+            id: pedido_id.clone(),
+            id_fornecedor: fornecedor_id.to_string(),
+            id_deposito: DEPOSITO_PADRAO.to_string(),
+            previsao_entrega: None,
+            observacoes: None,
+            created_by: fornecedor_id.to_string(),
+        }
+        .into();
+        let itens = itens
+            .iter()
+            .map(|(produto_id, quantidade, custo)| {
+                NewPedidoCompraItem {
+                    id: Uuid::new_v4().to_string(),
+                    id_pedido_compra: pedido_id.clone(),
+                    id_produto: produto_id.to_string(),
+                    quantidade: *quantidade,
+                    custo_unitario: BigDecimal::from_str(custo).unwrap(),
+                    created_by: fornecedor_id.to_string(),
+                }
+                .into()
+            })
+            .collect();
+        (pedido, itens)
+    }
+
+    /// Remove a pedido de compra created by a test whatever its status
+    pub fn delete_pedido_compra(pedido_id: Uuid) {
+        let mut conn = get_pool().get().unwrap();
+        diesel::delete(pedidos_compra::table.find(pedido_id.to_string()))
+            .execute(&mut conn)
+            .unwrap();
+    }
+
+    fn linha(produto_id: Uuid, descricao: &str, quantidade: i32, quantidade_recebida: i32) -> PedidoCompraLinha {
+        let (_, itens) = new_pedido(Uuid::new_v4(), &[(produto_id, quantidade, "2.5")]);
+        let mut item = itens[0].clone();
+        item.quantidade_recebida = quantidade_recebida;
+        let produto: Produto = NewProduto {
+            id: produto_id.to_string(),
+            descricao: descricao.to_string(),
+            codigo_de_barras: None,
+            icms: None,
+            unidade_medida: None,
+            preco_venda: None,
+            preco_minimo: None,
+            custo_medio: None,
+            pis: None,
+            cofins: None,
+            ncm: None,
+            cest: None,
+            cod_ipi: None,
+            porcentagem_ipi: None,
+            created_by: produto_id.to_string(),
+            updated_by: produto_id.to_string(),
+            aliquota_icms: None,
+            aliquota_pis: None,
+            aliquota_cofins: None,
+        }
+        .into();
+        (item, produto)
+    }
+
+    fn recebido(produto_id: Uuid, quantidade: i32) -> ItemRecebimento {
+        ItemRecebimento {
+            id_produto: produto_id.to_string(),
+            quantidade,
+            custo_unitario: None,
+        }
+    }
+
+    #[test]
+    fn it_moves_through_the_lifecycle() {
+        use PedidoCompraStatus::*;
+
+        assert!(Rascunho.can_transition_to(Aprovado));
+        assert!(Aprovado.can_transition_to(Parcial));
+        assert!(Parcial.can_transition_to(Parcial));
+        assert!(Parcial.can_transition_to(Cancelado));
+        assert!(!Rascunho.can_transition_to(Recebido));
+        assert!(!Recebido.can_transition_to(Cancelado));
+        assert!(!Cancelado.can_transition_to(Aprovado));
+        assert_eq!(PedidoCompraStatus::from_str("parcial").unwrap(), Parcial);
+    }
+
+    #[test]
+    fn it_refuses_invalid_lines() {
+        let produto_id = Uuid::new_v4();
+        let (_, itens) = new_pedido(Uuid::new_v4(), &[(produto_id, 0, "-1"), (produto_id, 2, "1")]);
+        let expected_error = ApiError::ValidationError(vec![
+            format!("A quantidade do produto {} deve ser maior que zero", produto_id),
+            "custo_unitario: o custo não pode ser negativo, recebido -1".to_string(),
+            format!("O produto {} aparece mais de uma vez no pedido de compra", produto_id),
+        ]);
+        assert_eq!(validar_itens(&itens).unwrap_err(), expected_error);
+    }
+
+    #[test]
+    fn it_matches_a_delivery_to_the_lines() {
+        let (parafuso, porca) = (Uuid::new_v4(), Uuid::new_v4());
+        let itens = vec![linha(parafuso, "Parafuso", 10, 4), linha(porca, "Porca", 5, 5)];
+
+        let tudo = a_receber(&itens, &[]).unwrap();
+        assert_eq!(tudo, vec![(0, 6, BigDecimal::from_str("2.5").unwrap())]);
+
+        let mut com_custo = recebido(parafuso, 3);
+        com_custo.custo_unitario = Some(BigDecimal::from(3));
+        assert_eq!(a_receber(&itens, &[com_custo]).unwrap(), vec![(0, 3, BigDecimal::from(3))]);
+
+        let desconhecido = Uuid::new_v4();
+        let expected_error = ApiError::ValidationError(vec![
+            "A quantidade recebida de Parafuso é maior que a pendente: pendente 6, recebida 7".to_string(),
+            "A quantidade recebida de Porca é maior que a pendente: pendente 0, recebida 1".to_string(),
+            format!("O produto {} não está no pedido de compra", desconhecido),
+        ]);
+        let entrega = [recebido(parafuso, 7), recebido(porca, 1), recebido(desconhecido, 1)];
+        assert_eq!(a_receber(&itens, &entrega).unwrap_err(), expected_error);
+    }
+
+    #[test]
+    fn it_receives_a_pedido_de_compra_in_deliveries() {
+        let produto = create_produto().unwrap();
+        let fornecedor = create_fornecedor().unwrap();
+        let (pedido, itens) = new_pedido(fornecedor.id, &[(produto.id, 10, "3.00")]);
+        let pedido_id = Uuid::parse_str(&pedido.id).unwrap();
+        let created = create(&get_pool(), &pedido, &itens).unwrap();
+        assert_eq!(created.status, "rascunho");
+        assert!(receber(&get_pool(), pedido_id, &[], &pedido.created_by).is_err());
+
+        aprovar(&get_pool(), pedido_id, &pedido.created_by).unwrap();
+        let parcial = receber(&get_pool(), pedido_id, &[recebido(produto.id, 4)], &pedido.created_by).unwrap();
+        assert_eq!(parcial.status, "parcial");
+        assert_eq!(parcial.itens[0].quantidade_pendente, 6);
+        let pendencias = pendentes(&get_pool(), Some(produto.id)).unwrap().0;
+        assert_eq!(pendencias[0].quantidade_pendente, 6);
+        assert_eq!(pendencias[0].pedidos[0].id_pedido_compra, pedido_id);
+
+        let recebido = receber(&get_pool(), pedido_id, &[], &pedido.created_by).unwrap();
+        assert_eq!(recebido.status, "recebido");
+        assert!(pendentes(&get_pool(), Some(produto.id)).unwrap().0.is_empty());
+        assert_eq!(
+            find_produto(&get_pool(), produto.id).unwrap().custo_medio,
+            Some(BigDecimal::from(3))
+        );
+        assert!(cancelar(&get_pool(), pedido_id, &pedido.created_by).is_err());
+
+        delete_pedido_compra(pedido_id);
+        delete_movimentos(produto.id);
+        delete_fornecedor(&get_pool(), fornecedor.id).unwrap();
+        delete_produto(&get_pool(), produto.id).unwrap();
+    }
+
+    #[test]
+    fn it_only_deletes_a_rascunho() {
+        let produto = create_produto().unwrap();
+        let fornecedor = create_fornecedor().unwrap();
+        let (pedido, itens) = new_pedido(fornecedor.id, &[(produto.id, 1, "1")]);
+        let pedido_id = Uuid::parse_str(&pedido.id).unwrap();
+        create(&get_pool(), &pedido, &itens).unwrap();
+        cancelar(&get_pool(), pedido_id, &pedido.created_by).unwrap();
+        assert!(delete(&get_pool(), pedido_id).is_err());
+
+        delete_pedido_compra(pedido_id);
+        delete_fornecedor(&get_pool(), fornecedor.id).unwrap();
+        delete_produto(&get_pool(), produto.id).unwrap();
+    }
+}
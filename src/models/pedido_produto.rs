@@ -0,0 +1,79 @@
+use crate::schema::pedido_produtos;
+use bigdecimal::BigDecimal;
+use chrono::{NaiveDateTime, Utc};
+use diesel::prelude::*;
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Queryable, Identifiable, Insertable)]
+pub struct PedidoProduto {
+    pub id: String,
+    pub id_produto: String,
+    pub id_pedido: String,
+    pub quantidade: i32,
+    pub preco_unitario: BigDecimal,
+    pub created_by: String,
+    pub created_at: NaiveDateTime,
+    pub desconto: BigDecimal,
+    pub descricao: String,
+    pub unidade_medida: Option<String>,
+    pub ncm: Option<String>,
+    pub cest: Option<String>,
+    pub icms: Option<String>,
+    pub pis: Option<String>,
+    pub cofins: Option<String>,
+    pub porcentagem_ipi: Option<BigDecimal>,
+    pub aliquota_icms: Option<BigDecimal>,
+    pub aliquota_pis: Option<BigDecimal>,
+    pub aliquota_cofins: Option<BigDecimal>,
+    pub cod_ipi: Option<String>,
+}
+
+/// The produto is copied as the line sold it, the nota is issued from it
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct NewPedidoProduto {
+    pub id: String,
+    pub id_produto: String,
+    pub id_pedido: String,
+    pub quantidade: i32,
+    pub preco_unitario: BigDecimal,
+    pub desconto: BigDecimal,
+    pub created_by: String,
+    pub descricao: String,
+    pub unidade_medida: Option<String>,
+    pub ncm: Option<String>,
+    pub cest: Option<String>,
+    pub icms: Option<String>,
+    pub pis: Option<String>,
+    pub cofins: Option<String>,
+    pub porcentagem_ipi: Option<BigDecimal>,
+    pub aliquota_icms: Option<BigDecimal>,
+    pub aliquota_pis: Option<BigDecimal>,
+    pub aliquota_cofins: Option<BigDecimal>,
+    pub cod_ipi: Option<String>,
+}
+
+impl From<NewPedidoProduto> for PedidoProduto {
+    fn from(pedido_produto: NewPedidoProduto) -> Self {
+        PedidoProduto {
+            id: pedido_produto.id,
+            id_produto: pedido_produto.id_produto,
+            id_pedido: pedido_produto.id_pedido,
+            quantidade: pedido_produto.quantidade,
+            preco_unitario: pedido_produto.preco_unitario,
+            created_by: pedido_produto.created_by,
+            created_at: Utc::now().naive_utc(),
+            desconto: pedido_produto.desconto,
+            descricao: pedido_produto.descricao,
+            unidade_medida: pedido_produto.unidade_medida,
+            ncm: pedido_produto.ncm,
+            cest: pedido_produto.cest,
+            icms: pedido_produto.icms,
+            pis: pedido_produto.pis,
+            cofins: pedido_produto.cofins,
+            porcentagem_ipi: pedido_produto.porcentagem_ipi,
+            aliquota_icms: pedido_produto.aliquota_icms,
+            aliquota_pis: pedido_produto.aliquota_pis,
+            aliquota_cofins: pedido_produto.aliquota_cofins,
+            cod_ipi: pedido_produto.cod_ipi,
+        }
+    }
+}
@@ -2,6 +2,8 @@ use crate::auth::hash;
 use crate::database::PoolType;
 use crate::errors::ApiError;
 use crate::handlers::produto::{ProdutoResponse, ProdutosResponse};
+use crate::models::catalogo_fiscal::validar_produto;
+use crate::models::produto_custo::{self, validar_custo, NewProdutoCusto, OrigemCusto};
 use crate::schema::produtos;
 use chrono::{NaiveDateTime, NaiveDate, NaiveTime, Utc};
 use diesel::prelude::*;
@@ -28,6 +30,11 @@ pub struct Produto {
     pub created_at: NaiveDateTime,
     pub updated_by: String,
     pub updated_at: NaiveDateTime,
+    pub aliquota_icms: Option<BigDecimal>,
+    pub aliquota_pis: Option<BigDecimal>,
+    pub aliquota_cofins: Option<BigDecimal>,
+    /// Created by an import and not reviewed yet
+    pub rascunho: bool,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -48,6 +55,9 @@ pub struct NewProduto {
     pub porcentagem_ipi: Option<BigDecimal>,
     pub created_by: String,
     pub updated_by: String,
+    pub aliquota_icms: Option<BigDecimal>,
+    pub aliquota_pis: Option<BigDecimal>,
+    pub aliquota_cofins: Option<BigDecimal>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, AsChangeset)]
@@ -68,6 +78,9 @@ pub struct UpdateProduto {
     pub cod_ipi: Option<String>,
     pub porcentagem_ipi: Option<BigDecimal>,
     pub updated_by: String,
+    pub aliquota_icms: Option<BigDecimal>,
+    pub aliquota_pis: Option<BigDecimal>,
+    pub aliquota_cofins: Option<BigDecimal>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -101,31 +114,92 @@ pub fn find(pool: &PoolType, produto_id: Uuid) -> Result<ProdutoResponse, ApiErr
 }
 
 /// Create a new produto
+///
+/// The ncm and cest must be in the fiscal catalog, and a produto without a
+/// porcentagem_ipi takes the TIPI rate of its ncm. A custo_medio given here
+/// starts the cost history of the produto.
 pub fn create(pool: &PoolType, new_produto: &Produto) -> Result<ProdutoResponse, ApiError> {
     use crate::schema::produtos::dsl::produtos;
 
+    if let Some(custo_medio) = &new_produto.custo_medio {
+        validar_custo("custo_medio", custo_medio)?;
+    }
     let mut conn = pool.get()?;
-    diesel::insert_into(produtos).values(new_produto).execute(&mut conn)?;
-    Ok(new_produto.clone().into())
+    conn.transaction::<_, ApiError, _>(|conn| {
+        let catalogo = validar_produto(conn, &new_produto.ncm, &new_produto.cest)?;
+        let new_produto = Produto {
+            porcentagem_ipi: new_produto
+                .porcentagem_ipi
+                .clone()
+                .or_else(|| catalogo.and_then(|ncm| ncm.aliquota_ipi)),
+            ..new_produto.clone()
+        };
+        diesel::insert_into(produtos).values(&new_produto).execute(conn)?;
+        if let Some(custo_medio) = &new_produto.custo_medio {
+            let cadastro = NewProdutoCusto::new(
+                &new_produto.id,
+                None,
+                custo_medio.clone(),
+                OrigemCusto::Cadastro,
+                &new_produto.created_by,
+            );
+            produto_custo::create(conn, &cadastro.into())?;
+        }
+        Ok(new_produto.into())
+    })
 }
 
 /// Update a produto
+///
+/// custo_medio follows the entradas of stock, so it can only be sent back as
+/// it is; changing it takes a cost adjustment. Saving a rascunho is what
+/// reviews it.
 pub fn update(pool: &PoolType, update_produto: &UpdateProduto) -> Result<ProdutoResponse, ApiError> {
-    use crate::schema::produtos::dsl::{id, produtos};
+    use crate::schema::produtos::dsl::{custo_medio, id, produtos, rascunho};
 
     let mut conn = pool.get()?;
+    validar_produto(&mut conn, &update_produto.ncm, &update_produto.cest)?;
+    if let Some(custo_enviado) = &update_produto.custo_medio {
+        let custo_atual = produtos
+            .filter(id.eq(update_produto.id.clone()))
+            .select(custo_medio)
+            .first::<Option<BigDecimal>>(&mut conn)
+            .optional()?
+            .flatten();
+        if custo_atual.as_ref() != Some(custo_enviado) {
+            return Err(ApiError::ValidationError(vec![
+                "custo_medio: o custo médio é calculado pelas entradas de estoque, use um ajuste de custo para alterá-lo"
+                    .to_string(),
+            ]));
+        }
+    }
+    let update_produto = UpdateProduto {
+        custo_medio: None,
+        ..update_produto.clone()
+    };
     diesel::update(produtos)
         .filter(id.eq(update_produto.id.clone()))
-        .set(update_produto)
+        .set((&update_produto, rascunho.eq(false)))
         .execute(&mut conn)?;
     find(&pool, Uuid::parse_str(&update_produto.id)?)
 }
 
-/// Delete a produto
+/// Delete a produto that never moved in the stock
 pub fn delete(pool: &PoolType, produto_id: Uuid) -> Result<(), ApiError> {
+    use crate::schema::estoque_movimentos::dsl::{estoque_movimentos, id_produto};
     use crate::schema::produtos::dsl::{id, produtos};
 
     let mut conn = pool.get()?;
+    let movimentos = estoque_movimentos
+        .filter(id_produto.eq(produto_id.to_string()))
+        .count()
+        .get_result::<i64>(&mut conn)?;
+    if movimentos > 0 {
+        return Err(ApiError::ValidationError(vec![format!(
+            "O produto tem {} movimento(s) de estoque e não pode ser excluído",
+            movimentos
+        )]));
+    }
     diesel::delete(produtos)
         .filter(id.eq(produto_id.to_string()))
         .execute(&mut conn)?;
@@ -153,6 +227,10 @@ impl From<NewProduto> for Produto {
             created_at: Utc::now().naive_utc(),
             updated_by: produto.updated_by,
             updated_at: Utc::now().naive_utc(),
+            aliquota_icms: produto.aliquota_icms,
+            aliquota_pis: produto.aliquota_pis,
+            aliquota_cofins: produto.aliquota_cofins,
+            rascunho: false,
         }
     }
 }
@@ -164,6 +242,8 @@ pub mod tests {
     use bigdecimal::FromPrimitive;
 
     use super::*;
+    use crate::models::estoque::tests::{delete_movimentos, movimento};
+    use crate::models::estoque::{movimentar, ConfigEstoque, TipoMovimento};
     use crate::tests::helpers::tests::get_pool;
 
     pub fn get_all_produtos() -> Result<ProdutosResponse, ApiError> {
@@ -177,19 +257,22 @@ pub mod tests {
             id: produto_id.to_string(),
             descricao: "Model".to_string(),
             codigo_de_barras: Some("1".to_string()),
-            icms: Some("1".to_string()),
+            icms: Some("00".to_string()),
             unidade_medida: Some("UN".to_string()),
             preco_venda: Some(BigDecimal::from_str("1.00").unwrap().with_scale(2)),
             preco_minimo: Some(BigDecimal::from_str("1.00").unwrap().with_scale(2)),
             custo_medio: Some(BigDecimal::from_str("1.00").unwrap().with_scale(2)),
-            pis: Some("1".to_string()),
-            cofins: Some("00".to_string()),
-            ncm: Some("00".to_string()),
-            cest: Some("00".to_string()),
-            cod_ipi: Some("00".to_string()),
+            pis: Some("01".to_string()),
+            cofins: Some("01".to_string()),
+            ncm: Some("22021000".to_string()),
+            cest: Some("0300700".to_string()),
+            cod_ipi: Some("50".to_string()),
             porcentagem_ipi: Some(BigDecimal::from_str("1.00").unwrap().with_scale(2)),
             created_by: produto_id.to_string(),
             updated_by: produto_id.to_string(),
+            aliquota_icms: Some(BigDecimal::from_str("18.00").unwrap().with_scale(2)),
+            aliquota_pis: Some(BigDecimal::from_str("1.65").unwrap().with_scale(2)),
+            aliquota_cofins: Some(BigDecimal::from_str("7.60").unwrap().with_scale(2)),
         };
         let produto: Produto = new_produto.into();
         create(&get_pool(), &produto)
@@ -201,19 +284,22 @@ pub mod tests {
             id: produto_id.to_string(),
             descricao: "Model".to_string(),
             codigo_de_barras: Some("1".to_string()),
-            icms: Some("1".to_string()),
+            icms: Some("00".to_string()),
             unidade_medida: Some("UN".to_string()),
             preco_venda: Some(BigDecimal::from_str("1.00").unwrap().with_scale(2)),
             preco_minimo: Some(BigDecimal::from_str("1.00").unwrap().with_scale(2)),
             custo_medio: Some(BigDecimal::from_str("1.00").unwrap().with_scale(2)),
-            pis: Some("1".to_string()),
-            cofins: Some("00".to_string()),
-            ncm: Some("00".to_string()),
-            cest: Some("00".to_string()),
-            cod_ipi: Some("00".to_string()),
+            pis: Some("01".to_string()),
+            cofins: Some("01".to_string()),
+            ncm: Some("22021000".to_string()),
+            cest: Some("0300700".to_string()),
+            cod_ipi: Some("50".to_string()),
             porcentagem_ipi: Some(BigDecimal::from_str("1.00").unwrap().with_scale(2)),
             created_by: produto_id.to_string(),
             updated_by: produto_id.to_string(),
+            aliquota_icms: Some(BigDecimal::from_str("18.00").unwrap().with_scale(2)),
+            aliquota_pis: Some(BigDecimal::from_str("1.65").unwrap().with_scale(2)),
+            aliquota_cofins: Some(BigDecimal::from_str("7.60").unwrap().with_scale(2)),
         };
         let produto: Produto = new_produto.into();
         create(&get_pool(), &produto)
@@ -251,6 +337,39 @@ pub mod tests {
         assert_eq!(unwrapped, found_produto);
     }
 
+    #[test]
+    fn it_checks_the_ncm_and_cest_against_the_catalog() {
+        let produto_id = Uuid::new_v4();
+        let new_produto = NewProduto {
+            id: produto_id.to_string(),
+            descricao: "Cerveja".to_string(),
+            codigo_de_barras: None,
+            icms: Some("00".to_string()),
+            unidade_medida: Some("UN".to_string()),
+            preco_venda: Some(BigDecimal::from_str("5.00").unwrap().with_scale(2)),
+            preco_minimo: None,
+            custo_medio: None,
+            pis: Some("01".to_string()),
+            cofins: Some("01".to_string()),
+            ncm: Some("22030000".to_string()),
+            cest: Some("0300700".to_string()),
+            cod_ipi: Some("50".to_string()),
+            porcentagem_ipi: None,
+            created_by: produto_id.to_string(),
+            updated_by: produto_id.to_string(),
+            aliquota_icms: None,
+            aliquota_pis: None,
+            aliquota_cofins: None,
+        };
+        let produto: Produto = new_produto.into();
+        assert!(create(&get_pool(), &produto).is_err());
+
+        let produto = Produto { cest: Some("0302100".to_string()), ..produto };
+        let created = create(&get_pool(), &produto).unwrap();
+        assert_eq!(created.porcentagem_ipi, Some(BigDecimal::from_str("3.90").unwrap()));
+        delete(&get_pool(), created.id).unwrap();
+    }
+
     #[test]
     fn it_updates_a_produto() {
         let created = create_produto_by_email("teste_model_update6@teste.com").unwrap();
@@ -258,18 +377,21 @@ pub mod tests {
             id: created.id.to_string(),
             descricao: "Model".to_string(),
             codigo_de_barras: Some("1".to_string()),
-            icms: Some("1".to_string()),
+            icms: Some("00".to_string()),
             unidade_medida: Some("UN".to_string()),
             preco_venda: Some(BigDecimal::from_str("1.00").unwrap().with_scale(2)),
             preco_minimo: Some(BigDecimal::from_str("1.00").unwrap().with_scale(2)),
             custo_medio: Some(BigDecimal::from_str("1.00").unwrap().with_scale(2)),
-            pis: Some("1".to_string()),
-            cofins: Some("00".to_string()),
-            ncm: Some("00".to_string()),
-            cest: Some("00".to_string()),
-            cod_ipi: Some("00".to_string()),
+            pis: Some("01".to_string()),
+            cofins: Some("01".to_string()),
+            ncm: Some("22021000".to_string()),
+            cest: Some("0300700".to_string()),
+            cod_ipi: Some("50".to_string()),
             porcentagem_ipi: Some(BigDecimal::from_str("1.00").unwrap().with_scale(2)),
             updated_by: created.id.to_string(),
+            aliquota_icms: Some(BigDecimal::from_str("18.00").unwrap().with_scale(2)),
+            aliquota_pis: Some(BigDecimal::from_str("1.65").unwrap().with_scale(2)),
+            aliquota_cofins: Some(BigDecimal::from_str("7.60").unwrap().with_scale(2)),
         };
         let updated = update(&get_pool(), &update_produto);
         assert!(updated.is_ok());
@@ -278,6 +400,42 @@ pub mod tests {
         delete(&get_pool(), created.id);
     }
 
+    #[test]
+    fn it_doesnt_change_the_custo_medio_on_update() {
+        let created = create_produto().unwrap();
+        let update_produto = UpdateProduto {
+            id: created.id.to_string(),
+            descricao: created.descricao.clone(),
+            codigo_de_barras: created.codigo_de_barras.clone(),
+            icms: created.icms.clone(),
+            unidade_medida: created.unidade_medida.clone(),
+            preco_venda: created.preco_venda.clone(),
+            preco_minimo: created.preco_minimo.clone(),
+            custo_medio: Some(BigDecimal::from_str("9.99").unwrap()),
+            pis: created.pis.clone(),
+            cofins: created.cofins.clone(),
+            ncm: created.ncm.clone(),
+            cest: created.cest.clone(),
+            cod_ipi: created.cod_ipi.clone(),
+            porcentagem_ipi: created.porcentagem_ipi.clone(),
+            updated_by: created.id.to_string(),
+            aliquota_icms: created.aliquota_icms.clone(),
+            aliquota_pis: created.aliquota_pis.clone(),
+            aliquota_cofins: created.aliquota_cofins.clone(),
+        };
+        let updated = update(&get_pool(), &update_produto);
+        let expected_error = ApiError::ValidationError(vec![
+            "custo_medio: o custo médio é calculado pelas entradas de estoque, use um ajuste de custo para alterá-lo"
+                .to_string(),
+        ]);
+        assert_eq!(updated.unwrap_err(), expected_error);
+
+        let update_produto = UpdateProduto { custo_medio: None, ..update_produto };
+        let updated = update(&get_pool(), &update_produto).unwrap();
+        assert_eq!(updated.custo_medio, created.custo_medio);
+        delete(&get_pool(), created.id).unwrap();
+    }
+
     #[test]
     fn it_fails_to_update_a_nonexistent_produto() {
         let produto_id = Uuid::new_v4();
@@ -285,18 +443,21 @@ pub mod tests {
             id: produto_id.to_string(),
             descricao: "Model".to_string(),
             codigo_de_barras: Some("12345678901".to_string()),
-            icms: Some("123456789".to_string()),
+            icms: Some("00".to_string()),
             unidade_medida: Some("123456789".to_string()),
             preco_venda: Some(BigDecimal::from_str("1.00").unwrap()),
             preco_minimo: Some(BigDecimal::from_str("1.00").unwrap()),
             custo_medio: Some(BigDecimal::from_str("1.00").unwrap().with_scale(2)),
-            pis: Some("123456789".to_string()),
-            cofins: Some("00".to_string()),
-            ncm: Some("00".to_string()),
-            cest: Some("00".to_string()),
-            cod_ipi: Some("00".to_string()),
+            pis: Some("01".to_string()),
+            cofins: Some("01".to_string()),
+            ncm: Some("22021000".to_string()),
+            cest: Some("0300700".to_string()),
+            cod_ipi: Some("50".to_string()),
             porcentagem_ipi: Some(BigDecimal::from_str("1.00").unwrap()),
             updated_by: produto_id.to_string(),
+            aliquota_icms: None,
+            aliquota_pis: None,
+            aliquota_cofins: None,
         };
         let updated = update(&get_pool(), &update_produto);
         assert!(updated.is_err());
@@ -312,4 +473,19 @@ pub mod tests {
         let produto = find(&get_pool(), produto_id);
         assert!(produto.is_err());
     }
+
+    #[test]
+    fn it_doesnt_delete_a_produto_with_movimentos() {
+        let produto = create_produto().unwrap();
+        movimentar(&get_pool(), &movimento(produto.id, TipoMovimento::Entrada, 2), &ConfigEstoque::default()).unwrap();
+        let response = delete(&get_pool(), produto.id);
+        let expected_error = ApiError::ValidationError(vec![
+            "O produto tem 1 movimento(s) de estoque e não pode ser excluído".to_string(),
+        ]);
+        assert_eq!(response.unwrap_err(), expected_error);
+        assert!(find(&get_pool(), produto.id).is_ok());
+
+        delete_movimentos(produto.id);
+        delete(&get_pool(), produto.id).unwrap();
+    }
 }
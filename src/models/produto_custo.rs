@@ -0,0 +1,356 @@
+//! Cost history of produtos
+//!
+//! custo_medio is the weighted moving average of what the stock on hand
+//! cost: each entrada with a custo_unitario blends its cost into it, and
+//! every change of it is kept here together with what caused it.
+
+use crate::database::PoolType;
+use crate::errors::ApiError;
+use crate::handlers::produto::{ProdutoCustoResponse, ProdutoCustosResponse};
+use crate::models::estoque::{saldo_total, EstoqueMovimento, TipoMovimento};
+use crate::schema::produto_custos;
+use bigdecimal::{BigDecimal, Zero};
+use chrono::{NaiveDateTime, Utc};
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use std::collections::BTreeMap;
+use uuid::Uuid;
+
+/// Decimal places custo_medio is kept with
+const CASAS_CUSTO: i64 = 4;
+
+/// What changed the custo_medio of a produto
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OrigemCusto {
+    /// Cost typed in when the produto was created
+    Cadastro,
+    Entrada,
+    /// Explicit correction, always with a motivo
+    Ajuste,
+}
+
+impl OrigemCusto {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OrigemCusto::Cadastro => "cadastro",
+            OrigemCusto::Entrada => "entrada",
+            OrigemCusto::Ajuste => "ajuste",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Queryable, Identifiable, Insertable)]
+pub struct ProdutoCusto {
+    pub id: String,
+    pub id_produto: String,
+    pub custo_anterior: Option<BigDecimal>,
+    pub custo_novo: BigDecimal,
+    pub origem: String,
+    pub id_movimento: Option<String>,
+    pub motivo: Option<String>,
+    pub created_by: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct NewProdutoCusto {
+    pub id: String,
+    pub id_produto: String,
+    pub custo_anterior: Option<BigDecimal>,
+    pub custo_novo: BigDecimal,
+    pub origem: String,
+    pub id_movimento: Option<String>,
+    pub motivo: Option<String>,
+    pub created_by: String,
+}
+
+/// Weighted moving average of the cost after an entrada
+///
+/// When there is no stock on hand, or no cost to average with, the entrada
+/// sets the cost by itself.
+pub fn calcular(
+    saldo: i64,
+    custo_atual: Option<&BigDecimal>,
+    quantidade: i32,
+    custo_entrada: &BigDecimal,
+) -> BigDecimal {
+    match custo_atual {
+        Some(custo_atual) if saldo > 0 => {
+            let valor_estoque = BigDecimal::from(saldo) * custo_atual;
+            let valor_entrada = BigDecimal::from(quantidade) * custo_entrada;
+            let quantidade_total = BigDecimal::from(saldo + i64::from(quantidade));
+            ((valor_estoque + valor_entrada) / quantidade_total).round(CASAS_CUSTO)
+        }
+        _ => custo_entrada.round(CASAS_CUSTO),
+    }
+}
+
+/// Get the cost history of a produto, oldest first
+pub fn find_by_produto(pool: &PoolType, produto_id: Uuid) -> Result<ProdutoCustosResponse, ApiError> {
+    use crate::schema::produto_custos::dsl::{created_at, id_produto, produto_custos};
+
+    let mut conn = pool.get()?;
+    custo_medio(&mut conn, &produto_id.to_string())?;
+    let historico = produto_custos
+        .filter(id_produto.eq(produto_id.to_string()))
+        .order(created_at)
+        .load::<ProdutoCusto>(&mut conn)?;
+
+    Ok(historico.into())
+}
+
+/// Set the custo_medio of a produto by hand, recording why
+pub fn ajustar(
+    pool: &PoolType,
+    produto_id: Uuid,
+    custo: &BigDecimal,
+    motivo: &str,
+    user_id: &str,
+) -> Result<ProdutoCustoResponse, ApiError> {
+    validar_custo("custo", custo)?;
+
+    let mut conn = pool.get()?;
+    conn.transaction::<_, ApiError, _>(|conn| {
+        let custo_anterior = custo_medio(conn, &produto_id.to_string())?;
+        let ajuste: ProdutoCusto = NewProdutoCusto::new(
+            &produto_id.to_string(),
+            custo_anterior,
+            custo.round(CASAS_CUSTO),
+            OrigemCusto::Ajuste,
+            user_id,
+        )
+        .motivo(motivo)
+        .into();
+        aplicar(conn, std::slice::from_ref(&ajuste))?;
+        Ok(ajuste.into())
+    })
+}
+
+/// Refuse a cost below zero, naming the field it came in
+pub fn validar_custo(campo: &str, custo: &BigDecimal) -> Result<(), ApiError> {
+    if custo < &BigDecimal::zero() {
+        return Err(ApiError::ValidationError(vec![format!(
+            "{}: o custo não pode ser negativo, recebido {}",
+            campo, custo
+        )]));
+    }
+    Ok(())
+}
+
+/// The cost changes the costed entradas among some ledger rows cause
+///
+/// Must run before the rows are inserted, as the averages start from the
+/// balance they don't take part in yet. The other rows of the same produto
+/// still move that balance, in the order they come.
+pub fn custos_das_entradas(
+    conn: &mut PgConnection,
+    movimentos: &[EstoqueMovimento],
+) -> Result<Vec<ProdutoCusto>, ApiError> {
+    let mut correntes: BTreeMap<&str, (i64, Option<BigDecimal>)> = BTreeMap::new();
+    for movimento in movimentos.iter().filter(|movimento| custo_da_entrada(movimento).is_some()) {
+        let produto_id = movimento.id_produto.as_str();
+        if !correntes.contains_key(produto_id) {
+            let corrente = (saldo_total(conn, produto_id)?, custo_medio(conn, produto_id)?);
+            correntes.insert(produto_id, corrente);
+        }
+    }
+
+    let mut custos = Vec::new();
+    for movimento in movimentos {
+        if let Some((saldo, custo)) = correntes.get_mut(movimento.id_produto.as_str()) {
+            if let Some(custo_entrada) = custo_da_entrada(movimento) {
+                let custo_novo = calcular(*saldo, custo.as_ref(), movimento.quantidade, custo_entrada);
+                let entrada: ProdutoCusto = NewProdutoCusto::new(
+                    &movimento.id_produto,
+                    custo.clone(),
+                    custo_novo.clone(),
+                    OrigemCusto::Entrada,
+                    &movimento.created_by,
+                )
+                .movimento(&movimento.id)
+                .into();
+                custos.push(entrada);
+                *custo = Some(custo_novo);
+            }
+            *saldo += i64::from(movimento.quantidade);
+        }
+    }
+    Ok(custos)
+}
+
+/// Record cost changes and move the custo_medio of their produtos along
+///
+/// Takes a connection so it can join the transaction that caused them.
+pub fn aplicar(conn: &mut PgConnection, custos: &[ProdutoCusto]) -> Result<(), ApiError> {
+    use crate::schema::produtos::dsl::{custo_medio, id, produtos};
+
+    for custo in custos {
+        create(conn, custo)?;
+        diesel::update(produtos)
+            .filter(id.eq(&custo.id_produto))
+            .set(custo_medio.eq(Some(&custo.custo_novo)))
+            .execute(conn)?;
+    }
+    Ok(())
+}
+
+/// Record a cost change of a produto
+pub fn create(conn: &mut PgConnection, new_custo: &ProdutoCusto) -> Result<(), ApiError> {
+    use crate::schema::produto_custos::dsl::produto_custos;
+
+    diesel::insert_into(produto_custos).values(new_custo).execute(conn)?;
+    Ok(())
+}
+
+/// The custo_medio of a produto or error out if there is no such produto
+fn custo_medio(conn: &mut PgConnection, produto_id: &str) -> Result<Option<BigDecimal>, ApiError> {
+    use crate::schema::produtos::dsl::{custo_medio, id, produtos};
+
+    let not_found = format!("Produto {} not found", produto_id);
+    produtos
+        .filter(id.eq(produto_id))
+        .select(custo_medio)
+        .first::<Option<BigDecimal>>(conn)
+        .map_err(|_| ApiError::NotFound(not_found))
+}
+
+/// Unit cost of a row, if it is an entrada that carries one
+fn custo_da_entrada(movimento: &EstoqueMovimento) -> Option<&BigDecimal> {
+    if movimento.tipo == TipoMovimento::Entrada.as_str() && movimento.quantidade > 0 {
+        movimento.custo_unitario.as_ref()
+    } else {
+        None
+    }
+}
+
+impl NewProdutoCusto {
+    pub fn new(
+        id_produto: &str,
+        custo_anterior: Option<BigDecimal>,
+        custo_novo: BigDecimal,
+        origem: OrigemCusto,
+        created_by: &str,
+    ) -> Self {
+        NewProdutoCusto {
+            id: Uuid::new_v4().to_string(),
+            id_produto: id_produto.to_string(),
+            custo_anterior,
+            custo_novo,
+            origem: origem.as_str().to_string(),
+            id_movimento: None,
+            motivo: None,
+            created_by: created_by.to_string(),
+        }
+    }
+
+    pub fn movimento(self, id_movimento: &str) -> Self {
+        NewProdutoCusto {
+            id_movimento: Some(id_movimento.to_string()),
+            ..self
+        }
+    }
+
+    pub fn motivo(self, motivo: &str) -> Self {
+        NewProdutoCusto {
+            motivo: Some(motivo.trim().to_string()),
+            ..self
+        }
+    }
+}
+
+impl From<NewProdutoCusto> for ProdutoCusto {
+    fn from(custo: NewProdutoCusto) -> Self {
+        ProdutoCusto {
+            id: custo.id,
+            id_produto: custo.id_produto,
+            custo_anterior: custo.custo_anterior,
+            custo_novo: custo.custo_novo,
+            origem: custo.origem,
+            id_movimento: custo.id_movimento,
+            motivo: custo.motivo,
+            created_by: custo.created_by,
+            created_at: Utc::now().naive_utc(),
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::models::estoque::tests::{delete_movimentos, movimento};
+    use crate::models::estoque::{movimentar, ConfigEstoque};
+    use crate::models::produto::tests::create_produto;
+    use crate::models::produto::{delete as delete_produto, find};
+    use crate::tests::helpers::tests::get_pool;
+    use std::str::FromStr;
+
+    fn decimal(valor: &str) -> BigDecimal {
+        BigDecimal::from_str(valor).unwrap()
+    }
+
+    fn entrada(produto_id: Uuid, quantidade: i32, custo: &str) {
+        let mut entrada = movimento(produto_id, TipoMovimento::Entrada, quantidade);
+        entrada.custo_unitario = Some(decimal(custo));
+        movimentar(&get_pool(), &entrada, &ConfigEstoque::default()).unwrap();
+    }
+
+    #[test]
+    fn it_averages_the_cost_by_quantity() {
+        let custo = calcular(10, Some(&decimal("2.00")), 30, &decimal("4.00"));
+        assert_eq!(custo, decimal("3.5"));
+        let custo = calcular(3, Some(&decimal("1.00")), 1, &decimal("2.00"));
+        assert_eq!(custo, decimal("1.25"));
+        let custo = calcular(2, Some(&decimal("1.00")), 1, &decimal("0.00"));
+        assert_eq!(custo, decimal("0.6667"));
+    }
+
+    #[test]
+    fn it_takes_the_cost_of_the_entrada_without_stock_on_hand() {
+        assert_eq!(calcular(0, Some(&decimal("9.00")), 5, &decimal("2.50")), decimal("2.50"));
+        assert_eq!(calcular(-4, Some(&decimal("9.00")), 5, &decimal("2.50")), decimal("2.50"));
+        assert_eq!(calcular(10, None, 5, &decimal("2.50")), decimal("2.50"));
+    }
+
+    #[test]
+    fn it_recalculates_the_cost_from_the_entradas() {
+        let produto = create_produto().unwrap();
+        entrada(produto.id, 10, "2.00");
+        entrada(produto.id, 30, "4.00");
+        let saida = movimento(produto.id, TipoMovimento::Saida, 20);
+        movimentar(&get_pool(), &saida, &ConfigEstoque::default()).unwrap();
+        entrada(produto.id, 20, "1.50");
+
+        assert_eq!(find(&get_pool(), produto.id).unwrap().custo_medio, Some(decimal("2.5")));
+        let historico = find_by_produto(&get_pool(), produto.id).unwrap().0;
+        let origens: Vec<&str> = historico.iter().map(|custo| custo.origem.as_str()).collect();
+        assert_eq!(origens, vec!["cadastro", "entrada", "entrada", "entrada"]);
+        assert_eq!(historico[3].custo_anterior, Some(decimal("3.5")));
+        assert!(historico[3].id_movimento.is_some());
+
+        delete_movimentos(produto.id);
+        delete_produto(&get_pool(), produto.id).unwrap();
+    }
+
+    #[test]
+    fn it_adjusts_the_cost_with_a_motivo() {
+        let produto = create_produto().unwrap();
+        let ajuste = ajustar(&get_pool(), produto.id, &decimal("7.25"), " inventário ", "test").unwrap();
+        assert_eq!(ajuste.custo_anterior, Some(decimal("1.00")));
+        assert_eq!(ajuste.motivo, Some("inventário".to_string()));
+        assert_eq!(find(&get_pool(), produto.id).unwrap().custo_medio, Some(decimal("7.25")));
+
+        let negativo = ajustar(&get_pool(), produto.id, &decimal("-1"), "erro", "test");
+        let expected_error = ApiError::ValidationError(vec![
+            "custo: o custo não pode ser negativo, recebido -1".to_string(),
+        ]);
+        assert_eq!(negativo.unwrap_err(), expected_error);
+        delete_produto(&get_pool(), produto.id).unwrap();
+    }
+
+    #[test]
+    fn it_doesnt_find_the_costs_of_an_unknown_produto() {
+        let produto_id = Uuid::new_v4();
+        let expected_error = ApiError::NotFound(format!("Produto {} not found", produto_id));
+        assert_eq!(find_by_produto(&get_pool(), produto_id).unwrap_err(), expected_error);
+    }
+}
@@ -0,0 +1,251 @@
+use crate::database::PoolType;
+use crate::errors::ApiError;
+use crate::handlers::titulo_receber::{TituloReceberResponse, TitulosReceberResponse};
+use crate::models::cliente::find_cliente;
+use crate::schema::titulos_receber;
+use bigdecimal::{BigDecimal, Zero};
+use chrono::{NaiveDateTime, Utc};
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use uuid::Uuid;
+
+/// A receivable of a cliente, paid off in one or more baixas
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Queryable, Identifiable, Insertable)]
+#[table_name = "titulos_receber"]
+pub struct TituloReceber {
+    pub id: String,
+    pub id_cliente: String,
+    pub id_pedido: Option<String>,
+    /// Number of the duplicata or boleto, as printed
+    pub documento: Option<String>,
+    pub valor: BigDecimal,
+    pub valor_pago: BigDecimal,
+    pub vencimento: NaiveDateTime,
+    pub pago_at: Option<NaiveDateTime>,
+    pub created_by: String,
+    pub created_at: NaiveDateTime,
+    pub updated_by: String,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct NewTituloReceber {
+    pub id: String,
+    pub id_cliente: String,
+    pub id_pedido: Option<String>,
+    pub documento: Option<String>,
+    pub valor: BigDecimal,
+    pub vencimento: NaiveDateTime,
+    pub created_by: String,
+}
+
+impl TituloReceber {
+    /// What is still to be paid
+    pub fn saldo(&self) -> BigDecimal {
+        &self.valor - &self.valor_pago
+    }
+
+    /// Unpaid after the day it was due
+    pub fn vencido(&self, hoje: NaiveDateTime) -> bool {
+        self.pago_at.is_none() && self.vencimento.date() < hoje.date()
+    }
+}
+
+/// Get the títulos of a cliente, the ones due first first
+pub fn find_by_cliente(pool: &PoolType, cliente_id: Uuid) -> Result<TitulosReceberResponse, ApiError> {
+    use crate::schema::titulos_receber::dsl::{id_cliente, titulos_receber, vencimento};
+
+    let mut conn = pool.get()?;
+    find_cliente(&mut conn, &cliente_id.to_string())?;
+    let titulos = titulos_receber
+        .filter(id_cliente.eq(cliente_id.to_string()))
+        .order(vencimento)
+        .load::<TituloReceber>(&mut conn)?;
+
+    Ok(titulos.into())
+}
+
+/// The títulos of a cliente that are not paid off yet
+pub fn find_abertos(conn: &mut PgConnection, cliente_id: &str) -> Result<Vec<TituloReceber>, ApiError> {
+    use crate::schema::titulos_receber::dsl::{id_cliente, pago_at, titulos_receber, vencimento};
+
+    let titulos = titulos_receber
+        .filter(id_cliente.eq(cliente_id))
+        .filter(pago_at.is_null())
+        .order(vencimento)
+        .load::<TituloReceber>(conn)?;
+    Ok(titulos)
+}
+
+/// Find a título or error out
+pub fn find(pool: &PoolType, titulo_id: Uuid) -> Result<TituloReceberResponse, ApiError> {
+    let mut conn = pool.get()?;
+    Ok(find_titulo(&mut conn, &titulo_id.to_string())?.into())
+}
+
+fn find_titulo(conn: &mut PgConnection, titulo_id: &str) -> Result<TituloReceber, ApiError> {
+    use crate::schema::titulos_receber::dsl::{id, titulos_receber};
+
+    let not_found = format!("Titulo {} not found", titulo_id);
+    titulos_receber
+        .filter(id.eq(titulo_id))
+        .for_update()
+        .first::<TituloReceber>(conn)
+        .map_err(|_| ApiError::NotFound(not_found))
+}
+
+/// Create a título for a cliente, optionally tied to one of the cliente's pedidos
+pub fn create(pool: &PoolType, new_titulo: &TituloReceber) -> Result<TituloReceberResponse, ApiError> {
+    use crate::schema::pedidos::dsl::{id, id_cliente, pedidos};
+    use crate::schema::titulos_receber::dsl::titulos_receber;
+
+    let mut conn = pool.get()?;
+    find_cliente(&mut conn, &new_titulo.id_cliente)?;
+    if let Some(pedido_id) = &new_titulo.id_pedido {
+        let do_cliente: bool = diesel::select(diesel::dsl::exists(
+            pedidos.filter(id.eq(pedido_id)).filter(id_cliente.eq(&new_titulo.id_cliente)),
+        ))
+        .get_result(&mut conn)?;
+        if !do_cliente {
+            return Err(ApiError::ValidationError(vec![format!(
+                "id_pedido: o pedido {} não é do cliente",
+                pedido_id
+            )]));
+        }
+    }
+    if new_titulo.valor <= BigDecimal::zero() {
+        return Err(ApiError::ValidationError(vec!["valor: o valor deve ser maior que zero".to_string()]));
+    }
+    diesel::insert_into(titulos_receber).values(new_titulo).execute(&mut conn)?;
+    Ok(new_titulo.clone().into())
+}
+
+/// Register a payment of a título, which is paid off once nothing is left
+pub fn baixar(
+    pool: &PoolType,
+    titulo_id: Uuid,
+    valor: &BigDecimal,
+    user_id: &str,
+) -> Result<TituloReceberResponse, ApiError> {
+    use crate::schema::titulos_receber::dsl::{id, pago_at, titulos_receber, updated_at, updated_by, valor_pago};
+
+    let mut conn = pool.get()?;
+    conn.transaction::<_, ApiError, _>(|conn| {
+        let titulo = find_titulo(conn, &titulo_id.to_string())?;
+        if titulo.pago_at.is_some() {
+            return Err(ApiError::Conflict(format!("Titulo {} is already paid", titulo_id)));
+        }
+        let saldo = titulo.saldo();
+        if *valor <= BigDecimal::zero() {
+            return Err(ApiError::ValidationError(vec!["valor: o pagamento deve ser maior que zero".to_string()]));
+        }
+        if *valor > saldo {
+            return Err(ApiError::ValidationError(vec![format!(
+                "valor: o pagamento de {} passa do saldo de {} do título",
+                valor, saldo
+            )]));
+        }
+
+        let agora = Utc::now().naive_utc();
+        let pago = &titulo.valor_pago + valor;
+        let quitado = if pago == titulo.valor { Some(agora) } else { None };
+        diesel::update(titulos_receber)
+            .filter(id.eq(&titulo.id))
+            .set((
+                valor_pago.eq(&pago),
+                pago_at.eq(quitado),
+                updated_by.eq(user_id),
+                updated_at.eq(agora),
+            ))
+            .execute(conn)?;
+        Ok(find_titulo(conn, &titulo.id)?.into())
+    })
+}
+
+impl From<NewTituloReceber> for TituloReceber {
+    fn from(titulo: NewTituloReceber) -> Self {
+        TituloReceber {
+            id: titulo.id,
+            id_cliente: titulo.id_cliente,
+            id_pedido: titulo.id_pedido,
+            documento: titulo.documento,
+            valor: titulo.valor,
+            valor_pago: BigDecimal::zero(),
+            vencimento: titulo.vencimento,
+            pago_at: None,
+            created_by: titulo.created_by.clone(),
+            created_at: Utc::now().naive_utc(),
+            updated_by: titulo.created_by,
+            updated_at: Utc::now().naive_utc(),
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::models::cliente::delete as delete_cliente;
+    use crate::models::cliente::tests::create_cliente;
+    use crate::tests::helpers::tests::get_pool;
+    use chrono::Duration;
+    use std::str::FromStr;
+
+    pub fn new_titulo(cliente_id: Uuid, valor: &str, vencimento: NaiveDateTime) -> TituloReceber {
+        NewTituloReceber {
+            id: Uuid::new_v4().to_string(),
+            id_cliente: cliente_id.to_string(),
+            id_pedido: None,
+            documento: Some("123/1".to_string()),
+            valor: BigDecimal::from_str(valor).unwrap(),
+            vencimento,
+            created_by: cliente_id.to_string(),
+        }
+        .into()
+    }
+
+    /// Títulos block their cliente from being deleted
+    pub fn delete_titulos(cliente_id: Uuid) {
+        use crate::schema::titulos_receber::dsl::{id_cliente, titulos_receber};
+
+        let mut conn = get_pool().get().unwrap();
+        diesel::delete(titulos_receber)
+            .filter(id_cliente.eq(cliente_id.to_string()))
+            .execute(&mut conn)
+            .unwrap();
+    }
+
+    #[test]
+    fn it_tells_when_a_titulo_is_overdue() {
+        let hoje = Utc::now().naive_utc();
+        let titulo = new_titulo(Uuid::new_v4(), "100.00", hoje - Duration::days(1));
+        assert!(titulo.vencido(hoje));
+        assert!(!new_titulo(Uuid::new_v4(), "100.00", hoje).vencido(hoje));
+        let pago = TituloReceber { pago_at: Some(hoje), ..titulo };
+        assert!(!pago.vencido(hoje));
+    }
+
+    #[test]
+    fn it_pays_off_a_titulo_in_baixas() {
+        let cliente = create_cliente().unwrap();
+        let titulo = new_titulo(cliente.id, "100.00", Utc::now().naive_utc());
+        let created = create(&get_pool(), &titulo).unwrap();
+
+        let parcial = baixar(&get_pool(), created.id, &BigDecimal::from(40), &titulo.created_by).unwrap();
+        assert_eq!(parcial.saldo, BigDecimal::from(60));
+        assert!(parcial.pago_at.is_none());
+        let excesso = baixar(&get_pool(), created.id, &BigDecimal::from(61), &titulo.created_by);
+        let expected_error = ApiError::ValidationError(vec![
+            "valor: o pagamento de 61 passa do saldo de 60.00 do título".to_string(),
+        ]);
+        assert_eq!(excesso.unwrap_err(), expected_error);
+
+        let quitado = baixar(&get_pool(), created.id, &BigDecimal::from(60), &titulo.created_by).unwrap();
+        assert!(quitado.pago_at.is_some());
+        let mut conn = get_pool().get().unwrap();
+        assert!(find_abertos(&mut conn, &cliente.id.to_string()).unwrap().is_empty());
+        assert_eq!(find_by_cliente(&get_pool(), cliente.id).unwrap().0.len(), 1);
+
+        delete_titulos(cliente.id);
+        delete_cliente(&get_pool(), cliente.id).unwrap();
+    }
+}
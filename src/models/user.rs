@@ -177,7 +177,7 @@ pub mod tests {
             id: user_id.to_string(),
             nome: "Model".to_string(),
             sobrenome: "Test".to_string(),
-            cpf: Some("12345678901".to_string()),
+            cpf: Some("52998224725".to_string()),
             rg: Some("123456789".to_string()),
             data_nascimento: Some(NaiveDateTime::new(NaiveDate::from_ymd(1990, 1, 1),NaiveTime::from_hms_milli(0, 0, 0, 0))),
             sexo: Some("M".to_string()),
@@ -198,7 +198,7 @@ pub mod tests {
             id: user_id.to_string(),
             nome: "Model".to_string(),
             sobrenome: "Test".to_string(),
-            cpf: Some("12345678901".to_string()),
+            cpf: Some("52998224725".to_string()),
             rg: Some("123456789".to_string()),
             data_nascimento: Some(NaiveDateTime::new(NaiveDate::from_ymd(1990, 1, 1),NaiveTime::from_hms_milli(0, 0, 0, 0))),
             sexo: Some("M".to_string()),
@@ -252,7 +252,7 @@ pub mod tests {
             id: created.id.to_string(),
             nome: "ModelUpdate".to_string(),
             sobrenome: "TestUpdate".to_string(),
-            cpf: Some("12345678901".to_string()),
+            cpf: Some("52998224725".to_string()),
             rg: Some("123456789".to_string()),
             data_nascimento: Some(NaiveDateTime::new(NaiveDate::from_ymd(1990, 1, 1),NaiveTime::from_hms_milli(0, 0, 0, 0))),
             sexo: Some("M".to_string()),
@@ -275,7 +275,7 @@ pub mod tests {
             id: user_id.to_string(),
             nome: "ModelUpdateFailure".to_string(),
             sobrenome: "TestUpdateFailure".to_string(),
-            cpf: Some("12345678901".to_string()),
+            cpf: Some("52998224725".to_string()),
             rg: Some("123456789".to_string()),
             data_nascimento: Some(NaiveDateTime::new(NaiveDate::from_ymd(1990, 1, 1),NaiveTime::from_hms_milli(0, 0, 0, 0))),
             sexo: Some("M".to_string()),
@@ -0,0 +1,147 @@
+use crate::database::PoolType;
+use crate::errors::ApiError;
+use crate::schema::user_permissoes;
+use chrono::{NaiveDateTime, Utc};
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use uuid::Uuid;
+
+/// Lets a user quote lines below the produto's preco_minimo
+pub const VENDER_ABAIXO_PRECO_MINIMO: &str = "vender_abaixo_preco_minimo";
+
+/// Lets a user sell past the credit of a cliente and change credit limits
+pub const LIBERAR_CREDITO: &str = "liberar_credito";
+
+/// Lets a user load reference tables, like the CEP base, through the API
+pub const IMPORTAR_TABELAS: &str = "importar_tabelas";
+
+/// Every permission that can be granted to a user
+pub const PERMISSOES: &[&str] = &[VENDER_ABAIXO_PRECO_MINIMO, LIBERAR_CREDITO, IMPORTAR_TABELAS];
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Queryable, Identifiable, Insertable)]
+#[table_name = "user_permissoes"]
+pub struct UserPermissao {
+    pub id: String,
+    pub id_user: String,
+    pub permissao: String,
+    pub created_by: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct NewUserPermissao {
+    pub id: String,
+    pub id_user: String,
+    pub permissao: String,
+    pub created_by: String,
+}
+
+/// Whether a user holds a permission
+pub fn has_permissao(conn: &mut PgConnection, user_id: &str, nome: &str) -> Result<bool, ApiError> {
+    use crate::schema::user_permissoes::dsl::{id_user, permissao, user_permissoes};
+
+    let has = diesel::select(diesel::dsl::exists(
+        user_permissoes
+            .filter(id_user.eq(user_id))
+            .filter(permissao.eq(nome)),
+    ))
+    .get_result(conn)?;
+    Ok(has)
+}
+
+/// Get the names of the permissions a user holds
+pub fn find_by_user(pool: &PoolType, user_id: Uuid) -> Result<Vec<String>, ApiError> {
+    use crate::schema::user_permissoes::dsl::{id_user, permissao, user_permissoes};
+
+    let mut conn = pool.get()?;
+    let permissoes = user_permissoes
+        .filter(id_user.eq(user_id.to_string()))
+        .select(permissao)
+        .order(permissao)
+        .load::<String>(&mut conn)?;
+    Ok(permissoes)
+}
+
+/// Grant a permission to a user, doing nothing if it is already held
+pub fn grant(pool: &PoolType, new_permissao: &UserPermissao) -> Result<(), ApiError> {
+    use crate::schema::user_permissoes::dsl::user_permissoes;
+
+    if !PERMISSOES.contains(&new_permissao.permissao.as_str()) {
+        return Err(ApiError::ValidationError(vec![format!(
+            "Permissão {} desconhecida",
+            new_permissao.permissao
+        )]));
+    }
+
+    let mut conn = pool.get()?;
+    diesel::insert_into(user_permissoes)
+        .values(new_permissao)
+        .on_conflict_do_nothing()
+        .execute(&mut conn)?;
+    Ok(())
+}
+
+/// Revoke a permission from a user
+pub fn revoke(pool: &PoolType, user_id: Uuid, nome: &str) -> Result<(), ApiError> {
+    use crate::schema::user_permissoes::dsl::{id_user, permissao, user_permissoes};
+
+    let mut conn = pool.get()?;
+    diesel::delete(user_permissoes)
+        .filter(id_user.eq(user_id.to_string()))
+        .filter(permissao.eq(nome))
+        .execute(&mut conn)?;
+    Ok(())
+}
+
+impl From<NewUserPermissao> for UserPermissao {
+    fn from(permissao: NewUserPermissao) -> Self {
+        UserPermissao {
+            id: permissao.id,
+            id_user: permissao.id_user,
+            permissao: permissao.permissao,
+            created_by: permissao.created_by,
+            created_at: Utc::now().naive_utc(),
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::tests::helpers::tests::get_pool;
+
+    pub fn grant_permissao(user_id: &str, permissao: &str) {
+        let new_permissao: UserPermissao = NewUserPermissao {
+            id: Uuid::new_v4().to_string(),
+            id_user: user_id.to_string(),
+            permissao: permissao.to_string(),
+            created_by: user_id.to_string(),
+        }
+        .into();
+        grant(&get_pool(), &new_permissao).unwrap();
+    }
+
+    #[test]
+    fn it_grants_and_revokes_a_permissao() {
+        let user_id = "1802d2f8-1a18-43c1-9c58-1c3f7100c842";
+        let mut conn = get_pool().get().unwrap();
+        grant_permissao(user_id, VENDER_ABAIXO_PRECO_MINIMO);
+        assert!(has_permissao(&mut conn, user_id, VENDER_ABAIXO_PRECO_MINIMO).unwrap());
+        revoke(&get_pool(), Uuid::parse_str(user_id).unwrap(), VENDER_ABAIXO_PRECO_MINIMO).unwrap();
+        assert!(!has_permissao(&mut conn, user_id, VENDER_ABAIXO_PRECO_MINIMO).unwrap());
+    }
+
+    #[test]
+    fn it_doesnt_grant_an_unknown_permissao() {
+        let new_permissao: UserPermissao = NewUserPermissao {
+            id: Uuid::new_v4().to_string(),
+            id_user: "00000000-0000-0000-0000-000000000000".to_string(),
+            permissao: "voar".to_string(),
+            created_by: "00000000-0000-0000-0000-000000000000".to_string(),
+        }
+        .into();
+        let granted = grant(&get_pool(), &new_permissao);
+        let expected_error = ApiError::ValidationError(vec!["Permissão voar desconhecida".to_string()]);
+        assert_eq!(granted.unwrap_err(), expected_error);
+    }
+}
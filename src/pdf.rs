@@ -0,0 +1,345 @@
+//! Drawing helpers shared by the PDF documents the API serves
+//!
+//! Positions are in millimeters from the top left corner of the page, the
+//! way the layouts are measured, and converted to the PDF bottom left origin
+//! here. The DejaVu fonts are compiled into the binary so rendering needs
+//! nothing from the machine it runs on.
+
+use crate::errors::ApiError;
+use bigdecimal::BigDecimal;
+use printpdf::image::{self as imagem, DynamicImage, GenericImageView};
+use printpdf::{
+    Color, Greyscale, Image, IndirectFontRef, Line, Mm, PdfDocument, PdfDocumentReference, PdfLayerReference, Point,
+};
+use std::io::BufWriter;
+
+const FONTE: &[u8] = include_bytes!("../assets/fonts/DejaVuSansCondensed.ttf");
+const FONTE_NEGRITO: &[u8] = include_bytes!("../assets/fonts/DejaVuSansCondensed-Bold.ttf");
+
+/// Millimeters in a typographic point
+const MM_POR_PONTO: f64 = 25.4 / 72.0;
+/// Resolution printpdf places images at when no scale is given
+const DPI_IMAGEM: f64 = 300.0;
+
+pub const A4_LARGURA: f64 = 210.0;
+pub const A4_ALTURA: f64 = 297.0;
+
+pub struct Documento {
+    documento: PdfDocumentReference,
+    camada: PdfLayerReference,
+    regular: IndirectFontRef,
+    negrito: IndirectFontRef,
+    metricas_regular: rusttype::Font<'static>,
+    metricas_negrito: rusttype::Font<'static>,
+    largura: f64,
+    altura: f64,
+}
+
+impl Documento {
+    pub fn new(titulo: &str, largura: f64, altura: f64) -> Result<Self, ApiError> {
+        let (documento, pagina, camada) = PdfDocument::new(titulo, Mm(largura), Mm(altura), "conteudo");
+        let regular = documento.add_external_font(FONTE).map_err(erro_pdf)?;
+        let negrito = documento.add_external_font(FONTE_NEGRITO).map_err(erro_pdf)?;
+        let camada = documento.get_page(pagina).get_layer(camada);
+        Ok(Documento {
+            documento,
+            camada,
+            regular,
+            negrito,
+            metricas_regular: rusttype::Font::from_bytes(FONTE).map_err(erro_pdf)?,
+            metricas_negrito: rusttype::Font::from_bytes(FONTE_NEGRITO).map_err(erro_pdf)?,
+            largura,
+            altura,
+        })
+    }
+
+    pub fn nova_pagina(&mut self) {
+        let (pagina, camada) = self.documento.add_page(Mm(self.largura), Mm(self.altura), "conteudo");
+        self.camada = self.documento.get_page(pagina).get_layer(camada);
+    }
+
+    /// Write text with its baseline at `y`
+    pub fn texto(&self, texto: &str, tamanho: f64, x: f64, y: f64, negrito: bool) {
+        let fonte = if negrito { &self.negrito } else { &self.regular };
+        self.camada.use_text(texto, tamanho, Mm(x), Mm(self.altura - y), fonte);
+    }
+
+    /// Write text ending at `x`, for numeric columns
+    pub fn texto_direita(&self, texto: &str, tamanho: f64, x: f64, y: f64, negrito: bool) {
+        let largura = self.largura_texto(texto, tamanho, negrito);
+        self.texto(texto, tamanho, x - largura, y, negrito);
+    }
+
+    /// Write text centered on `x`
+    pub fn texto_centro(&self, texto: &str, tamanho: f64, x: f64, y: f64, negrito: bool) {
+        let largura = self.largura_texto(texto, tamanho, negrito);
+        self.texto(texto, tamanho, x - largura / 2.0, y, negrito);
+    }
+
+    pub fn largura_texto(&self, texto: &str, tamanho: f64, negrito: bool) -> f64 {
+        let metricas = if negrito { &self.metricas_negrito } else { &self.metricas_regular };
+        let escala = rusttype::Scale::uniform(tamanho as f32);
+        let pontos: f32 = texto
+            .chars()
+            .map(|c| metricas.glyph(c).scaled(escala).h_metrics().advance_width)
+            .sum();
+        f64::from(pontos) * MM_POR_PONTO
+    }
+
+    /// Shorten text with an ellipsis until it fits in `largura`
+    pub fn ajustar(&self, texto: &str, tamanho: f64, largura: f64, negrito: bool) -> String {
+        if self.largura_texto(texto, tamanho, negrito) <= largura {
+            return texto.to_string();
+        }
+        let mut caracteres: Vec<char> = texto.chars().collect();
+        while !caracteres.is_empty() {
+            caracteres.pop();
+            let cortado = format!("{}…", caracteres.iter().collect::<String>().trim_end());
+            if self.largura_texto(&cortado, tamanho, negrito) <= largura {
+                return cortado;
+            }
+        }
+        String::new()
+    }
+
+    /// Break text into lines that fit in `largura`, at spaces
+    pub fn quebrar(&self, texto: &str, tamanho: f64, largura: f64, negrito: bool) -> Vec<String> {
+        let mut linhas = Vec::new();
+        let mut atual = String::new();
+        for palavra in texto.split_whitespace() {
+            let candidata = if atual.is_empty() { palavra.to_string() } else { format!("{} {}", atual, palavra) };
+            if !atual.is_empty() && self.largura_texto(&candidata, tamanho, negrito) > largura {
+                linhas.push(atual);
+                atual = palavra.to_string();
+            } else {
+                atual = candidata;
+            }
+        }
+        if !atual.is_empty() {
+            linhas.push(atual);
+        }
+        linhas
+    }
+
+    /// Outline of a box whose top left corner is at `x`, `y`
+    pub fn caixa(&self, x: f64, y: f64, largura: f64, altura: f64) {
+        self.retangulo(x, y, largura, altura, false);
+    }
+
+    /// Filled black box, used for the bars of barcodes
+    pub fn barra(&self, x: f64, y: f64, largura: f64, altura: f64) {
+        self.retangulo(x, y, largura, altura, true);
+    }
+
+    pub fn linha(&self, x1: f64, y1: f64, x2: f64, y2: f64) {
+        self.camada.set_outline_thickness(0.5);
+        self.camada.add_shape(Line {
+            points: vec![(self.ponto(x1, y1), false), (self.ponto(x2, y2), false)],
+            has_stroke: true,
+            ..Line::default()
+        });
+    }
+
+    /// Draw a Code-128 barcode of `dados` filling `largura`
+    pub fn codigo_de_barras(&self, dados: &str, x: f64, y: f64, largura: f64, altura: f64) {
+        let modulos = code128(dados);
+        let total: u32 = modulos.iter().map(|modulo| u32::from(*modulo)).sum();
+        let modulo = largura / f64::from(total);
+        let mut posicao = x;
+        for (indice, largura_modulo) in modulos.iter().enumerate() {
+            let largura_barra = modulo * f64::from(*largura_modulo);
+            // patterns alternate bar and space, starting with a bar
+            if indice % 2 == 0 {
+                self.barra(posicao, y, largura_barra, altura);
+            }
+            posicao += largura_barra;
+        }
+    }
+
+    /// Draw a PNG or JPEG inside the box at `x`, `y`, keeping its proportions
+    pub fn imagem(&self, bytes: &[u8], x: f64, y: f64, largura: f64, altura: f64) -> Result<(), ApiError> {
+        let decodificada = imagem::load_from_memory(bytes).map_err(erro_pdf)?;
+        // PDF images carry no alpha channel, transparent logos go on white
+        let decodificada = DynamicImage::ImageRgb8(decodificada.to_rgb8());
+        let (pixels_x, pixels_y) = decodificada.dimensions();
+        let natural_x = f64::from(pixels_x) * 25.4 / DPI_IMAGEM;
+        let natural_y = f64::from(pixels_y) * 25.4 / DPI_IMAGEM;
+        let escala = (largura / natural_x).min(altura / natural_y);
+        Image::from_dynamic_image(&decodificada).add_to_layer(
+            self.camada.clone(),
+            Some(Mm(x)),
+            Some(Mm(self.altura - y - natural_y * escala)),
+            None,
+            Some(escala),
+            Some(escala),
+            Some(DPI_IMAGEM),
+        );
+        Ok(())
+    }
+
+    pub fn salvar(self) -> Result<Vec<u8>, ApiError> {
+        let mut bytes = Vec::new();
+        self.documento
+            .save(&mut BufWriter::new(&mut bytes))
+            .map_err(erro_pdf)?;
+        Ok(bytes)
+    }
+
+    fn retangulo(&self, x: f64, y: f64, largura: f64, altura: f64, cheio: bool) {
+        self.camada.set_outline_thickness(0.5);
+        self.camada.set_fill_color(Color::Greyscale(Greyscale::new(0.0, None)));
+        self.camada.add_shape(Line {
+            points: vec![
+                (self.ponto(x, y), false),
+                (self.ponto(x + largura, y), false),
+                (self.ponto(x + largura, y + altura), false),
+                (self.ponto(x, y + altura), false),
+            ],
+            is_closed: true,
+            has_fill: cheio,
+            has_stroke: !cheio,
+            is_clipping_path: false,
+        });
+    }
+
+    fn ponto(&self, x: f64, y: f64) -> Point {
+        Point::new(Mm(x), Mm(self.altura - y))
+    }
+}
+
+fn erro_pdf<E: std::fmt::Debug>(error: E) -> ApiError {
+    ApiError::InternalServerError(format!("Erro ao gerar o PDF: {:?}", error))
+}
+
+/// A value the Brazilian way, `1.234,56`
+pub fn numero(valor: &BigDecimal, casas: i64) -> String {
+    let texto = valor.round(casas).with_scale(casas).to_string();
+    let (sinal, texto) = match texto.strip_prefix('-') {
+        Some(resto) => ("-", resto.to_string()),
+        None => ("", texto),
+    };
+    let (inteira, decimais) = match texto.split_once('.') {
+        Some((inteira, decimais)) => (inteira.to_string(), Some(decimais.to_string())),
+        None => (texto, None),
+    };
+    let digitos: Vec<char> = inteira.chars().collect();
+    let mut agrupada = String::new();
+    for (posicao, digito) in digitos.iter().enumerate() {
+        if posicao > 0 && (digitos.len() - posicao) % 3 == 0 {
+            agrupada.push('.');
+        }
+        agrupada.push(*digito);
+    }
+    match decimais {
+        Some(decimais) => format!("{}{},{}", sinal, agrupada, decimais),
+        None => format!("{}{}", sinal, agrupada),
+    }
+}
+
+/// A CNPJ with its punctuation, anything that is not 14 digits is kept as is
+pub fn formatar_cnpj(cnpj: &str) -> String {
+    if cnpj.len() != 14 {
+        return cnpj.to_string();
+    }
+    format!("{}.{}.{}/{}-{}", &cnpj[0..2], &cnpj[2..5], &cnpj[5..8], &cnpj[8..12], &cnpj[12..14])
+}
+
+pub fn formatar_cep(cep: &str) -> String {
+    if cep.len() != 8 {
+        return cep.to_string();
+    }
+    format!("{}-{}", &cep[0..5], &cep[5..8])
+}
+
+/// Bar and space widths of the 107 Code-128 symbols, the last one is stop
+const CODE128: [&str; 107] = [
+    "212222", "222122", "222221", "121223", "121322", "131222", "122213", "122312", "132212", "221213",
+    "221312", "231212", "112232", "122132", "122231", "113222", "123122", "123221", "223211", "221132",
+    "221231", "213212", "223112", "312131", "311222", "321122", "321221", "312212", "322112", "322211",
+    "212123", "212321", "232121", "111323", "131123", "131321", "112313", "132113", "132311", "211313",
+    "231113", "231311", "112133", "112331", "132131", "113123", "113321", "133121", "313121", "211331",
+    "231131", "213113", "213311", "213131", "311123", "311321", "331121", "312113", "312311", "332111",
+    "314111", "221411", "431111", "111224", "111422", "121124", "121421", "141122", "141221", "112214",
+    "112412", "122114", "122411", "142112", "142211", "241211", "221114", "413111", "241112", "134111",
+    "111242", "121142", "121241", "114212", "124112", "124211", "411212", "421112", "421211", "212141",
+    "214121", "412121", "111143", "111341", "131141", "114113", "114311", "411113", "411311", "113141",
+    "114131", "311141", "411131", "211412", "211214", "211232", "2331112",
+];
+
+const START_B: usize = 104;
+const START_C: usize = 105;
+const STOP: usize = 106;
+
+/// Module widths of `dados` in Code-128, quiet zones not included
+///
+/// An even run of digits, like a chave de acesso, uses code set C with two
+/// digits per symbol; anything else uses code set B.
+pub fn code128(dados: &str) -> Vec<u8> {
+    let numerico = !dados.is_empty() && dados.len() % 2 == 0 && dados.chars().all(|c| c.is_ascii_digit());
+    let mut simbolos = Vec::new();
+    if numerico {
+        simbolos.push(START_C);
+        for par in dados.as_bytes().chunks(2) {
+            simbolos.push(usize::from((par[0] - b'0') * 10 + (par[1] - b'0')));
+        }
+    } else {
+        simbolos.push(START_B);
+        for c in dados.chars() {
+            let codigo = c as usize;
+            simbolos.push(if (32..128).contains(&codigo) { codigo - 32 } else { '?' as usize - 32 });
+        }
+    }
+    let verificador = simbolos
+        .iter()
+        .enumerate()
+        .map(|(posicao, simbolo)| posicao.max(1) * simbolo)
+        .sum::<usize>()
+        % 103;
+    simbolos.push(verificador);
+    simbolos.push(STOP);
+
+    simbolos
+        .iter()
+        .flat_map(|simbolo| CODE128[*simbolo].bytes().map(|largura| largura - b'0'))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn it_formats_numbers_the_brazilian_way() {
+        assert_eq!(numero(&BigDecimal::from_str("1234567.891").unwrap(), 2), "1.234.567,89");
+        assert_eq!(numero(&BigDecimal::from_str("-0.5").unwrap(), 2), "-0,50");
+        assert_eq!(numero(&BigDecimal::from(3), 0), "3");
+    }
+
+    #[test]
+    fn it_encodes_code128() {
+        assert!(CODE128[..STOP].iter().all(|padrao| padrao.bytes().map(|b| b - b'0').sum::<u8>() == 11));
+        // start C, 12, 34, check digit (105 + 12 + 2 * 34) % 103 = 82, stop
+        let esperado: Vec<u8> = ["211232", "112232", "131123", "121241", "2331112"]
+            .iter()
+            .flat_map(|padrao| padrao.bytes().map(|b| b - b'0'))
+            .collect();
+        assert_eq!(code128("1234"), esperado);
+        assert_eq!(code128(&"1".repeat(44)).len(), 6 * 24 + 7);
+    }
+
+    #[test]
+    fn it_renders_a_pdf() {
+        let documento = Documento::new("teste", A4_LARGURA, A4_ALTURA).unwrap();
+        documento.texto("Orçamento nº 1", 12.0, 10.0, 10.0, true);
+        documento.codigo_de_barras("1234", 10.0, 20.0, 40.0, 10.0);
+        let mut logo = Vec::new();
+        DynamicImage::new_rgba8(40, 20)
+            .write_to(&mut logo, imagem::ImageOutputFormat::Png)
+            .unwrap();
+        documento.imagem(&logo, 10.0, 40.0, 30.0, 30.0).unwrap();
+        assert!(documento.imagem(b"logo", 10.0, 40.0, 30.0, 30.0).is_err());
+        let pdf = documento.salvar().unwrap();
+        assert!(pdf.starts_with(b"%PDF"));
+    }
+}
@@ -6,8 +6,42 @@ use crate::handlers::{
     health::get_health,
     user::{create_user, delete_user, get_user, get_users, update_user},
     cliente::{create_cliente, delete_cliente, get_cliente, get_clientes, update_cliente},
-    produto::{create_produto, delete_produto, get_produto, get_produtos, update_produto},
-    orcamento::{create_orcamento, delete_orcamento, get_orcamento},
+    cliente_duplicado::{get_cliente_duplicados, mesclar_cliente},
+    cliente_endereco::{
+        create_cliente_endereco, delete_cliente_endereco, get_cliente_endereco, get_cliente_enderecos,
+        update_cliente_endereco,
+    },
+    cep::{get_cep, importar_ceps, LIMITE_IMPORTACAO},
+    credito::{get_cliente_credito, update_cliente_credito},
+    titulo_receber::{baixar_titulo, create_cliente_titulo, get_cliente_titulos, get_titulo},
+    lgpd::{anonimizar_cliente, get_cliente_lgpd_export},
+    fornecedor::{
+        add_fornecedor_produto, create_fornecedor, delete_fornecedor, get_fornecedor, get_fornecedor_produtos,
+        get_fornecedores, get_produto_fornecedores, remove_fornecedor_produto, update_fornecedor,
+    },
+    produto::{
+        ajustar_produto_custo, create_produto, delete_produto, get_produto, get_produto_custos, get_produtos,
+        update_produto,
+    },
+    orcamento::{
+        aprovar_orcamento, converter_orcamento, create_orcamento, delete_orcamento, enviar_orcamento, expirar_orcamento,
+        get_cliente_orcamentos, get_orcamento, get_orcamento_historico, get_orcamento_pdf, recusar_orcamento,
+        update_orcamento,
+    },
+    orcamento_produto::{add_orcamento_item, remove_orcamento_item, update_orcamento_item},
+    pedido::{get_pedido, get_pedidos},
+    pedido_compra::{
+        aprovar_pedido_compra, cancelar_pedido_compra, create_pedido_compra, delete_pedido_compra,
+        get_pedido_compra, get_pedidos_compra, get_pendencias_compra, receber_pedido_compra,
+    },
+    fiscal::get_ncms,
+    deposito::{create_deposito, get_deposito, get_depositos},
+    estoque::{create_estoque_movimento, get_estoque, get_produto_estoque, get_produto_movimentos},
+    nota_entrada::{get_nota_entrada, importar_nota_entrada},
+    nota_fiscal::{
+        create_orcamento_nfe, create_pedido_nfe, get_nota_fiscal, get_nota_fiscal_danfe, get_nota_fiscal_xml,
+        transmitir_nota_fiscal,
+    },
 };
 use crate::middleware::auth::Auth as AuthMiddleware;
 use actix_files::Files;
@@ -42,15 +76,44 @@ pub fn routes(cfg: &mut web::ServiceConfig) {
                 // CLIENTE routes
                 .service(
                     web::scope("/cliente")
+                        .route("/duplicados", web::get().to(get_cliente_duplicados))
+                        .route("/{id}/mesclar", web::post().to(mesclar_cliente))
+                        .route("/{id}/lgpd/export", web::get().to(get_cliente_lgpd_export))
+                        .route("/{id}/lgpd/anonimizar", web::post().to(anonimizar_cliente))
+                        .route("/{id}/enderecos/{endereco_id}", web::get().to(get_cliente_endereco))
+                        .route("/{id}/enderecos/{endereco_id}", web::put().to(update_cliente_endereco))
+                        .route("/{id}/enderecos/{endereco_id}", web::delete().to(delete_cliente_endereco))
+                        .route("/{id}/enderecos", web::get().to(get_cliente_enderecos))
+                        .route("/{id}/enderecos", web::post().to(create_cliente_endereco))
+                        .route("/{id}/orcamentos", web::get().to(get_cliente_orcamentos))
+                        .route("/{id}/credito", web::get().to(get_cliente_credito))
+                        .route("/{id}/credito", web::put().to(update_cliente_credito))
+                        .route("/{id}/titulos", web::get().to(get_cliente_titulos))
+                        .route("/{id}/titulos", web::post().to(create_cliente_titulo))
                         .route("/{id}", web::get().to(get_cliente))
                         .route("/{id}", web::put().to(update_cliente))
                         .route("/{id}", web::delete().to(delete_cliente))
                         .route("", web::get().to(get_clientes))
                         .route("", web::post().to(create_cliente)),
                 )
+                // FORNECEDOR routes
+                .service(
+                    web::scope("/fornecedor")
+                        .route("/{id}/produtos/{codigo}", web::delete().to(remove_fornecedor_produto))
+                        .route("/{id}/produtos", web::get().to(get_fornecedor_produtos))
+                        .route("/{id}/produtos", web::post().to(add_fornecedor_produto))
+                        .route("/{id}", web::get().to(get_fornecedor))
+                        .route("/{id}", web::put().to(update_fornecedor))
+                        .route("/{id}", web::delete().to(delete_fornecedor))
+                        .route("", web::get().to(get_fornecedores))
+                        .route("", web::post().to(create_fornecedor)),
+                )
                 // PRODUTO routes
                 .service(
                     web::scope("/produto")
+                        .route("/{id}/custos", web::get().to(get_produto_custos))
+                        .route("/{id}/custo", web::post().to(ajustar_produto_custo))
+                        .route("/{id}/fornecedores", web::get().to(get_produto_fornecedores))
                         .route("/{id}", web::get().to(get_produto))
                         .route("/{id}", web::put().to(update_produto))
                         .route("/{id}", web::delete().to(delete_produto))
@@ -60,9 +123,89 @@ pub fn routes(cfg: &mut web::ServiceConfig) {
                 // ORCAMENTO routes
                 .service(
                     web::scope("/orcamento")
+                        .route("/{id}/itens/{item_id}", web::put().to(update_orcamento_item))
+                        .route("/{id}/itens/{item_id}", web::delete().to(remove_orcamento_item))
+                        .route("/{id}/itens", web::post().to(add_orcamento_item))
+                        .route("/{id}/historico", web::get().to(get_orcamento_historico))
+                        .route("/{id}/enviar", web::post().to(enviar_orcamento))
+                        .route("/{id}/aprovar", web::post().to(aprovar_orcamento))
+                        .route("/{id}/recusar", web::post().to(recusar_orcamento))
+                        .route("/{id}/expirar", web::post().to(expirar_orcamento))
+                        .route("/{id}/converter", web::post().to(converter_orcamento))
+                        .route("/{id}/nfe", web::post().to(create_orcamento_nfe))
+                        .route("/{id}/pdf", web::get().to(get_orcamento_pdf))
                         .route("/{id}", web::get().to(get_orcamento))
+                        .route("/{id}", web::put().to(update_orcamento))
                         .route("/{id}", web::delete().to(delete_orcamento))
                         .route("", web::post().to(create_orcamento)),
+                )
+                // PEDIDO routes
+                .service(
+                    web::scope("/pedido")
+                        .route("/{id}/nfe", web::post().to(create_pedido_nfe))
+                        .route("/{id}", web::get().to(get_pedido))
+                        .route("", web::get().to(get_pedidos)),
+                )
+                // FISCAL routes
+                .service(
+                    web::scope("/fiscal")
+                        .route("/ncm", web::get().to(get_ncms)),
+                )
+                // CEP routes
+                .service(
+                    web::scope("/cep")
+                        .route(
+                            "/importar",
+                            web::post().data(web::PayloadConfig::new(LIMITE_IMPORTACAO)).to(importar_ceps),
+                        )
+                        .route("/{cep}", web::get().to(get_cep)),
+                )
+                // DEPOSITO routes
+                .service(
+                    web::scope("/deposito")
+                        .route("/{id}", web::get().to(get_deposito))
+                        .route("", web::get().to(get_depositos))
+                        .route("", web::post().to(create_deposito)),
+                )
+                // ESTOQUE routes
+                .service(
+                    web::scope("/estoque")
+                        .route("/movimentos", web::post().to(create_estoque_movimento))
+                        .route("/produto/{id}/movimentos", web::get().to(get_produto_movimentos))
+                        .route("/produto/{id}", web::get().to(get_produto_estoque))
+                        .route("", web::get().to(get_estoque)),
+                )
+                // PEDIDO DE COMPRA routes
+                .service(
+                    web::scope("/pedido-compra")
+                        .route("/pendentes", web::get().to(get_pendencias_compra))
+                        .route("/{id}/aprovar", web::post().to(aprovar_pedido_compra))
+                        .route("/{id}/receber", web::post().to(receber_pedido_compra))
+                        .route("/{id}/cancelar", web::post().to(cancelar_pedido_compra))
+                        .route("/{id}", web::get().to(get_pedido_compra))
+                        .route("/{id}", web::delete().to(delete_pedido_compra))
+                        .route("", web::get().to(get_pedidos_compra))
+                        .route("", web::post().to(create_pedido_compra)),
+                )
+                // TITULO A RECEBER routes
+                .service(
+                    web::scope("/titulo")
+                        .route("/{id}/baixar", web::post().to(baixar_titulo))
+                        .route("/{id}", web::get().to(get_titulo)),
+                )
+                // NOTA DE ENTRADA routes
+                .service(
+                    web::scope("/nota-entrada")
+                        .route("/{id}", web::get().to(get_nota_entrada))
+                        .route("", web::post().to(importar_nota_entrada)),
+                )
+                // NF-e routes
+                .service(
+                    web::scope("/nfe")
+                        .route("/{id}/xml", web::get().to(get_nota_fiscal_xml))
+                        .route("/{id}/danfe", web::get().to(get_nota_fiscal_danfe))
+                        .route("/{id}/transmitir", web::post().to(transmitir_nota_fiscal))
+                        .route("/{id}", web::get().to(get_nota_fiscal)),
                 ),
 
         )
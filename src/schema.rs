@@ -1,13 +1,71 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    ceps (cep) {
+        #[max_length = 8]
+        cep -> Varchar,
+        #[max_length = 150]
+        logradouro -> Nullable<Varchar>,
+        #[max_length = 100]
+        bairro -> Nullable<Varchar>,
+        #[max_length = 7]
+        codigo_municipio -> Varchar,
+    }
+}
+
+diesel::table! {
+    cests (codigo, ncm) {
+        #[max_length = 7]
+        codigo -> Varchar,
+        #[max_length = 8]
+        ncm -> Varchar,
+        #[max_length = 255]
+        descricao -> Varchar,
+    }
+}
+
+diesel::table! {
+    cliente_enderecos (id) {
+        #[max_length = 36]
+        id -> Varchar,
+        #[max_length = 36]
+        id_cliente -> Varchar,
+        #[max_length = 10]
+        tipo -> Varchar,
+        #[max_length = 150]
+        logradouro -> Varchar,
+        #[max_length = 20]
+        numero -> Varchar,
+        #[max_length = 100]
+        complemento -> Nullable<Varchar>,
+        #[max_length = 100]
+        bairro -> Varchar,
+        #[max_length = 7]
+        codigo_municipio -> Varchar,
+        #[max_length = 100]
+        municipio -> Varchar,
+        #[max_length = 2]
+        uf -> Bpchar,
+        #[max_length = 8]
+        cep -> Varchar,
+        #[max_length = 36]
+        created_by -> Varchar,
+        created_at -> Timestamp,
+        #[max_length = 36]
+        updated_by -> Varchar,
+        updated_at -> Timestamp,
+        principal -> Bool,
+    }
+}
+
 diesel::table! {
     clientes (id) {
         #[max_length = 36]
         id -> Varchar,
         #[max_length = 100]
-        nome -> Varchar,
+        nome -> Nullable<Varchar>,
         #[max_length = 100]
-        sobrenome -> Varchar,
+        sobrenome -> Nullable<Varchar>,
         #[max_length = 11]
         cpf -> Nullable<Bpchar>,
         #[max_length = 20]
@@ -25,6 +83,201 @@ diesel::table! {
         #[max_length = 36]
         updated_by -> Varchar,
         updated_at -> Timestamp,
+        #[max_length = 10]
+        tipo_pessoa -> Varchar,
+        #[max_length = 14]
+        cnpj -> Nullable<Varchar>,
+        #[max_length = 150]
+        razao_social -> Nullable<Varchar>,
+        #[max_length = 150]
+        nome_fantasia -> Nullable<Varchar>,
+        #[max_length = 20]
+        inscricao_estadual -> Nullable<Varchar>,
+        limite_credito -> Nullable<Numeric>,
+    }
+}
+
+diesel::table! {
+    clientes_documentos_repetidos (id_cliente) {
+        #[max_length = 36]
+        id_cliente -> Varchar,
+        #[max_length = 11]
+        cpf -> Nullable<Bpchar>,
+        #[max_length = 14]
+        cnpj -> Nullable<Varchar>,
+    }
+}
+
+diesel::table! {
+    depositos (id) {
+        #[max_length = 36]
+        id -> Varchar,
+        #[max_length = 100]
+        nome -> Varchar,
+        #[max_length = 36]
+        created_by -> Varchar,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    estoque_movimentos (id) {
+        #[max_length = 36]
+        id -> Varchar,
+        #[max_length = 36]
+        id_produto -> Varchar,
+        #[max_length = 36]
+        id_deposito -> Varchar,
+        #[max_length = 20]
+        tipo -> Varchar,
+        quantidade -> Int4,
+        #[max_length = 36]
+        id_documento -> Nullable<Varchar>,
+        #[max_length = 255]
+        observacao -> Nullable<Varchar>,
+        #[max_length = 36]
+        created_by -> Varchar,
+        created_at -> Timestamp,
+        custo_unitario -> Nullable<Numeric>,
+    }
+}
+
+diesel::table! {
+    fornecedor_produtos (id_fornecedor, codigo) {
+        #[max_length = 36]
+        id_fornecedor -> Varchar,
+        #[max_length = 60]
+        codigo -> Varchar,
+        #[max_length = 36]
+        id_produto -> Varchar,
+        #[max_length = 36]
+        created_by -> Varchar,
+        created_at -> Timestamp,
+        preferencial -> Bool,
+    }
+}
+
+diesel::table! {
+    fornecedores (id) {
+        #[max_length = 36]
+        id -> Varchar,
+        #[max_length = 14]
+        cnpj -> Varchar,
+        #[max_length = 150]
+        razao_social -> Varchar,
+        #[max_length = 150]
+        nome_fantasia -> Nullable<Varchar>,
+        #[max_length = 20]
+        inscricao_estadual -> Nullable<Varchar>,
+        #[max_length = 36]
+        created_by -> Varchar,
+        created_at -> Timestamp,
+        #[max_length = 36]
+        updated_by -> Varchar,
+        updated_at -> Timestamp,
+        #[max_length = 100]
+        contato -> Nullable<Varchar>,
+        #[max_length = 100]
+        email -> Nullable<Varchar>,
+        #[max_length = 22]
+        telefone -> Nullable<Varchar>,
+    }
+}
+
+diesel::table! {
+    lgpd_registros (id) {
+        #[max_length = 36]
+        id -> Varchar,
+        #[max_length = 36]
+        id_cliente -> Varchar,
+        #[max_length = 20]
+        operacao -> Varchar,
+        #[max_length = 36]
+        created_by -> Varchar,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    municipios (codigo) {
+        #[max_length = 7]
+        codigo -> Varchar,
+        #[max_length = 100]
+        nome -> Varchar,
+        #[max_length = 2]
+        uf -> Bpchar,
+    }
+}
+
+diesel::table! {
+    ncms (codigo) {
+        #[max_length = 8]
+        codigo -> Varchar,
+        #[max_length = 255]
+        descricao -> Varchar,
+        aliquota_ipi -> Nullable<Numeric>,
+    }
+}
+
+diesel::table! {
+    notas_entrada (id) {
+        #[max_length = 36]
+        id -> Varchar,
+        #[max_length = 44]
+        chave -> Varchar,
+        serie -> Int4,
+        numero -> Int4,
+        #[max_length = 36]
+        id_fornecedor -> Varchar,
+        emissao -> Timestamp,
+        valor_total -> Numeric,
+        xml -> Text,
+        #[max_length = 36]
+        created_by -> Varchar,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    notas_fiscais (id) {
+        #[max_length = 36]
+        id -> Varchar,
+        #[max_length = 36]
+        id_pedido -> Nullable<Varchar>,
+        #[max_length = 36]
+        id_orcamento -> Nullable<Varchar>,
+        serie -> Int4,
+        numero -> Int4,
+        #[max_length = 44]
+        chave -> Varchar,
+        ambiente -> Int2,
+        #[max_length = 20]
+        status -> Varchar,
+        xml -> Text,
+        #[max_length = 15]
+        protocolo -> Nullable<Varchar>,
+        #[max_length = 255]
+        motivo -> Nullable<Varchar>,
+        #[max_length = 36]
+        created_by -> Varchar,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    orcamento_historicos (id) {
+        #[max_length = 36]
+        id -> Varchar,
+        #[max_length = 36]
+        id_orcamento -> Varchar,
+        #[max_length = 20]
+        status_anterior -> Nullable<Varchar>,
+        #[max_length = 20]
+        status_novo -> Varchar,
+        #[max_length = 36]
+        created_by -> Varchar,
+        created_at -> Timestamp,
     }
 }
 
@@ -40,6 +293,30 @@ diesel::table! {
         #[max_length = 36]
         created_by -> Varchar,
         created_at -> Timestamp,
+        #[max_length = 10]
+        tipo_desconto -> Nullable<Varchar>,
+        valor_desconto -> Numeric,
+        preco_unitario -> Numeric,
+        #[max_length = 100]
+        descricao -> Varchar,
+        #[max_length = 2]
+        unidade_medida -> Nullable<Bpchar>,
+        #[max_length = 8]
+        ncm -> Nullable<Varchar>,
+        #[max_length = 12]
+        cest -> Nullable<Varchar>,
+        #[max_length = 12]
+        icms -> Nullable<Varchar>,
+        #[max_length = 4]
+        pis -> Nullable<Varchar>,
+        #[max_length = 4]
+        cofins -> Nullable<Varchar>,
+        porcentagem_ipi -> Nullable<Numeric>,
+        aliquota_icms -> Nullable<Numeric>,
+        aliquota_pis -> Nullable<Numeric>,
+        aliquota_cofins -> Nullable<Numeric>,
+        #[max_length = 12]
+        cod_ipi -> Nullable<Varchar>,
     }
 }
 
@@ -50,6 +327,137 @@ diesel::table! {
         #[max_length = 36]
         created_by -> Varchar,
         created_at -> Timestamp,
+        #[max_length = 20]
+        status -> Varchar,
+        validade -> Nullable<Timestamp>,
+        #[max_length = 36]
+        id_cliente -> Varchar,
+        #[max_length = 36]
+        updated_by -> Varchar,
+        updated_at -> Timestamp,
+        #[max_length = 36]
+        id_pedido -> Nullable<Varchar>,
+        #[max_length = 10]
+        tipo_desconto -> Nullable<Varchar>,
+        valor_desconto -> Numeric,
+        observacoes -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    pedido_compra_itens (id) {
+        #[max_length = 36]
+        id -> Varchar,
+        #[max_length = 36]
+        id_pedido_compra -> Varchar,
+        #[max_length = 36]
+        id_produto -> Varchar,
+        quantidade -> Int4,
+        quantidade_recebida -> Int4,
+        custo_unitario -> Numeric,
+        #[max_length = 36]
+        created_by -> Varchar,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    pedido_produtos (id) {
+        #[max_length = 36]
+        id -> Varchar,
+        #[max_length = 36]
+        id_produto -> Varchar,
+        #[max_length = 36]
+        id_pedido -> Varchar,
+        quantidade -> Int4,
+        preco_unitario -> Numeric,
+        #[max_length = 36]
+        created_by -> Varchar,
+        created_at -> Timestamp,
+        desconto -> Numeric,
+        #[max_length = 100]
+        descricao -> Varchar,
+        #[max_length = 2]
+        unidade_medida -> Nullable<Bpchar>,
+        #[max_length = 8]
+        ncm -> Nullable<Varchar>,
+        #[max_length = 12]
+        cest -> Nullable<Varchar>,
+        #[max_length = 12]
+        icms -> Nullable<Varchar>,
+        #[max_length = 4]
+        pis -> Nullable<Varchar>,
+        #[max_length = 4]
+        cofins -> Nullable<Varchar>,
+        porcentagem_ipi -> Nullable<Numeric>,
+        aliquota_icms -> Nullable<Numeric>,
+        aliquota_pis -> Nullable<Numeric>,
+        aliquota_cofins -> Nullable<Numeric>,
+        #[max_length = 12]
+        cod_ipi -> Nullable<Varchar>,
+    }
+}
+
+diesel::table! {
+    pedidos (id) {
+        #[max_length = 36]
+        id -> Varchar,
+        #[max_length = 36]
+        id_cliente -> Varchar,
+        #[max_length = 36]
+        id_orcamento -> Nullable<Varchar>,
+        #[max_length = 20]
+        status -> Varchar,
+        #[max_length = 36]
+        created_by -> Varchar,
+        created_at -> Timestamp,
+        #[max_length = 36]
+        updated_by -> Varchar,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    pedidos_compra (id) {
+        #[max_length = 36]
+        id -> Varchar,
+        #[max_length = 36]
+        id_fornecedor -> Varchar,
+        #[max_length = 36]
+        id_deposito -> Varchar,
+        #[max_length = 20]
+        status -> Varchar,
+        previsao_entrega -> Nullable<Timestamp>,
+        observacoes -> Nullable<Text>,
+        #[max_length = 36]
+        aprovado_by -> Nullable<Varchar>,
+        aprovado_at -> Nullable<Timestamp>,
+        #[max_length = 36]
+        created_by -> Varchar,
+        created_at -> Timestamp,
+        #[max_length = 36]
+        updated_by -> Varchar,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    produto_custos (id) {
+        #[max_length = 36]
+        id -> Varchar,
+        #[max_length = 36]
+        id_produto -> Varchar,
+        custo_anterior -> Nullable<Numeric>,
+        custo_novo -> Numeric,
+        #[max_length = 20]
+        origem -> Varchar,
+        #[max_length = 36]
+        id_movimento -> Nullable<Varchar>,
+        #[max_length = 255]
+        motivo -> Nullable<Varchar>,
+        #[max_length = 36]
+        created_by -> Varchar,
+        created_at -> Timestamp,
     }
 }
 
@@ -85,6 +493,47 @@ diesel::table! {
         #[max_length = 36]
         updated_by -> Varchar,
         updated_at -> Timestamp,
+        aliquota_icms -> Nullable<Numeric>,
+        aliquota_pis -> Nullable<Numeric>,
+        aliquota_cofins -> Nullable<Numeric>,
+        rascunho -> Bool,
+    }
+}
+
+diesel::table! {
+    titulos_receber (id) {
+        #[max_length = 36]
+        id -> Varchar,
+        #[max_length = 36]
+        id_cliente -> Varchar,
+        #[max_length = 36]
+        id_pedido -> Nullable<Varchar>,
+        #[max_length = 60]
+        documento -> Nullable<Varchar>,
+        valor -> Numeric,
+        valor_pago -> Numeric,
+        vencimento -> Timestamp,
+        pago_at -> Nullable<Timestamp>,
+        #[max_length = 36]
+        created_by -> Varchar,
+        created_at -> Timestamp,
+        #[max_length = 36]
+        updated_by -> Varchar,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    user_permissoes (id) {
+        #[max_length = 36]
+        id -> Varchar,
+        #[max_length = 36]
+        id_user -> Varchar,
+        #[max_length = 50]
+        permissao -> Varchar,
+        #[max_length = 36]
+        created_by -> Varchar,
+        created_at -> Timestamp,
     }
 }
 
@@ -120,13 +569,59 @@ diesel::table! {
     }
 }
 
+diesel::joinable!(ceps -> municipios (codigo_municipio));
+diesel::joinable!(cliente_enderecos -> clientes (id_cliente));
+diesel::joinable!(cliente_enderecos -> municipios (codigo_municipio));
+diesel::joinable!(clientes_documentos_repetidos -> clientes (id_cliente));
+diesel::joinable!(estoque_movimentos -> depositos (id_deposito));
+diesel::joinable!(estoque_movimentos -> produtos (id_produto));
+diesel::joinable!(fornecedor_produtos -> fornecedores (id_fornecedor));
+diesel::joinable!(fornecedor_produtos -> produtos (id_produto));
+diesel::joinable!(notas_entrada -> fornecedores (id_fornecedor));
+diesel::joinable!(notas_fiscais -> orcamentos (id_orcamento));
+diesel::joinable!(notas_fiscais -> pedidos (id_pedido));
+diesel::joinable!(orcamento_historicos -> orcamentos (id_orcamento));
 diesel::joinable!(orcamento_produtos -> orcamentos (id_orcamento));
 diesel::joinable!(orcamento_produtos -> produtos (id_produto));
+diesel::joinable!(orcamentos -> clientes (id_cliente));
+diesel::joinable!(pedido_compra_itens -> pedidos_compra (id_pedido_compra));
+diesel::joinable!(pedido_compra_itens -> produtos (id_produto));
+diesel::joinable!(pedido_produtos -> pedidos (id_pedido));
+diesel::joinable!(pedido_produtos -> produtos (id_produto));
+diesel::joinable!(pedidos -> clientes (id_cliente));
+diesel::joinable!(pedidos_compra -> depositos (id_deposito));
+diesel::joinable!(pedidos_compra -> fornecedores (id_fornecedor));
+diesel::joinable!(produto_custos -> estoque_movimentos (id_movimento));
+diesel::joinable!(produto_custos -> produtos (id_produto));
+diesel::joinable!(titulos_receber -> clientes (id_cliente));
+diesel::joinable!(titulos_receber -> pedidos (id_pedido));
+diesel::joinable!(user_permissoes -> users (id_user));
 
 diesel::allow_tables_to_appear_in_same_query!(
+    ceps,
+    cests,
+    cliente_enderecos,
     clientes,
+    clientes_documentos_repetidos,
+    depositos,
+    estoque_movimentos,
+    fornecedor_produtos,
+    fornecedores,
+    lgpd_registros,
+    municipios,
+    ncms,
+    notas_entrada,
+    notas_fiscais,
+    orcamento_historicos,
     orcamento_produtos,
     orcamentos,
+    pedido_compra_itens,
+    pedido_produtos,
+    pedidos,
+    pedidos_compra,
+    produto_custos,
     produtos,
+    titulos_receber,
+    user_permissoes,
     users,
 );
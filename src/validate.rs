@@ -1,8 +1,11 @@
 //! Validation-related functions to work with the validator crate.
 
 use crate::errors::ApiError;
+use crate::models::codigo_fiscal;
+use crate::models::documento::{Cnpj, Cpf};
 use actix_web::web::Json;
-use validator::{Validate, ValidationErrors};
+use std::str::FromStr;
+use validator::{Validate, ValidationError, ValidationErrors};
 
 /// Validate a struct and collect and return the errors
 pub fn validate<T>(params: &Json<T>) -> Result<(), ApiError>
@@ -15,6 +18,45 @@ where
   }
 }
 
+/// Custom validator for CPF fields, accepts the digits with or without the mask
+pub fn validar_cpf(cpf: &str) -> Result<(), ValidationError> {
+  Cpf::from_str(cpf).map(|_| ()).map_err(|erro| {
+    let mut error = ValidationError::new("cpf");
+    error.message = Some(format!("cpf: {}", erro).into());
+    error
+  })
+}
+
+/// The CPF as stored, digits only
+pub fn normalizar_cpf(cpf: &Option<String>) -> Result<Option<String>, ApiError> {
+  codigo_fiscal::normalizar::<Cpf>(cpf)
+    .map_err(|erro| ApiError::ValidationError(vec![format!("cpf: {}", erro)]))
+}
+
+/// A CPF with its punctuation, anything that is not 11 digits is kept as is
+pub fn formatar_cpf(cpf: &str) -> String {
+  if cpf.len() != 11 || !cpf.chars().all(|c| c.is_ascii_digit()) {
+    return cpf.to_string();
+  }
+  format!("{}.{}.{}-{}", &cpf[0..3], &cpf[3..6], &cpf[6..9], &cpf[9..11])
+}
+
+/// Custom validator for CNPJ fields, accepts the digits with or without the mask
+pub fn validar_cnpj(cnpj: &str) -> Result<(), ValidationError> {
+  Cnpj::from_str(cnpj).map(|_| ()).map_err(|erro| {
+    let mut error = ValidationError::new("cnpj");
+    error.message = Some(format!("cnpj: {}", erro).into());
+    error
+  })
+}
+
+/// The CNPJ as stored, digits only
+pub fn normalizar_cnpj(cnpj: &str) -> Result<String, ApiError> {
+  Cnpj::from_str(cnpj)
+    .map(|cnpj| cnpj.to_string())
+    .map_err(|erro| ApiError::ValidationError(vec![format!("cnpj: {}", erro)]))
+}
+
 /// Collect ValidationErrors and return a vector of the messages
 /// Adds a default_error when none is supplied
 fn collect_errors(error: ValidationErrors) -> Vec<String> {
@@ -44,10 +86,13 @@ mod tests {
       message = "nome is required and must be at least 3 characters"
     ))]
     pub nome: String,
+
+    #[validate(custom = "validar_cpf")]
+    pub cpf: Option<String>,
   }
 
   fn get_test_request() -> TestRequest {
-    let json = json!({"nome": "a"});
+    let json = json!({"nome": "a", "cpf": "529.982.247-25"});
     serde_json::from_value::<TestRequest>(json).unwrap()
   }
 
@@ -68,4 +113,37 @@ mod tests {
     ]);
     assert_eq!(response, expected_error);
   }
+
+  #[test]
+  fn it_validates_a_cpf() {
+    let request = TestRequest {
+      nome: "Satoshi".into(),
+      cpf: Some("00000000000".into()),
+    };
+    let response = validate(&Json(request)).unwrap_err();
+    let expected_error =
+      ApiError::ValidationError(vec!["cpf: o CPF 00000000000 é inválido".to_string()]);
+    assert_eq!(response, expected_error);
+  }
+
+  #[test]
+  fn it_normalizes_a_cpf() {
+    let cpf = normalizar_cpf(&Some("529.982.247-25".into())).unwrap();
+    assert_eq!(cpf, Some("52998224725".to_string()));
+    assert_eq!(normalizar_cpf(&None).unwrap(), None);
+  }
+
+  #[test]
+  fn it_formats_a_cpf() {
+    assert_eq!(formatar_cpf("52998224725"), "529.982.247-25");
+    assert_eq!(formatar_cpf("5299822472"), "5299822472");
+  }
+
+  #[test]
+  fn it_normalizes_a_cnpj() {
+    assert_eq!(normalizar_cnpj("11.222.333/0001-81").unwrap(), "11222333000181");
+    let expected_error =
+      ApiError::ValidationError(vec!["cnpj: o CNPJ 11222333000182 é inválido".to_string()]);
+    assert_eq!(normalizar_cnpj("11222333000182").unwrap_err(), expected_error);
+  }
 }
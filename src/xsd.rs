@@ -0,0 +1,433 @@
+//! Checking XML documents against an XML Schema, entirely in process
+//!
+//! Covers the part of XSD 1.0 the SEFAZ schemas are written in: elements
+//! with named or inline types, sequence and choice groups with minOccurs and
+//! maxOccurs, xs:any, required attributes, and simple types restricted by
+//! pattern, enumeration and length. Built-in types are taken as strings, the
+//! SEFAZ schemas restrict every value they care about with a pattern.
+//!
+//! Elements are matched in the target namespace of the schema, which is what
+//! elementFormDefault="qualified" asks for.
+
+use crate::errors::ApiError;
+use regex::Regex;
+use roxmltree::{Document, Node};
+use std::collections::HashMap;
+
+const XS: &str = "http://www.w3.org/2001/XMLSchema";
+
+/// Check `xml` against `schema`, listing every violation found
+pub fn validar(schema: &str, xml: &str) -> Result<(), ApiError> {
+    let schema = Document::parse(schema)
+        .map_err(|error| ApiError::InternalServerError(format!("Schema XSD inválido: {}", error)))?;
+    let documento = Document::parse(xml)
+        .map_err(|error| ApiError::ValidationError(vec![format!("XML inválido: {}", error)]))?;
+    let raiz_schema = schema.root_element();
+    let mut validador = Validador {
+        schema: raiz_schema,
+        alvo: raiz_schema.attribute("targetNamespace").unwrap_or_default(),
+        padroes: HashMap::new(),
+        errors: Vec::new(),
+    };
+
+    let raiz = documento.root_element();
+    let nome = raiz.tag_name().name();
+    match validador.global("element", nome) {
+        Some(declaracao) if raiz.tag_name().namespace().unwrap_or_default() == validador.alvo => {
+            validador.elemento(declaracao, raiz, nome)
+        }
+        _ => validador.errors.push(format!("XSD {}: elemento raiz não previsto", nome)),
+    }
+
+    if validador.errors.is_empty() {
+        Ok(())
+    } else {
+        Err(ApiError::ValidationError(validador.errors))
+    }
+}
+
+/// How a particle of a content model fared against the children of an element
+enum Casamento {
+    /// Matched, the children up to this position are taken
+    Casou(usize),
+    /// Nothing was taken, with the message to give if it was required
+    Ausente(String),
+    /// Matched in part and then broke the model
+    Erro(String),
+}
+
+/// A type a declaration points to
+enum Tipo<'a, 'input> {
+    /// A built-in type of XML Schema
+    Nativo,
+    Simples(Node<'a, 'input>),
+    Complexo(Node<'a, 'input>),
+}
+
+struct Validador<'a, 'input> {
+    schema: Node<'a, 'input>,
+    alvo: &'a str,
+    /// Patterns compiled so far, a nota repeats the same types on every item
+    padroes: HashMap<&'a str, Regex>,
+    errors: Vec<String>,
+}
+
+impl<'a, 'input> Validador<'a, 'input> {
+    /// Top level declaration of the schema with this kind and name
+    fn global(&self, tipo: &str, nome: &str) -> Option<Node<'a, 'input>> {
+        self.schema
+            .children()
+            .find(|filho| filho.has_tag_name((XS, tipo)) && filho.attribute("name") == Some(nome))
+    }
+
+    /// Resolve the QName of a type as written in `no`
+    fn tipo(&self, no: Node<'a, 'input>, nome: &str) -> Result<Tipo<'a, 'input>, String> {
+        let (prefixo, local) = match nome.split_once(':') {
+            Some((prefixo, local)) => (Some(prefixo), local),
+            None => (None, nome),
+        };
+        match no.lookup_namespace_uri(prefixo) {
+            Some(XS) => Ok(Tipo::Nativo),
+            Some(namespace) if namespace == self.alvo => {
+                if let Some(complexo) = self.global("complexType", local) {
+                    Ok(Tipo::Complexo(complexo))
+                } else if let Some(simples) = self.global("simpleType", local) {
+                    Ok(Tipo::Simples(simples))
+                } else {
+                    Err(format!("tipo {} não definido no schema", nome))
+                }
+            }
+            _ => Err(format!("tipo {} fora do schema", nome)),
+        }
+    }
+
+    /// Type of an element or attribute declaration, named or inline
+    fn tipo_declarado(&self, declaracao: Node<'a, 'input>) -> Result<Tipo<'a, 'input>, String> {
+        if let Some(nome) = declaracao.attribute("type") {
+            return self.tipo(declaracao, nome);
+        }
+        Ok(declaracao
+            .children()
+            .find_map(|filho| {
+                if filho.has_tag_name((XS, "complexType")) {
+                    Some(Tipo::Complexo(filho))
+                } else if filho.has_tag_name((XS, "simpleType")) {
+                    Some(Tipo::Simples(filho))
+                } else {
+                    None
+                }
+            })
+            .unwrap_or(Tipo::Nativo))
+    }
+
+    fn elemento(&mut self, declaracao: Node<'a, 'input>, no: Node, caminho: &str) {
+        let tipo = match self.tipo_declarado(declaracao) {
+            Ok(tipo) => tipo,
+            Err(error) => return self.errors.push(format!("XSD {}: {}", caminho, error)),
+        };
+        match tipo {
+            Tipo::Complexo(complexo) => self.complexo(complexo, no, caminho),
+            Tipo::Simples(_) | Tipo::Nativo => {
+                if no.children().any(|filho| filho.is_element()) {
+                    return self.errors.push(format!("XSD {}: elemento com filhos não previsto", caminho));
+                }
+                self.atributos(None, no, caminho);
+                let valor: String = no.children().filter_map(|filho| filho.text()).collect();
+                if let Err(error) = self.simples(&tipo, &valor) {
+                    self.errors.push(format!("XSD {}: {}", caminho, error));
+                }
+            }
+        }
+    }
+
+    fn complexo(&mut self, complexo: Node<'a, 'input>, no: Node, caminho: &str) {
+        self.atributos(Some(complexo), no, caminho);
+        if no.children().any(|filho| filho.is_text() && !filho.text().unwrap_or_default().trim().is_empty()) {
+            self.errors.push(format!("XSD {}: texto não previsto", caminho));
+        }
+
+        let filhos: Vec<Node> = no.children().filter(Node::is_element).collect();
+        let modelo = complexo
+            .children()
+            .find(|filho| filho.has_tag_name((XS, "sequence")) || filho.has_tag_name((XS, "choice")));
+        let posicao = match modelo.map(|modelo| self.particula(modelo, &filhos, 0, caminho)) {
+            None => 0,
+            Some(Casamento::Casou(posicao)) => posicao,
+            Some(Casamento::Ausente(error)) | Some(Casamento::Erro(error)) => return self.errors.push(error),
+        };
+        if let Some(sobra) = filhos.get(posicao) {
+            self.errors.push(format!("XSD {}: {} não previsto", caminho, sobra.tag_name().name()));
+        }
+    }
+
+    /// Required attributes are there, the ones there are declared and valid
+    fn atributos(&mut self, complexo: Option<Node<'a, 'input>>, no: Node, caminho: &str) {
+        let declarados: Vec<Node<'a, 'input>> = complexo
+            .map(|complexo| complexo.children().filter(|filho| filho.has_tag_name((XS, "attribute"))).collect())
+            .unwrap_or_default();
+        for declaracao in &declarados {
+            let nome = declaracao.attribute("name").unwrap_or_default();
+            match no.attribute(nome) {
+                Some(valor) => {
+                    let resultado = self
+                        .tipo_declarado(*declaracao)
+                        .and_then(|tipo| self.simples(&tipo, valor));
+                    if let Err(error) = resultado {
+                        self.errors.push(format!("XSD {}@{}: {}", caminho, nome, error));
+                    }
+                }
+                None if declaracao.attribute("use") == Some("required") => {
+                    self.errors.push(format!("XSD {}: atributo {} ausente", caminho, nome));
+                }
+                None => {}
+            }
+        }
+        for atributo in no.attributes() {
+            if !declarados.iter().any(|declaracao| declaracao.attribute("name") == Some(atributo.name())) {
+                self.errors.push(format!("XSD {}: atributo {} não previsto", caminho, atributo.name()));
+            }
+        }
+    }
+
+    /// Match a particle with its minOccurs and maxOccurs from `posicao` on
+    fn particula(&mut self, particula: Node<'a, 'input>, filhos: &[Node], posicao: usize, caminho: &str) -> Casamento {
+        let minimo: usize = particula.attribute("minOccurs").and_then(|valor| valor.parse().ok()).unwrap_or(1);
+        let maximo: usize = match particula.attribute("maxOccurs") {
+            Some("unbounded") => usize::MAX,
+            valor => valor.and_then(|valor| valor.parse().ok()).unwrap_or(1),
+        };
+        let mut posicao = posicao;
+        let mut ocorrencias = 0;
+        while ocorrencias < maximo {
+            match self.uma_vez(particula, filhos, posicao, caminho, ocorrencias, maximo > 1) {
+                // a group of optional particles can take nothing, stop there
+                Casamento::Casou(proxima) if proxima == posicao => break,
+                Casamento::Casou(proxima) => {
+                    posicao = proxima;
+                    ocorrencias += 1;
+                }
+                Casamento::Ausente(error) if ocorrencias < minimo => {
+                    return if ocorrencias == 0 { Casamento::Ausente(error) } else { Casamento::Erro(error) };
+                }
+                Casamento::Ausente(_) => break,
+                Casamento::Erro(error) => return Casamento::Erro(error),
+            }
+        }
+        Casamento::Casou(posicao)
+    }
+
+    fn uma_vez(
+        &mut self,
+        particula: Node<'a, 'input>,
+        filhos: &[Node],
+        posicao: usize,
+        caminho: &str,
+        ocorrencia: usize,
+        repete: bool,
+    ) -> Casamento {
+        let atual = filhos.get(posicao);
+        let encontrado = atual
+            .map(|filho| format!(", encontrado {}", filho.tag_name().name()))
+            .unwrap_or_default();
+        match particula.tag_name().name() {
+            "element" => {
+                let nome = particula.attribute("name").unwrap_or_default();
+                match atual {
+                    Some(filho) if filho.has_tag_name((self.alvo, nome)) => {
+                        let caminho = if repete {
+                            format!("{}/{}[{}]", caminho, nome, ocorrencia + 1)
+                        } else {
+                            format!("{}/{}", caminho, nome)
+                        };
+                        self.elemento(particula, *filho, &caminho);
+                        Casamento::Casou(posicao + 1)
+                    }
+                    _ => Casamento::Ausente(format!("XSD {}: {} ausente{}", caminho, nome, encontrado)),
+                }
+            }
+            "any" => {
+                let namespace = atual.and_then(|filho| filho.tag_name().namespace()).unwrap_or_default();
+                let aceito = match particula.attribute("namespace").unwrap_or("##any") {
+                    "##any" => atual.is_some(),
+                    "##other" => atual.is_some() && namespace != self.alvo,
+                    lista => atual.is_some() && lista.split_whitespace().any(|aceito| aceito == namespace),
+                };
+                if aceito {
+                    Casamento::Casou(posicao + 1)
+                } else {
+                    Casamento::Ausente(format!("XSD {}: elemento de outro namespace ausente{}", caminho, encontrado))
+                }
+            }
+            "sequence" => {
+                let mut proxima = posicao;
+                for membro in particula.children().filter(Node::is_element) {
+                    match self.particula(membro, filhos, proxima, caminho) {
+                        Casamento::Casou(depois) => proxima = depois,
+                        Casamento::Ausente(error) if proxima == posicao => return Casamento::Ausente(error),
+                        Casamento::Ausente(error) | Casamento::Erro(error) => return Casamento::Erro(error),
+                    }
+                }
+                Casamento::Casou(proxima)
+            }
+            "choice" => {
+                let mut nomes = Vec::new();
+                let mut vazio = false;
+                for membro in particula.children().filter(Node::is_element) {
+                    match self.particula(membro, filhos, posicao, caminho) {
+                        Casamento::Casou(depois) if depois > posicao => return Casamento::Casou(depois),
+                        Casamento::Casou(_) => vazio = true,
+                        Casamento::Ausente(_) => nomes.push(membro.attribute("name").unwrap_or("grupo")),
+                        Casamento::Erro(error) => return Casamento::Erro(error),
+                    }
+                }
+                if vazio {
+                    Casamento::Casou(posicao)
+                } else {
+                    Casamento::Ausente(format!("XSD {}: esperado um de {}{}", caminho, nomes.join(", "), encontrado))
+                }
+            }
+            outro => Casamento::Erro(format!("XSD {}: xs:{} não suportado", caminho, outro)),
+        }
+    }
+
+    /// Check a value against a simple type and the types it restricts
+    fn simples(&mut self, tipo: &Tipo<'a, 'input>, valor: &str) -> Result<(), String> {
+        let simples = match tipo {
+            Tipo::Nativo => return Ok(()),
+            Tipo::Complexo(_) => return Err("tipo complexo num valor simples".to_string()),
+            Tipo::Simples(simples) => *simples,
+        };
+        let restricao = simples
+            .children()
+            .find(|filho| filho.has_tag_name((XS, "restriction")))
+            .ok_or_else(|| "só restrições são suportadas nos tipos simples".to_string())?;
+        let base = match restricao.attribute("base") {
+            Some(base) => self.tipo(restricao, base)?,
+            None => self.tipo_declarado(restricao)?,
+        };
+        self.simples(&base, valor)?;
+
+        let facetas: Vec<(&str, &'a str)> = restricao
+            .children()
+            .filter(|faceta| faceta.is_element())
+            .filter_map(|faceta| faceta.attribute("value").map(|value| (faceta.tag_name().name(), value)))
+            .collect();
+        let tamanho = valor.chars().count();
+        let mut padroes = Vec::new();
+        let mut opcoes = Vec::new();
+        for (faceta, limite) in facetas {
+            let fora = match faceta {
+                "pattern" => {
+                    padroes.push(limite);
+                    false
+                }
+                "enumeration" => {
+                    opcoes.push(limite);
+                    false
+                }
+                "length" => limite.parse() != Ok(tamanho),
+                "minLength" => limite.parse().map(|minimo: usize| tamanho < minimo).unwrap_or(false),
+                "maxLength" => limite.parse().map(|maximo: usize| tamanho > maximo).unwrap_or(false),
+                _ => false,
+            };
+            if fora {
+                return Err(format!("tamanho de {} fora do permitido, {} {}", valor, faceta, limite));
+            }
+        }
+        if !opcoes.is_empty() && !opcoes.contains(&valor) {
+            return Err(format!("valor {} não permitido", valor));
+        }
+        if !padroes.is_empty() {
+            let mut aceito = false;
+            for padrao in padroes {
+                aceito |= self.padrao(padrao)?.is_match(valor);
+            }
+            if !aceito {
+                return Err(format!("valor {} fora do formato", valor));
+            }
+        }
+        Ok(())
+    }
+
+    /// XSD patterns match the whole value, so they are anchored here
+    fn padrao(&mut self, padrao: &'a str) -> Result<&Regex, String> {
+        if !self.padroes.contains_key(padrao) {
+            let regex = Regex::new(&format!("^(?:{})$", padrao))
+                .map_err(|error| format!("padrão {} inválido no schema: {}", padrao, error))?;
+            self.padroes.insert(padrao, regex);
+        }
+        Ok(&self.padroes[padrao])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCHEMA: &str = r#"<xs:schema xmlns="urn:teste" xmlns:xs="http://www.w3.org/2001/XMLSchema" targetNamespace="urn:teste" elementFormDefault="qualified">
+      <xs:element name="pedido">
+        <xs:complexType>
+          <xs:sequence>
+            <xs:element name="numero" type="TNumero"/>
+            <xs:choice>
+              <xs:element name="cpf" type="TNumero"/>
+              <xs:element name="cnpj" type="TNumero"/>
+            </xs:choice>
+            <xs:element name="item" maxOccurs="3">
+              <xs:complexType>
+                <xs:sequence>
+                  <xs:element name="uf">
+                    <xs:simpleType>
+                      <xs:restriction base="xs:string">
+                        <xs:enumeration value="SP"/>
+                        <xs:enumeration value="RJ"/>
+                      </xs:restriction>
+                    </xs:simpleType>
+                  </xs:element>
+                  <xs:element name="obs" type="xs:string" minOccurs="0"/>
+                </xs:sequence>
+                <xs:attribute name="n" type="TNumero" use="required"/>
+              </xs:complexType>
+            </xs:element>
+          </xs:sequence>
+        </xs:complexType>
+      </xs:element>
+      <xs:simpleType name="TNumero">
+        <xs:restriction base="xs:string">
+          <xs:maxLength value="3"/>
+          <xs:pattern value="[1-9][0-9]*"/>
+        </xs:restriction>
+      </xs:simpleType>
+    </xs:schema>"#;
+
+    #[test]
+    fn it_accepts_a_document_that_follows_the_schema() {
+        let xml = r#"<pedido xmlns="urn:teste"><numero>12</numero><cnpj>1</cnpj><item n="1"><uf>SP</uf></item><item n="2"><uf>RJ</uf><obs>frágil</obs></item></pedido>"#;
+        assert_eq!(validar(SCHEMA, xml), Ok(()));
+    }
+
+    #[test]
+    fn it_lists_what_breaks_the_schema() {
+        let xml = r#"<pedido xmlns="urn:teste"><numero>0123</numero><rg>1</rg><item><uf>MG</uf></item></pedido>"#;
+        let expected_error = ApiError::ValidationError(vec![
+            "XSD pedido/numero: tamanho de 0123 fora do permitido, maxLength 3".to_string(),
+            "XSD pedido: esperado um de cpf, cnpj, encontrado rg".to_string(),
+        ]);
+        assert_eq!(validar(SCHEMA, xml).unwrap_err(), expected_error);
+
+        let xml = r#"<pedido xmlns="urn:teste"><numero>1</numero><cpf>1</cpf><item><uf>MG</uf></item><extra/></pedido>"#;
+        let expected_error = ApiError::ValidationError(vec![
+            "XSD pedido/item[1]: atributo n ausente".to_string(),
+            "XSD pedido/item[1]/uf: valor MG não permitido".to_string(),
+            "XSD pedido: extra não previsto".to_string(),
+        ]);
+        assert_eq!(validar(SCHEMA, xml).unwrap_err(), expected_error);
+    }
+
+    #[test]
+    fn it_only_takes_elements_of_the_target_namespace() {
+        let xml = r#"<pedido><numero>1</numero></pedido>"#;
+        let expected_error = ApiError::ValidationError(vec!["XSD pedido: elemento raiz não previsto".to_string()]);
+        assert_eq!(validar(SCHEMA, xml).unwrap_err(), expected_error);
+    }
+}